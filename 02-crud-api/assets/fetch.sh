@@ -0,0 +1,28 @@
+#!/bin/sh
+# Downloads the ReDoc and Scalar bundles that src/doc_uis.rs serves, and
+# writes the Subresource Integrity hash of each next to it (<file>.sri). The
+# versions match REDOC_VERSION and SCALAR_VERSION there; bump both together.
+# Commit the files: the binary embeds the bundles and serves them itself, with
+# the .sri hash in the page's integrity attribute. A build without a bundle
+# warns and leaves that UI out.
+#
+# The committed copies are the same releases as vendored by the aide crate
+# (0.15.1, res/redoc and res/scalar; its Scalar copy is jsDelivr's minified
+# build). Running this replaces them with jsDelivr's originals.
+set -eu
+cd "$(dirname "$0")"
+
+REDOC_VERSION=2.0.0
+SCALAR_VERSION=1.31.3
+
+curl -fsSL -o redoc.standalone.js \
+  "https://cdn.jsdelivr.net/npm/redoc@${REDOC_VERSION}/bundles/redoc.standalone.js"
+curl -fsSL -o scalar.standalone.js \
+  "https://cdn.jsdelivr.net/npm/@scalar/api-reference@${SCALAR_VERSION}/dist/browser/standalone.js"
+
+for file in redoc.standalone.js scalar.standalone.js; do
+  printf 'sha384-%s\n' "$(openssl dgst -sha384 -binary "$file" | openssl base64 -A)" > "$file.sri"
+done
+
+ls -l redoc.standalone.js scalar.standalone.js
+cat redoc.standalone.js.sri scalar.standalone.js.sri
@@ -0,0 +1,55 @@
+// Embeds the ReDoc and Scalar bundles from assets/ (see src/doc_uis.rs).
+//
+// Each bundle is `Some(include_bytes!(..))` when its file is there. A missing
+// one is a build warning and `None`: that UI isn't served, and its tests are
+// ignored, until assets/fetch.sh has been run and the files committed. Its
+// `.sri` file, the bundle's Subresource Integrity hash, goes into the page.
+
+use std::path::Path;
+
+const ASSETS: &[(&str, &str)] = &[
+    ("REDOC", "redoc.standalone.js"),
+    ("SCALAR", "scalar.standalone.js"),
+];
+
+fn main() {
+    let dir = Path::new(&std::env::var("CARGO_MANIFEST_DIR").unwrap()).join("assets");
+    println!("cargo:rerun-if-changed={}", dir.display());
+
+    let mut out = String::new();
+    for (name, file) in ASSETS {
+        let cfg = format!("{}_bundle", name.to_lowercase());
+        println!("cargo:rustc-check-cfg=cfg({})", cfg);
+
+        let path = dir.join(file);
+        println!("cargo:rerun-if-changed={}", path.display());
+        let value = if path.is_file() {
+            println!("cargo:rustc-cfg={}", cfg);
+            format!("Some(include_bytes!({:?}))", path.display().to_string())
+        } else {
+            println!(
+                "cargo:warning=assets/{} is missing, so its UI isn't served; run assets/fetch.sh and commit the files",
+                file
+            );
+            "None".to_string()
+        };
+        out.push_str(&format!(
+            "pub const {}_JS: Option<&[u8]> = {};\n",
+            name, value
+        ));
+
+        let sri = dir.join(format!("{}.sri", file));
+        println!("cargo:rerun-if-changed={}", sri.display());
+        let value = match std::fs::read_to_string(&sri) {
+            Ok(hash) => format!("Some({:?})", hash.trim()),
+            Err(_) => "None".to_string(),
+        };
+        out.push_str(&format!(
+            "pub const {}_SRI: Option<&str> = {};\n",
+            name, value
+        ));
+    }
+
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    std::fs::write(Path::new(&out_dir).join("doc_assets.rs"), out).unwrap();
+}
@@ -0,0 +1,260 @@
+// ---------------------------------------------------------------------------
+// ReDoc, Scalar and the raw spec
+// ---------------------------------------------------------------------------
+//
+// RustApi::auto() serves Swagger UI at /docs. Some readers prefer ReDoc's
+// three panels or Scalar's client; external tools (generators, linters,
+// gateways) want the spec itself. All three, next to /docs:
+//
+//   RustApi::auto()
+//       .openapi_json("/openapi.json")
+//       .redoc("/redoc")
+//       .scalar("/scalar")
+//
+//   openapi_json(path)   the spec as JSON at `path`; the UIs mounted after
+//                        it load it from there (from /docs/openapi.json
+//                        otherwise)
+//   redoc(path)          ReDoc at `path`
+//   scalar(path)         Scalar at `path`
+//
+// Both UIs are served from the binary: the page at `path` and the bundle at
+// `{path}/<name>-<version>.standalone.js`, which never changes and is cached
+// for a year. Nothing is loaded from a CDN, so the docs work offline and
+// behind a strict Content-Security-Policy.
+//
+//   <script src="/redoc/redoc-2.0.0.standalone.js" integrity="sha384-..">
+//
+// The bundles live in assets/ and are embedded by build.rs; assets/fetch.sh
+// downloads the versions below and records their Subresource Integrity
+// hashes, which the page passes on to the browser. A bundle that isn't there
+// is a build warning, and its UI isn't mounted.
+//
+// NOTE: call openapi_json() after every route, like docs(): the spec is
+//       serialized once, when it's mounted.
+
+use bytes::Bytes;
+use http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use http::HeaderValue;
+use rustapi_rs::prelude::*;
+use rustapi_rs::ResponseBody;
+
+include!(concat!(env!("OUT_DIR"), "/doc_assets.rs"));
+
+/// The ReDoc release in assets/redoc.standalone.js.
+pub const REDOC_VERSION: &str = "2.0.0";
+/// The Scalar (@scalar/api-reference) release in assets/scalar.standalone.js.
+pub const SCALAR_VERSION: &str = "1.31.3";
+
+/// One UI's embedded script and its hash.
+struct Bundle {
+    name: &'static str,
+    /// File name under the UI's path, with the version in it.
+    file: String,
+    embedded: Option<&'static [u8]>,
+    sri: Option<&'static str>,
+}
+
+impl Bundle {
+    fn redoc() -> Self {
+        Self {
+            name: "ReDoc",
+            file: format!("redoc-{}.standalone.js", REDOC_VERSION),
+            embedded: REDOC_JS,
+            sri: REDOC_SRI,
+        }
+    }
+
+    fn scalar() -> Self {
+        Self {
+            name: "Scalar",
+            file: format!("scalar-{}.standalone.js", SCALAR_VERSION),
+            embedded: SCALAR_JS,
+            sri: SCALAR_SRI,
+        }
+    }
+
+    /// The `<script>` that loads the bundle from under `path`.
+    fn script_tag(&self, path: &str) -> String {
+        let integrity = self
+            .sri
+            .map(|sri| format!(" integrity=\"{}\"", sri))
+            .unwrap_or_default();
+        format!(
+            "<script src=\"{}/{}\"{}></script>",
+            path.trim_end_matches('/'),
+            self.file,
+            integrity
+        )
+    }
+}
+
+/// Where the UIs load the spec from when openapi_json() wasn't called.
+const DEFAULT_SPEC_URL: &str = "/docs/openapi.json";
+
+/// Where openapi_json() serves the spec, kept in the state for the UIs.
+#[derive(Debug, Clone)]
+struct SpecUrl(String);
+
+pub trait DocUisExt {
+    /// Serves the OpenAPI spec, as JSON, at `path`.
+    fn openapi_json(self, path: &str) -> Self;
+
+    /// Serves ReDoc at `path`.
+    fn redoc(self, path: &str) -> Self;
+
+    /// Serves Scalar at `path`.
+    fn scalar(self, path: &str) -> Self;
+}
+
+impl DocUisExt for RustApi {
+    fn openapi_json(self, path: &str) -> Self {
+        let json = serde_json::to_string_pretty(&self.openapi_spec().to_json())
+            .unwrap_or_else(|_| "{}".to_string());
+        let handler = move || {
+            let json = json.clone();
+            async move { with_type(Response::new(ResponseBody::from(json)), "application/json") }
+        };
+        self.route(path, get(handler))
+            .state(SpecUrl(path.to_string()))
+    }
+
+    fn redoc(self, path: &str) -> Self {
+        let spec_url = spec_url(&self);
+        mount_ui(self, path, Bundle::redoc(), |script| {
+            format!(
+                "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n  <meta charset=\"utf-8\">\n  \
+                 <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n  \
+                 <title>API Documentation</title>\n  <style>body {{ margin: 0; }}</style>\n\
+                 </head>\n<body>\n  <redoc spec-url=\"{}\"></redoc>\n  {}\n</body>\n</html>\n",
+                spec_url, script
+            )
+        })
+    }
+
+    fn scalar(self, path: &str) -> Self {
+        let spec_url = spec_url(&self);
+        mount_ui(self, path, Bundle::scalar(), |script| {
+            format!(
+                "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n  <meta charset=\"utf-8\">\n  \
+                 <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n  \
+                 <title>API Reference</title>\n</head>\n<body>\n  \
+                 <script id=\"api-reference\" data-url=\"{}\"></script>\n  {}\n</body>\n</html>\n",
+                spec_url, script
+            )
+        })
+    }
+}
+
+fn spec_url(app: &RustApi) -> String {
+    app.router()
+        .state_ref()
+        .get::<SpecUrl>()
+        .map(|url| url.0.clone())
+        .unwrap_or_else(|| DEFAULT_SPEC_URL.to_string())
+}
+
+fn with_type(mut response: Response, content_type: &'static str) -> Response {
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+    response
+}
+
+/// The page at `path`, built by `page` around the bundle's `<script>`, and
+/// the embedded bundle at `{path}/{file}`. Mounts nothing without a bundle.
+fn mount_ui(
+    app: RustApi,
+    path: &str,
+    bundle: Bundle,
+    page: impl FnOnce(&str) -> String,
+) -> RustApi {
+    let Some(asset) = bundle.embedded else {
+        warn!(
+            "{} at {} is not mounted: its bundle wasn't embedded; \
+             run 02-crud-api/assets/fetch.sh and rebuild",
+            bundle.name, path
+        );
+        return app;
+    };
+
+    let page = page(&bundle.script_tag(path));
+    let page_handler = move || {
+        let page = page.clone();
+        async move {
+            with_type(
+                Response::new(ResponseBody::from(page)),
+                "text/html; charset=utf-8",
+            )
+        }
+    };
+    let bundle_handler = move || async move {
+        let mut response = with_type(
+            Response::new(ResponseBody::new(Bytes::from_static(asset))),
+            "text/javascript; charset=utf-8",
+        );
+        // The file name has the version in it: a new one is a new URL.
+        response.headers_mut().insert(
+            CACHE_CONTROL,
+            HeaderValue::from_static("public, max-age=31536000, immutable"),
+        );
+        response
+    };
+
+    let path = path.trim_end_matches('/');
+    app.route(path, get(page_handler))
+        .route(&format!("{}/{}", path, bundle.file), get(bundle_handler))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+    use rustapi_core::{BodyVariant, PathParams};
+
+    async fn get_page(app: &RequestDispatcher, path: &str) -> (StatusCode, String) {
+        let (parts, ()) = http::Request::get(path).body(()).unwrap().into_parts();
+        let request = Request::new(
+            parts,
+            BodyVariant::Buffered(Default::default()),
+            app.state_ref(),
+            PathParams::new(),
+        );
+        let response = app.dispatch(request).await;
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, String::from_utf8_lossy(&body).into_owned())
+    }
+
+    /// Serves the UI at `path`, then the bundle its page loads, which must
+    /// come from this origin.
+    async fn assert_served(path: &str, bundle: &Bundle) {
+        let app = RustApi::new()
+            .openapi_json("/openapi.json")
+            .redoc("/redoc")
+            .scalar("/scalar")
+            .request_dispatcher();
+
+        let (status, page) = get_page(&app, path).await;
+        assert_eq!(status, StatusCode::OK);
+        let script = format!("<script src=\"{}/{}\"", path, bundle.file);
+        assert!(page.contains(&script), "{}", page);
+        assert!(page.contains("/openapi.json"), "{}", page);
+        assert!(!page.contains("://"), "loads from another origin: {}", page);
+
+        let (status, js) = get_page(&app, &format!("{}/{}", path, bundle.file)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(js.len(), bundle.embedded.unwrap().len());
+    }
+
+    #[tokio::test]
+    #[cfg_attr(not(redoc_bundle), ignore = "run assets/fetch.sh to embed ReDoc")]
+    async fn redoc_is_served_from_this_origin() {
+        assert_served("/redoc", &Bundle::redoc()).await;
+    }
+
+    #[tokio::test]
+    #[cfg_attr(not(scalar_bundle), ignore = "run assets/fetch.sh to embed Scalar")]
+    async fn scalar_is_served_from_this_origin() {
+        assert_served("/scalar", &Bundle::scalar()).await;
+    }
+}
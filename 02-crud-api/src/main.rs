@@ -1,9 +1,23 @@
 // Run with: cargo run -p crud-api
-// Then visit: http://127.0.0.1:3000/docs
+// Then visit: http://127.0.0.1:3000/docs (Swagger UI), /redoc or /scalar
+//           (ReDoc and Scalar load their bundles from this server, see assets/)
+//
+// Or write the OpenAPI spec and exit, without serving:
+//           cargo run -p crud-api -- openapi openapi.yaml
 //
 // Lesson: CRUD endpoints, shared state via Arc<RwLock>, typed extractors,
 //         and proper extractor ordering (body extractor goes last).
+//         Every error is RFC 9457 problem+json (src/problem.rs).
+//         The spec is also served raw, and through ReDoc and Scalar
+//         (src/doc_uis.rs).
+
+mod doc_uis;
+mod openapi_export;
+mod problem;
 
+use doc_uis::DocUisExt;
+use openapi_export::WriteOpenApiExt;
+use problem::{Problem, ProblemExt};
 use rustapi_rs::prelude::*;
 use rustapi_rs::{delete, get, post, put, summary, tag};
 use std::{
@@ -25,6 +39,37 @@ struct AppState {
     notes: Arc<RwLock<HashMap<u64, Note>>>,
 }
 
+impl AppState {
+    /// A brand-new store holding the seed notes.
+    ///
+    /// Every call allocates its own maps and counter, so two apps built from
+    /// two calls — e.g. two tests — never see each other's writes.
+    fn seeded() -> Self {
+        let seed = [
+            ("Welcome", "Notes live in memory and reset on restart."),
+            ("Try it", "POST /notes with a title and a body."),
+        ];
+
+        let notes = seed
+            .into_iter()
+            .zip(1u64..)
+            .map(|((title, body), id)| {
+                let note = Note {
+                    id,
+                    title: title.to_string(),
+                    body: body.to_string(),
+                };
+                (id, note)
+            })
+            .collect::<HashMap<_, _>>();
+
+        Self {
+            next_id: Arc::new(AtomicU64::new(notes.len() as u64 + 1)),
+            notes: Arc::new(RwLock::new(notes)),
+        }
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Models
 // ---------------------------------------------------------------------------
@@ -109,7 +154,7 @@ async fn create_note(
 async fn get_note(
     State(state): State<AppState>,
     Path(id): Path<u64>,
-) -> Result<Json<Note>, ApiError> {
+) -> Result<Json<Note>, Problem> {
     state
         .notes
         .read()
@@ -117,7 +162,7 @@ async fn get_note(
         .get(&id)
         .cloned()
         .map(Json)
-        .ok_or_else(|| ApiError::not_found("Note not found"))
+        .ok_or_else(|| Problem::from(ApiError::from(NoteError::NotFound)).with("note_id", id))
 }
 
 #[put("/notes/{id}")]
@@ -130,7 +175,9 @@ async fn update_note(
     Json(payload): Json<UpdateNote>,
 ) -> Result<Json<Note>, ApiError> {
     let mut notes = state.notes.write().await;
-    let note = notes.get_mut(&id).ok_or_else(|| ApiError::not_found("Note not found"))?;
+    let note = notes
+        .get_mut(&id)
+        .ok_or_else(|| ApiError::not_found("Note not found"))?;
     if let Some(t) = payload.title {
         note.title = t;
     }
@@ -154,16 +201,61 @@ async fn delete_note(
     }
 }
 
+// ---------------------------------------------------------------------------
+// App factory
+// ---------------------------------------------------------------------------
+
+/// Builds the API around `state`.
+///
+/// NOTE: keep state out of globals and pass it in here instead. A test harness
+///       then calls `app(AppState::seeded())` once per test and every test
+///       starts from the same seed data, isolated from the others.
+fn app(state: AppState) -> RustApi {
+    // Only state, the error format and the doc pages are provided — all
+    // other routes are auto-discovered from the macros above.
+    RustApi::auto()
+        .openapi_json("/openapi.json")
+        .redoc("/redoc")
+        .scalar("/scalar")
+        .problem_details()
+        .state(state)
+}
+
+/// A `type` URI per error code, under `base`: `{base}/not_found`. Problems
+/// that already have a type keep it.
+fn typed_problem(problem: Problem, base: &str) -> Response {
+    let code = problem
+        .extensions
+        .get("code")
+        .and_then(|code| code.as_str())
+        .map(str::to_string);
+    match code {
+        Some(code) if problem.type_uri == "about:blank" => {
+            let title = problem.title.clone();
+            problem
+                .type_uri(format!("{}/{}", base.trim_end_matches('/'), code))
+                .title(title)
+                .into_response()
+        }
+        _ => problem.into_response(),
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Entry point
 // ---------------------------------------------------------------------------
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let state = AppState {
-        next_id: Arc::new(AtomicU64::new(1)),
-        notes: Arc::new(RwLock::new(HashMap::new())),
-    };
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("openapi") {
+        let api = app(AppState::seeded());
+        // A file, not stdout: the framework logs there.
+        let path = args.get(2).map(String::as_str).unwrap_or("openapi.json");
+        api.write_openapi(path)?;
+        eprintln!("Wrote {}", path);
+        return Ok(());
+    }
 
     println!("Starting crud-api example…");
     println!(" -> GET    http://127.0.0.1:3000/notes");
@@ -172,12 +264,105 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     println!(" -> PUT    http://127.0.0.1:3000/notes/{{id}}");
     println!(" -> DELETE http://127.0.0.1:3000/notes/{{id}}");
     println!(" -> GET    http://127.0.0.1:3000/docs");
+    // Each UI is only mounted when its bundle was embedded.
+    if doc_uis::REDOC_JS.is_some() {
+        println!(" -> GET    http://127.0.0.1:3000/redoc");
+    }
+    if doc_uis::SCALAR_JS.is_some() {
+        println!(" -> GET    http://127.0.0.1:3000/scalar");
+    }
+    println!(" -> GET    http://127.0.0.1:3000/openapi.json");
     println!(" -> GET    http://127.0.0.1:3000/__rustapi/dashboard");
 
-    // Only state is provided — all routes are auto-discovered from the macros above.
-    RustApi::auto()
-        .state(state)
-        .dashboard(DashboardConfig::new())
+    // Set to give each kind of error its own `type` URI instead of about:blank.
+    let mut api = app(AppState::seeded());
+    if let Ok(base) = std::env::var("PROBLEM_TYPE_BASE") {
+        api = api.error_handler(move |problem| typed_problem(problem, &base));
+    }
+
+    api.dashboard(DashboardConfig::new())
         .run("127.0.0.1:3000")
         .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+    use rustapi_core::{BodyVariant, PathParams};
+    use serde_json::Value;
+
+    /// Sends one request through `app` in-process; returns status and body.
+    async fn send(app: &RequestDispatcher, method: &str, uri: &str, body: &str) -> (u16, Value) {
+        let (parts, _) = http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(())
+            .unwrap()
+            .into_parts();
+        let request = Request::new(
+            parts,
+            BodyVariant::Buffered(body.to_string().into()),
+            app.state_ref(),
+            PathParams::new(),
+        );
+        let response = app.dispatch(request).await;
+        let status = response.status().as_u16();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        (
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        )
+    }
+
+    fn ids(notes: &Value) -> Vec<u64> {
+        let mut ids: Vec<u64> = notes
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|note| note["id"].as_u64().unwrap())
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[tokio::test]
+    async fn created_notes_stay_in_their_own_app() {
+        let first = app(AppState::seeded()).request_dispatcher();
+        let second = app(AppState::seeded()).request_dispatcher();
+
+        let note = r#"{"title":"Only here","body":"in the first app"}"#;
+        let (status, created) = send(&first, "POST", "/notes", note).await;
+        assert_eq!(status, 201);
+        assert_eq!(created["id"], 3);
+
+        assert_eq!(ids(&send(&first, "GET", "/notes", "").await.1), [1, 2, 3]);
+        assert_eq!(ids(&send(&second, "GET", "/notes", "").await.1), [1, 2]);
+        assert_eq!(send(&second, "GET", "/notes/3", "").await.0, 404);
+
+        // Each app's counter is its own too: the second hands out 3 as well.
+        let note = r#"{"title":"Also 3","body":"in the second app"}"#;
+        let (_, created) = send(&second, "POST", "/notes", note).await;
+        assert_eq!(created["id"], 3);
+        assert_eq!(
+            send(&first, "GET", "/notes/3", "").await.1["title"],
+            "Only here"
+        );
+    }
+
+    #[tokio::test]
+    async fn deleted_seed_notes_stay_in_other_apps() {
+        let first = app(AppState::seeded()).request_dispatcher();
+        assert_eq!(send(&first, "DELETE", "/notes/1", "").await.0, 204);
+        assert_eq!(ids(&send(&first, "GET", "/notes", "").await.1), [2]);
+
+        // A fresh app, built after the delete, starts from the seed again.
+        let second = app(AppState::seeded()).request_dispatcher();
+        assert_eq!(ids(&send(&second, "GET", "/notes", "").await.1), [1, 2]);
+        assert_eq!(
+            send(&second, "GET", "/notes/1", "").await.1["title"],
+            "Welcome"
+        );
+    }
+}
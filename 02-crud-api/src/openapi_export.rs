@@ -0,0 +1,69 @@
+// ---------------------------------------------------------------------------
+// The OpenAPI spec as a file
+// ---------------------------------------------------------------------------
+//
+// Contract tests in CI want the spec without a server to ask. The app
+// builds it while registering routes, so building the app is enough:
+//
+//   cargo run -p crud-api -- openapi openapi.yaml    # openapi.json if no path
+//
+//   app.openapi_spec()             rustapi's OpenApiSpec, to inspect in code
+//   app.write_openapi(path)        JSON or YAML on disk, by the extension
+//   render_openapi(&app, format)   the same text, for a build script or a
+//                                  bin target of your own
+//
+// Both formats are deterministic: every object's keys are sorted, so
+// regenerating an unchanged spec gives the same bytes, and a diff of the
+// committed file shows only what changed. In CI:
+//
+//   cargo run -p crud-api -- openapi openapi.yaml && git diff --exit-code openapi.yaml
+//
+// NOTE: the spec describes routes registered with the app, nothing bound to
+//       a port: no server starts, and state isn't read.
+
+use rustapi_rs::prelude::*;
+use std::path::Path;
+
+/// How render_openapi() writes the spec.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecFormat {
+    Json,
+    Yaml,
+}
+
+impl SpecFormat {
+    /// YAML for `.yaml` and `.yml`, JSON otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml" | "yml") => Self::Yaml,
+            _ => Self::Json,
+        }
+    }
+}
+
+/// The app's spec as text, keys sorted, ending in a newline.
+pub fn render_openapi(app: &RustApi, format: SpecFormat) -> std::io::Result<String> {
+    // serde_json's Value keeps object keys in a BTreeMap: sorted.
+    let spec = app.openapi_spec().to_json();
+    let mut text = match format {
+        SpecFormat::Json => serde_json::to_string_pretty(&spec)?,
+        SpecFormat::Yaml => serde_yaml::to_string(&spec).map_err(std::io::Error::other)?,
+    };
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+/// `RustApi::auto().write_openapi("openapi.yaml")`
+pub trait WriteOpenApiExt {
+    /// Writes the spec to `path`, in the format its extension names.
+    fn write_openapi(&self, path: impl AsRef<Path>) -> std::io::Result<()>;
+}
+
+impl WriteOpenApiExt for RustApi {
+    fn write_openapi(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, render_openapi(self, SpecFormat::from_path(path))?)
+    }
+}
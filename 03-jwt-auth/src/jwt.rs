@@ -0,0 +1,406 @@
+// ---------------------------------------------------------------------------
+// JWTs: signing, verifying, and an extractor and layer on top
+// ---------------------------------------------------------------------------
+//
+// rustapi's JwtLayer checks HS256 tokens against one secret. This module
+// takes a set of keys — an HS256 secret, RS256 public keys from a JWKS — and
+// picks the one each token names:
+//
+//   let verifier = JwtVerifier::new()
+//       .hs256(SECRET)
+//       .jwks(include_str!("../keys/jwks.json"))?
+//       .leeway(Duration::from_secs(30));
+//
+//   RustApi::auto()
+//       .state(verifier.clone())
+//       .layer(AuthLayer::new(verifier).protect("/profile"))
+//
+//   async fn profile(Jwt(claims): Jwt<Claims>) -> ..
+//
+// AuthLayer turns a request under a protected prefix away with a 401 unless
+// it carries a valid `Authorization: Bearer <token>`. Jwt<C> hands the claims
+// to the handler: the ones AuthLayer verified, or, on a route the layer
+// doesn't cover, the token verified on the spot against the JwtVerifier in
+// the app state. Either way a bad token gets the same 401 body, and
+// AuthLayer adds `WWW-Authenticate: Bearer` to it — so install the layer
+// even with nothing to protect, as `AuthLayer::new(verifier)`.
+//
+//   no Authorization header      401 "Missing bearer token"
+//   bad signature, unknown kid   401 "Invalid token"
+//   exp in the past              401 "Token expired"
+//   nbf in the future            401 "Token not valid yet"
+//   claims that don't fit C      401 "Token claims don't fit"
+//
+// `exp` and `nbf` are both checked, each with the same leeway for clocks
+// that disagree. A token without `exp` is rejected.
+//
+// encode_jwt / decode_jwt do the same work outside a request.
+//
+// NOTE: a key only ever verifies the algorithm it was added for. A token
+//       that says `alg: HS256` is never checked against an RSA public key,
+//       which would let anyone who has the (public) key forge tokens.
+
+use crate::openapi::{require_scheme, BEARER_SCHEME};
+use http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use http::HeaderValue;
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::jwk::{AlgorithmParameters, JwkSet};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_openapi::{MediaType, Operation, OperationModifier, ResponseSpec, SchemaRef};
+use rustapi_rs::prelude::*;
+use rustapi_rs::FromRequestParts;
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A key that signs tokens.
+pub struct SigningKey {
+    algorithm: Algorithm,
+    kid: Option<String>,
+    key: EncodingKey,
+}
+
+impl SigningKey {
+    /// HMAC with SHA-256 and a shared secret.
+    pub fn hs256(secret: &[u8]) -> Self {
+        Self {
+            algorithm: Algorithm::HS256,
+            kid: None,
+            key: EncodingKey::from_secret(secret),
+        }
+    }
+
+    /// RSA with SHA-256, from a PKCS#1 DER private key. `kid` goes in every
+    /// token's header, so verifiers can find the public key in their JWKS.
+    pub fn rs256_der(kid: &str, der: &[u8]) -> Self {
+        Self {
+            algorithm: Algorithm::RS256,
+            kid: Some(kid.to_string()),
+            key: EncodingKey::from_rsa_der(der),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct VerifyingKey {
+    algorithm: Algorithm,
+    kid: Option<String>,
+    key: DecodingKey,
+}
+
+impl VerifyingKey {
+    /// A token naming a kid is only checked against the key with that kid,
+    /// or a key that has none.
+    fn fits(&self, header: &Header) -> bool {
+        self.algorithm == header.alg
+            && match (&self.kid, &header.kid) {
+                (Some(ours), Some(theirs)) => ours == theirs,
+                _ => true,
+            }
+    }
+}
+
+/// The keys tokens may be signed with, and how much clock skew to allow.
+#[derive(Clone, Default)]
+pub struct JwtVerifier {
+    keys: Arc<Vec<VerifyingKey>>,
+    leeway: u64,
+}
+
+impl JwtVerifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with(mut self, key: VerifyingKey) -> Self {
+        Arc::make_mut(&mut self.keys).push(key);
+        self
+    }
+
+    /// Accepts HS256 tokens signed with `secret`.
+    pub fn hs256(self, secret: &[u8]) -> Self {
+        self.with(VerifyingKey {
+            algorithm: Algorithm::HS256,
+            kid: None,
+            key: DecodingKey::from_secret(secret),
+        })
+    }
+
+    /// Accepts RS256 tokens signed by any RSA key in the JWKS document
+    /// `jwks`. Keys of other types are skipped.
+    pub fn jwks(mut self, jwks: &str) -> std::result::Result<Self, jsonwebtoken::errors::Error> {
+        let set: JwkSet = serde_json::from_str(jwks)
+            .map_err(|err| jsonwebtoken::errors::Error::from(ErrorKind::Json(Arc::new(err))))?;
+        for jwk in &set.keys {
+            if let AlgorithmParameters::RSA(_) = jwk.algorithm {
+                self = self.with(VerifyingKey {
+                    algorithm: Algorithm::RS256,
+                    kid: jwk.common.key_id.clone(),
+                    key: DecodingKey::from_jwk(jwk)?,
+                });
+            }
+        }
+        Ok(self)
+    }
+
+    /// How far `exp` may be in the past and `nbf` in the future. Default 0.
+    pub fn leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway.as_secs();
+        self
+    }
+
+    fn validation(&self, algorithm: Algorithm) -> Validation {
+        let mut validation = Validation::new(algorithm);
+        validation.leeway = self.leeway;
+        validation.validate_nbf = true;
+        validation
+    }
+}
+
+/// Signs `claims` into a compact JWT.
+pub fn encode_jwt<C: Serialize>(claims: &C, key: &SigningKey) -> Result<String> {
+    let mut header = Header::new(key.algorithm);
+    header.kid = key.kid.clone();
+    jsonwebtoken::encode(&header, claims, &key.key)
+        .map_err(|err| ApiError::internal(format!("Couldn't sign the token: {err}")))
+}
+
+/// Verifies `token` with whichever of `verifier`'s keys it names, and reads
+/// its claims. Every failure is a 401.
+pub fn decode_jwt<C: DeserializeOwned>(token: &str, verifier: &JwtVerifier) -> Result<C> {
+    let header =
+        jsonwebtoken::decode_header(token).map_err(|_| ApiError::unauthorized("Invalid token"))?;
+
+    let mut failure = ApiError::unauthorized("Invalid token");
+    for key in verifier.keys.iter().filter(|key| key.fits(&header)) {
+        match jsonwebtoken::decode::<C>(token, &key.key, &verifier.validation(key.algorithm)) {
+            Ok(data) => return Ok(data.claims),
+            // Some other key may have signed it.
+            Err(err) if matches!(err.kind(), ErrorKind::InvalidSignature) => {}
+            Err(err) => {
+                failure = ApiError::unauthorized(match err.kind() {
+                    ErrorKind::ExpiredSignature => "Token expired",
+                    ErrorKind::ImmatureSignature => "Token not valid yet",
+                    ErrorKind::Json(_) | ErrorKind::MissingRequiredClaim(_) => {
+                        "Token claims don't fit"
+                    }
+                    _ => "Invalid token",
+                });
+                break;
+            }
+        }
+    }
+    Err(failure)
+}
+
+/// The token of an `Authorization: Bearer <token>` header.
+fn bearer(req: &Request) -> Result<&str> {
+    req.headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            value
+                .strip_prefix("Bearer ")
+                .or_else(|| value.strip_prefix("bearer "))
+        })
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| ApiError::unauthorized("Missing bearer token"))
+}
+
+/// Claims AuthLayer has verified, for Jwt<C> to read in whatever shape.
+#[derive(Clone)]
+struct Verified(serde_json::Value);
+
+/// The verified claims of the request's bearer token.
+pub struct Jwt<C>(pub C);
+
+impl<C: DeserializeOwned + Send + Sync + 'static> FromRequestParts for Jwt<C> {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        if let Some(Verified(claims)) = req.extensions().get::<Verified>() {
+            return serde_json::from_value(claims.clone())
+                .map(Jwt)
+                .map_err(|_| ApiError::unauthorized("Token claims don't fit"));
+        }
+        let verifier = req
+            .state()
+            .get::<JwtVerifier>()
+            .ok_or_else(|| ApiError::internal("No JwtVerifier in the app state"))?;
+        decode_jwt(bearer(req)?, verifier).map(Jwt)
+    }
+}
+
+// A handler that takes Jwt<C> needs a bearer token: Swagger UI sends the one
+// given to "Authorize" (see src/openapi.rs).
+impl<C> OperationModifier for Jwt<C> {
+    fn update_operation(op: &mut Operation) {
+        require_scheme(op, BEARER_SCHEME);
+        let mut content = BTreeMap::new();
+        content.insert(
+            "application/json".to_string(),
+            MediaType {
+                schema: Some(SchemaRef::Ref {
+                    reference: "#/components/schemas/ErrorSchema".to_string(),
+                }),
+                example: None,
+            },
+        );
+        op.responses.insert(
+            "401".to_string(),
+            ResponseSpec {
+                description: "Missing, invalid or expired bearer token".to_string(),
+                content,
+                headers: BTreeMap::new(),
+            },
+        );
+    }
+}
+
+/// `response`, with the challenge RFC 6750 asks for if it is a 401 that
+/// lacks one.
+fn challenge(mut response: Response) -> Response {
+    if response.status() == StatusCode::UNAUTHORIZED
+        && !response.headers().contains_key(WWW_AUTHENTICATE)
+    {
+        response.headers_mut().insert(
+            WWW_AUTHENTICATE,
+            HeaderValue::from_static("Bearer realm=\"jwt-auth\""),
+        );
+    }
+    response
+}
+
+/// Requires a valid bearer token for every path under the protected
+/// prefixes. Other paths pass through, and any 401 from them gets the same
+/// challenge.
+#[derive(Clone)]
+pub struct AuthLayer {
+    verifier: JwtVerifier,
+    prefixes: Vec<String>,
+}
+
+impl AuthLayer {
+    pub fn new(verifier: JwtVerifier) -> Self {
+        Self {
+            verifier,
+            prefixes: Vec::new(),
+        }
+    }
+
+    /// Protects `prefix` and everything below it: `/admin` covers `/admin`
+    /// and `/admin/users`, not `/administrator`.
+    pub fn protect(mut self, prefix: &str) -> Self {
+        self.prefixes.push(prefix.trim_end_matches('/').to_string());
+        self
+    }
+
+    fn covers(&self, path: &str) -> bool {
+        self.prefixes.iter().any(|prefix| {
+            prefix.is_empty()
+                || path
+                    .strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+    }
+}
+
+impl MiddlewareLayer for AuthLayer {
+    fn call(
+        &self,
+        mut req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        if self.covers(req.path()) {
+            let claims = bearer(&req)
+                .and_then(|token| decode_jwt::<serde_json::Value>(token, &self.verifier));
+            match claims {
+                Ok(claims) => {
+                    req.extensions_mut().insert(Verified(claims));
+                }
+                Err(err) => return Box::pin(async move { challenge(err.into_response()) }),
+            }
+        }
+
+        // Jwt<C> rejects with an ApiError, which has no headers: its 401s,
+        // on any path, get the challenge here on the way out.
+        let response = next(req);
+        Box::pin(async move { challenge(response.await) })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustapi_core::{BodyVariant, PathParams};
+
+    const SECRET: &[u8] = b"test secret";
+
+    async fn subject(Jwt(claims): Jwt<serde_json::Value>) -> String {
+        claims["sub"].as_str().unwrap_or_default().to_string()
+    }
+
+    async fn get_response(path: &str, token: Option<&str>) -> Response {
+        let verifier = JwtVerifier::new().hs256(SECRET);
+        let app = RustApi::new()
+            .state(verifier.clone())
+            .route("/protected", get(subject))
+            .route("/open", get(subject))
+            .layer(AuthLayer::new(verifier).protect("/protected"))
+            .request_dispatcher();
+        let mut builder = http::Request::get(path);
+        if let Some(token) = token {
+            builder = builder.header(AUTHORIZATION, format!("Bearer {token}"));
+        }
+        let (parts, ()) = builder.body(()).unwrap().into_parts();
+        let request = Request::new(
+            parts,
+            BodyVariant::Buffered(Default::default()),
+            app.state_ref(),
+            PathParams::new(),
+        );
+        app.dispatch(request).await
+    }
+
+    #[tokio::test]
+    async fn every_401_carries_the_bearer_challenge() {
+        // AuthLayer rejects under /protected; Jwt<C> rejects on /open.
+        for path in ["/protected", "/open"] {
+            for token in [None, Some("not.a.token")] {
+                let response = get_response(path, token).await;
+                assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{path}");
+                assert_eq!(
+                    response.headers()[WWW_AUTHENTICATE],
+                    "Bearer realm=\"jwt-auth\"",
+                    "{path} {token:?}"
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn a_valid_token_gets_no_challenge() {
+        let exp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 60;
+        let token = encode_jwt(
+            &serde_json::json!({ "sub": "alice", "exp": exp }),
+            &SigningKey::hs256(SECRET),
+        )
+        .unwrap();
+        for path in ["/protected", "/open"] {
+            let response = get_response(path, Some(&token)).await;
+            assert_eq!(response.status(), StatusCode::OK, "{path}");
+            assert!(!response.headers().contains_key(WWW_AUTHENTICATE));
+        }
+    }
+}
@@ -1,23 +1,38 @@
 // Run with: cargo run -p jwt-auth
-// Then visit: http://127.0.0.1:3000/docs
+// Then visit: http://127.0.0.1:3000/docs  ("Authorize" takes the token)
 //
 // Quick test:
 //   1. POST /auth/login  {"username":"alice","password":"secret"}
 //   2. Copy the token from the response.
 //   3. GET /profile  -H "Authorization: Bearer <token>"
 //
+//   Or sign with RS256: {"username":"alice","password":"secret","alg":"RS256"}
+//
 // Lesson: JWT authentication with zero manual route registration.
-//         #[post/get] macros handle routing; JwtLayer + AuthUser<T> handle auth.
+//         #[post/get] macros handle routing; AuthLayer + Jwt<T> handle auth,
+//         for HS256 and RS256 tokens alike (src/jwt.rs).
+//         KeyedRateLimitLayer limits each user, not each IP; anonymous
+//         requests fall back to the peer address, kept by src/serve.rs.
+
+mod jwt;
+mod openapi;
+mod rate_limit;
+mod serve;
 
+use jwt::{encode_jwt, AuthLayer, Jwt, JwtVerifier, SigningKey};
+use openapi::{OpenApiSecurityExt, SecurityScheme, BEARER_SCHEME};
+use rate_limit::KeyedRateLimitLayer;
 use rustapi_rs::prelude::*;
-use rustapi_rs::{description, get, post, summary, tag};
-use std::time::{SystemTime, UNIX_EPOCH};
+use rustapi_rs::{description, errors, get, post, summary, tag, FromRequestParts};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 // ---------------------------------------------------------------------------
 // JWT Claims
 // ---------------------------------------------------------------------------
 
-/// Claims embedded in the JWT.  Must impl `Deserialize + Clone + Send + Sync`.
+/// Claims embedded in the JWT.  Must impl `Deserialize + Send + Sync`.
 #[derive(Debug, Clone, Serialize, Deserialize, Schema)]
 struct Claims {
     /// Subject — the user's identifier.
@@ -26,10 +41,25 @@ struct Claims {
     role: String,
     /// Expiry (Unix timestamp).
     exp: u64,
+    /// Not valid before (Unix timestamp).
+    nbf: u64,
 }
 
 const JWT_SECRET: &str = "change-me-in-production";
 
+// NOTE: a demo RSA key pair, checked in so the example runs as is. The
+//       private half signs RS256 tokens; the JWKS holds the public half and
+//       is served at /.well-known/jwks.json. Never ship a committed key.
+const RSA_KEY_ID: &str = "demo-2026";
+const RSA_PRIVATE_KEY_DER: &[u8] = include_bytes!("../keys/demo-rs256.der");
+const JWKS: &str = include_str!("../keys/jwks.json");
+
+/// Clock skew allowed on `exp` and `nbf`.
+const LEEWAY: Duration = Duration::from_secs(30);
+
+/// Requests per minute, per signed-in user — or per IP without a token.
+const REQUESTS_PER_MINUTE: u32 = 10;
+
 fn now_plus_secs(secs: u64) -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -38,6 +68,13 @@ fn now_plus_secs(secs: u64) -> u64 {
         + secs
 }
 
+/// The keys `/auth/login` signs with.
+#[derive(Clone)]
+struct Signers {
+    hs256: Arc<SigningKey>,
+    rs256: Arc<SigningKey>,
+}
+
 // ---------------------------------------------------------------------------
 // Request / response types
 // ---------------------------------------------------------------------------
@@ -46,6 +83,8 @@ fn now_plus_secs(secs: u64) -> u64 {
 struct LoginRequest {
     username: String,
     password: String,
+    /// `HS256` (default) or `RS256`.
+    alg: Option<String>,
 }
 
 #[derive(Debug, Serialize, Schema)]
@@ -88,20 +127,34 @@ impl From<AuthError> for ApiError {
 #[tag("auth")]
 #[summary("Login")]
 #[description("Returns a signed JWT that must be sent as `Authorization: Bearer <token>`.")]
-async fn login(Json(payload): Json<LoginRequest>) -> Result<Json<TokenResponse>, ApiError> {
+#[errors(400 = "Unsupported algorithm")]
+async fn login(
+    State(signers): State<Signers>,
+    Json(payload): Json<LoginRequest>,
+) -> Result<Json<TokenResponse>, ApiError> {
     // Hard-coded credentials for the demo.  Use a real user store in production.
     if payload.username != "alice" || payload.password != "secret" {
-        return Err(ApiError::unauthorized("Invalid credentials"));
+        return Err(AuthError::InvalidCredentials.into());
     }
+    let key = match payload.alg.as_deref().unwrap_or("HS256") {
+        "HS256" => &signers.hs256,
+        "RS256" => &signers.rs256,
+        other => {
+            return Err(ApiError::bad_request(format!(
+                "Unsupported algorithm {other}; use HS256 or RS256"
+            )))
+        }
+    };
 
     let ttl = 3600_u64;
     let claims = Claims {
         sub: payload.username,
         role: "user".to_string(),
         exp: now_plus_secs(ttl),
+        nbf: now_plus_secs(0),
     };
 
-    let token = create_token(&claims, JWT_SECRET).map_err(|_| ApiError::unauthorized("Invalid credentials"))?;;
+    let token = encode_jwt(&claims, key)?;
     Ok(Json(TokenResponse {
         token,
         expires_in: ttl,
@@ -111,14 +164,29 @@ async fn login(Json(payload): Json<LoginRequest>) -> Result<Json<TokenResponse>,
 #[get("/profile")]
 #[tag("profile")]
 #[summary("Get my profile")]
-#[description("Requires `Authorization: Bearer <token>` header.")]
-async fn profile(AuthUser(claims): AuthUser<Claims>) -> Json<ProfileResponse> {
+#[description("Requires `Authorization: Bearer <token>` header; AuthLayer checks it.")]
+async fn profile(Jwt(claims): Jwt<Claims>) -> Json<ProfileResponse> {
     Json(ProfileResponse {
         username: claims.sub,
         role: claims.role,
     })
 }
 
+#[get("/whoami")]
+#[tag("profile")]
+#[summary("Who the token belongs to")]
+#[description("Outside AuthLayer: `Jwt<Claims>` verifies the bearer token itself.")]
+async fn whoami(Jwt(claims): Jwt<Claims>) -> String {
+    claims.sub
+}
+
+#[get("/.well-known/jwks.json")]
+#[tag("auth")]
+#[summary("Public keys for RS256 tokens")]
+async fn jwks() -> Json<serde_json::Value> {
+    Json(serde_json::from_str(JWKS).expect("keys/jwks.json is valid JSON"))
+}
+
 #[get("/health")]
 #[tag("ops")]
 #[summary("Health check")]
@@ -135,17 +203,63 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     println!("Starting jwt-auth example…");
     println!(" -> POST http://127.0.0.1:3000/auth/login   {{\"username\":\"alice\",\"password\":\"secret\"}}");
     println!(" -> GET  http://127.0.0.1:3000/profile      (Authorization: Bearer <token>)");
+    println!(" -> GET  http://127.0.0.1:3000/whoami       (Authorization: Bearer <token>)");
+    println!(" -> GET  http://127.0.0.1:3000/.well-known/jwks.json");
     println!(" -> GET  http://127.0.0.1:3000/health       (public)");
     println!(" -> GET  http://127.0.0.1:3000/docs         (Swagger UI)");
     println!(" -> GET  http://127.0.0.1:3000/__rustapi/dashboard");
 
-    // Only the JWT layer is wired up — all routes are auto-discovered from the macros above.
-    RustApi::auto()
+    // Only behind a proxy that appends to X-Forwarded-For; TRUSTED_PROXIES
+    // lists the addresses of any proxies behind that one.
+    let trust_forwarded_for = match std::env::var("TRUST_FORWARDED_FOR") {
+        Ok(value) => value.parse()?,
+        Err(_) => false,
+    };
+    let trusted_proxies = match std::env::var("TRUSTED_PROXIES") {
+        Ok(list) => list
+            .split(',')
+            .map(|ip| ip.trim().parse())
+            .collect::<Result<Vec<IpAddr>, _>>()?,
+        Err(_) => Vec::new(),
+    };
+
+    let verifier = JwtVerifier::new()
+        .hs256(JWT_SECRET.as_bytes())
+        .jwks(JWKS)?
+        .leeway(LEEWAY);
+    let signers = Signers {
+        hs256: Arc::new(SigningKey::hs256(JWT_SECRET.as_bytes())),
+        rs256: Arc::new(SigningKey::rs256_der(RSA_KEY_ID, RSA_PRIVATE_KEY_DER)),
+    };
+
+    // Only the auth and rate-limit layers are wired up — all routes are
+    // auto-discovered from the macros above. The docs are mounted last, once
+    // the bearer scheme is declared (RustApi::auto() would mount them first).
+    let app = RustApi::config()
+        .docs_enabled(false)
+        .build()
+        .openapi_security(BEARER_SCHEME, SecurityScheme::http_bearer("JWT"))
+        .state(verifier.clone())
+        .state(signers)
+        .layer(AuthLayer::new(verifier).protect("/profile"))
+        // Added after AuthLayer, so it runs inside it and sees the claims.
+        // Other paths are limited per IP, unless they carry a valid token.
         .layer(
-            JwtLayer::<Claims>::new(JWT_SECRET)
-                .skip_paths(vec!["/auth/login", "/health", "/docs", "/__rustapi/dashboard"]),
+            KeyedRateLimitLayer::new(REQUESTS_PER_MINUTE, Duration::from_secs(60))
+                .key_by(|req| {
+                    Jwt::<Claims>::from_request_parts(req)
+                        .ok()
+                        .map(|Jwt(claims)| claims.sub)
+                })
+                .trust_forwarded_for(trust_forwarded_for)
+                .trusted_proxies(trusted_proxies)
+                // Users and IPs with a live window; about 100 bytes each.
+                .max_keys(100_000),
         )
         .dashboard(DashboardConfig::new())
-        .run("127.0.0.1:3000")
-        .await
+        .docs_with_security("/docs")
+        .layer(BodyLimitLayer::new(rustapi_core::DEFAULT_BODY_LIMIT));
+
+    // Not run(): the limiter needs each connection's address (src/serve.rs).
+    serve::serve(app, "127.0.0.1:3000").await
 }
@@ -0,0 +1,156 @@
+// ---------------------------------------------------------------------------
+// Security schemes in the OpenAPI spec
+// ---------------------------------------------------------------------------
+//
+// Swagger UI's "Authorize" button only knows a scheme the spec declares, and
+// only sends it to operations that list it under `security`. Both halves:
+//
+//   RustApi::config()
+//       .docs_enabled(false)
+//       .build()
+//       .openapi_security(BEARER_SCHEME, SecurityScheme::http_bearer("JWT"))
+//       .docs_with_security("/docs")
+//
+//   async fn profile(Jwt(claims): Jwt<Claims>) -> ..   // security: [bearer]
+//
+//   openapi_security(name, scheme)   declares `name` in
+//                                    components.securitySchemes
+//   require_scheme(op, name)         from an extractor's OperationModifier:
+//                                    the operation lists `name` under
+//                                    `security`; Jwt<C> requires
+//                                    BEARER_SCHEME
+//   docs_with_security(path)         serves Swagger UI and
+//                                    `{path}/openapi.json` with the schemes
+//                                    in; call it last, after every route
+//
+// The spec is fixed when the docs are mounted, so RustApi::auto() (which
+// mounts them at once) would serve it without the schemes: build the app with
+// docs off instead, and mount them here. An operation that requires a scheme
+// nobody declared is logged at startup; Swagger UI can't authorize it.
+//
+// NOTE: AuthLayer protects paths, not handlers. A handler under a protected
+//       prefix that doesn't take Jwt<C> isn't marked; take the extractor,
+//       even if the claims go unused.
+
+use rustapi_openapi::Operation;
+use rustapi_rs::prelude::*;
+use rustapi_rs::ResponseBody;
+use std::collections::BTreeMap;
+
+/// The name Jwt<C> requires, and the app declares.
+pub const BEARER_SCHEME: &str = "bearer";
+
+/// One entry of `components.securitySchemes`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum SecurityScheme {
+    #[serde(rename_all = "camelCase")]
+    Http {
+        scheme: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        bearer_format: Option<String>,
+    },
+}
+
+impl SecurityScheme {
+    /// `Authorization: Bearer <token>`; `format` is a hint for readers, like
+    /// `JWT`. Swagger UI adds the `Bearer ` prefix itself.
+    pub fn http_bearer(format: &str) -> Self {
+        Self::Http {
+            scheme: "bearer".to_string(),
+            bearer_format: Some(format.to_string()),
+        }
+    }
+}
+
+/// The schemes declared so far, kept in the app state until the docs are
+/// mounted.
+#[derive(Debug, Clone, Default)]
+struct SecuritySchemes(BTreeMap<String, SecurityScheme>);
+
+/// Lists `name` under the operation's `security`: a request needs it.
+pub fn require_scheme(op: &mut Operation, name: &str) {
+    let requirement = BTreeMap::from([(name.to_string(), Vec::new())]);
+    if !op.security.contains(&requirement) {
+        op.security.push(requirement);
+    }
+}
+
+pub trait OpenApiSecurityExt {
+    /// Declares `scheme` as `name`. Declaring a name again replaces it.
+    fn openapi_security(self, name: &str, scheme: SecurityScheme) -> Self;
+
+    /// Serves Swagger UI at `path` and the spec, with every declared scheme,
+    /// at `{path}/openapi.json`.
+    fn docs_with_security(self, path: &str) -> Self;
+}
+
+impl OpenApiSecurityExt for RustApi {
+    fn openapi_security(self, name: &str, scheme: SecurityScheme) -> Self {
+        let mut schemes = self
+            .router()
+            .state_ref()
+            .get::<SecuritySchemes>()
+            .cloned()
+            .unwrap_or_default();
+        schemes.0.insert(name.to_string(), scheme);
+        self.state(schemes)
+    }
+
+    fn docs_with_security(self, path: &str) -> Self {
+        let schemes = self
+            .router()
+            .state_ref()
+            .get::<SecuritySchemes>()
+            .cloned()
+            .unwrap_or_default()
+            .0;
+        let spec = self.openapi_spec();
+        for (route, item) in &spec.paths {
+            let operations = [&item.get, &item.put, &item.post, &item.delete, &item.patch];
+            for op in operations.into_iter().flatten() {
+                for name in op
+                    .security
+                    .iter()
+                    .flat_map(|requirement| requirement.keys())
+                {
+                    if !schemes.contains_key(name) {
+                        warn!(
+                            "{} requires security scheme '{}', which isn't declared",
+                            route, name
+                        );
+                    }
+                }
+            }
+        }
+
+        let mut json = spec.to_json();
+        if !schemes.is_empty() {
+            json["components"]["securitySchemes"] =
+                serde_json::to_value(&schemes).unwrap_or_default();
+        }
+        let json = serde_json::to_string_pretty(&json).unwrap_or_else(|_| "{}".to_string());
+
+        let path = path.trim_end_matches('/');
+        let openapi_url = format!("{}/openapi.json", path);
+        let spec_handler = move || {
+            let json = json.clone();
+            async move {
+                let mut response = Response::new(ResponseBody::from(json));
+                response.headers_mut().insert(
+                    http::header::CONTENT_TYPE,
+                    http::HeaderValue::from_static("application/json"),
+                );
+                response
+            }
+        };
+        let url = openapi_url.clone();
+        let docs_handler = move || {
+            let url = url.clone();
+            async move { rustapi_openapi::swagger_ui_html(&url).map(ResponseBody::Full) }
+        };
+
+        self.route(&openapi_url, get(spec_handler))
+            .route(path, get(docs_handler))
+    }
+}
@@ -0,0 +1,359 @@
+// ---------------------------------------------------------------------------
+// Rate limiting by key
+// ---------------------------------------------------------------------------
+//
+// rustapi's RateLimitLayer counts requests per client IP. Behind an office
+// NAT, a hundred users share one IP; one user with a token can spread
+// requests across many. For an authenticated API the unit that matters is
+// the user, or the API key.
+//
+// KeyedRateLimitLayer takes the key from a closure:
+//
+//   KeyedRateLimitLayer::new(60, Duration::from_secs(60))
+//       .key_by(|req| Jwt::<Claims>::from_request_parts(req).ok()
+//                         .map(|Jwt(claims)| claims.sub))
+//
+// A request the closure returns None for (no token, a public route) falls
+// back to its client IP, so anonymous traffic is still limited. Keys and IPs
+// are counted apart: `key:alice` never shares a budget with `ip:10.0.0.7`.
+//
+// Where the client IP comes from:
+//
+//   X-Forwarded-For   only with .trust_forwarded_for(true): the rightmost
+//                     address that isn't one of .trusted_proxies([..])
+//   the peer address  a `SocketAddr` in the request extensions, which
+//                     src/serve.rs inserts
+//   neither           the shared `ip:unknown` bucket
+//
+// Each proxy appends the address it got the request from, so the entries
+// right of the client's are your proxies', and everything left of it is
+// whatever the client wrote. Read from the right, the first address that
+// isn't a known proxy is the client. The leftmost entry is never trusted:
+// every made-up address there would be a fresh budget.
+//
+// With a peer address, X-Forwarded-For is also ignored unless the peer is
+// in trusted_proxies: a client that connects directly can't send one.
+//
+// NOTE: RustApi::run() doesn't put the peer address in the extensions, and
+//       ClientIp answers 127.0.0.1 when it can't tell. Reached directly,
+//       every anonymous client would share that one budget while looking
+//       like localhost. main.rs serves through src/serve.rs, which inserts
+//       the address; without it, requests land in `ip:unknown` and the layer
+//       logs a warning the first time it happens.
+//
+// Fixed windows, as rustapi's layer uses by default, with the same headers:
+// X-RateLimit-Limit / -Remaining / -Reset on every response, and a 429 with
+// Retry-After once the budget is spent.
+//
+// Memory is bounded. Expired windows are swept out once per window length,
+// not on every request. Past .max_keys(n) live windows (100,000 by default),
+// a new key shares the one `overflow` budget until a sweep frees room, and
+// the layer logs a warning the first time. Keys already tracked keep their
+// own budget.
+//
+// NOTE: the key closure runs where the layer sits. To key by user, add the
+//       layer *after* AuthLayer, so it runs inside it and sees the claims.
+
+use http::HeaderValue;
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_rs::prelude::*;
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Live windows tracked at once by default; see max_keys().
+pub const DEFAULT_MAX_KEYS: usize = 100_000;
+
+/// The budget new keys share while the table is full.
+const OVERFLOW: &str = "overflow";
+
+type KeyFn = dyn Fn(&Request) -> Option<String> + Send + Sync;
+
+struct Window {
+    started: Instant,
+    count: u32,
+}
+
+struct Windows {
+    by_bucket: HashMap<String, Window>,
+    last_sweep: Instant,
+}
+
+#[derive(Clone)]
+pub struct KeyedRateLimitLayer {
+    requests: u32,
+    window: Duration,
+    key: Arc<KeyFn>,
+    trust_forwarded_for: bool,
+    trusted_proxies: Arc<[IpAddr]>,
+    max_keys: usize,
+    windows: Arc<Mutex<Windows>>,
+    warned_unknown: Arc<AtomicBool>,
+    warned_full: Arc<AtomicBool>,
+}
+
+impl KeyedRateLimitLayer {
+    /// `requests` per `window` for each key. Until `.key_by(...)` is set, every
+    /// request is keyed by client IP.
+    pub fn new(requests: u32, window: Duration) -> Self {
+        Self {
+            requests,
+            window,
+            key: Arc::new(|_| None),
+            trust_forwarded_for: false,
+            trusted_proxies: Arc::new([]),
+            max_keys: DEFAULT_MAX_KEYS,
+            windows: Arc::new(Mutex::new(Windows {
+                by_bucket: HashMap::new(),
+                last_sweep: Instant::now(),
+            })),
+            warned_unknown: Arc::new(AtomicBool::new(false)),
+            warned_full: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Where the key comes from: a user id, an API key, a tenant. `None`
+    /// falls back to the client IP.
+    pub fn key_by<F>(mut self, key: F) -> Self
+    where
+        F: Fn(&Request) -> Option<String> + Send + Sync + 'static,
+    {
+        self.key = Arc::new(key);
+        self
+    }
+
+    /// Whether the IP fallback reads X-Forwarded-For. Default false; turn it
+    /// on only when every request comes through a proxy that appends to it.
+    pub fn trust_forwarded_for(mut self, trust: bool) -> Self {
+        self.trust_forwarded_for = trust;
+        self
+    }
+
+    /// Addresses of your own proxies: skipped when reading X-Forwarded-For
+    /// from the right, and the only peers whose header is read at all.
+    pub fn trusted_proxies(mut self, proxies: impl IntoIterator<Item = IpAddr>) -> Self {
+        self.trusted_proxies = proxies.into_iter().collect();
+        self
+    }
+
+    /// Live windows tracked at most; new keys past it share one budget.
+    pub fn max_keys(mut self, max_keys: usize) -> Self {
+        self.max_keys = max_keys;
+        self
+    }
+
+    fn client_ip(&self, req: &Request) -> Option<IpAddr> {
+        let peer = req.extensions().get::<SocketAddr>().map(SocketAddr::ip);
+        let from_proxy = peer.is_none_or(|peer| self.trusted_proxies.contains(&peer));
+        if !self.trust_forwarded_for || !from_proxy {
+            return peer;
+        }
+        forwarded_client(
+            req.headers()
+                .get_all("x-forwarded-for")
+                .iter()
+                .filter_map(|value| value.to_str().ok()),
+            &self.trusted_proxies,
+        )
+        .or(peer)
+    }
+
+    /// The bucket for `req`: `key:<key>`, `ip:<client ip>` or `ip:unknown`.
+    fn bucket(&self, req: &Request) -> String {
+        if let Some(key) = (self.key)(req) {
+            return format!("key:{key}");
+        }
+        match self.client_ip(req) {
+            Some(ip) => format!("ip:{ip}"),
+            None => {
+                if !self.warned_unknown.swap(true, Ordering::Relaxed) {
+                    warn!(
+                        "Rate limiting a request without a key or a client IP; such \
+                         requests share one budget. Put a proxy that appends \
+                         to X-Forwarded-For in front and trust it, or key them \
+                         with .key_by(...)"
+                    );
+                }
+                "ip:unknown".to_string()
+            }
+        }
+    }
+
+    /// Counts one request against `bucket`: whether it's allowed, how many
+    /// remain, and how long until the window resets.
+    fn check(&self, bucket: String) -> (bool, u32, Duration) {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().expect("rate limit windows poisoned");
+        // Once a window length: every window started before the last sweep
+        // has expired since, so each one is swept out within two lengths.
+        if now.duration_since(windows.last_sweep) >= self.window {
+            windows
+                .by_bucket
+                .retain(|_, window| now.duration_since(window.started) < self.window);
+            windows.last_sweep = now;
+        }
+
+        let full = windows.by_bucket.len() >= self.max_keys;
+        let bucket = if full && !windows.by_bucket.contains_key(&bucket) {
+            if !self.warned_full.swap(true, Ordering::Relaxed) {
+                warn!(
+                    "Rate limiter is tracking {} keys; new keys share one budget \
+                     until old windows expire",
+                    self.max_keys
+                );
+            }
+            OVERFLOW.to_string()
+        } else {
+            bucket
+        };
+
+        let window = windows.by_bucket.entry(bucket).or_insert(Window {
+            started: now,
+            count: 0,
+        });
+        if now.duration_since(window.started) >= self.window {
+            *window = Window {
+                started: now,
+                count: 0,
+            };
+        }
+        let reset_in = self
+            .window
+            .saturating_sub(now.duration_since(window.started));
+        if window.count >= self.requests {
+            return (false, 0, reset_in);
+        }
+        window.count += 1;
+        (true, self.requests - window.count, reset_in)
+    }
+}
+
+/// The rightmost address in the X-Forwarded-For `lines` that isn't in
+/// `proxies`; None if that entry isn't an address, or there isn't one.
+fn forwarded_client<'a>(
+    lines: impl DoubleEndedIterator<Item = &'a str>,
+    proxies: &[IpAddr],
+) -> Option<IpAddr> {
+    let mut entries = lines.rev().flat_map(|line| line.rsplit(','));
+    loop {
+        let ip: IpAddr = entries.next()?.trim().parse().ok()?;
+        if !proxies.contains(&ip) {
+            return Some(ip);
+        }
+    }
+}
+
+fn header(value: impl ToString) -> HeaderValue {
+    HeaderValue::try_from(value.to_string()).expect("a number is a valid header value")
+}
+
+impl MiddlewareLayer for KeyedRateLimitLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let (allowed, remaining, reset_in) = self.check(self.bucket(&req));
+        let limit = self.requests;
+        // Whole seconds, rounded up: a client that waits this long gets in.
+        let retry_after = reset_in.as_secs() + u64::from(reset_in.subsec_nanos() > 0);
+        let reset_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs())
+            + retry_after;
+
+        Box::pin(async move {
+            let mut response = if allowed {
+                next(req).await
+            } else {
+                let mut response = ApiError::new(
+                    StatusCode::TOO_MANY_REQUESTS,
+                    "rate_limit_exceeded",
+                    format!("Too many requests; retry in {} s", retry_after),
+                )
+                .into_response();
+                response
+                    .headers_mut()
+                    .insert(http::header::RETRY_AFTER, header(retry_after));
+                response
+            };
+
+            let headers = response.headers_mut();
+            headers.insert("x-ratelimit-limit", header(limit));
+            headers.insert("x-ratelimit-remaining", header(remaining));
+            headers.insert("x-ratelimit-reset", header(reset_at));
+            response
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_keys_share_one_budget_once_full() {
+        let limiter = KeyedRateLimitLayer::new(2, Duration::from_secs(60)).max_keys(2);
+        assert!(limiter.check("key:a".to_string()).0);
+        assert!(limiter.check("key:b".to_string()).0);
+        // Full: c and d draw on the overflow budget (the table holds it
+        // past max_keys), a keeps its own.
+        assert!(limiter.check("key:c".to_string()).0);
+        assert!(limiter.check("key:d".to_string()).0);
+        assert!(!limiter.check("key:e".to_string()).0);
+        assert!(limiter.check("key:a".to_string()).0);
+        assert_eq!(limiter.windows.lock().unwrap().by_bucket.len(), 3);
+    }
+
+    #[test]
+    fn expired_windows_are_swept_once_a_window() {
+        let limiter = KeyedRateLimitLayer::new(1, Duration::from_millis(20)).max_keys(1);
+        assert!(limiter.check("key:a".to_string()).0);
+        std::thread::sleep(Duration::from_millis(30));
+        // a's window has expired and is swept, so b gets a budget of its own.
+        assert!(limiter.check("key:b".to_string()).0);
+        assert!(!limiter.check("key:b".to_string()).0);
+        let windows = limiter.windows.lock().unwrap();
+        assert!(windows.by_bucket.contains_key("key:b"));
+        assert!(!windows.by_bucket.contains_key("key:a"));
+    }
+
+    fn ip(text: &str) -> IpAddr {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn the_rightmost_untrusted_address_is_the_client() {
+        let proxies = [ip("10.0.0.2")];
+        // The client made up 1.1.1.1; the edge proxy appended 203.0.113.7,
+        // and the internal one 10.0.0.2.
+        let client = forwarded_client(["1.1.1.1, 203.0.113.7, 10.0.0.2"].into_iter(), &proxies);
+        assert_eq!(client, Some(ip("203.0.113.7")));
+    }
+
+    #[test]
+    fn entries_on_several_lines_are_read_from_the_last() {
+        let client = forwarded_client(["1.1.1.1", "203.0.113.7"].into_iter(), &[]);
+        assert_eq!(client, Some(ip("203.0.113.7")));
+    }
+
+    #[test]
+    fn garbage_in_the_client_position_is_no_address() {
+        assert_eq!(
+            forwarded_client(["203.0.113.7, nonsense"].into_iter(), &[]),
+            None
+        );
+        assert_eq!(
+            forwarded_client(["10.0.0.2"].into_iter(), &[ip("10.0.0.2")]),
+            None
+        );
+    }
+}
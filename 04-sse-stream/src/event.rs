@@ -0,0 +1,44 @@
+// ---------------------------------------------------------------------------
+// SseEvent builder helpers
+// ---------------------------------------------------------------------------
+//
+// SseEvent::new(data) takes the data up front, and `.retry(3000)` takes
+// milliseconds as a bare number. With these two, an event reads in field
+// order and the retry carries its unit:
+//
+//   SseEvent::default()
+//       .event("clock")
+//       .id("42")
+//       .data("12:00:00")
+//       .retry_after(Duration::from_secs(3))
+//
+//   event: clock
+//   id: 42
+//   retry: 3000
+//   data: 12:00:00
+//
+// Multi-line data is split into one `data:` line per line; EventSource joins
+// them back with `\n`.
+
+use rustapi_rs::prelude::*;
+use std::time::Duration;
+
+pub trait SseEventExt {
+    /// The event's data, replacing any set before.
+    fn data(self, data: impl Into<String>) -> Self;
+
+    /// How long the browser waits before reconnecting once the stream drops.
+    /// Sent as whole milliseconds.
+    fn retry_after(self, delay: Duration) -> Self;
+}
+
+impl SseEventExt for SseEvent {
+    fn data(mut self, data: impl Into<String>) -> Self {
+        self.data = data.into();
+        self
+    }
+
+    fn retry_after(self, delay: Duration) -> Self {
+        self.retry(u64::try_from(delay.as_millis()).unwrap_or(u64::MAX))
+    }
+}
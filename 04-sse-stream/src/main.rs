@@ -1,13 +1,36 @@
 // Run with: cargo run -p sse-stream
 // Then open: http://127.0.0.1:3000/         (HTML test page)
 //            http://127.0.0.1:3000/events   (raw SSE stream)
+//            http://127.0.0.1:3000/notifications (resumable stream)
+//            http://127.0.0.1:3000/clock    (registered with .route())
 //
 // Lesson: Server-Sent Events with #[get] auto-registration.
 //         RustApi::auto() — zero .route() calls.
+//         A request timeout must stop at the first byte of a stream, or it
+//         kills every long-lived connection.
+//         A client that reconnects with Last-Event-ID gets what it missed
+//         replayed first (src/replay.rs).
 
+mod event;
+mod ndjson;
+mod replay;
+mod timeout;
+
+use event::SseEventExt;
+use futures_util::StreamExt;
+use ndjson::{NdJson, NdJsonLines};
+use replay::{EventLog, LastEventId, Logged, Subscription};
 use rustapi_rs::prelude::*;
-use rustapi_rs::{description, get, summary};
+use rustapi_rs::{description, errors, get, post, summary};
 use std::convert::Infallible;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use timeout::RequestTimeoutLayer;
+
+/// Deadline for producing a response head; see src/timeout.rs.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Notifications kept for clients that reconnect; override with REPLAY_BUFFER.
+const REPLAY_BUFFER: usize = 100;
 
 // ---------------------------------------------------------------------------
 // Models
@@ -19,28 +42,182 @@ struct Tick {
     message: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Schema)]
+struct Notification {
+    message: String,
+}
+
+/// `?strict=` for `POST /notifications`.
+#[derive(Debug, Deserialize, Schema)]
+struct IngestParams {
+    /// Reject the rest of the body at the first invalid line. Default false:
+    /// invalid lines are skipped.
+    strict: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Schema)]
+struct Ingested {
+    published: usize,
+    skipped: usize,
+}
+
+/// `?follow=` for `GET /notifications/log`.
+#[derive(Debug, Deserialize, Schema)]
+struct LogParams {
+    /// Keep the response open and add notifications as they are published,
+    /// like `tail -f`. Default false.
+    follow: Option<bool>,
+}
+
 // ---------------------------------------------------------------------------
 // Handlers
 // ---------------------------------------------------------------------------
 
 #[get("/events")]
 #[summary("Event stream")]
-#[description("Sends a `tick` SSE event once per second for 10 ticks, then closes.")]
+#[description(
+    "Sends a `tick` SSE event once per second for 10 ticks, then closes. \
+     Runs well past the 2s request timeout: streams are exempt once they start."
+)]
 async fn event_stream(
 ) -> Sse<impl futures_util::Stream<Item = std::result::Result<SseEvent, Infallible>>> {
-    let events: Vec<Result<SseEvent, Infallible>> = (1..=10_u64)
-        .map(|i| {
-            Ok(SseEvent::json_data(&Tick {
-                count: i,
-                message: format!("tick {i} of 10"),
-            })
-            .expect("tick should serialize")
-            .event("tick")
-            .id(i.to_string()))
+    let events = futures_util::stream::unfold(1_u64, |i| async move {
+        if i > 10 {
+            return None;
+        }
+        if i > 1 {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        let event = SseEvent::json_data(&Tick {
+            count: i,
+            message: format!("tick {i} of 10"),
         })
-        .collect();
+        .expect("tick should serialize")
+        .event("tick")
+        .id(i.to_string());
+        Some((Ok(event), i + 1))
+    });
+
+    Sse::new(events).keep_alive(KeepAlive::new())
+}
 
-    sse_from_iter(events).keep_alive(KeepAlive::new())
+fn notification_event(logged: Logged<Notification>) -> SseEvent {
+    SseEvent::json_data(&logged.event)
+        .expect("notification should serialize")
+        .event("notification")
+        .id(logged.id.to_string())
+}
+
+#[get("/notifications")]
+#[summary("Resumable notification stream")]
+#[description(
+    "A `notification` event every 2s, for as long as the client stays. On reconnect, \
+     events after `Last-Event-ID` are replayed from a bounded buffer before live ones; \
+     a `gap` event first means some were too old to keep."
+)]
+async fn notifications(
+    State(log): State<EventLog<Notification>>,
+    LastEventId(last_seen): LastEventId,
+) -> Sse<impl futures_util::Stream<Item = std::result::Result<SseEvent, Infallible>>> {
+    let Subscription { replay, gap, live } = log.subscribe(last_seen);
+
+    let gap = gap.then(|| SseEvent::new("some notifications were missed").event("gap"));
+    let missed = gap
+        .into_iter()
+        .chain(replay.into_iter().map(notification_event));
+    let live = futures_util::stream::unfold(live, |mut live| async move {
+        // Lagging ends the stream too: the browser reconnects with
+        // Last-Event-ID and the log fills in what was skipped.
+        let logged = live.recv().await.ok()?;
+        Some((notification_event(logged), live))
+    });
+
+    Sse::new(futures_util::stream::iter(missed).chain(live).map(Ok)).keep_alive(KeepAlive::new())
+}
+
+#[post("/notifications")]
+#[summary("Publish notifications from an NDJSON body")]
+#[description(
+    "One `{\"message\": ...}` per line, published in order as they are read. \
+     Invalid lines are skipped and counted; with `strict=true` the first one ends \
+     the request with a 400 (the lines before it stay published)."
+)]
+#[errors(
+    400 = "strict=true and a line is invalid",
+    413 = "A line is over 64 KiB",
+    415 = "Not application/x-ndjson"
+)]
+async fn publish_notifications(
+    State(log): State<EventLog<Notification>>,
+    Query(params): Query<IngestParams>,
+    lines: NdJsonLines<Notification>,
+) -> Result<Json<Ingested>> {
+    let mut lines = lines.skip_invalid(!params.strict.unwrap_or(false));
+    let mut published = 0;
+    while let Some(notification) = lines.next().await? {
+        log.publish(notification);
+        published += 1;
+    }
+    Ok(Json(Ingested {
+        published,
+        skipped: lines.skipped(),
+    }))
+}
+
+#[get("/notifications/log")]
+#[summary("Buffered notifications as NDJSON")]
+#[description(
+    "Every buffered notification as one JSON line, oldest first, then the response ends. \
+     With `follow=true` it stays open and new notifications are added as they come."
+)]
+async fn notification_log(
+    State(log): State<EventLog<Notification>>,
+    Query(params): Query<LogParams>,
+) -> NdJson<impl futures_util::Stream<Item = Logged<Notification>>> {
+    // Seen nothing yet, but unlike a fresh EventSource, wants the backlog.
+    let Subscription { replay, live, .. } = log.subscribe(Some(0));
+    let follow = params.follow.unwrap_or(false);
+
+    let live = futures_util::stream::unfold(live, move |mut live| async move {
+        if !follow {
+            return None;
+        }
+        let logged = live.recv().await.ok()?;
+        Some((logged, live))
+    });
+    NdJson(futures_util::stream::iter(replay).chain(live))
+}
+
+/// Registered with `.route()` rather than `#[get]`: the Sse return type is
+/// the same either way.
+async fn clock() -> Sse<impl futures_util::Stream<Item = std::result::Result<SseEvent, Infallible>>>
+{
+    let ticks = futures_util::stream::unfold(0_u64, |n| async move {
+        if n > 0 {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        let event = SseEvent::default()
+            .event("clock")
+            .id(n.to_string())
+            .data(format!("unix time {now}"))
+            .retry_after(Duration::from_secs(3));
+        Some((Ok(event), n + 1))
+    });
+
+    // Quiet for 10s between ticks: a comment every 3s keeps proxies from
+    // timing the connection out.
+    Sse::new(ticks).keep_alive(KeepAlive::new().interval(Duration::from_secs(3)))
+}
+
+#[get("/slow")]
+#[summary("Slow unary request")]
+#[description("Takes 5s to answer — longer than the 2s request timeout, so it gets a 503.")]
+async fn slow() -> &'static str {
+    tokio::time::sleep(Duration::from_secs(5)).await;
+    "too late"
 }
 
 #[get("/")]
@@ -53,6 +230,9 @@ async fn index() -> Html<&'static str> {
   <h1>SSE Stream Demo</h1>
   <p>Events appear below (10 ticks, then stream closes):</p>
   <ul id="log"></ul>
+  <p>Notifications (stop the server for a few seconds and restart it — or
+  watch the network tab reconnect — nothing is skipped):</p>
+  <ul id="notifications"></ul>
   <script>
     const es = new EventSource('/events');
     es.addEventListener('tick', e => {
@@ -61,6 +241,16 @@ async fn index() -> Html<&'static str> {
       document.getElementById('log').appendChild(li);
     });
     es.onerror = () => es.close();
+
+    // Reconnects by itself, sending the last id it saw as Last-Event-ID.
+    const notes = new EventSource('/notifications');
+    const note = text => {
+      const li = document.createElement('li');
+      li.textContent = text;
+      document.getElementById('notifications').appendChild(li);
+    };
+    notes.addEventListener('notification', e => note(`#${e.lastEventId} ${JSON.parse(e.data).message}`));
+    notes.addEventListener('gap', e => note(`(${e.data})`));
   </script>
 </body>
 </html>"#,
@@ -75,11 +265,39 @@ async fn index() -> Html<&'static str> {
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     println!("Starting sse-stream example…");
     println!(" -> GET http://127.0.0.1:3000/         (HTML test page)");
-    println!(" -> GET http://127.0.0.1:3000/events   (raw SSE stream)");
+    println!(" -> GET http://127.0.0.1:3000/events   (raw SSE stream, 10s)");
+    println!(" -> GET http://127.0.0.1:3000/notifications (resumable, Last-Event-ID)");
+    println!(" -> GET http://127.0.0.1:3000/notifications/log[?follow=true] (NDJSON)");
+    println!(" -> POST http://127.0.0.1:3000/notifications (NDJSON body)");
+    println!(" -> GET http://127.0.0.1:3000/clock    (every 10s, keep-alive every 3s)");
+    println!(" -> GET http://127.0.0.1:3000/slow     (503 after 2s)");
     println!(" -> GET http://127.0.0.1:3000/__rustapi/dashboard");
 
-    // Zero configuration — #[get] macros register all routes at compile time.
+    let replay_buffer = std::env::var("REPLAY_BUFFER")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(REPLAY_BUFFER);
+    let log = EventLog::new(replay_buffer);
+    println!("    replay buffer: {} notifications", log.capacity());
+
+    let publisher = log.clone();
+    tokio::spawn(async move {
+        let started = Instant::now();
+        let mut every = tokio::time::interval(Duration::from_secs(2));
+        loop {
+            every.tick().await;
+            publisher.publish(Notification {
+                message: format!("{}s since start", started.elapsed().as_secs()),
+            });
+        }
+    });
+
+    // #[get] macros register the routes at compile time; /clock shows the
+    // explicit form.
     RustApi::auto()
+        .route("/clock", get(clock))
+        .state(log)
+        .layer(RequestTimeoutLayer::new(REQUEST_TIMEOUT))
         .dashboard(DashboardConfig::new())
         .run("127.0.0.1:3000")
         .await
@@ -0,0 +1,381 @@
+// ---------------------------------------------------------------------------
+// JSON Lines (NDJSON), both ways
+// ---------------------------------------------------------------------------
+//
+// One JSON value per line, `\n` after each. Unlike one big array, it can be
+// written and read a record at a time: `jq`, `grep`, log shippers and
+// `curl | while read` all handle it, and neither side ever holds the whole
+// thing.
+//
+//   NdJson(stream)     response: every item the stream yields is written as
+//                      one line and sent at once (`application/x-ndjson`)
+//   NdJsonLines<T>     extractor: the request body parsed line by line, off
+//                      the connection, as it arrives
+//
+//   async fn ingest(mut lines: NdJsonLines<Entry>) -> Result<Json<Ingested>> {
+//       while let Some(entry) = lines.next().await? { ... }
+//   }
+//
+// Bad input, and what happens to it:
+//
+//   a line that isn't valid JSON,   skip (default): logged with its line
+//   or not a T                      number and counted in `skipped()`; the
+//                                   lines after it are still read
+//                                   abort (`.skip_invalid(false)`): next()
+//                                   returns a 400 naming the line
+//   blank lines                     ignored, `\r\n` endings accepted
+//   a last line without `\n`        parsed like any other line
+//   a line over MAX_LINE bytes      413, always: there is no telling where
+//                                   the next line starts without reading
+//                                   this one
+//   the body itself fails           400, always (client went away, or a
+//                                   chunked body was cut short)
+//
+// Either way, the lines before the bad one have already been handed out.
+// A handler that must be all-or-nothing collects first and applies after.
+//
+// On the way out, an item that fails to serialize is logged and left out;
+// by then the 200 is long gone and there's no other way to say so.
+
+use bytes::{Bytes, BytesMut};
+use futures_util::{Stream, StreamExt};
+use http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use http::HeaderValue;
+use http_body_util::BodyExt;
+use rustapi_openapi::{
+    MediaType, Operation, OperationModifier, RequestBody, ResponseModifier, ResponseSpec, SchemaRef,
+};
+use rustapi_rs::prelude::*;
+use rustapi_rs::{FromRequest, ResponseBody};
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::pin::Pin;
+
+pub const CONTENT_TYPE_NDJSON: &str = "application/x-ndjson";
+
+/// Longest line `NdJsonLines` accepts, in bytes.
+pub const MAX_LINE: usize = 64 * 1024;
+
+fn ndjson_media_type(description: &str, example: &str) -> BTreeMap<String, MediaType> {
+    let mut content = BTreeMap::new();
+    content.insert(
+        CONTENT_TYPE_NDJSON.to_string(),
+        MediaType {
+            schema: Some(SchemaRef::Inline(serde_json::json!({
+                "type": "string",
+                "description": description,
+            }))),
+            example: Some(serde_json::json!(example)),
+        },
+    );
+    content
+}
+
+// ---------------------------------------------------------------------------
+// Writing
+// ---------------------------------------------------------------------------
+
+/// Streams every item of `S` as one line of JSON.
+pub struct NdJson<S>(pub S);
+
+impl<S, T> IntoResponse for NdJson<S>
+where
+    S: Stream<Item = T> + Send + 'static,
+    T: Serialize,
+{
+    fn into_response(self) -> Response {
+        let lines = self.0.filter_map(|item| {
+            let line = match serde_json::to_vec(&item) {
+                Ok(mut line) => {
+                    line.push(b'\n');
+                    Some(Ok::<_, ApiError>(Bytes::from(line)))
+                }
+                Err(err) => {
+                    error!("Left an item out of an NDJSON stream: {}", err);
+                    None
+                }
+            };
+            std::future::ready(line)
+        });
+
+        let mut response = Response::new(ResponseBody::from_stream(lines));
+        let headers = response.headers_mut();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static(CONTENT_TYPE_NDJSON));
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        // Same as Sse: ask nginx to pass lines on as they come.
+        headers.insert("x-accel-buffering", HeaderValue::from_static("no"));
+        response
+    }
+}
+
+impl<S> ResponseModifier for NdJson<S> {
+    fn update_response(op: &mut Operation) {
+        op.responses.insert(
+            "200".to_string(),
+            ResponseSpec {
+                description: "Newline-delimited JSON, sent a line at a time".to_string(),
+                content: ndjson_media_type(
+                    "One JSON object per line",
+                    "{\"id\":1,\"message\":\"hello\"}\n{\"id\":2,\"message\":\"world\"}\n",
+                ),
+                headers: BTreeMap::new(),
+            },
+        );
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Reading
+// ---------------------------------------------------------------------------
+
+type Chunks = Pin<Box<dyn Stream<Item = std::result::Result<Bytes, String>> + Send>>;
+
+/// An NDJSON request body, read one `T` at a time.
+pub struct NdJsonLines<T> {
+    chunks: Chunks,
+    buffer: BytesMut,
+    /// The body has ended; whatever is in `buffer` is the last line.
+    done: bool,
+    line: usize,
+    skip_invalid: bool,
+    skipped: usize,
+    _item: PhantomData<fn() -> T>,
+}
+
+fn bad_line(line: usize, err: impl std::fmt::Display) -> ApiError {
+    ApiError::bad_request(format!("Line {}: {}", line, err))
+}
+
+impl<T: DeserializeOwned> NdJsonLines<T> {
+    /// Whether a line that doesn't parse is skipped (true, the default) or
+    /// ends the read with a 400.
+    pub fn skip_invalid(mut self, skip: bool) -> Self {
+        self.skip_invalid = skip;
+        self
+    }
+
+    /// Lines skipped so far.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// The next item, or None at the end of the body.
+    pub async fn next(&mut self) -> Result<Option<T>> {
+        while let Some(line) = self.next_line().await? {
+            self.line += 1;
+            let line = line.strip_suffix(b"\r").unwrap_or(&line);
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            match serde_json::from_slice(line) {
+                Ok(item) => return Ok(Some(item)),
+                Err(err) if self.skip_invalid => {
+                    warn!("Skipped NDJSON line {}: {}", self.line, err);
+                    self.skipped += 1;
+                }
+                Err(err) => return Err(bad_line(self.line, err)),
+            }
+        }
+        Ok(None)
+    }
+
+    /// The next raw line, without its `\n`.
+    async fn next_line(&mut self) -> Result<Option<Bytes>> {
+        loop {
+            let end = self.buffer.iter().position(|&byte| byte == b'\n');
+            if end.unwrap_or(self.buffer.len()) > MAX_LINE {
+                return Err(ApiError::new(
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    "payload_too_large",
+                    format!("Line {} exceeds limit of {} bytes", self.line + 1, MAX_LINE),
+                ));
+            }
+            if let Some(end) = end {
+                let line = self.buffer.split_to(end + 1).freeze();
+                return Ok(Some(line.slice(..end)));
+            }
+            if self.done {
+                return Ok((!self.buffer.is_empty()).then(|| self.buffer.split().freeze()));
+            }
+            match self.chunks.next().await {
+                Some(Ok(chunk)) => self.buffer.extend_from_slice(&chunk),
+                Some(Err(err)) => {
+                    return Err(ApiError::bad_request(format!(
+                        "Failed to read body after line {}: {}",
+                        self.line, err
+                    )))
+                }
+                None => self.done = true,
+            }
+        }
+    }
+}
+
+fn is_ndjson(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    [
+        "application/x-ndjson",
+        "application/jsonl",
+        "application/json-lines",
+    ]
+    .iter()
+    .any(|accepted| essence.eq_ignore_ascii_case(accepted))
+}
+
+impl<T: DeserializeOwned + Send> FromRequest for NdJsonLines<T> {
+    async fn from_request(req: &mut Request) -> Result<Self> {
+        let content_type = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        if !is_ndjson(content_type) {
+            return Err(ApiError::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "unsupported_media_type",
+                format!("Expected {}", CONTENT_TYPE_NDJSON),
+            ));
+        }
+
+        // Straight off the connection when we can; in-process dispatch (and
+        // any layer that read the body) leaves it buffered instead.
+        let chunks: Chunks = if let Some(stream) = req.take_stream() {
+            Box::pin(
+                stream
+                    .into_data_stream()
+                    .map(|chunk| chunk.map_err(|err| err.to_string())),
+            )
+        } else {
+            let body = req
+                .take_body()
+                .ok_or_else(|| ApiError::internal("Body already consumed"))?;
+            Box::pin(futures_util::stream::once(async move { Ok(body) }))
+        };
+
+        Ok(Self {
+            chunks,
+            buffer: BytesMut::new(),
+            done: false,
+            line: 0,
+            skip_invalid: true,
+            skipped: 0,
+            _item: PhantomData,
+        })
+    }
+}
+
+impl<T> OperationModifier for NdJsonLines<T> {
+    fn update_operation(op: &mut Operation) {
+        op.request_body = Some(RequestBody {
+            description: Some("One JSON object per line.".to_string()),
+            required: Some(true),
+            content: ndjson_media_type(
+                "One JSON object per line",
+                "{\"message\":\"hello\"}\n{\"message\":\"world\"}\n",
+            ),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Item {
+        n: u32,
+    }
+
+    /// A reader over a body that arrives in `chunks`.
+    fn lines(chunks: &[&str]) -> NdJsonLines<Item> {
+        let chunks: Vec<_> = chunks
+            .iter()
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk.as_bytes())))
+            .collect();
+        NdJsonLines {
+            chunks: Box::pin(futures_util::stream::iter(chunks)),
+            buffer: BytesMut::new(),
+            done: false,
+            line: 0,
+            skip_invalid: true,
+            skipped: 0,
+            _item: PhantomData,
+        }
+    }
+
+    async fn all(mut lines: NdJsonLines<Item>) -> (Vec<u32>, usize) {
+        let mut items = Vec::new();
+        while let Some(item) = lines.next().await.unwrap() {
+            items.push(item.n);
+        }
+        (items, lines.skipped())
+    }
+
+    const MIXED: &str = "{\"n\":1}\nnot json\n{\"n\":\"two\"}\n{\"n\":4}\n";
+
+    #[tokio::test]
+    async fn skip_policy_reads_past_bad_lines() {
+        assert_eq!(all(lines(&[MIXED])).await, (vec![1, 4], 2));
+    }
+
+    #[tokio::test]
+    async fn abort_policy_stops_at_the_first_bad_line() {
+        let mut lines = lines(&[MIXED]).skip_invalid(false);
+        assert_eq!(lines.next().await.unwrap(), Some(Item { n: 1 }));
+
+        let err = lines.next().await.unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        assert!(err.message.starts_with("Line 2: "), "{}", err.message);
+    }
+
+    #[tokio::test]
+    async fn a_last_line_without_a_newline_is_read() {
+        assert_eq!(all(lines(&["{\"n\":1}\n{\"n\":2}"])).await, (vec![1, 2], 0));
+        // Blank lines and \r\n endings are fine too.
+        assert_eq!(
+            all(lines(&["\n{\"n\":1}\r\n  \r\n{\"n\":2}\r\n"])).await,
+            (vec![1, 2], 0)
+        );
+    }
+
+    #[tokio::test]
+    async fn lines_may_span_chunks() {
+        let chunks = ["{\"n\"", ":1}\n{\"n\":2", "}\n{", "\"n\":3}"];
+        assert_eq!(all(lines(&chunks)).await, (vec![1, 2, 3], 0));
+    }
+
+    #[tokio::test]
+    async fn a_line_over_the_limit_is_a_413() {
+        // Refused once MAX_LINE bytes are in without a newline, not at the end.
+        let long = format!("{{\"n\":1}}\n{}", " ".repeat(MAX_LINE + 1));
+        let mut lines = lines(&[&long]);
+        assert_eq!(lines.next().await.unwrap(), Some(Item { n: 1 }));
+
+        let err = lines.next().await.unwrap_err();
+        assert_eq!(err.status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(err.message.starts_with("Line 2 "), "{}", err.message);
+    }
+
+    #[tokio::test]
+    async fn a_line_of_exactly_the_limit_is_read() {
+        let padded = format!("{{\"n\":7}}{}\n", " ".repeat(MAX_LINE - 7));
+        assert_eq!(padded.len(), MAX_LINE + 1);
+        assert_eq!(all(lines(&[&padded])).await, (vec![7], 0));
+    }
+
+    #[tokio::test]
+    async fn a_body_that_fails_is_a_400() {
+        let chunks = vec![
+            Ok(Bytes::from_static(b"{\"n\":1}\n")),
+            Err("connection reset".to_string()),
+        ];
+        let mut lines = lines(&[]);
+        lines.chunks = Box::pin(futures_util::stream::iter(chunks));
+
+        assert_eq!(lines.next().await.unwrap(), Some(Item { n: 1 }));
+        let err = lines.next().await.unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        assert!(err.message.contains("connection reset"), "{}", err.message);
+    }
+}
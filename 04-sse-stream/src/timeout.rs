@@ -0,0 +1,180 @@
+// ---------------------------------------------------------------------------
+// Request timeout that leaves streams alone
+// ---------------------------------------------------------------------------
+//
+// A deadline on "the whole response" would cut every SSE stream and
+// WebSocket off after N seconds. This layer bounds only the part that
+// should be bounded: the time until the handler has produced the response
+// head (status + headers).
+//
+// When the timer stops:
+//   - unary responses (fully buffered body): when the handler returns. The
+//     body is already complete, so this is the whole request/response cycle.
+//   - streaming responses (SSE, chunked streams): when the handler returns
+//     the stream — i.e. time to first byte. The body may then run forever.
+//   - WebSocket upgrades (101): when the handshake response is returned. The
+//     socket itself is never timed.
+//
+// A handler that never returns at all gets a 503, streaming or not. Not a
+// 408: that tells the client it was too slow sending its request, and some
+// clients resend the request at once. The request arrived; the server was
+// the slow one.
+
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_rs::prelude::*;
+use rustapi_rs::ResponseBody;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+#[derive(Clone)]
+pub struct RequestTimeoutLayer {
+    timeout: Duration,
+}
+
+impl RequestTimeoutLayer {
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout }
+    }
+}
+
+/// Whether the response keeps going after its head was sent.
+fn is_streaming(response: &Response) -> bool {
+    response.status() == StatusCode::SWITCHING_PROTOCOLS
+        || matches!(response.body(), ResponseBody::Streaming(_))
+}
+
+impl MiddlewareLayer for RequestTimeoutLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let timeout = self.timeout;
+
+        Box::pin(async move {
+            let path = req.path().to_owned();
+
+            match tokio::time::timeout(timeout, next(req)).await {
+                Ok(response) => {
+                    if is_streaming(&response) {
+                        debug!("{} is streaming; timeout no longer applies", path);
+                    }
+                    response
+                }
+                Err(_) => {
+                    warn!("{} produced no response within {:?}", path, timeout);
+                    ApiError::new(
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        "timeout",
+                        format!("Request exceeded timeout of {}ms", timeout.as_millis()),
+                    )
+                    .into_response()
+                }
+            }
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use futures_util::Stream;
+    use http_body_util::BodyExt;
+    use rustapi_core::{BodyVariant, PathParams};
+    use std::convert::Infallible;
+
+    async fn slow() -> &'static str {
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        "too late"
+    }
+
+    async fn fast() -> &'static str {
+        "on time"
+    }
+
+    /// Five events, one a second: the last comes 4s in.
+    async fn ticks() -> Sse<impl Stream<Item = std::result::Result<SseEvent, Infallible>>> {
+        let events = futures_util::stream::unfold(1, |i| async move {
+            if i > 5 {
+                return None;
+            }
+            if i > 1 {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Some((Ok(SseEvent::new(format!("tick {i}"))), i + 1))
+        });
+        Sse::new(events)
+    }
+
+    /// A plain chunked body, in the same rhythm.
+    async fn chunks() -> Response {
+        let chunks = futures_util::stream::unfold(1, |i| async move {
+            if i > 5 {
+                return None;
+            }
+            if i > 1 {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Some((
+                Ok::<_, ApiError>(Bytes::from(format!("chunk {i}\n"))),
+                i + 1,
+            ))
+        });
+        Response::new(ResponseBody::from_stream(chunks))
+    }
+
+    async fn get_response(path: &str) -> Response {
+        let app = RustApi::new()
+            .route("/slow", get(slow))
+            .route("/fast", get(fast))
+            .route("/ticks", get(ticks))
+            .route("/chunks", get(chunks))
+            .layer(RequestTimeoutLayer::new(Duration::from_secs(2)))
+            .request_dispatcher();
+        let (parts, ()) = http::Request::get(path).body(()).unwrap().into_parts();
+        let request = Request::new(
+            parts,
+            BodyVariant::Buffered(Default::default()),
+            app.state_ref(),
+            PathParams::new(),
+        );
+        app.dispatch(request).await
+    }
+
+    async fn get_status(path: &str) -> StatusCode {
+        get_response(path).await.status()
+    }
+
+    // The clock is paused and jumps ahead whenever every task is asleep.
+    #[tokio::test(start_paused = true)]
+    async fn slow_handler_gets_503() {
+        assert_eq!(get_status("/slow").await, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fast_handler_is_untouched() {
+        assert_eq!(get_status("/fast").await, StatusCode::OK);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn streams_run_past_the_deadline() {
+        for (path, last) in [("/ticks", "data: tick 5"), ("/chunks", "chunk 5")] {
+            let started = tokio::time::Instant::now();
+            let response = get_response(path).await;
+            assert_eq!(response.status(), StatusCode::OK, "{path}");
+
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            let body = String::from_utf8_lossy(&body);
+            // Twice the 2s timeout, and every part arrived.
+            assert!(started.elapsed() >= Duration::from_secs(4), "{path}");
+            assert!(body.contains(last), "{path}: {body}");
+            assert!(!body.contains("timeout"), "{path}: {body}");
+        }
+    }
+}
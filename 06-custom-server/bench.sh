@@ -0,0 +1,28 @@
+#!/usr/bin/env bash
+# Tail-latency comparison of the two runtime modes on GET /hello/{name}.
+#
+# Usage: ./06-custom-server/bench.sh [connections] [duration]
+# Needs: oha (cargo install oha)
+
+set -euo pipefail
+
+CONNECTIONS="${1:-256}"
+DURATION="${2:-30s}"
+URL="http://127.0.0.1:3000/hello/bench"
+
+cargo build -p custom-server --release
+
+for mode in work-stealing thread-per-core; do
+    RUNTIME_MODE="$mode" ./target/release/custom-server >/dev/null 2>&1 &
+    pid=$!
+    sleep 1
+
+    # Warm up, then measure.
+    oha -z 5s -c "$CONNECTIONS" --no-tui "$URL" >/dev/null
+    echo "== $mode (c=$CONNECTIONS, $DURATION) =="
+    oha -z "$DURATION" -c "$CONNECTIONS" --no-tui "$URL" \
+        | grep -E 'Requests/sec|50.00%|99.00%|99.90%|99.99%'
+
+    kill "$pid"
+    wait "$pid" 2>/dev/null || true
+done
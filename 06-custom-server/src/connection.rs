@@ -0,0 +1,121 @@
+// ---------------------------------------------------------------------------
+// Connection reuse
+// ---------------------------------------------------------------------------
+//
+// HTTP/1.1 keeps connections open by default. Three ways to end one after the
+// current response:
+//
+//   KEEP_ALIVE=off           — globally (ConnectionOptions): hyper answers
+//                              every request with `Connection: close` and
+//                              hangs up
+//   Close(response)          — one handler, one response
+//   CloseUnderLoadLayer      — every response while too many requests are
+//                              in flight, so clients reconnect and the
+//                              kernel spreads them out again
+//
+// hyper sees `Connection: close` on a response, sends it, then closes the
+// socket once the body is written.
+//
+// NOTE: HTTP/2 has no `Connection` header — it is a protocol error there
+//       (RFC 9113 §8.2.2) and hyper's HTTP/2 server strips it. Ending an
+//       HTTP/2 connection means a GOAWAY frame, i.e. the connection's
+//       graceful_shutdown(). This server only speaks HTTP/1, but the layer
+//       still leaves HTTP/2 responses alone rather than rely on that.
+
+use http::header::CONNECTION;
+use http::{HeaderValue, Version};
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_openapi::{OpenApiSpec, Operation, ResponseModifier};
+use rustapi_rs::prelude::*;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+fn mark_close(response: &mut Response) {
+    response
+        .headers_mut()
+        .insert(CONNECTION, HeaderValue::from_static("close"));
+}
+
+/// Sends `T` and closes the connection afterwards.
+///
+/// ```ignore
+/// async fn bye() -> Close<&'static str> { Close("Goodbye!") }
+/// ```
+pub struct Close<T>(pub T);
+
+impl<T: IntoResponse> IntoResponse for Close<T> {
+    fn into_response(self) -> Response {
+        let mut response = self.0.into_response();
+        mark_close(&mut response);
+        response
+    }
+}
+
+// A transport detail: the documented response is just T's.
+impl<T: ResponseModifier> ResponseModifier for Close<T> {
+    fn update_response(op: &mut Operation) {
+        T::update_response(op);
+    }
+
+    fn register_components(spec: &mut OpenApiSpec) {
+        T::register_components(spec);
+    }
+}
+
+/// Closes HTTP/1 connections after serving while more than `max_in_flight`
+/// requests are running. Nothing is rejected — the current request is
+/// answered as usual, the client just has to reconnect for the next one.
+#[derive(Clone)]
+pub struct CloseUnderLoadLayer {
+    max_in_flight: usize,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl CloseUnderLoadLayer {
+    pub fn new(max_in_flight: usize) -> Self {
+        Self {
+            max_in_flight,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+/// Decrements the in-flight count however the request ends.
+struct InFlight(Arc<AtomicUsize>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl MiddlewareLayer for CloseUnderLoadLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let max_in_flight = self.max_in_flight;
+        let in_flight = self.in_flight.clone();
+
+        Box::pin(async move {
+            let http1 = req.version() <= Version::HTTP_11;
+            in_flight.fetch_add(1, Ordering::Relaxed);
+            let _guard = InFlight(in_flight.clone());
+
+            let mut response = next(req).await;
+
+            // Measured on the way out: the load while this request was served.
+            if http1 && in_flight.load(Ordering::Relaxed) > max_in_flight {
+                mark_close(&mut response);
+            }
+            response
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
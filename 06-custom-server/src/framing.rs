@@ -0,0 +1,237 @@
+// ---------------------------------------------------------------------------
+// Request framing guard
+// ---------------------------------------------------------------------------
+//
+// When a proxy and the origin disagree on where a request body ends, the
+// leftover bytes become the *next* request (request smuggling). Ambiguous
+// framing is therefore refused outright instead of guessed at.
+//
+// hyper already answers 400 for:
+//   - Content-Length headers with different values (`3` and `4`, or `3, 4`)
+//   - a Transfer-Encoding whose last coding isn't `chunked`
+//
+// Whether both framings reach this layer depends on their order on the wire:
+//
+//   Content-Length, then Transfer-Encoding   both kept; rejected here
+//   Transfer-Encoding, then Content-Length   hyper drops the Content-Length
+//                                            and reads the body as chunked,
+//                                            as RFC 9112 §6.3 says; the layer
+//                                            sees chunked alone and lets it by
+//
+// The second is still safe behind a proxy that also lets Transfer-Encoding
+// win; one that reads Content-Length instead must refuse the request itself.
+//
+// Also rejected with 400, as hyper lets them through:
+//   - Transfer-Encoding sent more than once, or listing `chunked` twice
+//
+// NOTE: hyper collapses identical duplicate Content-Length headers into one
+//       before we see them. The duplicate check below still guards requests
+//       built by other means (tests, in-process dispatch).
+
+use http::header::{CONNECTION, CONTENT_LENGTH, TRANSFER_ENCODING};
+use http::{HeaderMap, HeaderValue};
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_rs::prelude::*;
+use std::future::Future;
+use std::pin::Pin;
+
+#[derive(Clone, Default)]
+pub struct FramingGuardLayer;
+
+impl FramingGuardLayer {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// Why a request's framing is ambiguous, if it is.
+fn ambiguity(headers: &HeaderMap) -> Option<&'static str> {
+    let content_lengths = headers.get_all(CONTENT_LENGTH).iter().count();
+    let transfer_encodings: Vec<_> = headers.get_all(TRANSFER_ENCODING).iter().collect();
+
+    if content_lengths > 1 {
+        return Some("Multiple Content-Length headers");
+    }
+    if content_lengths == 1 && !transfer_encodings.is_empty() {
+        return Some("Both Content-Length and Transfer-Encoding present");
+    }
+    if transfer_encodings.len() > 1 {
+        return Some("Multiple Transfer-Encoding headers");
+    }
+
+    let chunked = transfer_encodings
+        .first()
+        .and_then(|value| value.to_str().ok())
+        .map(|value| {
+            value
+                .split(',')
+                .filter(|coding| coding.trim().eq_ignore_ascii_case("chunked"))
+                .count()
+        })
+        .unwrap_or(0);
+    if chunked > 1 {
+        return Some("Transfer-Encoding lists chunked more than once");
+    }
+
+    None
+}
+
+impl MiddlewareLayer for FramingGuardLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        Box::pin(async move {
+            if let Some(reason) = ambiguity(req.headers()) {
+                warn!("Rejected ambiguous request framing: {}", reason);
+                let mut response = ApiError::bad_request(reason).into_response();
+                // We can't know where this body ends, so the connection can't be reused.
+                response
+                    .headers_mut()
+                    .insert(CONNECTION, HeaderValue::from_static("close"));
+                return response;
+            }
+
+            next(req).await
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustapi_core::{BodyVariant, PathParams};
+    use std::sync::Arc;
+
+    fn header_map(pairs: &[(http::HeaderName, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    /// Runs a request with `headers` through the layer, in front of a
+    /// handler that answers 200.
+    async fn call(headers: &[(http::HeaderName, &'static str)]) -> Response {
+        let mut builder = http::Request::builder().method("POST").uri("/echo");
+        for (name, value) in headers {
+            builder = builder.header(name, *value);
+        }
+        let (parts, _) = builder.body(()).unwrap().into_parts();
+        let req = Request::new(
+            parts,
+            BodyVariant::Buffered(Default::default()),
+            Arc::new(http::Extensions::new()),
+            PathParams::new(),
+        );
+        let next: BoxedNext = Arc::new(|_req| Box::pin(async { "ok".into_response() }));
+        FramingGuardLayer::new().call(req, next).await
+    }
+
+    #[test]
+    fn single_framing_passes() {
+        assert_eq!(ambiguity(&HeaderMap::new()), None);
+        assert_eq!(ambiguity(&header_map(&[(CONTENT_LENGTH, "3")])), None);
+        assert_eq!(
+            ambiguity(&header_map(&[(TRANSFER_ENCODING, "gzip, chunked")])),
+            None
+        );
+    }
+
+    #[test]
+    fn duplicate_content_length_is_ambiguous() {
+        // Identical values too: hyper merges those, in-process dispatch doesn't.
+        let same = header_map(&[(CONTENT_LENGTH, "3"), (CONTENT_LENGTH, "3")]);
+        assert_eq!(ambiguity(&same), Some("Multiple Content-Length headers"));
+        let conflicting = header_map(&[(CONTENT_LENGTH, "3"), (CONTENT_LENGTH, "4")]);
+        assert_eq!(
+            ambiguity(&conflicting),
+            Some("Multiple Content-Length headers")
+        );
+    }
+
+    #[test]
+    fn content_length_with_transfer_encoding_is_ambiguous() {
+        let headers = header_map(&[(CONTENT_LENGTH, "3"), (TRANSFER_ENCODING, "chunked")]);
+        assert_eq!(
+            ambiguity(&headers),
+            Some("Both Content-Length and Transfer-Encoding present")
+        );
+    }
+
+    #[test]
+    fn repeated_transfer_encoding_is_ambiguous() {
+        let twice = header_map(&[
+            (TRANSFER_ENCODING, "chunked"),
+            (TRANSFER_ENCODING, "chunked"),
+        ]);
+        assert_eq!(
+            ambiguity(&twice),
+            Some("Multiple Transfer-Encoding headers")
+        );
+        let listed_twice = header_map(&[(TRANSFER_ENCODING, "chunked, Chunked")]);
+        assert_eq!(
+            ambiguity(&listed_twice),
+            Some("Transfer-Encoding lists chunked more than once")
+        );
+    }
+
+    #[tokio::test]
+    async fn ambiguous_requests_get_400_and_close() {
+        let response = call(&[(CONTENT_LENGTH, "3"), (CONTENT_LENGTH, "4")]).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(response.headers()[CONNECTION], "close");
+
+        let response = call(&[(CONTENT_LENGTH, "3")]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(CONNECTION).is_none());
+    }
+
+    /// Sends `head` and a chunked `abc` body to a hyper connection serving
+    /// the layer in front of an echo handler, and returns the raw response.
+    async fn over_the_wire(head: &str) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        async fn echo(Body(body): Body) -> String {
+            String::from_utf8_lossy(&body).into_owned()
+        }
+        let dispatcher = RustApi::new()
+            .layer(FramingGuardLayer::new())
+            .route("/echo", post(echo))
+            .request_dispatcher();
+        let (mut client, server) = tokio::io::duplex(4096);
+        tokio::spawn(crate::server::serve_connection(
+            server,
+            dispatcher,
+            crate::server::ConnectionOptions::default(),
+        ));
+
+        let request = format!("POST /echo HTTP/1.1\r\nHost: x\r\n{head}Connection: close\r\n\r\n3\r\nabc\r\n0\r\n\r\n");
+        client.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn content_length_before_transfer_encoding_is_refused() {
+        let response = over_the_wire("Content-Length: 3\r\nTransfer-Encoding: chunked\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 400"), "{response}");
+        assert!(response.contains("Both Content-Length and Transfer-Encoding"));
+        assert!(response.contains("connection: close"), "{response}");
+    }
+
+    #[tokio::test]
+    async fn content_length_after_transfer_encoding_is_dropped_by_hyper() {
+        // The body is read as chunked: `abc`, not the 3 bytes `3\r\na`.
+        let response = over_the_wire("Transfer-Encoding: chunked\r\nContent-Length: 3\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(response.ends_with("abc"), "{response}");
+    }
+}
@@ -0,0 +1,148 @@
+// Run with: cargo run -p custom-server --release
+//           RUNTIME_MODE=thread-per-core cargo run -p custom-server --release
+//           RUNTIME_MODE=embedded cargo run -p custom-server --release
+//           KEEP_ALIVE=off cargo run -p custom-server --release
+//           TCP_NODELAY=off cargo run -p custom-server --release
+//           TLS_CERT=cert.pem TLS_KEY=key.pem cargo run -p custom-server --release
+// Then visit: http://127.0.0.1:3000/docs
+//
+// Lesson: RustApi::run() is the easy path, but the app itself is just a
+//         RequestDispatcher. Drive it from your own accept loop and you choose
+//         the runtime, the sockets and how connections map onto cores.
+
+mod connection;
+mod framing;
+mod runtime;
+mod server;
+mod tls;
+
+use connection::{Close, CloseUnderLoadLayer};
+use framing::FramingGuardLayer;
+use runtime::RuntimeMode;
+use rustapi_rs::prelude::*;
+use rustapi_rs::{get, summary, tag};
+use server::ConnectionOptions;
+use std::net::SocketAddr;
+use tls::{RunTlsExt, TlsConfig};
+
+// ---------------------------------------------------------------------------
+// Handlers
+// ---------------------------------------------------------------------------
+
+#[derive(Serialize, Schema)]
+struct Greeting {
+    message: String,
+    framework: &'static str,
+}
+
+#[get("/")]
+#[summary("Root")]
+async fn root() -> &'static str {
+    "Welcome to RustAPI!"
+}
+
+#[get("/hello/{name}")]
+#[tag("hello")]
+#[summary("Say hello")]
+async fn hello(Path(name): Path<String>) -> Json<Greeting> {
+    Json(Greeting {
+        message: format!("Hello, {}!", name),
+        framework: "RustAPI",
+    })
+}
+
+#[get("/bye")]
+#[summary("Respond, then close the connection")]
+async fn bye() -> Close<&'static str> {
+    Close("Goodbye!")
+}
+
+// ---------------------------------------------------------------------------
+// Entry point
+// ---------------------------------------------------------------------------
+
+/// Above this many concurrent requests, HTTP/1 connections are closed after
+/// their response so clients reconnect instead of queueing on a busy socket.
+const CLOSE_ABOVE_IN_FLIGHT: usize = 512;
+
+/// HTTPS settings from the environment, or None to serve plain HTTP:
+///
+///   TLS_CERT, TLS_KEY    PEM files for the default certificate
+///   TLS_SNI              more certificates, `host=cert.pem:key.pem,...`
+///   HTTP_REDIRECT_ADDR   also listen here and redirect plain HTTP to HTTPS
+fn tls_from_env() -> Result<Option<TlsConfig>, Box<dyn std::error::Error + Send + Sync>> {
+    let (Ok(cert), Ok(key)) = (std::env::var("TLS_CERT"), std::env::var("TLS_KEY")) else {
+        return Ok(None);
+    };
+    let mut tls = TlsConfig::from_pem_files(cert, key)?;
+
+    for entry in std::env::var("TLS_SNI").unwrap_or_default().split(',') {
+        if entry.trim().is_empty() {
+            continue;
+        }
+        let (host, files) = entry
+            .trim()
+            .split_once('=')
+            .ok_or_else(|| format!("TLS_SNI entry '{entry}' is not host=cert.pem:key.pem"))?;
+        let (cert, key) = files
+            .split_once(':')
+            .ok_or_else(|| format!("TLS_SNI entry '{entry}' is not host=cert.pem:key.pem"))?;
+        tls = tls.with_sni_pem_files(host, cert, key)?;
+    }
+
+    if let Ok(addr) = std::env::var("HTTP_REDIRECT_ADDR") {
+        tls = tls.redirect_http_from(addr);
+    }
+    Ok(Some(tls))
+}
+
+// NOTE: no #[tokio::main] here — which runtime to build is the whole point.
+fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mode = RuntimeMode::from_env();
+    let options = ConnectionOptions::from_env();
+    let addr: SocketAddr = "127.0.0.1:3000".parse()?;
+    let tls = match tls_from_env() {
+        Ok(tls) => tls,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            std::process::exit(1);
+        }
+    };
+    let scheme = if tls.is_some() { "https" } else { "http" };
+
+    // run_tls() is a drop-in for run(): one work-stealing runtime, default
+    // connection options. RUNTIME_MODE, KEEP_ALIVE and TCP_NODELAY don't apply.
+    if tls.is_some() {
+        println!("Starting custom-server example (HTTPS, work-stealing runtime)…");
+    } else {
+        println!(
+            "Starting custom-server example ({} runtime, {})…",
+            mode.as_str(),
+            options
+        );
+    }
+    println!(" -> GET  {}://{}/", scheme, addr);
+    println!(" -> GET  {}://{}/hello/{{name}}", scheme, addr);
+    println!(" -> GET  {}://{}/bye  (Connection: close)", scheme, addr);
+    println!(" -> GET  {}://{}/docs", scheme, addr);
+
+    // Layers run in the order they're added: framing is checked before anything
+    // trusts Content-Length. run() would add the body limit for us; here we add it.
+    let app = RustApi::auto()
+        .layer(FramingGuardLayer::new())
+        .layer(CloseUnderLoadLayer::new(CLOSE_ABOVE_IN_FLIGHT))
+        .layer(BodyLimitLayer::new(rustapi_core::DEFAULT_BODY_LIMIT));
+
+    if let Some(tls) = tls {
+        let runtime = tokio::runtime::Runtime::new()?;
+        return runtime.block_on(app.run_tls(&addr.to_string(), tls));
+    }
+
+    // Print the ServeError itself rather than its Debug form: the Display text
+    // says "address already in use" when the port is taken.
+    if let Err(err) = runtime::run(mode, addr, app.request_dispatcher(), options) {
+        eprintln!("Error: {err}");
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,217 @@
+// ---------------------------------------------------------------------------
+// Runtime selection
+// ---------------------------------------------------------------------------
+//
+// Three ways to run the same RustAPI app:
+//
+//   work-stealing   — one Tokio multi-thread runtime (what #[tokio::main] gives
+//                     you). A single listener; idle workers steal tasks from
+//                     busy ones, so one slow request can't starve a core.
+//
+//   thread-per-core — one pinned OS thread per core, each with its own
+//                     current-thread runtime and its own SO_REUSEPORT
+//                     listener. The kernel spreads connections across the
+//                     listeners and a connection never leaves its core:
+//                     no cross-thread wakeups, warm caches, tighter tails —
+//                     as long as the load is evenly spread.
+//
+//   embedded        — on a runtime you built and own, through its Handle:
+//                     spawn_on() binds, puts the accept loop and every
+//                     connection on that runtime and returns at once. The
+//                     runtime keeps running your other tasks alongside.
+//
+//     let runtime = tokio::runtime::Builder::new_multi_thread()
+//         .worker_threads(2)
+//         .enable_all()
+//         .build()?;
+//     let (addr, server) = runtime::spawn_on(runtime.handle(), addr, dispatcher, options)?;
+//     runtime.block_on(your_main_loop());   // server.abort() to stop serving
+//
+// See README.md for the tradeoffs and a benchmark recipe.
+
+use crate::server::{self, ConnectionOptions, ServeError};
+use rustapi_rs::prelude::*;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimeMode {
+    WorkStealing,
+    ThreadPerCore,
+    Embedded,
+}
+
+impl RuntimeMode {
+    /// Reads RUNTIME_MODE (`work-stealing` | `thread-per-core` | `embedded`).
+    /// Anything else — including unset — falls back to work-stealing.
+    pub fn from_env() -> Self {
+        match std::env::var("RUNTIME_MODE").as_deref() {
+            Ok("thread-per-core") => Self::ThreadPerCore,
+            Ok("embedded") => Self::Embedded,
+            _ => Self::WorkStealing,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::WorkStealing => "work-stealing",
+            Self::ThreadPerCore => "thread-per-core",
+            Self::Embedded => "embedded",
+        }
+    }
+}
+
+/// Blocks the calling thread, serving `dispatcher` on `addr` until the process exits.
+pub fn run(
+    mode: RuntimeMode,
+    addr: SocketAddr,
+    dispatcher: RequestDispatcher,
+    options: ConnectionOptions,
+) -> Result<(), ServeError> {
+    match mode {
+        RuntimeMode::WorkStealing => {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()
+                .map_err(ServeError::Runtime)?;
+
+            runtime.block_on(async move {
+                let listener = TcpListener::bind(addr)
+                    .await
+                    .map_err(|source| ServeError::Bind { addr, source })?;
+                server::serve(listener, dispatcher, options).await;
+                Ok(())
+            })
+        }
+        RuntimeMode::ThreadPerCore => {
+            let cores = std::thread::available_parallelism()
+                .map_err(ServeError::Runtime)?
+                .get();
+
+            // Bind every listener here, before any worker starts, so a taken
+            // port fails the whole server at once instead of one stray thread.
+            let listeners = (0..cores)
+                .map(|_| {
+                    reuse_port_listener(addr).map_err(|source| ServeError::Bind { addr, source })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let workers = listeners
+                .into_iter()
+                .enumerate()
+                .map(|(core, listener)| {
+                    let dispatcher = dispatcher.clone();
+                    std::thread::Builder::new()
+                        .name(format!("rustapi-core-{core}"))
+                        .spawn(move || -> Result<(), ServeError> {
+                            pin_to_core(core);
+
+                            // current_thread: tokio::spawn stays on this thread,
+                            // so every connection accepted here is served here.
+                            let runtime = tokio::runtime::Builder::new_current_thread()
+                                .enable_all()
+                                .build()
+                                .map_err(ServeError::Runtime)?;
+
+                            runtime.block_on(async move {
+                                let listener =
+                                    TcpListener::from_std(listener).map_err(ServeError::Runtime)?;
+                                server::serve(listener, dispatcher, options).await;
+                                Ok(())
+                            })
+                        })
+                        .map_err(ServeError::Runtime)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            for worker in workers {
+                worker.join().expect("worker thread panicked")?;
+            }
+            Ok(())
+        }
+        RuntimeMode::Embedded => {
+            // Stands in for the runtime a host application already has: its
+            // own thread count and names, built before the server exists.
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .thread_name("host-worker")
+                .enable_all()
+                .build()
+                .map_err(ServeError::Runtime)?;
+
+            let (_, server) = spawn_on(runtime.handle(), addr, dispatcher, options)?;
+            // The host's own work would go here; serving needs nothing else.
+            let _ = runtime.block_on(server);
+            Ok(())
+        }
+    }
+}
+
+/// Serves `dispatcher` on `addr` from tasks on `handle`'s runtime, without
+/// blocking: returns the bound address (for port `0`) and the accept loop's
+/// task, which runs until aborted or until the runtime shuts down.
+///
+/// The runtime needs IO enabled (`enable_all()` or `enable_io()`).
+/// Connections are spawned through `handle`, so this may be called from any
+/// thread, inside a runtime or not.
+pub fn spawn_on(
+    handle: &Handle,
+    addr: SocketAddr,
+    dispatcher: RequestDispatcher,
+    options: ConnectionOptions,
+) -> Result<(SocketAddr, JoinHandle<()>), ServeError> {
+    // Bound here, not in the task, so a taken port is this call's error.
+    let listener =
+        std::net::TcpListener::bind(addr).map_err(|source| ServeError::Bind { addr, source })?;
+    let bound = listener.local_addr().map_err(ServeError::Runtime)?;
+    listener
+        .set_nonblocking(true)
+        .map_err(ServeError::Runtime)?;
+    let listener = {
+        // Registers the socket with the reactor of `handle`'s runtime.
+        let _runtime = handle.enter();
+        TcpListener::from_std(listener).map_err(ServeError::Runtime)?
+    };
+
+    let spawner = handle.clone();
+    let server = handle.spawn(async move {
+        server::serve_with(listener, dispatcher, options, &spawner).await;
+    });
+    Ok((bound, server))
+}
+
+/// A listener that may share its port with the other per-core listeners.
+fn reuse_port_listener(addr: SocketAddr) -> io::Result<std::net::TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(true)?;
+    // NOTE: SO_REUSEPORT load-balancing is a Linux/BSD feature. Elsewhere the
+    //       second bind fails with "address in use" — use work-stealing there.
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    Ok(socket.into())
+}
+
+#[cfg(target_os = "linux")]
+fn pin_to_core(core: usize) {
+    // SAFETY: cpu_set_t is a plain bitmask; zeroed is the empty set, and
+    // sched_setaffinity(0, ..) only affects the calling thread.
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(core, &mut set);
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            warn!("Could not pin worker to core {}", core);
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_to_core(_core: usize) {
+    // Affinity APIs differ per OS; unpinned threads still get per-core listeners.
+}
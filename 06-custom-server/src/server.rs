@@ -0,0 +1,212 @@
+// ---------------------------------------------------------------------------
+// Accept loop
+// ---------------------------------------------------------------------------
+//
+// RustApi::run() owns its listener and runtime. When we need control over the
+// socket (SO_REUSEPORT, one listener per core, ...) we drive the very same
+// pipeline — interceptors, layers, router — through a RequestDispatcher and
+// serve connections with hyper ourselves.
+//
+// ConnectionOptions holds the per-connection switches; the defaults match
+// what RustApi::run() does (keep-alive on, TCP_NODELAY on).
+//
+// Each accepted connection becomes a task, handed to a Spawn:
+//
+//   serve(..)                 tokio::spawn, on the runtime running the loop
+//   serve_with(.., handle)    handle.spawn, on the runtime `handle` is for
+//
+// NOTE: connections are Tokio streams, driven through hyper-util's TokioIo.
+//       An executor of your own can take the tasks, as long as a Tokio
+//       reactor drives their sockets; glommio or monoio need their own
+//       accept loop and IO adapter around serve_connection's service.
+
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper_util::rt::TokioIo;
+use rustapi_core::{BodyVariant, PathParams};
+use rustapi_rs::prelude::*;
+use std::convert::Infallible;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio::runtime::Handle;
+
+/// How each accepted connection is set up.
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectionOptions {
+    keep_alive: bool,
+    tcp_nodelay: bool,
+}
+
+impl Default for ConnectionOptions {
+    fn default() -> Self {
+        Self {
+            keep_alive: true,
+            tcp_nodelay: true,
+        }
+    }
+}
+
+impl ConnectionOptions {
+    /// Reads KEEP_ALIVE and TCP_NODELAY (`on` | `off`). Anything but `off`
+    /// keeps the default, which is on for both.
+    pub fn from_env() -> Self {
+        let off = |name| matches!(std::env::var(name).as_deref(), Ok("off"));
+        Self::default()
+            .keep_alive(!off("KEEP_ALIVE"))
+            .tcp_nodelay(!off("TCP_NODELAY"))
+    }
+
+    /// With keep-alive off, every connection is closed after its first
+    /// response.
+    pub fn keep_alive(mut self, on: bool) -> Self {
+        self.keep_alive = on;
+        self
+    }
+
+    /// With TCP_NODELAY on, a response goes out as soon as hyper writes it.
+    /// Off, Nagle's algorithm may hold a small write back until the client
+    /// ACKs the previous one — up to the client's delayed-ACK timer (~40ms).
+    pub fn tcp_nodelay(mut self, on: bool) -> Self {
+        self.tcp_nodelay = on;
+        self
+    }
+}
+
+impl fmt::Display for ConnectionOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let on_off = |on| if on { "on" } else { "off" };
+        write!(
+            f,
+            "keep-alive {}, TCP_NODELAY {}",
+            on_off(self.keep_alive),
+            on_off(self.tcp_nodelay)
+        )
+    }
+}
+
+/// Why the server couldn't start.
+#[derive(Debug)]
+pub enum ServeError {
+    /// The listening socket couldn't be bound — most often because the port is
+    /// still held by another process (or the previous run of this one).
+    Bind { addr: SocketAddr, source: io::Error },
+    /// The Tokio runtime or a worker thread couldn't be created.
+    Runtime(io::Error),
+}
+
+impl fmt::Display for ServeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bind { addr, source } if source.kind() == io::ErrorKind::AddrInUse => write!(
+                f,
+                "failed to bind {addr}: address already in use \
+                 (is another server, or a previous run, still listening?): {source}"
+            ),
+            Self::Bind { addr, source } => write!(f, "failed to bind {addr}: {source}"),
+            Self::Runtime(source) => write!(f, "failed to start runtime: {source}"),
+        }
+    }
+}
+
+impl std::error::Error for ServeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Bind { source, .. } | Self::Runtime(source) => Some(source),
+        }
+    }
+}
+
+/// One connection, served to the end.
+pub type ConnectionTask = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// Runs each connection's task somewhere: implement it to put connections
+/// on an executor of your own.
+pub trait Spawn: Send + Sync + 'static {
+    fn spawn(&self, task: ConnectionTask);
+}
+
+/// The runtime the handle is for, whichever runtime the accept loop runs on.
+impl Spawn for Handle {
+    fn spawn(&self, task: ConnectionTask) {
+        Handle::spawn(self, task);
+    }
+}
+
+/// Serves connections from `listener` forever, each in a task of its own on
+/// the current runtime.
+pub async fn serve(
+    listener: TcpListener,
+    dispatcher: RequestDispatcher,
+    options: ConnectionOptions,
+) {
+    serve_with(listener, dispatcher, options, &Handle::current()).await
+}
+
+/// Serves connections from `listener` forever, each in a task handed to
+/// `spawner`.
+pub async fn serve_with(
+    listener: TcpListener,
+    dispatcher: RequestDispatcher,
+    options: ConnectionOptions,
+    spawner: &impl Spawn,
+) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                // EMFILE and friends are transient — keep accepting.
+                error!("Accept error: {}", e);
+                continue;
+            }
+        };
+
+        if let Err(e) = stream.set_nodelay(options.tcp_nodelay) {
+            warn!("Could not set TCP_NODELAY: {}", e);
+        }
+
+        spawner.spawn(Box::pin(serve_connection(
+            stream,
+            dispatcher.clone(),
+            options,
+        )));
+    }
+}
+
+/// Serves HTTP/1 requests on one connection until either side closes it.
+/// `io` is a TCP stream, or a TLS stream around one (see `src/tls.rs`).
+pub async fn serve_connection<I>(io: I, dispatcher: RequestDispatcher, options: ConnectionOptions)
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = service_fn(move |req: hyper::Request<Incoming>| {
+        let dispatcher = dispatcher.clone();
+        async move {
+            let (parts, body) = req.into_parts();
+            let request = Request::new(
+                parts,
+                BodyVariant::Streaming(body),
+                dispatcher.state_ref(),
+                PathParams::new(),
+            );
+            Ok::<_, Infallible>(dispatcher.dispatch(request).await)
+        }
+    });
+
+    if let Err(err) = http1::Builder::new()
+        .keep_alive(options.keep_alive)
+        .serve_connection(TokioIo::new(io), service)
+        .with_upgrades()
+        .await
+    {
+        // Client disconnects are not worth logging.
+        if !err.is_incomplete_message() {
+            error!("Connection error: {}", err);
+        }
+    }
+}
@@ -0,0 +1,508 @@
+// ---------------------------------------------------------------------------
+// HTTPS with rustls
+// ---------------------------------------------------------------------------
+//
+// `app.run_tls(addr, tls)` is `app.run(addr)` with a TLS handshake in front
+// of every connection. Same app, same pipeline, same return type, so
+// switching is one line:
+//
+//   let tls = TlsConfig::from_pem_files("cert.pem", "key.pem")?
+//       .with_sni_pem_files("admin.example.com", "admin.pem", "admin-key.pem")?
+//       .redirect_http_from("0.0.0.0:80");
+//   app.run_tls("0.0.0.0:443", tls).await
+//
+//   from_pem_files / from_pem    the default certificate: PEM from disk or
+//                                from memory (a secret store, include_bytes!)
+//   with_sni_pem_files / _pem    another certificate, served to clients that
+//                                ask for that host name (SNI); everyone else
+//                                gets the default one
+//   redirect_http_from(addr)     also listen on `addr` in plain HTTP and
+//                                answer every request with a 308 to https://
+//
+// ALPN advertises `http/1.1`. Clients that offer `h2` as well fall back to
+// HTTP/1.1; clients that offer only `h2` are refused during the handshake
+// rather than spoken to in a protocol they didn't ask for.
+//
+// NOTE: HTTP/2 is out of scope: this server only speaks HTTP/1 (see
+//       "HTTP/2" in the README). Serving h2 takes hyper's `http2` feature
+//       and hyper-util's `server-auto` builder; then add `b"h2"` to
+//       ALPN_PROTOCOLS, first, and let the builder pick the protocol from
+//       `connection.alpn_protocol()`.
+
+use crate::server::{self, ConnectionOptions};
+use http::header::{HOST, LOCATION};
+use http::{HeaderValue, StatusCode};
+use http_body_util::Empty;
+use hyper::body::{Bytes, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper_util::rt::TokioIo;
+use rustapi_rs::prelude::*;
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::ServerConfig;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+
+/// Protocols offered in ALPN, most preferred first.
+const ALPN_PROTOCOLS: &[&[u8]] = &[b"http/1.1"];
+
+/// Longest a client gets to finish the handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Why a `TlsConfig` couldn't be built.
+#[derive(Debug)]
+pub enum TlsError {
+    /// A PEM file couldn't be read.
+    Read { path: PathBuf, source: io::Error },
+    /// No certificate, or no private key, in the PEM given for `what`.
+    Pem {
+        what: String,
+        source: rustls::pki_types::pem::Error,
+    },
+    /// The certificate and key were read but rustls won't use them, most
+    /// often because the key doesn't belong to the certificate.
+    Rejected { what: String, source: rustls::Error },
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read { path, source } => write!(f, "failed to read {}: {source}", path.display()),
+            Self::Pem { what, source } => write!(f, "bad PEM for {what}: {source}"),
+            Self::Rejected { what, source } => {
+                write!(f, "unusable certificate or key for {what}: {source}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TlsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Read { source, .. } => Some(source),
+            Self::Pem { source, .. } => Some(source),
+            Self::Rejected { source, .. } => Some(source),
+        }
+    }
+}
+
+/// Certificates to serve, and whether to redirect plain HTTP.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    resolver: Arc<SniResolver>,
+    redirect_from: Option<String>,
+}
+
+impl TlsConfig {
+    /// Reads the default certificate chain and its private key from PEM
+    /// files. The chain file holds the server certificate first, then any
+    /// intermediates.
+    pub fn from_pem_files(cert: impl AsRef<Path>, key: impl AsRef<Path>) -> Result<Self, TlsError> {
+        Self::from_pem(&read(cert.as_ref())?, &read(key.as_ref())?)
+    }
+
+    /// Like `from_pem_files`, with the PEM already in memory.
+    pub fn from_pem(cert: &[u8], key: &[u8]) -> Result<Self, TlsError> {
+        let default = certified_key("the default certificate", cert, key)?;
+        Ok(Self {
+            resolver: Arc::new(SniResolver {
+                default,
+                by_name: HashMap::new(),
+            }),
+            redirect_from: None,
+        })
+    }
+
+    /// Serves this certificate to clients asking for `host` (exact match,
+    /// any case).
+    pub fn with_sni_pem_files(
+        self,
+        host: &str,
+        cert: impl AsRef<Path>,
+        key: impl AsRef<Path>,
+    ) -> Result<Self, TlsError> {
+        self.with_sni_pem(host, &read(cert.as_ref())?, &read(key.as_ref())?)
+    }
+
+    /// Like `with_sni_pem_files`, with the PEM already in memory.
+    pub fn with_sni_pem(mut self, host: &str, cert: &[u8], key: &[u8]) -> Result<Self, TlsError> {
+        let key = certified_key(host, cert, key)?;
+        Arc::make_mut(&mut self.resolver)
+            .by_name
+            .insert(host.to_ascii_lowercase(), key);
+        Ok(self)
+    }
+
+    /// Also listen on `addr` in plain HTTP, redirecting every request to the
+    /// same path over HTTPS.
+    pub fn redirect_http_from(mut self, addr: impl Into<String>) -> Self {
+        self.redirect_from = Some(addr.into());
+        self
+    }
+
+    fn server_config(&self) -> Arc<ServerConfig> {
+        let mut config = ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .expect("ring supports the default protocol versions")
+            .with_no_client_auth()
+            .with_cert_resolver(self.resolver.clone());
+        config.alpn_protocols = ALPN_PROTOCOLS.iter().map(|p| p.to_vec()).collect();
+        Arc::new(config)
+    }
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, TlsError> {
+    std::fs::read(path).map_err(|source| TlsError::Read {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn certified_key(what: &str, cert: &[u8], key: &[u8]) -> Result<Arc<CertifiedKey>, TlsError> {
+    let pem_error = |source| TlsError::Pem {
+        what: what.to_string(),
+        source,
+    };
+    let chain = CertificateDer::pem_slice_iter(cert)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(pem_error)?;
+    if chain.is_empty() {
+        return Err(pem_error(rustls::pki_types::pem::Error::NoItemsFound));
+    }
+    let key = PrivateKeyDer::from_pem_slice(key).map_err(pem_error)?;
+
+    // Also checks that the key matches the certificate.
+    CertifiedKey::from_der(chain, key, &provider())
+        .map(Arc::new)
+        .map_err(|source| TlsError::Rejected {
+            what: what.to_string(),
+            source,
+        })
+}
+
+/// Picks the certificate by the host name the client sent.
+#[derive(Debug, Clone)]
+struct SniResolver {
+    default: Arc<CertifiedKey>,
+    by_name: HashMap<String, Arc<CertifiedKey>>,
+}
+
+impl ResolvesServerCert for SniResolver {
+    fn resolve(&self, hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        // rustls has already lowercased the name. Clients connecting by IP
+        // send none.
+        let named = hello.server_name().and_then(|name| self.by_name.get(name));
+        Some(named.unwrap_or(&self.default).clone())
+    }
+}
+
+/// `RustApi::new().route(..).run_tls("0.0.0.0:443", tls)`
+pub trait RunTlsExt {
+    /// Serves the app over HTTPS on `addr` until the process exits, and
+    /// redirects plain HTTP if the config says so.
+    fn run_tls(
+        self,
+        addr: &str,
+        tls: TlsConfig,
+    ) -> impl Future<Output = Result<(), Box<dyn std::error::Error + Send + Sync>>> + Send;
+}
+
+impl RunTlsExt for RustApi {
+    async fn run_tls(
+        self,
+        addr: &str,
+        tls: TlsConfig,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let listener = TcpListener::bind(addr).await?;
+        let bound = listener.local_addr()?;
+        let acceptor = TlsAcceptor::from(tls.server_config());
+
+        if let Some(redirect_from) = &tls.redirect_from {
+            let plain = TcpListener::bind(redirect_from.as_str()).await?;
+            info!(
+                "Redirecting http://{} to https://{}",
+                plain.local_addr()?,
+                bound
+            );
+            tokio::spawn(redirect_to_https(plain, bound.port()));
+        }
+
+        info!("Listening on https://{}", bound);
+        let dispatcher = self.request_dispatcher();
+        let options = ConnectionOptions::default();
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    error!("Accept error: {}", e);
+                    continue;
+                }
+            };
+            let _ = stream.set_nodelay(true);
+
+            // The handshake runs in the connection's task, so a slow client
+            // can't hold up the accept loop.
+            let acceptor = acceptor.clone();
+            let dispatcher = dispatcher.clone();
+            tokio::spawn(async move {
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => server::serve_connection(stream, dispatcher, options).await,
+                    // Scanners, plain HTTP sent to the TLS port, untrusted
+                    // certificates: common, and the client's problem.
+                    Ok(Err(err)) => debug!("TLS handshake with {} failed: {}", peer, err),
+                    Err(_) => debug!("TLS handshake with {} timed out", peer),
+                }
+            });
+        }
+    }
+}
+
+/// Answers every plain HTTP request with a 308 to the same host and path on
+/// the HTTPS port.
+async fn redirect_to_https(listener: TcpListener, https_port: u16) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                error!("Accept error: {}", e);
+                continue;
+            }
+        };
+
+        tokio::spawn(async move {
+            let service = service_fn(move |req: hyper::Request<Incoming>| async move {
+                Ok::<_, Infallible>(https_redirect(&req, https_port))
+            });
+            if let Err(err) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                if !err.is_incomplete_message() {
+                    error!("Connection error: {}", err);
+                }
+            }
+        });
+    }
+}
+
+fn https_redirect<B>(req: &http::Request<B>, https_port: u16) -> http::Response<Empty<Bytes>> {
+    let host = req
+        .headers()
+        .get(HOST)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|host| host.parse::<http::uri::Authority>().ok());
+    let path = req.uri().path_and_query().map_or("/", |path| path.as_str());
+
+    // 308, not 301: a POST is repeated as a POST, body and all.
+    let mut response = http::Response::new(Empty::new());
+    let location = host.and_then(|host| {
+        let target = match https_port {
+            443 => format!("https://{}{}", host.host(), path),
+            port => format!("https://{}:{}{}", host.host(), port, path),
+        };
+        HeaderValue::try_from(target).ok()
+    });
+    match location {
+        Some(location) => {
+            *response.status_mut() = StatusCode::PERMANENT_REDIRECT;
+            response.headers_mut().insert(LOCATION, location);
+        }
+        // HTTP/1.1 requires Host; without one there is nowhere to send them.
+        None => *response.status_mut() = StatusCode::BAD_REQUEST,
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+    use rustls::pki_types::{ServerName, UnixTime};
+    use rustls::{ClientConfig, DigitallySignedStruct, SignatureScheme};
+    use tokio_rustls::TlsConnector;
+
+    // Self-signed P-256 certificates for `localhost` and `admin.localhost`:
+    //   openssl req -x509 -newkey ec -pkeyopt ec_paramgen_curve:prime256v1 \
+    //     -nodes -days 36500 -subj /CN=admin.localhost \
+    //     -addext subjectAltName=DNS:admin.localhost -keyout admin-key.pem -out admin.pem
+    const DEFAULT_CERT: &[u8] = include_bytes!("../testdata/default.pem");
+    const DEFAULT_KEY: &[u8] = include_bytes!("../testdata/default-key.pem");
+    const ADMIN_CERT: &[u8] = include_bytes!("../testdata/admin.pem");
+    const ADMIN_KEY: &[u8] = include_bytes!("../testdata/admin-key.pem");
+
+    /// Takes whatever certificate the server shows, so the test can look at it.
+    #[derive(Debug)]
+    struct AcceptAny;
+
+    impl ServerCertVerifier for AcceptAny {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &CertificateDer<'_>,
+            _intermediates: &[CertificateDer<'_>],
+            _server_name: &ServerName<'_>,
+            _ocsp_response: &[u8],
+            _now: UnixTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            let algorithms = &provider().signature_verification_algorithms;
+            rustls::crypto::verify_tls12_signature(message, cert, dss, algorithms)
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            let algorithms = &provider().signature_verification_algorithms;
+            rustls::crypto::verify_tls13_signature(message, cert, dss, algorithms)
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            provider()
+                .signature_verification_algorithms
+                .supported_schemes()
+        }
+    }
+
+    fn config() -> TlsConfig {
+        TlsConfig::from_pem(DEFAULT_CERT, DEFAULT_KEY)
+            .unwrap()
+            .with_sni_pem("Admin.Localhost", ADMIN_CERT, ADMIN_KEY)
+            .unwrap()
+    }
+
+    fn der(pem: &[u8]) -> CertificateDer<'static> {
+        CertificateDer::from_pem_slice(pem).unwrap()
+    }
+
+    /// Connects to a server using `tls` as `server_name`, offering `alpn`.
+    /// The certificate the server showed, and the protocol agreed on.
+    async fn handshake(
+        tls: &TlsConfig,
+        server_name: &str,
+        alpn: &[&[u8]],
+    ) -> io::Result<(CertificateDer<'static>, Option<Vec<u8>>)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let acceptor = TlsAcceptor::from(tls.server_config());
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            acceptor.accept(stream).await
+        });
+
+        let mut client = ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(AcceptAny))
+            .with_no_client_auth();
+        client.alpn_protocols = alpn.iter().map(|p| p.to_vec()).collect();
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let name = ServerName::try_from(server_name.to_string()).unwrap();
+        let connected = TlsConnector::from(Arc::new(client))
+            .connect(name, stream)
+            .await;
+        let _ = server.await.unwrap();
+
+        let (_, connection) = connected?.into_inner();
+        let shown = connection.peer_certificates().unwrap()[0].clone();
+        Ok((shown, connection.alpn_protocol().map(<[u8]>::to_vec)))
+    }
+
+    #[tokio::test]
+    async fn sni_picks_the_certificate_by_host_name() {
+        let tls = config();
+        let (shown, _) = handshake(&tls, "admin.localhost", &[]).await.unwrap();
+        assert_eq!(shown, der(ADMIN_CERT));
+
+        // Any other name, or an IP address (which sends no SNI at all).
+        for name in ["localhost", "other.localhost", "127.0.0.1"] {
+            let (shown, _) = handshake(&tls, name, &[]).await.unwrap();
+            assert_eq!(shown, der(DEFAULT_CERT), "{name}");
+        }
+    }
+
+    #[tokio::test]
+    async fn alpn_settles_on_http_1_1_and_refuses_h2_only() {
+        let tls = config();
+        let (_, agreed) = handshake(&tls, "localhost", &[b"h2", b"http/1.1"])
+            .await
+            .unwrap();
+        assert_eq!(agreed.as_deref(), Some(&b"http/1.1"[..]));
+
+        assert!(handshake(&tls, "localhost", &[b"h2"]).await.is_err());
+    }
+
+    #[test]
+    fn mismatched_key_is_rejected() {
+        let err = TlsConfig::from_pem(DEFAULT_CERT, ADMIN_KEY).unwrap_err();
+        assert!(matches!(err, TlsError::Rejected { .. }), "{err}");
+        let err = TlsConfig::from_pem(b"not pem", DEFAULT_KEY).unwrap_err();
+        assert!(matches!(err, TlsError::Pem { .. }), "{err}");
+    }
+
+    fn redirect(method: &str, uri: &str, host: Option<&str>, https_port: u16) -> (u16, String) {
+        let mut req = http::Request::builder().method(method).uri(uri);
+        if let Some(host) = host {
+            req = req.header(HOST, host);
+        }
+        let response = https_redirect(&req.body(()).unwrap(), https_port);
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .map(|value| value.to_str().unwrap().to_string())
+            .unwrap_or_default();
+        (response.status().as_u16(), location)
+    }
+
+    #[test]
+    fn plain_http_is_sent_to_the_same_path_over_https() {
+        assert_eq!(
+            redirect("GET", "/items?page=2", Some("example.com:3080"), 3000),
+            (308, "https://example.com:3000/items?page=2".to_string())
+        );
+        // The default port is left out, and a POST stays a POST (308).
+        assert_eq!(
+            redirect("POST", "/orders", Some("example.com"), 443),
+            (308, "https://example.com/orders".to_string())
+        );
+        assert_eq!(
+            redirect("GET", "/", Some("[::1]:3080"), 3000),
+            (308, "https://[::1]:3000/".to_string())
+        );
+    }
+
+    #[test]
+    fn a_redirect_needs_a_usable_host() {
+        assert_eq!(redirect("GET", "/", None, 3000), (400, String::new()));
+        assert_eq!(
+            redirect("GET", "/", Some("bad host"), 3000),
+            (400, String::new())
+        );
+    }
+}
@@ -0,0 +1,237 @@
+// ---------------------------------------------------------------------------
+// Accept extractor
+// ---------------------------------------------------------------------------
+//
+// Parses `Accept: text/html;q=0.9, application/json, */*;q=0.1` into media
+// ranges sorted by preference, and picks the best of what we can produce.
+// No substring checks: `application/json;q=0` means "never JSON".
+
+use rustapi_openapi::{Operation, OperationModifier, Parameter, SchemaRef};
+use rustapi_rs::prelude::*;
+use rustapi_rs::FromRequestParts;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Schema)]
+pub struct MediaRange {
+    /// Main type, lowercased. `*` for wildcards.
+    pub kind: String,
+    /// Subtype, lowercased. `*` for wildcards.
+    pub subtype: String,
+    /// Quality weight between 0.0 and 1.0 (defaults to 1.0).
+    pub q: f32,
+    /// Media type parameters other than `q`, e.g. `charset=utf-8`.
+    pub params: Vec<MediaParam>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Schema)]
+pub struct MediaParam {
+    pub name: String,
+    pub value: String,
+}
+
+impl MediaRange {
+    /// Parses one comma-separated element. Malformed input yields None.
+    fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.split(';').map(str::trim);
+        let essence = parts.next()?.to_ascii_lowercase();
+
+        // Some clients send a bare `*`; treat it as `*/*`.
+        let (kind, subtype) = match essence.as_str() {
+            "*" => ("*".to_string(), "*".to_string()),
+            _ => {
+                let (kind, subtype) = essence.split_once('/')?;
+                if kind.is_empty() || subtype.is_empty() || (kind == "*" && subtype != "*") {
+                    return None;
+                }
+                (kind.to_string(), subtype.to_string())
+            }
+        };
+
+        let mut q = 1.0;
+        let mut params = Vec::new();
+        for param in parts.filter(|p| !p.is_empty()) {
+            let (name, value) = param.split_once('=')?;
+            let name = name.trim().to_ascii_lowercase();
+            let value = value.trim().trim_matches('"');
+            if name == "q" {
+                q = value
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|q| (0.0..=1.0).contains(q))?;
+            } else {
+                params.push(MediaParam {
+                    name,
+                    value: value.to_string(),
+                });
+            }
+        }
+
+        Some(Self {
+            kind,
+            subtype,
+            q,
+            params,
+        })
+    }
+
+    /// `*/*` < `text/*` < `text/html` < `text/html;level=1`.
+    fn specificity(&self) -> u8 {
+        match (self.kind.as_str(), self.subtype.as_str()) {
+            ("*", _) => 0,
+            (_, "*") => 1,
+            _ if self.params.is_empty() => 2,
+            _ => 3,
+        }
+    }
+
+    fn matches(&self, kind: &str, subtype: &str) -> bool {
+        (self.kind == "*" || self.kind.eq_ignore_ascii_case(kind))
+            && (self.subtype == "*" || self.subtype.eq_ignore_ascii_case(subtype))
+    }
+}
+
+/// The client's Accept header, most preferred range first.
+///
+/// A missing header means "anything" (`*/*`), as the RFC says.
+#[derive(Debug, Clone)]
+pub struct Accept(pub Vec<MediaRange>);
+
+impl Accept {
+    pub fn parse(header: &str) -> Self {
+        let mut ranges: Vec<MediaRange> = header.split(',').filter_map(MediaRange::parse).collect();
+        // Stable sort: equal q and specificity keep the client's order.
+        ranges.sort_by(|a, b| {
+            b.q.total_cmp(&a.q)
+                .then_with(|| b.specificity().cmp(&a.specificity()))
+        });
+        Self(ranges)
+    }
+
+    pub fn ranges(&self) -> &[MediaRange] {
+        &self.0
+    }
+
+    /// Quality the client assigns to `mime`, taken from the most specific
+    /// matching range. 0.0 means not acceptable.
+    pub fn quality(&self, mime: &str) -> f32 {
+        let Some((kind, subtype)) = mime.split_once('/') else {
+            return 0.0;
+        };
+        let subtype = subtype.split(';').next().unwrap_or(subtype).trim();
+
+        self.0
+            .iter()
+            .filter(|range| range.matches(kind, subtype))
+            .max_by_key(|range| range.specificity())
+            .map_or(0.0, |range| range.q)
+    }
+
+    /// Picks the entry of `available` the client likes best.
+    ///
+    /// `available` is in server preference order, which breaks ties — so
+    /// `Accept: */*` gets the first one. Returns None when nothing is
+    /// acceptable (the caller should answer 406).
+    pub fn best_match<'a>(&self, available: &[&'a str]) -> Option<&'a str> {
+        let mut best: Option<(&'a str, f32)> = None;
+        for &mime in available {
+            let q = self.quality(mime);
+            if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+                best = Some((mime, q));
+            }
+        }
+        best.map(|(mime, _)| mime)
+    }
+}
+
+impl Default for Accept {
+    fn default() -> Self {
+        Self::parse("*/*")
+    }
+}
+
+impl FromRequestParts for Accept {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        // Multiple Accept headers are equivalent to one comma-joined header.
+        let joined = req
+            .headers()
+            .get_all(http::header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        if joined.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        Ok(Self::parse(&joined))
+    }
+}
+
+impl OperationModifier for Accept {
+    fn update_operation(op: &mut Operation) {
+        op.parameters.push(Parameter {
+            name: "Accept".to_string(),
+            location: "header".to_string(),
+            description: Some("Media ranges with optional q-values".to_string()),
+            required: false,
+            deprecated: None,
+            schema: Some(SchemaRef::Inline(serde_json::json!({ "type": "string" }))),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AVAILABLE: &[&str] = &["application/json", "text/html", "text/plain"];
+
+    #[test]
+    fn any_picks_the_server_preference() {
+        assert_eq!(
+            Accept::parse("*/*").best_match(AVAILABLE),
+            Some("application/json")
+        );
+        assert_eq!(
+            Accept::parse("*").best_match(AVAILABLE),
+            Some("application/json")
+        );
+        assert_eq!(
+            Accept::default().best_match(AVAILABLE),
+            Some("application/json")
+        );
+    }
+
+    #[test]
+    fn type_wildcard_stays_within_the_type() {
+        let accept = Accept::parse("text/*");
+        assert_eq!(accept.best_match(AVAILABLE), Some("text/html"));
+        assert_eq!(accept.quality("application/json"), 0.0);
+    }
+
+    #[test]
+    fn q_zero_excludes_even_under_a_wildcard() {
+        let accept = Accept::parse("*/*, application/json;q=0");
+        assert_eq!(accept.quality("application/json"), 0.0);
+        assert_eq!(accept.best_match(AVAILABLE), Some("text/html"));
+
+        let accept = Accept::parse("text/*;q=0, */*;q=0.5");
+        assert_eq!(accept.best_match(AVAILABLE), Some("application/json"));
+        assert_eq!(Accept::parse("*/*;q=0").best_match(AVAILABLE), None);
+    }
+
+    #[test]
+    fn most_specific_range_wins() {
+        let accept = Accept::parse("text/*;q=0.2, text/plain;q=0.9, */*;q=0.1");
+        assert_eq!(accept.quality("text/plain"), 0.9);
+        assert_eq!(accept.quality("text/html"), 0.2);
+        assert_eq!(accept.quality("application/json"), 0.1);
+        assert_eq!(accept.best_match(AVAILABLE), Some("text/plain"));
+    }
+
+    #[test]
+    fn malformed_ranges_are_skipped() {
+        let accept = Accept::parse("*/html, text/html;q=2, text/plain");
+        assert_eq!(accept.ranges().len(), 1);
+        assert_eq!(accept.best_match(AVAILABLE), Some("text/plain"));
+    }
+}
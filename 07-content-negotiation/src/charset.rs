@@ -0,0 +1,175 @@
+// ---------------------------------------------------------------------------
+// Accept-Charset and non-UTF-8 text
+// ---------------------------------------------------------------------------
+//
+// Some older systems can't read UTF-8 and say so:
+//
+//   Accept-Charset: iso-8859-1, utf-8;q=0.5
+//
+// `AcceptCharset` parses the header the way `Accept` parses media ranges, and
+// `Negotiate` uses it to encode its HTML and plain-text bodies. The charset
+// it picked is named in the Content-Type (`text/plain; charset=iso-8859-1`).
+//
+//   no header           UTF-8, as before
+//   a charset in        the one the client likes best (q-value, then our
+//   CHARSETS            order below)
+//   none of CHARSETS    406, listing the ones we have
+//
+// Only text is transcoded. JSON is UTF-8 by definition (RFC 8259), so a JSON
+// response ignores the header.
+//
+// Not every character exists in every charset:
+//
+//   text/html    characters the charset lacks are written as `&#NNNN;`,
+//                which any HTML parser turns back into the character
+//   text/plain   has no such escape, so the next acceptable charset is
+//                tried instead; 406 if none of them can hold the text
+//
+// NOTE: names are matched exactly (`latin1` is not `iso-8859-1`), and
+//       ISO-8859-1 really is ISO-8859-1 here. encoding_rs, like browsers,
+//       treats that label as windows-1252, which differs in 0x80–0x9F.
+
+use encoding_rs::Encoding;
+use rustapi_openapi::{Operation, OperationModifier, Parameter, SchemaRef};
+use rustapi_rs::prelude::*;
+use rustapi_rs::FromRequestParts;
+
+/// Charsets text can be sent in, in server preference order.
+pub const CHARSETS: &[&str] = &[
+    "utf-8",
+    "iso-8859-1",
+    "windows-1252",
+    "iso-8859-15",
+    "shift_jis",
+];
+
+/// The client's Accept-Charset header.
+///
+/// A missing header accepts every charset, as RFC 9110 says.
+#[derive(Debug, Clone, Default)]
+pub struct AcceptCharset(Option<Vec<(String, f32)>>);
+
+impl AcceptCharset {
+    pub fn parse(header: &str) -> Self {
+        let entries = header
+            .split(',')
+            .filter_map(|element| {
+                let mut parts = element.split(';').map(str::trim);
+                let name = parts.next().filter(|name| !name.is_empty())?;
+                let mut q = 1.0;
+                for param in parts {
+                    if let Some(("q", value)) =
+                        param.split_once('=').map(|(n, v)| (n.trim(), v.trim()))
+                    {
+                        q = value
+                            .parse::<f32>()
+                            .ok()
+                            .filter(|q| (0.0..=1.0).contains(q))?;
+                    }
+                }
+                Some((name.to_ascii_lowercase(), q))
+            })
+            .collect();
+        Self(Some(entries))
+    }
+
+    /// Quality the client gives `charset`: its own entry, else `*`'s, else 0.
+    pub fn quality(&self, charset: &str) -> f32 {
+        let Some(entries) = &self.0 else {
+            return 1.0;
+        };
+        let named = entries.iter().find(|(name, _)| name == charset);
+        let any = entries.iter().find(|(name, _)| name == "*");
+        named.or(any).map_or(0.0, |(_, q)| *q)
+    }
+
+    /// The entries of `CHARSETS` the client accepts, best first.
+    pub fn preferences(&self) -> Vec<&'static str> {
+        let mut accepted: Vec<(&'static str, f32)> = CHARSETS
+            .iter()
+            .map(|&charset| (charset, self.quality(charset)))
+            .filter(|(_, q)| *q > 0.0)
+            .collect();
+        // Stable: equal q keeps our order.
+        accepted.sort_by(|a, b| b.1.total_cmp(&a.1));
+        accepted.into_iter().map(|(charset, _)| charset).collect()
+    }
+}
+
+/// `text` in `charset`, or None if it has characters the charset lacks and
+/// `escape_html` is false.
+fn encode(charset: &str, text: &str, escape_html: bool) -> Option<Vec<u8>> {
+    if charset == "iso-8859-1" {
+        let mut bytes = Vec::with_capacity(text.len());
+        for c in text.chars() {
+            match u8::try_from(u32::from(c)) {
+                Ok(byte) => bytes.push(byte),
+                Err(_) if escape_html => bytes.extend(format!("&#{};", u32::from(c)).bytes()),
+                Err(_) => return None,
+            }
+        }
+        return Some(bytes);
+    }
+
+    let encoding = Encoding::for_label(charset.as_bytes())?;
+    // encoding_rs writes `&#NNNN;` for what it can't map, and says so.
+    let (bytes, _, unmappable) = encoding.encode(text);
+    (escape_html || !unmappable).then(|| bytes.into_owned())
+}
+
+/// Encodes `text` in the best charset the client accepts that can carry it.
+/// Returns the charset and the bytes, or a 406.
+pub fn encode_text(
+    accept: &AcceptCharset,
+    text: &str,
+    escape_html: bool,
+) -> Result<(&'static str, Vec<u8>)> {
+    let preferences = accept.preferences();
+    preferences
+        .iter()
+        .find_map(|&charset| encode(charset, text, escape_html).map(|bytes| (charset, bytes)))
+        .ok_or_else(|| {
+            let message = if preferences.is_empty() {
+                format!("Supported charsets: {}", CHARSETS.join(", "))
+            } else {
+                format!(
+                    "The text can't be written in {}; try utf-8",
+                    preferences.join(" or ")
+                )
+            };
+            ApiError::new(StatusCode::NOT_ACCEPTABLE, "not_acceptable", message)
+        })
+}
+
+impl FromRequestParts for AcceptCharset {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        let joined = req
+            .headers()
+            .get_all(http::header::ACCEPT_CHARSET)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        if joined.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        Ok(Self::parse(&joined))
+    }
+}
+
+impl OperationModifier for AcceptCharset {
+    fn update_operation(op: &mut Operation) {
+        op.parameters.push(Parameter {
+            name: "Accept-Charset".to_string(),
+            location: "header".to_string(),
+            description: Some(format!(
+                "Charset for text responses: {}. JSON is always UTF-8",
+                CHARSETS.join(", ")
+            )),
+            required: false,
+            deprecated: None,
+            schema: Some(SchemaRef::Inline(serde_json::json!({ "type": "string" }))),
+        });
+    }
+}
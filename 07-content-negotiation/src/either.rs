@@ -0,0 +1,44 @@
+// ---------------------------------------------------------------------------
+// Either<L, R> response
+// ---------------------------------------------------------------------------
+//
+// `-> impl IntoResponse` doesn't compile on a #[get] handler: the macro also
+// needs the return type's ResponseModifier to document the route, and an
+// opaque type hides it. `-> Response` compiles, but /docs then only knows
+// "200, something".
+//
+// Either keeps both branches' types, so both are documented.
+
+use rustapi_openapi::{OpenApiSpec, Operation, ResponseModifier};
+use rustapi_rs::prelude::*;
+
+/// One of two response types, chosen at runtime.
+///
+/// Nest for more: `Either<A, Either<B, C>>`. For an error branch, prefer
+/// `Result<Either<..>, ApiError>` — `?` keeps working.
+pub enum Either<L, R> {
+    Left(L),
+    Right(R),
+}
+
+impl<L: IntoResponse, R: IntoResponse> IntoResponse for Either<L, R> {
+    fn into_response(self) -> Response {
+        match self {
+            Either::Left(left) => left.into_response(),
+            Either::Right(right) => right.into_response(),
+        }
+    }
+}
+
+impl<L: ResponseModifier, R: ResponseModifier> ResponseModifier for Either<L, R> {
+    fn update_response(op: &mut Operation) {
+        // NOTE: if both sides describe the same status code, R's entry wins.
+        L::update_response(op);
+        R::update_response(op);
+    }
+
+    fn register_components(spec: &mut OpenApiSpec) {
+        L::register_components(spec);
+        R::register_components(spec);
+    }
+}
@@ -0,0 +1,75 @@
+// ---------------------------------------------------------------------------
+// FromAny — the first extractor that succeeds
+// ---------------------------------------------------------------------------
+//
+// Some values may arrive in more than one place: an API version in a header
+// or the query string, a token in `Authorization` or a cookie. Instead of
+// hand-written fallbacks in every handler, list the sources in priority order:
+//
+//   FromAny<ApiVersion, (VersionHeader, VersionQuery)>
+//
+// Each source is an ordinary extractor that converts `Into<T>`. They are
+// tried left to right; the first `Ok` wins and the rest are not run.
+//
+// When every source fails, the errors are combined: the response keeps the
+// status and type of the *last* source's error, and its message lists every
+// source's message in order — so the client learns all the places it could
+// have sent the value.
+//
+// NOTE: sources are FromRequestParts only. A body source would consume the
+//       body even when a header already supplied the value, leaving nothing
+//       for the handler's own body extractor. Read the body field in the
+//       handler instead: `version.or(payload.version)`.
+
+use rustapi_openapi::{Operation, OperationModifier};
+use rustapi_rs::prelude::*;
+use rustapi_rs::FromRequestParts;
+use std::marker::PhantomData;
+
+/// `T` from the first of the `Sources` (a tuple of extractors) that succeeds.
+///
+/// Destructure it in the handler: `FromAny(version, _): Version`.
+pub struct FromAny<T, Sources>(pub T, pub PhantomData<fn() -> Sources>);
+
+/// Status and type of the last error, every source's message.
+fn all_failed(errors: Vec<ApiError>) -> ApiError {
+    let message = errors
+        .iter()
+        .map(|err| err.message.as_str())
+        .collect::<Vec<_>>()
+        .join("; ");
+    let last = errors.last().expect("FromAny has at least one source");
+    ApiError::new(last.status, last.error_type.clone(), message)
+}
+
+macro_rules! from_any {
+    ($($source:ident),+) => {
+        impl<T, $($source),+> FromRequestParts for FromAny<T, ($($source,)+)>
+        where
+            $($source: FromRequestParts + Into<T>,)+
+        {
+            fn from_request_parts(req: &Request) -> Result<Self> {
+                let mut errors = Vec::new();
+                $(
+                    match $source::from_request_parts(req) {
+                        Ok(value) => return Ok(FromAny(value.into(), PhantomData)),
+                        Err(err) => errors.push(err),
+                    }
+                )+
+                Err(all_failed(errors))
+            }
+        }
+
+        // Every source documents itself; together they describe all the
+        // places the value may come from.
+        impl<T, $($source: OperationModifier),+> OperationModifier for FromAny<T, ($($source,)+)> {
+            fn update_operation(op: &mut Operation) {
+                $($source::update_operation(op);)+
+            }
+        }
+    };
+}
+
+from_any!(A, B);
+from_any!(A, B, C);
+from_any!(A, B, C, D);
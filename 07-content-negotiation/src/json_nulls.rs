@@ -0,0 +1,312 @@
+// ---------------------------------------------------------------------------
+// Null fields in JSON responses
+// ---------------------------------------------------------------------------
+//
+// serde writes `None` as `null`, so a struct with a dozen optional fields
+// sends a dozen `"field":null`s. `#[serde(skip_serializing_if =
+// "Option::is_none")]` on every field fixes one struct at a time; this fixes
+// every response at once:
+//
+//   RustApi::config()
+//       .docs_enabled(false)
+//       .build()
+//       .json_nulls(JsonNulls::Omit)
+//       .docs_with_nulls("/docs")
+//
+//   JsonNulls::Keep   `{"ranges":[],"best_match":null}`   (the default)
+//   JsonNulls::Omit   `{"ranges":[]}`
+//
+// Omit rewrites JSON responses (`application/json` and `+json` types) on the
+// way out: every object member whose value is null is dropped, at any depth.
+// Nulls inside arrays stay, since their position means something.
+//
+// The spec says so too. A property that isn't required already may be
+// missing; with Omit it's never null either, so `"null"` leaves its type:
+//
+//   Keep   "best_match": {"type": ["string", "null"]}
+//   Omit   "best_match": {"type": "string"}
+//
+// As with 03-jwt-auth's docs_with_security, that's written into the spec
+// when the docs are mounted, which is why the app is built with docs off:
+// docs_with_nulls(path) serves Swagger UI and `{path}/openapi.json` in the
+// JsonNulls the app was given. Call it last, after every route.
+//
+// Keep it for clients that tell "absent" from "null", or that were written
+// against explicit nulls: dropping a field they index will break them.
+//
+// NOTE: a map with Option values (`HashMap<String, Option<T>>`) loses its
+//       null entries too. Bodies without a known length up to MAX_REWRITE
+//       (streams, large exports) are sent as they are.
+//
+// NOTE: a rewritten object's members come out in alphabetical order, as
+//       serde_json's Value keeps them, not in declaration order. JSON
+//       objects are unordered, but a client diffing payloads will notice.
+
+use bytes::Bytes;
+use http::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use http::HeaderValue;
+use http_body::Body as _;
+use http_body_util::BodyExt;
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_rs::prelude::*;
+use rustapi_rs::ResponseBody;
+use serde_json::Value;
+use std::future::Future;
+use std::pin::Pin;
+
+/// Largest body rewritten; bigger ones go out unchanged.
+pub const MAX_REWRITE: u64 = 1024 * 1024;
+
+/// What a JSON response does with null object members.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JsonNulls {
+    /// Send them, as serde wrote them.
+    #[default]
+    Keep,
+    /// Leave them out.
+    Omit,
+}
+
+/// Marks a response whose JSON is already as it should be sent.
+#[derive(Debug, Clone, Copy)]
+struct Finished;
+
+pub trait JsonNullsExt {
+    /// What every JSON response, and the spec, does with nulls.
+    fn json_nulls(self, nulls: JsonNulls) -> Self;
+
+    /// Serves Swagger UI at `path` and the spec, written for the app's
+    /// `JsonNulls`, at `{path}/openapi.json`.
+    fn docs_with_nulls(self, path: &str) -> Self;
+}
+
+impl JsonNullsExt for RustApi {
+    fn json_nulls(self, nulls: JsonNulls) -> Self {
+        let app = self.state(nulls);
+        match nulls {
+            JsonNulls::Keep => app,
+            JsonNulls::Omit => app.layer(OmitNullsLayer),
+        }
+    }
+
+    fn docs_with_nulls(self, path: &str) -> Self {
+        let nulls = self
+            .router()
+            .state_ref()
+            .get::<JsonNulls>()
+            .copied()
+            .unwrap_or_default();
+        let mut spec = self.openapi_spec().to_json();
+        if nulls == JsonNulls::Omit {
+            never_null(&mut spec);
+            omit_nulls(&mut spec);
+        }
+        let json = serde_json::to_string_pretty(&spec).unwrap_or_else(|_| "{}".to_string());
+
+        let path = path.trim_end_matches('/');
+        let openapi_url = format!("{}/openapi.json", path);
+        let spec_handler = move || {
+            let json = json.clone();
+            async move {
+                let mut response = Response::new(ResponseBody::from(json));
+                response
+                    .headers_mut()
+                    .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+                response.extensions_mut().insert(Finished);
+                response
+            }
+        };
+        let url = openapi_url.clone();
+        let docs_handler = move || {
+            let url = url.clone();
+            async move { rustapi_openapi::swagger_ui_html(&url).map(ResponseBody::Full) }
+        };
+
+        self.route(&openapi_url, get(spec_handler))
+            .route(path, get(docs_handler))
+    }
+}
+
+/// Drops null object members from `value`, at any depth.
+pub fn omit_nulls(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            object.retain(|_, member| !member.is_null());
+            object.values_mut().for_each(omit_nulls);
+        }
+        Value::Array(items) => items.iter_mut().for_each(omit_nulls),
+        _ => {}
+    }
+}
+
+/// Takes `"null"` out of the type of every property that isn't required, in
+/// every schema of `spec`.
+pub fn never_null(spec: &mut Value) {
+    match spec {
+        Value::Object(object) => {
+            let required: Vec<String> = object
+                .get("required")
+                .and_then(Value::as_array)
+                .map(|names| {
+                    names
+                        .iter()
+                        .filter_map(|name| name.as_str().map(str::to_string))
+                        .collect()
+                })
+                .unwrap_or_default();
+            if let Some(Value::Object(properties)) = object.get_mut("properties") {
+                for (name, property) in properties.iter_mut() {
+                    if !required.contains(name) {
+                        drop_null_type(property);
+                    }
+                }
+            }
+            object.values_mut().for_each(never_null);
+        }
+        Value::Array(items) => items.iter_mut().for_each(never_null),
+        _ => {}
+    }
+}
+
+/// `{"type": ["string", "null"]}` → `{"type": "string"}`.
+fn drop_null_type(schema: &mut Value) {
+    let Some(Value::Array(types)) = schema.get_mut("type") else {
+        return;
+    };
+    types.retain(|kind| kind != "null");
+    if types.len() == 1 {
+        let kind = types.remove(0);
+        schema["type"] = kind;
+    }
+}
+
+fn is_json(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    essence == "application/json" || essence.ends_with("+json")
+}
+
+#[derive(Clone)]
+struct OmitNullsLayer;
+
+impl MiddlewareLayer for OmitNullsLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        Box::pin(async move {
+            let response = next(req).await;
+            if response.extensions().get::<Finished>().is_some() {
+                return response;
+            }
+            let json = response
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(is_json);
+            let fits = response
+                .body()
+                .size_hint()
+                .exact()
+                .is_some_and(|length| length <= MAX_REWRITE);
+            if !json || !fits {
+                return response;
+            }
+
+            let (mut parts, body) = response.into_parts();
+            let original = match body.collect().await {
+                Ok(collected) => collected.to_bytes(),
+                Err(err) => return err.into_response(),
+            };
+            let Ok(mut value) = serde_json::from_slice::<Value>(&original) else {
+                return Response::from_parts(parts, ResponseBody::new(original));
+            };
+            omit_nulls(&mut value);
+            let body = match serde_json::to_vec(&value) {
+                Ok(rewritten) => Bytes::from(rewritten),
+                Err(_) => original,
+            };
+
+            parts.headers.remove(CONTENT_LENGTH);
+            Response::from_parts(parts, ResponseBody::new(body))
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustapi_core::{BodyVariant, PathParams};
+
+    #[derive(Serialize, Schema)]
+    struct Pick {
+        ranges: Vec<Option<u8>>,
+        best_match: Option<String>,
+    }
+
+    async fn pick() -> Json<Pick> {
+        Json(Pick {
+            ranges: vec![Some(1), None],
+            best_match: None,
+        })
+    }
+
+    async fn get_json(app: &RequestDispatcher, path: &str) -> Value {
+        let (parts, ()) = http::Request::get(path).body(()).unwrap().into_parts();
+        let request = Request::new(
+            parts,
+            BodyVariant::Buffered(Default::default()),
+            app.state_ref(),
+            PathParams::new(),
+        );
+        let response = app.dispatch(request).await;
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn app(nulls: JsonNulls) -> RequestDispatcher {
+        RustApi::new()
+            .json_nulls(nulls)
+            .route("/pick", get(pick))
+            .docs_with_nulls("/docs")
+            .request_dispatcher()
+    }
+
+    fn best_match_type(spec: &Value) -> Value {
+        let schemas = &spec["components"]["schemas"];
+        schemas["Pick"]["properties"]["best_match"]["type"].clone()
+    }
+
+    #[tokio::test]
+    async fn omit_drops_members_and_keeps_array_nulls() {
+        let body = get_json(&app(JsonNulls::Omit), "/pick").await;
+        assert_eq!(body, serde_json::json!({ "ranges": [1, null] }));
+    }
+
+    #[tokio::test]
+    async fn omit_takes_null_out_of_the_spec() {
+        let spec = get_json(&app(JsonNulls::Omit), "/docs/openapi.json").await;
+        assert_eq!(best_match_type(&spec), "string");
+    }
+
+    #[tokio::test]
+    async fn keep_sends_both_as_they_are() {
+        let app = app(JsonNulls::Keep);
+        let body = get_json(&app, "/pick").await;
+        assert_eq!(body["best_match"], Value::Null);
+        let spec = get_json(&app, "/docs/openapi.json").await;
+        assert_eq!(
+            best_match_type(&spec),
+            serde_json::json!(["string", "null"])
+        );
+    }
+}
@@ -0,0 +1,258 @@
+// Run with: cargo run -p content-negotiation
+// Then try:
+//   curl -H 'Accept: text/html;q=0.9, application/json' http://127.0.0.1:3000/greeting/Alice
+//   curl -H 'Accept: text/*' http://127.0.0.1:3000/greeting/Alice
+//   curl -H 'Accept: image/png' http://127.0.0.1:3000/greeting/Alice     # 406
+//   curl -H 'Accept: text/plain' -H 'Accept-Charset: iso-8859-1' http://127.0.0.1:3000/greeting/Alice
+//   curl -H 'Accept: image/png' http://127.0.0.1:3000/accept               # no best_match
+//   JSON_NULLS=keep cargo run -p content-negotiation                      # best_match: null
+//
+// Lesson: content negotiation is more than `accept.contains("json")`.
+//         Parse the media ranges, honour q-values and wildcards, and answer
+//         406 when nothing fits.
+
+mod accept;
+mod charset;
+mod either;
+mod from_any;
+mod json_nulls;
+mod negotiate;
+mod range;
+mod static_files;
+mod version;
+
+use accept::{Accept, MediaRange};
+use charset::AcceptCharset;
+use either::Either;
+use from_any::FromAny;
+use json_nulls::{JsonNulls, JsonNullsExt};
+use negotiate::{Negotiate, Representation};
+use range::{Range, Ranged};
+use rustapi_rs::prelude::*;
+use rustapi_rs::{description, get, summary, tag};
+use static_files::{StaticConfig, StaticExt};
+use version::{ApiVersion, Version};
+
+// ---------------------------------------------------------------------------
+// Models
+// ---------------------------------------------------------------------------
+
+#[derive(Serialize, Schema)]
+struct Greeting {
+    message: String,
+    framework: &'static str,
+}
+
+impl Representation for Greeting {
+    fn to_html(&self) -> String {
+        // message carries the caller's ?name, so it's escaped like the
+        // directory listings are.
+        format!(
+            "<h1>{}</h1><p>Served by {}</p>",
+            static_files::escape(&self.message),
+            static_files::escape(self.framework)
+        )
+    }
+
+    fn to_text(&self) -> String {
+        format!("{} (served by {})", self.message, self.framework)
+    }
+}
+
+#[derive(Clone, Serialize, Schema)]
+struct Language {
+    code: &'static str,
+    name: &'static str,
+}
+
+const LANGUAGES: &[Language] = &[
+    Language {
+        code: "en",
+        name: "English",
+    },
+    Language {
+        code: "tr",
+        name: "Türkçe",
+    },
+];
+
+/// Old codes that moved, and where they live now.
+const RENAMED: &[(&str, &str)] = &[("eng", "en"), ("tur", "tr")];
+
+/// `/hello/{name}` in version 1.
+#[derive(Serialize, Schema)]
+struct HelloV1 {
+    message: String,
+}
+
+/// `/hello/{name}` in version 2: the name is its own field.
+#[derive(Serialize, Schema)]
+struct HelloV2 {
+    greeting: String,
+    name: String,
+}
+
+#[derive(Serialize, Schema)]
+struct AcceptReport {
+    ranges: Vec<MediaRange>,
+    best_match: Option<String>,
+}
+
+// ---------------------------------------------------------------------------
+// Handlers
+// ---------------------------------------------------------------------------
+
+#[get("/greeting/{name}")]
+#[tag("negotiation")]
+#[summary("Negotiated greeting")]
+#[description(
+    "Returns JSON, HTML or plain text depending on the Accept header. HTML and text are sent in the charset Accept-Charset asks for."
+)]
+async fn greeting(
+    Path(name): Path<String>,
+    accept: Accept,
+    accept_charset: AcceptCharset,
+) -> Negotiate<Greeting> {
+    Negotiate::new(
+        accept,
+        Greeting {
+            message: format!("Hello, {}!", name),
+            framework: "RustAPI",
+        },
+    )
+    .accept_charset(accept_charset)
+}
+
+#[get("/accept")]
+#[tag("negotiation")]
+#[summary("Inspect Accept")]
+#[description("Shows how the Accept header was parsed and which type would win.")]
+async fn inspect(accept: Accept) -> Json<AcceptReport> {
+    Json(AcceptReport {
+        best_match: accept.best_match(negotiate::AVAILABLE).map(String::from),
+        ranges: accept.ranges().to_vec(),
+    })
+}
+
+#[get("/languages/{code}")]
+#[tag("conditional")]
+#[summary("Look up a language")]
+#[description(
+    "Three outcomes from one handler: the language as JSON, a 301 to the new code for a renamed one, or a 404."
+)]
+async fn language(Path(code): Path<String>) -> Result<Either<Json<Language>, Redirect>, ApiError> {
+    if let Some(language) = LANGUAGES.iter().find(|l| l.code == code) {
+        return Ok(Either::Left(Json(language.clone())));
+    }
+    if let Some((_, new)) = RENAMED.iter().find(|(old, _)| *old == code) {
+        return Ok(Either::Right(Redirect::permanent(&format!(
+            "/languages/{}",
+            new
+        ))));
+    }
+    Err(ApiError::not_found(format!(
+        "No language with code '{}'",
+        code
+    )))
+}
+
+#[get("/hello/{name}")]
+#[tag("versioning")]
+#[summary("Versioned greeting")]
+#[description(
+    "The version comes from `X-Api-Version` or, failing that, `?version=`. With neither, the 400 lists both."
+)]
+async fn hello(
+    Path(name): Path<String>,
+    FromAny(version, _): Version,
+) -> Either<Json<HelloV1>, Json<HelloV2>> {
+    match version {
+        ApiVersion::V1 => Either::Left(Json(HelloV1 {
+            message: format!("Hello, {}!", name),
+        })),
+        ApiVersion::V2 => Either::Right(Json(HelloV2 {
+            greeting: "Hello".to_string(),
+            name,
+        })),
+    }
+}
+
+/// A generated CSV report, large enough to be worth fetching in parts.
+fn report_csv() -> String {
+    let mut csv = String::from("day,requests,errors\n");
+    for day in 1..=365u32 {
+        let requests = 1_000 + (day * 7_919) % 4_000;
+        csv.push_str(&format!("{},{},{}\n", day, requests, requests / 97));
+    }
+    csv
+}
+
+#[get("/report.csv")]
+#[tag("ranges")]
+#[summary("Yearly report, with Range support")]
+#[description(
+    "Send `Range: bytes=0-99` for the start, `bytes=-100` for the end. A range past the end is a 416."
+)]
+async fn report(range: Range) -> Ranged {
+    Ranged::new(&range, "text/csv; charset=utf-8", report_csv())
+}
+
+// ---------------------------------------------------------------------------
+// Entry point
+// ---------------------------------------------------------------------------
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    println!("Starting content-negotiation example…");
+    println!(" -> GET  http://127.0.0.1:3000/greeting/{{name}}");
+    println!(" -> GET  http://127.0.0.1:3000/accept");
+    println!(" -> GET  http://127.0.0.1:3000/languages/{{code}}");
+    println!(" -> GET  http://127.0.0.1:3000/hello/{{name}}?version=2");
+    println!(" -> GET  http://127.0.0.1:3000/report.csv  (Range: bytes=-500)");
+    println!(" -> GET  http://127.0.0.1:3000/media/  (listing, ranges, cache headers)");
+    println!(" -> GET  http://127.0.0.1:3000/docs");
+
+    // Null fields are left out of JSON responses, unless JSON_NULLS=keep.
+    let nulls = match std::env::var("JSON_NULLS").as_deref() {
+        Ok("keep") => JsonNulls::Keep,
+        _ => JsonNulls::Omit,
+    };
+
+    // Docs off here: docs_with_nulls mounts them last, with the spec written
+    // for `nulls` (RustApi::auto() would mount them first).
+    RustApi::config()
+        .docs_enabled(false)
+        .build()
+        .json_nulls(nulls)
+        .serve_static_with(
+            "/media",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/media"),
+            StaticConfig {
+                cache_control: Some("public, max-age=300".to_string()),
+                autoindex: true,
+                ..StaticConfig::default()
+            },
+        )
+        .docs_with_nulls("/docs")
+        .dashboard(DashboardConfig::new())
+        .run("127.0.0.1:3000")
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn html_greeting_escapes_the_name() {
+        let greeting = Greeting {
+            message: "Hello, <script>alert(\"x\")</script> & co!".to_string(),
+            framework: "RustAPI",
+        };
+        assert_eq!(
+            greeting.to_html(),
+            "<h1>Hello, &lt;script&gt;alert(&quot;x&quot;)&lt;/script&gt; &amp; co!</h1>\
+             <p>Served by RustAPI</p>"
+        );
+    }
+}
@@ -0,0 +1,146 @@
+// ---------------------------------------------------------------------------
+// Negotiate<T> response
+// ---------------------------------------------------------------------------
+//
+// One handler, several representations. The Accept extractor decides which
+// one the client gets; nothing acceptable → 406 Not Acceptable. HTML and
+// plain text are also encoded in the charset Accept-Charset asks for
+// (src/charset.rs).
+
+use crate::accept::Accept;
+use crate::charset::{self, AcceptCharset};
+use http::header;
+use rustapi_openapi::schema::{RustApiSchema, SchemaCtx};
+use rustapi_openapi::{
+    MediaType, OpenApiSpec, Operation, ResponseModifier, ResponseSpec, SchemaRef,
+};
+use rustapi_rs::prelude::*;
+use std::collections::BTreeMap;
+
+/// Media types Negotiate can produce, in server preference order.
+pub const AVAILABLE: &[&str] = &["application/json", "text/html", "text/plain"];
+
+/// Non-JSON renderings of a response model.
+pub trait Representation: Serialize {
+    fn to_html(&self) -> String;
+    fn to_text(&self) -> String;
+}
+
+pub struct Negotiate<T> {
+    accept: Accept,
+    accept_charset: AcceptCharset,
+    value: T,
+}
+
+impl<T> Negotiate<T> {
+    pub fn new(accept: Accept, value: T) -> Self {
+        Self {
+            accept,
+            accept_charset: AcceptCharset::default(),
+            value,
+        }
+    }
+
+    /// Encodes text representations in the client's preferred charset
+    /// instead of always UTF-8.
+    pub fn accept_charset(mut self, accept_charset: AcceptCharset) -> Self {
+        self.accept_charset = accept_charset;
+        self
+    }
+}
+
+impl<T: Representation> IntoResponse for Negotiate<T> {
+    fn into_response(self) -> Response {
+        let (content_type, body) = match self.accept.best_match(AVAILABLE) {
+            Some(mime @ ("text/html" | "text/plain")) => {
+                let (text, escape_html) = match mime {
+                    "text/html" => (self.value.to_html(), true),
+                    _ => (self.value.to_text(), false),
+                };
+                match charset::encode_text(&self.accept_charset, &text, escape_html) {
+                    Ok((charset, bytes)) => (format!("{}; charset={}", mime, charset), bytes),
+                    Err(err) => return err.into_response(),
+                }
+            }
+            Some(_) => match serde_json::to_vec(&self.value) {
+                Ok(json) => ("application/json".to_string(), json),
+                Err(err) => {
+                    return ApiError::internal(format!("Failed to serialize response: {}", err))
+                        .into_response()
+                }
+            },
+            None => {
+                return ApiError::new(
+                    StatusCode::NOT_ACCEPTABLE,
+                    "not_acceptable",
+                    format!("Supported media types: {}", AVAILABLE.join(", ")),
+                )
+                .into_response()
+            }
+        };
+
+        http::Response::builder()
+            .header(header::CONTENT_TYPE, content_type)
+            // Caches must key on both, or an HTML client may get cached JSON,
+            // or a Latin-1 client cached UTF-8.
+            .header(header::VARY, "Accept, Accept-Charset")
+            .body(body.into())
+            .unwrap()
+    }
+}
+
+impl<T: RustApiSchema> ResponseModifier for Negotiate<T> {
+    fn update_response(op: &mut Operation) {
+        let mut ctx = SchemaCtx::new();
+        let mut content = BTreeMap::new();
+        content.insert(
+            "application/json".to_string(),
+            MediaType {
+                schema: Some(T::schema(&mut ctx)),
+                example: None,
+            },
+        );
+        for mime in ["text/html", "text/plain"] {
+            content.insert(
+                mime.to_string(),
+                MediaType {
+                    schema: Some(SchemaRef::Inline(serde_json::json!({ "type": "string" }))),
+                    example: None,
+                },
+            );
+        }
+        op.responses.insert(
+            "200".to_string(),
+            ResponseSpec {
+                description: "Representation chosen by the Accept header".to_string(),
+                content,
+                headers: BTreeMap::new(),
+            },
+        );
+
+        let mut error = BTreeMap::new();
+        error.insert(
+            "application/json".to_string(),
+            MediaType {
+                schema: Some(SchemaRef::Ref {
+                    reference: "#/components/schemas/ErrorSchema".to_string(),
+                }),
+                example: None,
+            },
+        );
+        op.responses.insert(
+            "406".to_string(),
+            ResponseSpec {
+                description: "None of the supported media types, or charsets, is acceptable"
+                    .to_string(),
+                content: error,
+                headers: BTreeMap::new(),
+            },
+        );
+    }
+
+    fn register_components(spec: &mut OpenApiSpec) {
+        spec.register_in_place::<T>();
+        <ApiError as ResponseModifier>::register_components(spec);
+    }
+}
@@ -0,0 +1,390 @@
+// ---------------------------------------------------------------------------
+// Range requests
+// ---------------------------------------------------------------------------
+//
+// `Range: bytes=0-499, -500` asks for parts of a representation: the first
+// 500 bytes, and the last 500. The extractor only parses; whether a range can
+// be served depends on the length of what the handler is about to send, so
+// that check happens in Range::select():
+//
+//   bytes=0-499      first 500 bytes             Bounded { first: 0, last: 499 }
+//   bytes=500-       everything from byte 500    From { first: 500 }
+//   bytes=-500       the last 500 bytes          Suffix { len: 500 }
+//
+// Ranged turns the selection into the response: 206 with Content-Range
+// for one satisfiable range, 416 with `Content-Range: bytes */len` when none
+// is, and a plain 200 otherwise.
+//
+// Following RFC 9110 §14.2, a Range header we can't use is ignored rather
+// than rejected: a syntax error, a unit other than `bytes`, or more ranges
+// than MAX_RANGES all just get the full 200. Several satisfiable ranges would
+// need a multipart/byteranges body; this example sends the full 200 for those
+// too, which the RFC allows. Ranged ignores If-Range; the static files
+// (src/static_files.rs) honour it.
+
+use bytes::Bytes;
+use http::header::{ACCEPT_RANGES, CONTENT_RANGE, CONTENT_TYPE, RANGE};
+use http::HeaderValue;
+use rustapi_openapi::{
+    MediaType, OpenApiSpec, Operation, OperationModifier, Parameter, ResponseModifier,
+    ResponseSpec, SchemaRef,
+};
+use rustapi_rs::prelude::*;
+use rustapi_rs::{FromRequestParts, ResponseBody};
+use std::collections::BTreeMap;
+use std::ops;
+
+/// More ranges than this and the header is ignored: a request for thousands
+/// of tiny ranges costs the server far more than the client.
+const MAX_RANGES: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    /// `first-last`, both inclusive.
+    Bounded { first: u64, last: u64 },
+    /// `first-`: to the end.
+    From { first: u64 },
+    /// `-len`: the last `len` bytes.
+    Suffix { len: u64 },
+}
+
+impl ByteRange {
+    fn parse(spec: &str) -> Option<Self> {
+        let (first, last) = spec.trim().split_once('-')?;
+        let number = |s: &str| {
+            // u64::from_str would also take a leading `+`.
+            if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) {
+                s.parse::<u64>().ok()
+            } else {
+                None
+            }
+        };
+        match (first.trim(), last.trim()) {
+            ("", len) => Some(Self::Suffix { len: number(len)? }),
+            (first, "") => Some(Self::From {
+                first: number(first)?,
+            }),
+            (first, last) => {
+                let (first, last) = (number(first)?, number(last)?);
+                // `500-499` makes the whole header invalid, not just this range.
+                (first <= last).then_some(Self::Bounded { first, last })
+            }
+        }
+    }
+
+    /// The bytes this range covers in a representation of `len` bytes, or
+    /// None if it covers none of them.
+    pub fn resolve(self, len: u64) -> Option<ops::Range<u64>> {
+        match self {
+            Self::Bounded { first, last } if first < len => Some(first..last.min(len - 1) + 1),
+            Self::From { first } if first < len => Some(first..len),
+            Self::Suffix { len: suffix } if suffix > 0 && len > 0 => {
+                Some(len - suffix.min(len)..len)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// What to send, for a representation of a given length.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selection {
+    /// No usable Range header, or several ranges: send everything, 200.
+    Full,
+    /// Exactly these bytes, 206.
+    Partial(ops::Range<u64>),
+    /// Nothing requested exists, 416.
+    Unsatisfiable,
+}
+
+/// The request's `Range` header, parsed. Never rejects the request: a header
+/// that can't be used reads as no header at all.
+#[derive(Debug, Clone, Default)]
+pub struct Range(Option<Vec<ByteRange>>);
+
+impl Range {
+    /// `bytes=0-499, -500`. None if the header should be ignored.
+    pub fn parse(header: &str) -> Option<Vec<ByteRange>> {
+        let (unit, set) = header.split_once('=')?;
+        if !unit.trim().eq_ignore_ascii_case("bytes") {
+            return None;
+        }
+        let ranges = set
+            .split(',')
+            .filter(|spec| !spec.trim().is_empty())
+            .map(ByteRange::parse)
+            .collect::<Option<Vec<_>>>()?;
+        (!ranges.is_empty() && ranges.len() <= MAX_RANGES).then_some(ranges)
+    }
+
+    /// Which part of a `len`-byte representation to send.
+    ///
+    /// Handlers that generate content can call this first and produce only
+    /// the selected bytes.
+    pub fn select(&self, len: u64) -> Selection {
+        let Some(ranges) = &self.0 else {
+            return Selection::Full;
+        };
+        let mut satisfiable = ranges.iter().filter_map(|range| range.resolve(len));
+        match (satisfiable.next(), satisfiable.next()) {
+            (None, _) => Selection::Unsatisfiable,
+            (Some(only), None) => Selection::Partial(only),
+            (Some(_), Some(_)) => Selection::Full,
+        }
+    }
+}
+
+impl FromRequestParts for Range {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        Ok(Self(
+            req.headers()
+                .get(RANGE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(Self::parse),
+        ))
+    }
+}
+
+impl OperationModifier for Range {
+    fn update_operation(op: &mut Operation) {
+        op.parameters.push(Parameter {
+            name: "Range".to_string(),
+            location: "header".to_string(),
+            description: Some(
+                "Byte range, e.g. `bytes=0-499`, `bytes=500-` or `bytes=-500` (the last 500)."
+                    .to_string(),
+            ),
+            required: false,
+            deprecated: None,
+            schema: Some(SchemaRef::Inline(serde_json::json!({ "type": "string" }))),
+        });
+    }
+}
+
+/// A complete body, answered in full or in part as the request's Range asks.
+pub struct Ranged {
+    selection: Selection,
+    content_type: &'static str,
+    body: Bytes,
+}
+
+impl Ranged {
+    pub fn new(range: &Range, content_type: &'static str, body: impl Into<Bytes>) -> Self {
+        let body = body.into();
+        Self {
+            selection: range.select(body.len() as u64),
+            content_type,
+            body,
+        }
+    }
+}
+
+impl IntoResponse for Ranged {
+    fn into_response(self) -> Response {
+        let total = self.body.len();
+        let (status, body, content_range) = match self.selection {
+            Selection::Full => (StatusCode::OK, self.body, None),
+            Selection::Partial(range) => {
+                let (start, end) = (range.start as usize, range.end as usize);
+                let content_range = format!("bytes {}-{}/{}", start, end - 1, total);
+                (
+                    StatusCode::PARTIAL_CONTENT,
+                    self.body.slice(start..end),
+                    Some(content_range),
+                )
+            }
+            Selection::Unsatisfiable => {
+                let mut response = ApiError::new(
+                    StatusCode::RANGE_NOT_SATISFIABLE,
+                    "range_not_satisfiable",
+                    format!("No requested range overlaps the {} bytes available", total),
+                )
+                .into_response();
+                let content_range = HeaderValue::try_from(format!("bytes */{}", total))
+                    .expect("digits are a valid header value");
+                response.headers_mut().insert(CONTENT_RANGE, content_range);
+                return response;
+            }
+        };
+
+        let mut response = Response::new(ResponseBody::new(body));
+        *response.status_mut() = status;
+        let headers = response.headers_mut();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static(self.content_type));
+        headers.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+        if let Some(content_range) = content_range {
+            headers.insert(
+                CONTENT_RANGE,
+                HeaderValue::try_from(content_range).expect("digits are a valid header value"),
+            );
+        }
+        response
+    }
+}
+
+impl ResponseModifier for Ranged {
+    fn update_response(op: &mut Operation) {
+        let mut respond = |status: &str, description: &str, content_range: Option<&str>| {
+            let mut headers = BTreeMap::new();
+            if let Some(example) = content_range {
+                // NOTE: rustapi_openapi doesn't export its Header type by name;
+                //       serde fills it in from the map's value type.
+                headers.insert(
+                    "Content-Range".to_string(),
+                    serde_json::from_value(serde_json::json!({
+                        "description": format!("Which bytes were sent, e.g. `{example}`"),
+                        "schema": { "type": "string" }
+                    }))
+                    .expect("valid OpenAPI header object"),
+                );
+            }
+            let mut content = BTreeMap::new();
+            content.insert(
+                // Any media type: the handler picks it at runtime.
+                "*/*".to_string(),
+                MediaType {
+                    schema: Some(SchemaRef::Inline(
+                        serde_json::json!({ "type": "string", "format": "binary" }),
+                    )),
+                    example: None,
+                },
+            );
+            op.responses.insert(
+                status.to_string(),
+                ResponseSpec {
+                    description: description.to_string(),
+                    content: if status == "416" {
+                        BTreeMap::new()
+                    } else {
+                        content
+                    },
+                    headers,
+                },
+            );
+        };
+
+        respond("200", "The whole representation", None);
+        respond("206", "The requested range", Some("bytes 0-499/1234"));
+        respond(
+            "416",
+            "No requested range is satisfiable",
+            Some("bytes */1234"),
+        );
+    }
+
+    fn register_components(_spec: &mut OpenApiSpec) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+
+    fn range(header: &str) -> Range {
+        Range(Range::parse(header))
+    }
+
+    async fn respond(header: &str, body: &'static str) -> (StatusCode, Option<String>, String) {
+        let response = Ranged::new(&range(header), "text/plain", body).into_response();
+        let status = response.status();
+        let content_range = response
+            .headers()
+            .get(CONTENT_RANGE)
+            .map(|value| value.to_str().unwrap().to_string());
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (
+            status,
+            content_range,
+            String::from_utf8_lossy(&body).into_owned(),
+        )
+    }
+
+    #[test]
+    fn parses_the_three_forms() {
+        assert_eq!(
+            Range::parse("bytes=0-499, 500-, -500"),
+            Some(vec![
+                ByteRange::Bounded {
+                    first: 0,
+                    last: 499
+                },
+                ByteRange::From { first: 500 },
+                ByteRange::Suffix { len: 500 },
+            ])
+        );
+    }
+
+    #[test]
+    fn unusable_headers_are_ignored() {
+        for header in [
+            "items=0-5",
+            "bytes=5-4",
+            "bytes=+1-2",
+            "bytes=a-",
+            "bytes=",
+            "bytes",
+        ] {
+            assert_eq!(Range::parse(header), None, "{}", header);
+        }
+        let too_many = format!("bytes={}", vec!["0-0"; MAX_RANGES + 1].join(","));
+        assert_eq!(Range::parse(&too_many), None);
+        assert_eq!(range("items=0-5").select(10), Selection::Full);
+    }
+
+    #[test]
+    fn suffix_takes_the_last_bytes() {
+        assert_eq!(range("bytes=-3").select(10), Selection::Partial(7..10));
+        // Longer than the representation: all of it.
+        assert_eq!(range("bytes=-50").select(10), Selection::Partial(0..10));
+        // The last zero bytes, or any suffix of nothing, can't be served.
+        assert_eq!(range("bytes=-0").select(10), Selection::Unsatisfiable);
+        assert_eq!(range("bytes=-5").select(0), Selection::Unsatisfiable);
+    }
+
+    #[test]
+    fn open_range_runs_to_the_end() {
+        assert_eq!(range("bytes=4-").select(10), Selection::Partial(4..10));
+        assert_eq!(range("bytes=9-").select(10), Selection::Partial(9..10));
+        assert_eq!(range("bytes=10-").select(10), Selection::Unsatisfiable);
+        // A bounded range past the end is cut short, not refused.
+        assert_eq!(range("bytes=8-20").select(10), Selection::Partial(8..10));
+    }
+
+    #[test]
+    fn several_satisfiable_ranges_get_everything() {
+        assert_eq!(range("bytes=0-1, -2").select(10), Selection::Full);
+        // Only one of them is satisfiable: that one is sent.
+        assert_eq!(range("bytes=0-1, 20-").select(10), Selection::Partial(0..2));
+    }
+
+    #[tokio::test]
+    async fn partial_responses_carry_content_range() {
+        assert_eq!(
+            respond("bytes=-4", "0123456789").await,
+            (
+                StatusCode::PARTIAL_CONTENT,
+                Some("bytes 6-9/10".to_string()),
+                "6789".to_string()
+            )
+        );
+        assert_eq!(
+            respond("bytes=7-", "0123456789").await,
+            (
+                StatusCode::PARTIAL_CONTENT,
+                Some("bytes 7-9/10".to_string()),
+                "789".to_string()
+            )
+        );
+        assert_eq!(
+            respond("items=1-2", "0123456789").await,
+            (StatusCode::OK, None, "0123456789".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn unsatisfiable_is_416_with_the_length() {
+        let (status, content_range, _) = respond("bytes=10-", "0123456789").await;
+        assert_eq!(status, StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(content_range.as_deref(), Some("bytes */10"));
+    }
+}
@@ -0,0 +1,645 @@
+// ---------------------------------------------------------------------------
+// Static files with ranges, cache headers and directory listings
+// ---------------------------------------------------------------------------
+//
+// RustApi::serve_static() sends whole files only, with a fixed max-age, and
+// quietly drops `..` segments. serve_static_with() mounts a directory with
+// the rest:
+//
+//   .serve_static_with("/media", "./media", StaticConfig {
+//       cache_control: Some("public, max-age=300".into()),
+//       autoindex: true,
+//       ..StaticConfig::default()
+//   })
+//
+//   Range: bytes=…       206 with Content-Range, 416 past the end (Range,
+//                        src/range.rs); what lets a player seek in audio
+//                        and video without downloading all of it
+//   If-Range: <date>     the range only if the date is the file's
+//                        Last-Modified; else the whole file, since it changed
+//   every file           Last-Modified, Accept-Ranges, and Cache-Control
+//                        when configured
+//   a directory          its index_file if there is one, else a listing
+//                        with `autoindex`, else 403
+//   `..` in the path     403, percent-encoded or not; so is a symlink that
+//                        leads out of the directory
+//
+// Files are streamed from disk in CHUNK-sized reads: a 2 GB video costs one
+// buffer of memory, and a range is read from its offset, not from byte 0.
+//
+// NOTE: dotfiles are served like any other file. Keep secrets out of the
+//       mounted directory rather than relying on their names.
+
+use crate::range::{Range, Selection};
+use bytes::Bytes;
+use http::header::{
+    ACCEPT_RANGES, CACHE_CONTROL, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, IF_RANGE,
+    LAST_MODIFIED,
+};
+use http::HeaderValue;
+use rustapi_openapi::{Operation, OperationModifier, Parameter, SchemaRef};
+use rustapi_rs::prelude::*;
+use rustapi_rs::{FromRequestParts, ResponseBody};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// Bytes read from disk at a time.
+const CHUNK: usize = 64 * 1024;
+
+/// How a mounted directory is served.
+#[derive(Debug, Clone)]
+pub struct StaticConfig {
+    /// Sent with every file, e.g. `public, max-age=300`. None sends none.
+    pub cache_control: Option<String>,
+    /// Answer `Range` requests with 206. Off: always the whole file, and
+    /// `Accept-Ranges: none`.
+    pub enable_ranges: bool,
+    /// Served for a directory that contains it.
+    pub index_file: Option<String>,
+    /// List directories without an index file, instead of a 403.
+    pub autoindex: bool,
+}
+
+impl Default for StaticConfig {
+    fn default() -> Self {
+        Self {
+            cache_control: Some("public, max-age=3600".to_string()),
+            enable_ranges: true,
+            index_file: Some("index.html".to_string()),
+            autoindex: false,
+        }
+    }
+}
+
+/// What the handler needs from the request.
+struct StaticRequest {
+    /// The path as sent, still percent-encoded.
+    path: String,
+    range: Range,
+    if_range: Option<String>,
+}
+
+impl FromRequestParts for StaticRequest {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        Ok(Self {
+            path: req.uri().path().to_string(),
+            range: Range::from_request_parts(req)?,
+            if_range: req
+                .headers()
+                .get(IF_RANGE)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+        })
+    }
+}
+
+impl OperationModifier for StaticRequest {
+    fn update_operation(op: &mut Operation) {
+        Range::update_operation(op);
+        op.parameters.push(Parameter {
+            name: "If-Range".to_string(),
+            location: "header".to_string(),
+            description: Some(
+                "The file's Last-Modified date: Range applies only if the file hasn't changed."
+                    .to_string(),
+            ),
+            required: false,
+            deprecated: None,
+            schema: Some(SchemaRef::Inline(serde_json::json!({ "type": "string" }))),
+        });
+    }
+}
+
+struct Mount {
+    prefix: String,
+    root: PathBuf,
+    config: StaticConfig,
+}
+
+/// `RustApi::auto().serve_static_with(prefix, dir, config)`
+pub trait StaticExt {
+    fn serve_static_with(self, prefix: &str, dir: impl Into<PathBuf>, config: StaticConfig)
+        -> Self;
+}
+
+impl StaticExt for RustApi {
+    fn serve_static_with(
+        self,
+        prefix: &str,
+        dir: impl Into<PathBuf>,
+        config: StaticConfig,
+    ) -> Self {
+        let prefix = prefix.trim_end_matches('/').to_string();
+        let mount = Arc::new(Mount {
+            prefix: prefix.clone(),
+            root: dir.into(),
+            config,
+        });
+        let handler = move |req: StaticRequest| {
+            let mount = mount.clone();
+            async move { mount.serve(req).await }
+        };
+
+        // The catch-all needs at least one character after the slash.
+        self.route(&prefix, get(handler.clone()))
+            .route(&format!("{}/", prefix), get(handler.clone()))
+            .route(&format!("{}/*path", prefix), get(handler))
+    }
+}
+
+fn forbidden(message: &str) -> Response {
+    ApiError::forbidden(message).into_response()
+}
+
+fn not_found() -> Response {
+    ApiError::not_found("No such file").into_response()
+}
+
+fn percent_decode(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// The path segments under the mount, or a 403 for any that would leave it.
+fn segments(relative: &str) -> Result<Vec<String>> {
+    let decoded =
+        percent_decode(relative).ok_or_else(|| ApiError::bad_request("Malformed path"))?;
+    let mut segments = Vec::new();
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => return Err(ApiError::forbidden("Path leaves the served directory")),
+            s if s.contains(['\\', '\0']) => {
+                return Err(ApiError::forbidden("Invalid path segment"))
+            }
+            s => segments.push(s.to_string()),
+        }
+    }
+    Ok(segments)
+}
+
+fn content_type(path: &Path) -> &'static str {
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+    match extension.to_ascii_lowercase().as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "ogg" => "audio/ogg",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// `text` with the characters HTML gives meaning to replaced by entities.
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// `segment` for use in a URL path.
+fn percent_encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+impl Mount {
+    async fn serve(&self, req: StaticRequest) -> Response {
+        let relative = req.path.strip_prefix(&self.prefix).unwrap_or_default();
+        let segments = match segments(relative) {
+            Ok(segments) => segments,
+            Err(err) => return err.into_response(),
+        };
+
+        // `..` is gone by now, but a symlink can still point anywhere.
+        let (Ok(root), Ok(path)) = (
+            tokio::fs::canonicalize(&self.root).await,
+            tokio::fs::canonicalize(self.root.join(segments.join("/"))).await,
+        ) else {
+            return not_found();
+        };
+        if !path.starts_with(&root) {
+            return forbidden("Path leaves the served directory");
+        }
+        let Ok(metadata) = tokio::fs::metadata(&path).await else {
+            return not_found();
+        };
+
+        if !metadata.is_dir() {
+            return self.file(&path, metadata, &req).await;
+        }
+        if let Some(index) = &self.config.index_file {
+            let index = path.join(index);
+            if let Ok(metadata) = tokio::fs::metadata(&index).await {
+                if metadata.is_file() {
+                    return self.file(&index, metadata, &req).await;
+                }
+            }
+        }
+        if self.config.autoindex {
+            return self.listing(&path, &segments).await;
+        }
+        forbidden("Directory listing is off")
+    }
+
+    async fn file(
+        &self,
+        path: &Path,
+        metadata: std::fs::Metadata,
+        req: &StaticRequest,
+    ) -> Response {
+        let len = metadata.len();
+        let last_modified = metadata.modified().ok().map(httpdate::fmt_http_date);
+        // There are no ETags here, so only an exact Last-Modified matches.
+        let unchanged = match &req.if_range {
+            Some(date) => last_modified.as_deref() == Some(date.trim()),
+            None => true,
+        };
+        let selection = if self.config.enable_ranges && unchanged {
+            req.range.select(len)
+        } else {
+            Selection::Full
+        };
+        let (status, span) = match selection {
+            Selection::Full => (StatusCode::OK, 0..len),
+            Selection::Partial(span) => (StatusCode::PARTIAL_CONTENT, span),
+            Selection::Unsatisfiable => {
+                let mut response = ApiError::new(
+                    StatusCode::RANGE_NOT_SATISFIABLE,
+                    "range_not_satisfiable",
+                    format!("No requested range overlaps the {} bytes available", len),
+                )
+                .into_response();
+                response.headers_mut().insert(
+                    CONTENT_RANGE,
+                    HeaderValue::try_from(format!("bytes */{}", len))
+                        .expect("digits are a valid header value"),
+                );
+                return response;
+            }
+        };
+
+        let mut file = match tokio::fs::File::open(path).await {
+            Ok(file) => file,
+            Err(_) => return not_found(),
+        };
+        if let Err(err) = file.seek(std::io::SeekFrom::Start(span.start)).await {
+            return ApiError::internal(format!("Can't read file: {}", err)).into_response();
+        }
+        let count = span.end - span.start;
+        let chunks = futures_util::stream::unfold((file, count), |(mut file, left)| async move {
+            if left == 0 {
+                return None;
+            }
+            let mut buffer = vec![0; CHUNK.min(left as usize)];
+            match file.read(&mut buffer).await {
+                // Shorter than it was a moment ago; end here.
+                Ok(0) => None,
+                Ok(n) => {
+                    buffer.truncate(n);
+                    Some((Ok(Bytes::from(buffer)), (file, left - n as u64)))
+                }
+                Err(err) => Some((
+                    Err(ApiError::internal(format!("Can't read file: {}", err))),
+                    (file, 0),
+                )),
+            }
+        });
+
+        let mut response = Response::new(ResponseBody::from_stream(chunks));
+        *response.status_mut() = status;
+        let headers = response.headers_mut();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type(path)));
+        headers.insert(CONTENT_LENGTH, HeaderValue::from(count));
+        headers.insert(
+            ACCEPT_RANGES,
+            HeaderValue::from_static(if self.config.enable_ranges {
+                "bytes"
+            } else {
+                "none"
+            }),
+        );
+        if status == StatusCode::PARTIAL_CONTENT {
+            let content_range = format!("bytes {}-{}/{}", span.start, span.end - 1, len);
+            headers.insert(
+                CONTENT_RANGE,
+                HeaderValue::try_from(content_range).expect("digits are a valid header value"),
+            );
+        }
+        if let Some(date) = last_modified {
+            headers.insert(
+                LAST_MODIFIED,
+                HeaderValue::try_from(date).expect("an HTTP date is a valid header value"),
+            );
+        }
+        if let Some(value) = self
+            .config
+            .cache_control
+            .as_deref()
+            .and_then(|value| HeaderValue::try_from(value).ok())
+        {
+            headers.insert(CACHE_CONTROL, value);
+        }
+        response
+    }
+
+    /// An HTML list of `dir`: subdirectories first, then files, by name.
+    async fn listing(&self, dir: &Path, segments: &[String]) -> Response {
+        let Ok(mut entries) = tokio::fs::read_dir(dir).await else {
+            return not_found();
+        };
+        let mut items = Vec::new();
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            // A name that isn't UTF-8 couldn't be linked to anyway.
+            let (Ok(name), Ok(metadata)) =
+                (entry.file_name().into_string(), entry.metadata().await)
+            else {
+                continue;
+            };
+            items.push((!metadata.is_dir(), name, metadata.len()));
+        }
+        items.sort();
+
+        let href = |segments: &[String]| {
+            let mut href = self.prefix.clone();
+            for segment in segments {
+                href.push('/');
+                href.push_str(&percent_encode(segment));
+            }
+            href
+        };
+        let title = escape(&format!("Index of {}/", href(segments)));
+        let mut page = format!(
+            "<!doctype html>\n<html>\n<head><meta charset=\"utf-8\"><title>{title}</title></head>\n<body>\n<h1>{title}</h1>\n<ul>\n"
+        );
+        if let Some((_, parent)) = segments.split_last() {
+            page.push_str(&format!(
+                "  <li><a href=\"{}/\">../</a></li>\n",
+                href(parent)
+            ));
+        }
+        for (is_file, name, size) in items {
+            let mut path = segments.to_vec();
+            path.push(name.clone());
+            let (slash, size) = if is_file {
+                ("", format!(" ({} bytes)", size))
+            } else {
+                ("/", String::new())
+            };
+            page.push_str(&format!(
+                "  <li><a href=\"{}{slash}\">{}{slash}</a>{size}</li>\n",
+                escape(&href(&path)),
+                escape(&name),
+            ));
+        }
+        page.push_str("</ul>\n</body>\n</html>\n");
+
+        let mut response = Html(page).into_response();
+        if let Some(value) = self
+            .config
+            .cache_control
+            .as_deref()
+            .and_then(|value| HeaderValue::try_from(value).ok())
+        {
+            response.headers_mut().insert(CACHE_CONTROL, value);
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::RANGE;
+    use http_body_util::BodyExt;
+    use rustapi_core::{BodyVariant, PathParams};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const HELLO: &str = "hello, static files\n";
+    const ODD_NAME: &str = "<b>&\"x.txt";
+
+    /// A mounted `root/` next to an `outside/` that must stay unreachable.
+    struct Fixture {
+        base: PathBuf,
+        app: RequestDispatcher,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            static NEXT: AtomicUsize = AtomicUsize::new(0);
+            let base = std::env::temp_dir().join(format!(
+                "static-files-test-{}-{}",
+                std::process::id(),
+                NEXT.fetch_add(1, Ordering::Relaxed)
+            ));
+            let root = base.join("root");
+            std::fs::create_dir_all(root.join("sub")).unwrap();
+            std::fs::create_dir_all(base.join("outside")).unwrap();
+            std::fs::write(root.join("hello.txt"), HELLO).unwrap();
+            std::fs::write(root.join(ODD_NAME), "odd").unwrap();
+            std::fs::write(base.join("outside/secret.txt"), "secret").unwrap();
+
+            let config = StaticConfig {
+                autoindex: true,
+                ..StaticConfig::default()
+            };
+            let app = RustApi::new()
+                .serve_static_with("/media", &root, config)
+                .request_dispatcher();
+            Self { base, app }
+        }
+
+        async fn get(&self, path: &str, headers: &[(&str, &str)]) -> (Response, String) {
+            let mut builder = http::Request::get(path);
+            for (name, value) in headers {
+                builder = builder.header(*name, *value);
+            }
+            let (parts, ()) = builder.body(()).unwrap().into_parts();
+            let request = Request::new(
+                parts,
+                BodyVariant::Buffered(Default::default()),
+                self.app.state_ref(),
+                PathParams::new(),
+            );
+            let response = self.app.dispatch(request).await;
+            let (parts, body) = response.into_parts();
+            let body = body.collect().await.unwrap().to_bytes();
+            (
+                Response::from_parts(parts, ResponseBody::empty()),
+                String::from_utf8_lossy(&body).into_owned(),
+            )
+        }
+
+        async fn status(&self, path: &str) -> StatusCode {
+            self.get(path, &[]).await.0.status()
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.base);
+        }
+    }
+
+    #[tokio::test]
+    async fn dot_dot_is_forbidden_however_it_is_spelled() {
+        let fixture = Fixture::new();
+        assert_eq!(fixture.status("/media/hello.txt").await, StatusCode::OK);
+
+        for path in [
+            "/media/../outside/secret.txt",
+            "/media/sub/../../outside/secret.txt",
+            "/media/%2e%2e/outside/secret.txt",
+            "/media/%2E%2E/outside/secret.txt",
+            "/media/sub/%2e%2e%2f%2e%2e%2foutside%2fsecret.txt",
+            "/media/%2e%2e%2foutside%2fsecret.txt",
+        ] {
+            assert_eq!(fixture.status(path).await, StatusCode::FORBIDDEN, "{path}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn symlinks_out_of_the_root_are_forbidden() {
+        let fixture = Fixture::new();
+        let root = fixture.base.join("root");
+        let outside = fixture.base.join("outside");
+        std::os::unix::fs::symlink(&outside, root.join("out-dir")).unwrap();
+        std::os::unix::fs::symlink(outside.join("secret.txt"), root.join("out-file")).unwrap();
+        std::os::unix::fs::symlink(root.join("hello.txt"), root.join("in-file")).unwrap();
+
+        assert_eq!(
+            fixture.status("/media/out-dir/secret.txt").await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            fixture.status("/media/out-file").await,
+            StatusCode::FORBIDDEN
+        );
+        // One that stays inside is served.
+        let (response, body) = fixture.get("/media/in-file", &[]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body, HELLO);
+    }
+
+    #[tokio::test]
+    async fn ranges_are_served_from_the_file() {
+        let fixture = Fixture::new();
+        let len = HELLO.len();
+
+        let (response, body) = fixture
+            .get("/media/hello.txt", &[("range", "bytes=7-12")])
+            .await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(body, "static");
+        assert_eq!(
+            response.headers()[CONTENT_RANGE],
+            format!("bytes 7-12/{len}").as_str()
+        );
+        assert_eq!(response.headers()[CONTENT_LENGTH], "6");
+        assert_eq!(response.headers()[ACCEPT_RANGES], "bytes");
+
+        let (response, body) = fixture
+            .get("/media/hello.txt", &[("range", "bytes=-6")])
+            .await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(body, "files\n");
+
+        let (response, _) = fixture
+            .get("/media/hello.txt", &[("range", "bytes=9999-")])
+            .await;
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(
+            response.headers()[CONTENT_RANGE],
+            format!("bytes */{len}").as_str()
+        );
+    }
+
+    #[tokio::test]
+    async fn if_range_keeps_the_range_only_for_an_unchanged_file() {
+        let fixture = Fixture::new();
+        let (response, body) = fixture.get("/media/hello.txt", &[]).await;
+        assert_eq!(body, HELLO);
+        let last_modified = response.headers()[LAST_MODIFIED]
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let (response, body) = fixture
+            .get(
+                "/media/hello.txt",
+                &[
+                    (RANGE.as_str(), "bytes=0-4"),
+                    (IF_RANGE.as_str(), &last_modified),
+                ],
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(body, "hello");
+
+        // Another date, or an ETag this server never sent: the whole file.
+        for validator in ["Thu, 01 Jan 1970 00:00:00 GMT", "\"some-etag\""] {
+            let (response, body) = fixture
+                .get(
+                    "/media/hello.txt",
+                    &[
+                        (RANGE.as_str(), "bytes=0-4"),
+                        (IF_RANGE.as_str(), validator),
+                    ],
+                )
+                .await;
+            assert_eq!(response.status(), StatusCode::OK, "{validator}");
+            assert_eq!(body, HELLO);
+            assert!(response.headers().get(CONTENT_RANGE).is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn autoindex_escapes_file_names() {
+        let fixture = Fixture::new();
+        let (response, page) = fixture.get("/media/", &[]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        assert!(
+            page.contains(
+                "<a href=\"/media/%3Cb%3E%26%22x.txt\">&lt;b&gt;&amp;&quot;x.txt</a> (3 bytes)"
+            ),
+            "{page}"
+        );
+        assert!(!page.contains("<b>"), "{page}");
+        assert!(page.contains("<a href=\"/media/sub/\">sub/</a>"), "{page}");
+    }
+}
@@ -0,0 +1,123 @@
+// ---------------------------------------------------------------------------
+// API version
+// ---------------------------------------------------------------------------
+//
+// Clients pick a version with `X-Api-Version: 2` or, where they can't set
+// headers (a link, an <img>), with `?version=2`. When both are usable, the
+// header wins.
+
+use crate::from_any::FromAny;
+use rustapi_openapi::{Operation, OperationModifier, Parameter, SchemaRef};
+use rustapi_rs::prelude::*;
+use rustapi_rs::FromRequestParts;
+
+const HEADER: &str = "x-api-version";
+const QUERY_PARAM: &str = "version";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiVersion {
+    V1,
+    V2,
+}
+
+impl ApiVersion {
+    const VALUES: &'static [&'static str] = &["1", "2"];
+
+    /// `1`/`v1` or `2`/`v2`.
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().trim_start_matches(['v', 'V']) {
+            "1" => Some(Self::V1),
+            "2" => Some(Self::V2),
+            _ => None,
+        }
+    }
+
+    fn invalid(source: &str, value: &str) -> ApiError {
+        ApiError::bad_request(format!(
+            "{source} '{value}' is not a version (expected one of: {})",
+            Self::VALUES.join(", ")
+        ))
+    }
+
+    fn parameter(name: &str, location: &str, description: &str) -> Parameter {
+        Parameter {
+            name: name.to_string(),
+            location: location.to_string(),
+            description: Some(description.to_string()),
+            required: false,
+            deprecated: None,
+            schema: Some(SchemaRef::Inline(
+                serde_json::json!({ "type": "string", "enum": Self::VALUES }),
+            )),
+        }
+    }
+}
+
+/// Header first, then query.
+pub type Version = FromAny<ApiVersion, (VersionHeader, VersionQuery)>;
+
+/// The version from `X-Api-Version`.
+pub struct VersionHeader(pub ApiVersion);
+
+impl FromRequestParts for VersionHeader {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        let value = req
+            .headers()
+            .get(HEADER)
+            .ok_or_else(|| ApiError::bad_request("X-Api-Version header is missing"))?
+            .to_str()
+            .map_err(|_| ApiError::bad_request("X-Api-Version header is not valid text"))?;
+        ApiVersion::parse(value)
+            .map(Self)
+            .ok_or_else(|| ApiVersion::invalid("X-Api-Version header", value))
+    }
+}
+
+impl OperationModifier for VersionHeader {
+    fn update_operation(op: &mut Operation) {
+        op.parameters.push(ApiVersion::parameter(
+            "X-Api-Version",
+            "header",
+            "API version. Takes precedence over `?version=`.",
+        ));
+    }
+}
+
+impl From<VersionHeader> for ApiVersion {
+    fn from(VersionHeader(version): VersionHeader) -> Self {
+        version
+    }
+}
+
+/// The version from `?version=`.
+pub struct VersionQuery(pub ApiVersion);
+
+impl FromRequestParts for VersionQuery {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        let value = req
+            .query_string()
+            .unwrap_or_default()
+            .split('&')
+            .find_map(|pair| pair.strip_prefix(QUERY_PARAM)?.strip_prefix('='))
+            .ok_or_else(|| ApiError::bad_request("version query parameter is missing"))?;
+        ApiVersion::parse(value)
+            .map(Self)
+            .ok_or_else(|| ApiVersion::invalid("version query parameter", value))
+    }
+}
+
+impl OperationModifier for VersionQuery {
+    fn update_operation(op: &mut Operation) {
+        op.parameters.push(ApiVersion::parameter(
+            QUERY_PARAM,
+            "query",
+            "API version, for clients that can't set headers.",
+        ));
+    }
+}
+
+impl From<VersionQuery> for ApiVersion {
+    fn from(VersionQuery(version): VersionQuery) -> Self {
+        version
+    }
+}
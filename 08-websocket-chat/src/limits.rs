@@ -0,0 +1,307 @@
+// ---------------------------------------------------------------------------
+// Connection limits
+// ---------------------------------------------------------------------------
+//
+// A WebSocket holds a task, two buffers and a socket for as long as the
+// client likes. Without a cap, a flood of idle connections exhausts memory
+// and file descriptors. Slots are taken *before* the 101 response, so an
+// over-limit client gets a proper 503 instead of a dropped connection.
+
+use rustapi_openapi::{Operation, OperationModifier};
+use rustapi_rs::prelude::*;
+use rustapi_rs::FromRequestParts;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Whether PeerIp reads X-Forwarded-For. Register it as state; without it,
+/// the header is ignored.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TrustForwardedFor(pub bool);
+
+/// The client address the per-IP limit is keyed on: the connection's peer
+/// address, as src/serve.rs records it.
+///
+/// With `TrustForwardedFor(true)`, the rightmost X-Forwarded-For entry
+/// instead: the address the proxy in front got the connection from. Entries
+/// to its left are whatever the client wrote. Only turn it on when every
+/// connection comes through that proxy, or clients dodge the per-IP cap by
+/// naming a new address each time.
+pub struct PeerIp(pub IpAddr);
+
+impl FromRequestParts for PeerIp {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        let trusted = req
+            .state()
+            .get::<TrustForwardedFor>()
+            .is_some_and(|trust| trust.0);
+        let forwarded = req
+            .headers()
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter(|_| trusted)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .next_back()
+            .and_then(|last| last.trim().parse().ok());
+        forwarded
+            .or_else(|| req.extensions().get::<SocketAddr>().map(SocketAddr::ip))
+            .map(PeerIp)
+            .ok_or_else(|| ApiError::internal("No peer address: serve the app with serve::serve()"))
+    }
+}
+
+impl OperationModifier for PeerIp {
+    fn update_operation(_op: &mut Operation) {}
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectionLimits {
+    /// Open WebSockets across all clients.
+    pub max_total: usize,
+    /// Open WebSockets from a single IP address.
+    pub max_per_ip: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitExceeded {
+    Total,
+    PerIp,
+}
+
+impl From<LimitExceeded> for ApiError {
+    fn from(e: LimitExceeded) -> Self {
+        match e {
+            LimitExceeded::Total => {
+                ApiError::service_unavailable("Too many WebSocket connections, try again later")
+            }
+            LimitExceeded::PerIp => ApiError::service_unavailable(
+                "Too many WebSocket connections from your address, close one and retry",
+            ),
+        }
+    }
+}
+
+/// Current counts, for /ws/stats and health checks.
+#[derive(Debug, Serialize, Schema)]
+pub struct ConnectionStats {
+    pub active: usize,
+    pub max_total: usize,
+    pub max_per_ip: usize,
+    pub distinct_ips: usize,
+    pub rejected_total_limit: u64,
+    pub rejected_per_ip_limit: u64,
+}
+
+#[derive(Default)]
+struct Counts {
+    total: usize,
+    per_ip: HashMap<IpAddr, usize>,
+}
+
+struct Inner {
+    limits: ConnectionLimits,
+    // One lock for both counters: check-and-increment must be atomic across them.
+    counts: Mutex<Counts>,
+    rejected_total: AtomicU64,
+    rejected_per_ip: AtomicU64,
+}
+
+#[derive(Clone)]
+pub struct ConnectionTracker {
+    inner: Arc<Inner>,
+}
+
+impl ConnectionTracker {
+    pub fn new(limits: ConnectionLimits) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                limits,
+                counts: Mutex::new(Counts::default()),
+                rejected_total: AtomicU64::new(0),
+                rejected_per_ip: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Reserves a slot for `ip`. The slot is freed when the permit is dropped,
+    /// so keep it alive for as long as the socket is open.
+    pub fn try_acquire(&self, ip: IpAddr) -> std::result::Result<ConnectionPermit, LimitExceeded> {
+        let limits = self.inner.limits;
+        let mut counts = self.inner.counts.lock().unwrap();
+
+        if counts.total >= limits.max_total {
+            self.inner.rejected_total.fetch_add(1, Ordering::Relaxed);
+            return Err(LimitExceeded::Total);
+        }
+        if counts.per_ip.get(&ip).copied().unwrap_or(0) >= limits.max_per_ip {
+            self.inner.rejected_per_ip.fetch_add(1, Ordering::Relaxed);
+            return Err(LimitExceeded::PerIp);
+        }
+
+        *counts.per_ip.entry(ip).or_insert(0) += 1;
+        counts.total += 1;
+        Ok(ConnectionPermit {
+            tracker: self.clone(),
+            ip,
+        })
+    }
+
+    pub fn stats(&self) -> ConnectionStats {
+        let counts = self.inner.counts.lock().unwrap();
+        ConnectionStats {
+            active: counts.total,
+            max_total: self.inner.limits.max_total,
+            max_per_ip: self.inner.limits.max_per_ip,
+            distinct_ips: counts.per_ip.len(),
+            rejected_total_limit: self.inner.rejected_total.load(Ordering::Relaxed),
+            rejected_per_ip_limit: self.inner.rejected_per_ip.load(Ordering::Relaxed),
+        }
+    }
+
+    fn release(&self, ip: IpAddr) {
+        let mut counts = self.inner.counts.lock().unwrap();
+        counts.total -= 1;
+        if let Some(from_ip) = counts.per_ip.get_mut(&ip) {
+            *from_ip -= 1;
+            if *from_ip == 0 {
+                counts.per_ip.remove(&ip);
+            }
+        }
+    }
+}
+
+/// One open WebSocket's slot.
+pub struct ConnectionPermit {
+    tracker: ConnectionTracker,
+    ip: IpAddr,
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        self.tracker.release(self.ip);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustapi_core::{BodyVariant, PathParams};
+
+    const A: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(192, 0, 2, 1));
+    const B: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(192, 0, 2, 2));
+    const C: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(192, 0, 2, 3));
+
+    fn tracker(max_total: usize, max_per_ip: usize) -> ConnectionTracker {
+        ConnectionTracker::new(ConnectionLimits {
+            max_total,
+            max_per_ip,
+        })
+    }
+
+    #[test]
+    fn per_ip_cap_refuses_that_ip_only() {
+        let tracker = tracker(10, 2);
+        let _first = tracker.try_acquire(A).unwrap();
+        let _second = tracker.try_acquire(A).unwrap();
+
+        assert_eq!(tracker.try_acquire(A).err(), Some(LimitExceeded::PerIp));
+        let _other = tracker.try_acquire(B).unwrap();
+
+        let stats = tracker.stats();
+        assert_eq!((stats.active, stats.distinct_ips), (3, 2));
+        assert_eq!(stats.rejected_per_ip_limit, 1);
+        assert_eq!(stats.rejected_total_limit, 0);
+    }
+
+    #[test]
+    fn total_cap_refuses_everyone() {
+        let tracker = tracker(2, 5);
+        let _a = tracker.try_acquire(A).unwrap();
+        let _b = tracker.try_acquire(B).unwrap();
+
+        // C has no connection yet, but the server is full.
+        assert_eq!(tracker.try_acquire(C).err(), Some(LimitExceeded::Total));
+        assert_eq!(tracker.try_acquire(A).err(), Some(LimitExceeded::Total));
+        assert_eq!(tracker.stats().rejected_total_limit, 2);
+    }
+
+    #[test]
+    fn dropping_the_last_permit_forgets_the_ip() {
+        let tracker = tracker(3, 1);
+        let first = tracker.try_acquire(A).unwrap();
+        let second = tracker.try_acquire(B).unwrap();
+        assert_eq!(tracker.try_acquire(A).err(), Some(LimitExceeded::PerIp));
+
+        drop(first);
+        let stats = tracker.stats();
+        assert_eq!((stats.active, stats.distinct_ips), (1, 1));
+
+        // The slot, and A's own allowance, are free again.
+        let _again = tracker.try_acquire(A).unwrap();
+        drop(second);
+        assert_eq!(tracker.stats().distinct_ips, 1);
+    }
+
+    #[test]
+    fn permits_of_one_ip_share_its_entry() {
+        let tracker = tracker(5, 3);
+        let permits: Vec<_> = (0..3).map(|_| tracker.try_acquire(A).unwrap()).collect();
+        assert_eq!(tracker.stats().distinct_ips, 1);
+
+        drop(permits);
+        let stats = tracker.stats();
+        assert_eq!((stats.active, stats.distinct_ips), (0, 0));
+    }
+
+    fn peer_ip(
+        trust: Option<bool>,
+        forwarded: Option<&str>,
+        peer: Option<IpAddr>,
+    ) -> Result<IpAddr> {
+        let mut builder = http::Request::get("/ws");
+        if let Some(forwarded) = forwarded {
+            builder = builder.header("x-forwarded-for", forwarded);
+        }
+        let (mut parts, ()) = builder.body(()).unwrap().into_parts();
+        if let Some(peer) = peer {
+            parts.extensions.insert(SocketAddr::new(peer, 40000));
+        }
+        let mut state = http::Extensions::new();
+        if let Some(trust) = trust {
+            state.insert(TrustForwardedFor(trust));
+        }
+        let req = Request::new(
+            parts,
+            BodyVariant::Buffered(Default::default()),
+            Arc::new(state),
+            PathParams::new(),
+        );
+        PeerIp::from_request_parts(&req).map(|PeerIp(ip)| ip)
+    }
+
+    #[test]
+    fn forwarded_for_is_ignored_unless_trusted() {
+        let forwarded = Some("198.51.100.7, 192.0.2.3");
+        assert_eq!(peer_ip(None, forwarded, Some(A)).unwrap(), A);
+        assert_eq!(peer_ip(Some(false), forwarded, Some(A)).unwrap(), A);
+        assert_eq!(peer_ip(None, None, Some(A)).unwrap(), A);
+    }
+
+    #[test]
+    fn trusted_forwarded_for_gives_the_rightmost_entry() {
+        // The entry the proxy appended, not the one the client wrote.
+        let forwarded = Some("198.51.100.7, 192.0.2.3");
+        assert_eq!(peer_ip(Some(true), forwarded, Some(A)).unwrap(), C);
+        // Nothing usable in it: the peer address.
+        assert_eq!(peer_ip(Some(true), Some("unknown"), Some(A)).unwrap(), A);
+        assert_eq!(peer_ip(Some(true), None, Some(A)).unwrap(), A);
+    }
+
+    #[test]
+    fn no_peer_address_is_a_500() {
+        let err = peer_ip(None, None, None).unwrap_err();
+        assert_eq!(err.status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -0,0 +1,382 @@
+// Run with: cargo run -p websocket-chat
+// Then open: http://127.0.0.1:3000/          (chat page, open it in two tabs)
+//            http://127.0.0.1:3000/ws/stats  (connection counts)
+//
+// Lesson: a WebSocket is a long-lived resource. Cap how many a server — and
+//         each client — may hold, and refuse extra upgrades with a clean 503
+//         before switching protocols.
+//         A client that reconnects with `?last_event_id=N` gets what it
+//         missed replayed first (src/replay.rs).
+//         Named rooms (`/ws/rooms/{room}`) each get their own broadcast
+//         channel; a client that falls behind skips the oldest messages
+//         (src/rooms.rs).
+//         Idle sockets are pinged, and closed when the client stops
+//         answering (src/ws.rs).
+
+mod limits;
+mod replay;
+mod rooms;
+mod serve;
+mod ws;
+
+use limits::{ConnectionLimits, ConnectionStats, ConnectionTracker, PeerIp, TrustForwardedFor};
+use replay::{EventLog, Logged};
+use rooms::Rooms;
+use rustapi_rs::prelude::*;
+use rustapi_rs::{description, get, summary, tag};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use ws::{close_code, Message, WebSocketUpgrade, KEEP_ALIVE};
+
+// ---------------------------------------------------------------------------
+// State
+// ---------------------------------------------------------------------------
+
+const LIMITS: ConnectionLimits = ConnectionLimits {
+    max_total: 1_000,
+    max_per_ip: 5,
+};
+
+/// Messages kept for clients that reconnect; override with REPLAY_BUFFER.
+const REPLAY_BUFFER: usize = 256;
+
+/// How far a client in a room may fall behind before it skips messages.
+const ROOM_BUFFER: usize = 64;
+
+/// Longest room name; names are `a-z`, `0-9`, `-` and `_`.
+const MAX_ROOM_NAME: usize = 32;
+
+/// The subprotocol the chat page asks for.
+const CHAT_PROTOCOL: &str = "chat.v1";
+
+#[derive(Clone)]
+struct ChatState {
+    tracker: ConnectionTracker,
+    messages: EventLog<String>,
+    /// Named rooms: live only, no replay.
+    rooms: Rooms<String>,
+    next_user: Arc<AtomicU64>,
+    /// How long a socket may be silent before it is pinged.
+    keep_alive: Duration,
+}
+
+#[derive(Debug, Deserialize, Schema)]
+struct Resume {
+    /// Id of the last message this client received, to replay what came after.
+    last_event_id: Option<u64>,
+}
+
+/// `{"id":42,"text":"user-1: hi"}` — the id is what a client resumes from.
+fn frame(message: &Logged<String>) -> String {
+    serde_json::json!({ "id": message.id, "text": message.event }).to_string()
+}
+
+// ---------------------------------------------------------------------------
+// Handlers
+// ---------------------------------------------------------------------------
+
+#[get("/ws")]
+#[tag("chat")]
+#[summary("Join the chat")]
+#[description(
+    "WebSocket endpoint. Every text message is broadcast to all connected clients \
+     as `{\"id\":42,\"text\":\"...\"}`. Reconnect with `?last_event_id=42` to get \
+     the messages sent since; `{\"gap\":true}` first means some were too old to keep. \
+     Answers 503 when the global or per-IP connection limit is reached. \
+     Speaks the `chat.v1` subprotocol."
+)]
+async fn join(
+    State(state): State<ChatState>,
+    PeerIp(ip): PeerIp,
+    Query(resume): Query<Resume>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    // Reserve the slot first: rejection is still a normal HTTP response here.
+    let permit = state.tracker.try_acquire(ip)?;
+    let user = state.next_user.fetch_add(1, Ordering::Relaxed);
+
+    let ws = ws
+        .protocols(&[CHAT_PROTOCOL])
+        .keep_alive(Some(state.keep_alive));
+    Ok(ws.on_upgrade(move |socket| async move {
+        // Held until this task ends, i.e. until the socket is gone.
+        let _permit = permit;
+        let (sender, mut receiver) = socket.split();
+        let subscription = state.messages.subscribe(resume.last_event_id);
+        let mut feed = subscription.live;
+
+        if subscription.gap {
+            let _ = sender.send_text(r#"{"gap":true}"#).await;
+        }
+        for missed in &subscription.replay {
+            if sender.send_text(frame(missed)).await.is_err() {
+                return;
+            }
+        }
+
+        state.messages.publish(format!("user-{user} joined"));
+
+        loop {
+            tokio::select! {
+                incoming = receiver.recv() => match incoming {
+                    Some(Message::Text(text)) => {
+                        state.messages.publish(format!("user-{user}: {text}"));
+                    }
+                    Some(Message::Close(_)) | None => break,
+                    Some(_) => {}
+                },
+                outgoing = feed.recv() => match outgoing {
+                    Ok(message) => {
+                        if sender.send_text(frame(&message)).await.is_err() {
+                            break;
+                        }
+                    }
+                    // Too slow to keep up with the room: drop the client
+                    // rather than buffer without bound. It can reconnect and
+                    // resume from the log.
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        let _ = sender.close(close_code::GOING_AWAY, "too slow").await;
+                        break;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+            }
+        }
+
+        state.messages.publish(format!("user-{user} left"));
+    }))
+}
+
+/// `{"room":"rust","text":"user-1: hi"}`
+fn room_frame(room: &str, text: &str) -> String {
+    serde_json::json!({ "room": room, "text": text }).to_string()
+}
+
+fn valid_room(room: &str) -> bool {
+    (1..=MAX_ROOM_NAME).contains(&room.len())
+        && room
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_')
+}
+
+#[get("/ws/rooms/{room}")]
+#[tag("chat")]
+#[summary("Join a named room")]
+#[description(
+    "WebSocket endpoint. Text messages go to the clients in the same room only, \
+     as `{\"room\":\"rust\",\"text\":\"...\"}`. Room names are 1 to 32 of \
+     `a-z`, `0-9`, `-` and `_` (else 400). No replay: a client that falls \
+     behind skips the oldest messages and is told `{\"skipped\":N}`. \
+     Counts against the same connection limits as `/ws`."
+)]
+async fn join_room(
+    State(state): State<ChatState>,
+    PeerIp(ip): PeerIp,
+    Path(room): Path<String>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    if !valid_room(&room) {
+        return Err(ApiError::bad_request(format!(
+            "Room names are 1 to {} of a-z, 0-9, - and _",
+            MAX_ROOM_NAME
+        )));
+    }
+    let permit = state.tracker.try_acquire(ip)?;
+    let user = state.next_user.fetch_add(1, Ordering::Relaxed);
+
+    let ws = ws
+        .protocols(&[CHAT_PROTOCOL])
+        .keep_alive(Some(state.keep_alive));
+    Ok(ws.on_upgrade(move |socket| async move {
+        let _permit = permit;
+        let (sender, mut receiver) = socket.split();
+        let mut feed = state.rooms.join(&room);
+        let mut skipped = 0;
+        state
+            .rooms
+            .send(&room, room_frame(&room, &format!("user-{user} joined")));
+
+        loop {
+            tokio::select! {
+                incoming = receiver.recv() => match incoming {
+                    Some(Message::Text(text)) => {
+                        state.rooms.send(&room, room_frame(&room, &format!("user-{user}: {text}")));
+                    }
+                    Some(Message::Close(_)) | None => break,
+                    Some(_) => {}
+                },
+                outgoing = feed.recv() => {
+                    let Some(message) = outgoing else { break };
+                    // Fell behind: say how much is missing, then carry on.
+                    if feed.skipped() > skipped {
+                        let gap = serde_json::json!({ "skipped": feed.skipped() - skipped });
+                        skipped = feed.skipped();
+                        if sender.send_text(gap.to_string()).await.is_err() {
+                            break;
+                        }
+                    }
+                    if sender.send_text(message).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        drop(feed);
+        state
+            .rooms
+            .send(&room, room_frame(&room, &format!("user-{user} left")));
+    }))
+}
+
+#[derive(Debug, Serialize, Schema)]
+struct RoomStats {
+    room: String,
+    clients: usize,
+}
+
+#[get("/ws/rooms")]
+#[tag("chat")]
+#[summary("Rooms with someone in them")]
+#[description("Each room and how many clients are in it, by name.")]
+async fn rooms(State(state): State<ChatState>) -> Json<Vec<RoomStats>> {
+    Json(
+        state
+            .rooms
+            .occupancy()
+            .into_iter()
+            .map(|(room, clients)| RoomStats { room, clients })
+            .collect(),
+    )
+}
+
+#[get("/ws/echo")]
+#[tag("chat")]
+#[summary("Echo every message back")]
+#[description(
+    "WebSocket endpoint that sends back each text or binary message. With the \
+     `echo.upper` subprotocol, text comes back in upper case. Not counted against \
+     the chat's connection limits."
+)]
+async fn echo(ws: WebSocketUpgrade) -> Response {
+    ws.protocols(&["echo.upper", "echo"])
+        .on_upgrade(|mut socket| async move {
+            let upper = socket.protocol() == Some("echo.upper");
+            while let Some(message) = socket.recv().await {
+                let reply = match message {
+                    Message::Text(text) if upper => Message::Text(text.to_uppercase()),
+                    Message::Text(_) | Message::Binary(_) => message,
+                    Message::Ping(_) | Message::Pong(_) | Message::Close(_) => continue,
+                };
+                if socket.send(reply).await.is_err() {
+                    break;
+                }
+            }
+        })
+}
+
+#[get("/ws/stats")]
+#[tag("chat")]
+#[summary("Connection counts")]
+#[description("Open WebSockets, configured limits and how many upgrades were refused.")]
+async fn stats(State(state): State<ChatState>) -> Json<ConnectionStats> {
+    Json(state.tracker.stats())
+}
+
+#[get("/")]
+async fn index() -> Html<&'static str> {
+    Html(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>WebSocket Chat</title></head>
+<body>
+  <h1>WebSocket Chat</h1>
+  <form id="form"><input id="text" autocomplete="off" autofocus> <button>Send</button></form>
+  <ul id="log"></ul>
+  <script>
+    const log = text => {
+      const li = document.createElement('li');
+      li.textContent = text;
+      document.getElementById('log').appendChild(li);
+    };
+    // `/?room=rust` joins that room instead of the lobby.
+    const room = new URLSearchParams(location.search).get('room');
+    if (room) document.querySelector('h1').textContent += ` — ${room}`;
+    let ws, lastId = null;
+    const connect = () => {
+      const resume = lastId === null ? '' : `?last_event_id=${lastId}`;
+      const path = room ? `/ws/rooms/${encodeURIComponent(room)}` : `/ws${resume}`;
+      ws = new WebSocket(`ws://${location.host}${path}`, ['chat.v1']);
+      ws.onmessage = e => {
+        const msg = JSON.parse(e.data);
+        if (msg.gap) return log('(some messages were missed)');
+        if (msg.skipped) return log(`(${msg.skipped} messages skipped)`);
+        lastId = msg.id;
+        log(msg.text);
+      };
+      ws.onclose = e => {
+        log(`disconnected (${e.code}), reconnecting…`);
+        setTimeout(connect, 1000);
+      };
+    };
+    connect();
+    document.getElementById('form').onsubmit = e => {
+      e.preventDefault();
+      const input = document.getElementById('text');
+      ws.send(input.value);
+      input.value = '';
+    };
+  </script>
+</body>
+</html>"#,
+    )
+}
+
+// ---------------------------------------------------------------------------
+// Entry point
+// ---------------------------------------------------------------------------
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let replay_buffer = std::env::var("REPLAY_BUFFER")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(REPLAY_BUFFER);
+    let keep_alive = std::env::var("PING_INTERVAL_SECS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(KEEP_ALIVE);
+    // Only behind a proxy that every connection goes through.
+    let trust_forwarded_for =
+        std::env::var("TRUST_FORWARDED_FOR").is_ok_and(|value| value == "true");
+    let state = ChatState {
+        tracker: ConnectionTracker::new(LIMITS),
+        messages: EventLog::new(replay_buffer),
+        rooms: Rooms::new(ROOM_BUFFER),
+        next_user: Arc::new(AtomicU64::new(1)),
+        keep_alive,
+    };
+
+    println!("Starting websocket-chat example…");
+    println!(" -> GET http://127.0.0.1:3000/          (chat page; /?room=rust for a room)");
+    println!(" -> WS  ws://127.0.0.1:3000/ws[?last_event_id=N]");
+    println!("    replay buffer: {} messages", state.messages.capacity());
+    println!("    idle sockets pinged after {:?}", state.keep_alive);
+    println!(" -> WS  ws://127.0.0.1:3000/ws/echo     (subprotocols: echo.upper, echo)");
+    println!(" -> WS  ws://127.0.0.1:3000/ws/rooms/{{room}}");
+    println!(" -> GET http://127.0.0.1:3000/ws/rooms    (rooms in use)");
+    println!(" -> GET http://127.0.0.1:3000/ws/stats");
+    println!(" -> GET http://127.0.0.1:3000/docs");
+
+    // serve() keeps each connection's address for the per-IP limit. Unlike
+    // run(), it adds no body limit, so the app carries its own.
+    let app = RustApi::auto()
+        .layer(BodyLimitLayer::new(rustapi_core::DEFAULT_BODY_LIMIT))
+        .state(state)
+        .state(TrustForwardedFor(trust_forwarded_for))
+        .dashboard(DashboardConfig::new());
+    serve::serve(app, "127.0.0.1:3000").await
+}
@@ -0,0 +1,137 @@
+// ---------------------------------------------------------------------------
+// Resumable subscriptions
+// ---------------------------------------------------------------------------
+//
+// A broadcast channel only reaches whoever is subscribed when a message is
+// sent. A client that drops for two seconds — a phone switching networks, a
+// tab put to sleep — misses whatever was said meanwhile, and nothing tells
+// it so.
+//
+// EventLog numbers every event and keeps the last `capacity` of them. On
+// reconnect the client says which id it saw last, and subscribe() returns
+// what came after it plus a receiver for what comes next:
+//
+//   seen 41, log holds 30..=45  →  replays 42..=45, then live from 46
+//   seen 12, log holds 30..=45  →  replays 30..=45, gap: 13..=29 are gone
+//   nothing seen (first visit)  →  live only
+//
+// An id the log has never issued (a server restart resets the counter) is
+// treated like an evicted one: everything buffered, flagged as a gap.
+//
+// NOTE: publish() sends while holding the lock that subscribe() takes, so an
+//       event is either in the replay or on the receiver — never both, never
+//       neither.
+//
+// NOTE: copied from 04-sse-stream/src/replay.rs, as examples don't share
+//       code. That copy adds what SSE needs and this one leaves out: Logged
+//       derives Serialize (here frame() in main.rs writes the JSON) and a
+//       Last-Event-ID extractor (a browser WebSocket can't send headers, so
+//       the id comes as `?last_event_id=`). The log itself is the same in
+//       both; a fix to one belongs in the other.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+/// An event and its position in the log. Ids start at 1 and never repeat.
+#[derive(Debug, Clone)]
+pub struct Logged<T> {
+    pub id: u64,
+    pub event: T,
+}
+
+struct Log<T> {
+    next_id: u64,
+    events: VecDeque<Logged<T>>,
+}
+
+/// A broadcast channel that remembers its last `capacity` events.
+#[derive(Clone)]
+pub struct EventLog<T> {
+    log: Arc<Mutex<Log<T>>>,
+    live: broadcast::Sender<Logged<T>>,
+    capacity: usize,
+}
+
+/// Where a subscriber starts: the events it missed, then the live feed.
+pub struct Subscription<T> {
+    pub replay: Vec<Logged<T>>,
+    /// Some events after the client's last id are no longer buffered; the
+    /// client should refetch whatever state it derives from the stream.
+    pub gap: bool,
+    pub live: broadcast::Receiver<Logged<T>>,
+}
+
+impl<T: Clone> EventLog<T> {
+    /// Keeps the last `capacity` events for replay. Live subscribers may
+    /// fall up to as many events behind before they lag.
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        let (live, _) = broadcast::channel(capacity);
+        Self {
+            log: Arc::new(Mutex::new(Log {
+                next_id: 1,
+                events: VecDeque::with_capacity(capacity),
+            })),
+            live,
+            capacity,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Appends `event`, evicting the oldest once full, and sends it to every
+    /// live subscriber. Returns its id.
+    pub fn publish(&self, event: T) -> u64 {
+        let mut log = self.log.lock().expect("event log poisoned");
+        let logged = Logged {
+            id: log.next_id,
+            event,
+        };
+        log.next_id += 1;
+        if log.events.len() == self.capacity {
+            log.events.pop_front();
+        }
+        log.events.push_back(logged.clone());
+        // No subscribers is fine: the log still has it.
+        let _ = self.live.send(logged.clone());
+        logged.id
+    }
+
+    /// Subscribes, replaying everything after `last_seen` that is still
+    /// buffered. `None` means a fresh client: live events only.
+    pub fn subscribe(&self, last_seen: Option<u64>) -> Subscription<T> {
+        let log = self.log.lock().expect("event log poisoned");
+        let live = self.live.subscribe();
+        let Some(last_seen) = last_seen else {
+            return Subscription {
+                replay: Vec::new(),
+                gap: false,
+                live,
+            };
+        };
+
+        let newest = log.next_id - 1;
+        let oldest = log.events.front().map_or(log.next_id, |logged| logged.id);
+        if last_seen > newest {
+            // Not an id we issued.
+            return Subscription {
+                replay: log.events.iter().cloned().collect(),
+                gap: true,
+                live,
+            };
+        }
+        Subscription {
+            replay: log
+                .events
+                .iter()
+                .filter(|logged| logged.id > last_seen)
+                .cloned()
+                .collect(),
+            gap: last_seen + 1 < oldest,
+            live,
+        }
+    }
+}
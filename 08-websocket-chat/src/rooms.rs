@@ -0,0 +1,156 @@
+// ---------------------------------------------------------------------------
+// Broadcast<T> and Rooms<T>
+// ---------------------------------------------------------------------------
+//
+// One sender, every subscriber gets a copy. Broadcast<T> is
+// `tokio::sync::broadcast` with the lag handling decided once, here; Rooms<T>
+// is one Broadcast per name, so a client only hears its own room:
+//
+//   RustApi::auto().state(Broadcast::<Notice>::new(64)).state(Rooms::<String>::new(64))
+//
+//   let mut feed = rooms.join("rust");        // a Subscriber<String>
+//   rooms.send("rust", "hi".to_string());     // everyone in "rust", not "go"
+//   while let Some(message) = feed.recv().await { .. }
+//
+// Each subscriber may fall `capacity` messages behind. Past that:
+//
+//   Subscriber::recv()   skips the oldest it missed, logs a warning, and
+//                        carries on from the oldest still buffered; skipped()
+//                        counts them, so a handler can tell its client
+//   EventLog (replay.rs) the lobby's: a lagging client is closed instead,
+//                        since it can resume from its last id
+//
+// A room exists while someone is in it: join() creates it, and rooms nobody
+// has been in since the last join() are swept then. Messages to a room
+// without subscribers are dropped, as on any broadcast channel.
+//
+// NOTE: room names are the handler's to check. Every distinct name costs a
+//       channel of `capacity` slots while it is in use.
+
+use rustapi_rs::prelude::warn;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+/// A channel every subscriber gets each message of. Clones send on the same
+/// channel; register one as state to share it between handlers.
+pub struct Broadcast<T> {
+    sender: broadcast::Sender<T>,
+}
+
+impl<T> Clone for Broadcast<T> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<T: Clone> Broadcast<T> {
+    /// Subscribers may fall `capacity` messages behind before they skip.
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        Self { sender }
+    }
+
+    /// Sends `message` to everyone subscribed now; returns how many that is.
+    pub fn send(&self, message: T) -> usize {
+        self.sender.send(message).unwrap_or(0)
+    }
+
+    /// Messages sent from now on.
+    pub fn subscribe(&self) -> Subscriber<T> {
+        Subscriber {
+            receiver: self.sender.subscribe(),
+            skipped: 0,
+        }
+    }
+
+    pub fn subscribers(&self) -> usize {
+        self.sender.receiver_count()
+    }
+}
+
+/// One subscriber's end of a Broadcast.
+pub struct Subscriber<T> {
+    receiver: broadcast::Receiver<T>,
+    skipped: u64,
+}
+
+impl<T: Clone> Subscriber<T> {
+    /// The next message, or None once every sender is gone. A subscriber
+    /// that fell behind skips the oldest messages it missed.
+    pub async fn recv(&mut self) -> Option<T> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(message) => return Some(message),
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    self.skipped += missed;
+                    warn!(
+                        "Broadcast subscriber fell behind: skipped the oldest {missed} message(s)"
+                    );
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Messages this subscriber skipped for falling behind, so far.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+}
+
+/// A Broadcast per room name. Clones share the rooms.
+pub struct Rooms<T> {
+    rooms: Arc<Mutex<HashMap<String, Broadcast<T>>>>,
+    capacity: usize,
+}
+
+impl<T> Clone for Rooms<T> {
+    fn clone(&self) -> Self {
+        Self {
+            rooms: self.rooms.clone(),
+            capacity: self.capacity,
+        }
+    }
+}
+
+impl<T: Clone> Rooms<T> {
+    /// Each room buffers `capacity` messages per subscriber, like
+    /// Broadcast::new.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            rooms: Arc::new(Mutex::new(HashMap::new())),
+            capacity,
+        }
+    }
+
+    /// Subscribes to `room`, creating it if nobody is in it.
+    pub fn join(&self, room: &str) -> Subscriber<T> {
+        let mut rooms = self.rooms.lock().unwrap_or_else(|e| e.into_inner());
+        rooms.retain(|_, channel| channel.subscribers() > 0);
+        rooms
+            .entry(room.to_string())
+            .or_insert_with(|| Broadcast::new(self.capacity))
+            .subscribe()
+    }
+
+    /// Sends `message` to everyone in `room`; returns how many that is.
+    pub fn send(&self, room: &str, message: T) -> usize {
+        let rooms = self.rooms.lock().unwrap_or_else(|e| e.into_inner());
+        rooms.get(room).map_or(0, |channel| channel.send(message))
+    }
+
+    /// Every room someone is in, and how many are in it, by name.
+    pub fn occupancy(&self) -> Vec<(String, usize)> {
+        let rooms = self.rooms.lock().unwrap_or_else(|e| e.into_inner());
+        let mut occupancy: Vec<(String, usize)> = rooms
+            .iter()
+            .map(|(name, channel)| (name.clone(), channel.subscribers()))
+            .filter(|(_, subscribers)| *subscribers > 0)
+            .collect();
+        occupancy.sort();
+        occupancy
+    }
+}
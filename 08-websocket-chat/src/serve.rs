@@ -0,0 +1,77 @@
+// ---------------------------------------------------------------------------
+// Accept loop that keeps the peer address
+// ---------------------------------------------------------------------------
+//
+// The per-IP limit needs the address each connection comes from. RustApi::run()
+// accepts it but doesn't hand it on, so this drives the same pipeline through
+// a RequestDispatcher (as 06-custom-server does) and puts the peer address in
+// every request's extensions, where PeerIp finds it:
+//
+//   serve(app, "127.0.0.1:3000").await
+//
+// Connections are HTTP/1 with upgrades on, so WebSocketUpgrade works as it
+// does under run().
+//
+// NOTE: run() adds a body limit layer of its own; the dispatcher doesn't.
+//       Add BodyLimitLayer to the app before serving it.
+
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper_util::rt::TokioIo;
+use rustapi_core::{BodyVariant, PathParams};
+use rustapi_rs::prelude::*;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+
+/// Serves `app` on `addr` until the process ends.
+pub async fn serve(
+    app: RustApi,
+    addr: &str,
+) -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let listener = TcpListener::bind(addr).await?;
+    let dispatcher = app.request_dispatcher();
+    info!("Listening on http://{}", listener.local_addr()?);
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // EMFILE and friends are transient — keep accepting.
+                error!("Accept error: {}", e);
+                continue;
+            }
+        };
+        let _ = stream.set_nodelay(true);
+
+        let dispatcher = dispatcher.clone();
+        let service = service_fn(move |req: hyper::Request<Incoming>| {
+            let dispatcher = dispatcher.clone();
+            async move {
+                let (mut parts, body) = req.into_parts();
+                parts.extensions.insert::<SocketAddr>(peer);
+                let request = Request::new(
+                    parts,
+                    BodyVariant::Streaming(body),
+                    dispatcher.state_ref(),
+                    PathParams::new(),
+                );
+                Ok::<_, Infallible>(dispatcher.dispatch(request).await)
+            }
+        });
+
+        tokio::spawn(async move {
+            if let Err(err) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .with_upgrades()
+                .await
+            {
+                // Client disconnects are not worth logging.
+                if !err.is_incomplete_message() {
+                    error!("Connection error: {}", err);
+                }
+            }
+        });
+    }
+}
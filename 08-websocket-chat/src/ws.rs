@@ -0,0 +1,960 @@
+// ---------------------------------------------------------------------------
+// WebSocket (RFC 6455), just enough for a chat
+// ---------------------------------------------------------------------------
+//
+// RustAPI's server already drives hyper with upgrades enabled, so a handler
+// can answer `101 Switching Protocols` and take the raw connection over.
+// This module does the handshake and the frame codec on top of that:
+//
+//   async fn ws(ws: WebSocketUpgrade) -> Response {
+//       ws.protocols(&["chat.v1"]).on_upgrade(|mut socket| async move {
+//           while let Some(message) = socket.recv().await {
+//               let _ = socket.send(message).await;   // echo
+//           }
+//       })
+//   }
+//
+// Split the socket to read and write from different tasks.
+//
+// Supported: text/binary messages, fragmentation, ping/pong, close,
+// subprotocols, keep-alive.
+// Not supported: extensions (permessage-deflate).
+//
+//   subprotocol   the first of `protocols` the client offered in
+//                 Sec-WebSocket-Protocol, named in the 101 and by
+//                 socket.protocol(). None in common: the 101 names none, and
+//                 a browser that asked for one fails the connection itself.
+//   keep-alive    nothing read for `keep_alive` (default 30 s): a ping.
+//                 Still nothing a `keep_alive` later: closed with 1001 and
+//                 recv() returns None. Any frame counts, so a busy
+//                 connection is never pinged.
+
+use base64::Engine;
+use http::header;
+use hyper::upgrade::{OnUpgrade, Upgraded};
+use hyper_util::rt::TokioIo;
+use rustapi_openapi::{Operation, OperationModifier};
+use rustapi_rs::prelude::*;
+use rustapi_rs::{FromRequest, ResponseBody};
+use std::future::Future;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::sync::{Mutex, Notify};
+use tokio::time::Instant;
+
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Largest message we'll buffer; bigger ones are closed with 1009.
+pub const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// How long a connection may be silent before it is pinged.
+pub const KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+// ---------------------------------------------------------------------------
+// Handshake
+// ---------------------------------------------------------------------------
+
+/// Extractor for a WebSocket handshake request.
+///
+/// Rejects anything that isn't a valid version-13 upgrade with 400, so the
+/// handler only runs for real WebSocket clients.
+pub struct WebSocketUpgrade {
+    accept_key: String,
+    on_upgrade: OnUpgrade,
+    /// Sec-WebSocket-Protocol, in the client's order.
+    offered: Vec<String>,
+    protocol: Option<&'static str>,
+    keep_alive: Option<Duration>,
+}
+
+/// Sec-WebSocket-Accept for a client's Sec-WebSocket-Key (RFC 6455 §4.2.2).
+fn accept_key(key: &[u8]) -> String {
+    let digest = ring::digest::digest(
+        &ring::digest::SHA1_FOR_LEGACY_USE_ONLY,
+        [key, ACCEPT_GUID.as_bytes()].concat().as_slice(),
+    );
+    base64::engine::general_purpose::STANDARD.encode(digest.as_ref())
+}
+
+fn header_has_token(req: &Request, name: header::HeaderName, token: &str) -> bool {
+    req.headers().get_all(name).iter().any(|value| {
+        value
+            .to_str()
+            .map(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+            .unwrap_or(false)
+    })
+}
+
+impl FromRequest for WebSocketUpgrade {
+    async fn from_request(req: &mut Request) -> Result<Self> {
+        if req.method() != http::Method::GET
+            || !header_has_token(req, header::CONNECTION, "upgrade")
+            || !header_has_token(req, header::UPGRADE, "websocket")
+        {
+            return Err(ApiError::bad_request(
+                "Expected a WebSocket upgrade request",
+            ));
+        }
+
+        if req
+            .headers()
+            .get(header::SEC_WEBSOCKET_VERSION)
+            .is_none_or(|v| v != "13")
+        {
+            return Err(ApiError::bad_request("Unsupported Sec-WebSocket-Version"));
+        }
+
+        let key = req
+            .headers()
+            .get(header::SEC_WEBSOCKET_KEY)
+            .ok_or_else(|| ApiError::bad_request("Missing Sec-WebSocket-Key"))?;
+
+        let accept_key = accept_key(key.as_bytes());
+
+        let on_upgrade = req
+            .extensions_mut()
+            .remove::<OnUpgrade>()
+            .ok_or_else(|| ApiError::internal("Connection cannot be upgraded"))?;
+
+        let offered = req
+            .headers()
+            .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|protocol| protocol.trim().to_string())
+            .filter(|protocol| !protocol.is_empty())
+            .collect();
+
+        Ok(Self {
+            accept_key,
+            on_upgrade,
+            offered,
+            protocol: None,
+            keep_alive: Some(KEEP_ALIVE),
+        })
+    }
+}
+
+impl OperationModifier for WebSocketUpgrade {
+    fn update_operation(_op: &mut Operation) {}
+}
+
+impl WebSocketUpgrade {
+    /// The subprotocols this endpoint speaks, preferred first. The first one
+    /// the client offered is chosen; names are case-sensitive.
+    pub fn protocols(mut self, supported: &[&'static str]) -> Self {
+        self.protocol = supported
+            .iter()
+            .copied()
+            .find(|protocol| self.offered.iter().any(|offered| offered == protocol));
+        self
+    }
+
+    /// Ping after this long without a frame from the client, and close a
+    /// second interval later; None never pings. Default: KEEP_ALIVE.
+    pub fn keep_alive(mut self, every: Option<Duration>) -> Self {
+        self.keep_alive = every;
+        self
+    }
+
+    /// Answers 101 and runs `callback` with the socket once hyper hands the
+    /// connection over. The callback runs on its own task.
+    pub fn on_upgrade<F, Fut>(self, callback: F) -> Response
+    where
+        F: FnOnce(WebSocket) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let (on_upgrade, protocol, keep_alive) = (self.on_upgrade, self.protocol, self.keep_alive);
+        tokio::spawn(async move {
+            match on_upgrade.await {
+                Ok(upgraded) => callback(WebSocket::new(upgraded, protocol, keep_alive)).await,
+                Err(e) => error!("WebSocket upgrade failed: {}", e),
+            }
+        });
+
+        let mut response = http::Response::builder()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header(header::CONNECTION, "upgrade")
+            .header(header::UPGRADE, "websocket")
+            .header(header::SEC_WEBSOCKET_ACCEPT, self.accept_key);
+        if let Some(protocol) = protocol {
+            response = response.header(header::SEC_WEBSOCKET_PROTOCOL, protocol);
+        }
+        response.body(ResponseBody::empty()).unwrap()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Messages and frames
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    /// A received ping is answered automatically, then passed on; a sent
+    /// one asks the peer for a pong. At most 125 bytes.
+    Ping(Vec<u8>),
+    /// At most 125 bytes.
+    Pong(Vec<u8>),
+    /// Close code and reason, if the peer sent one.
+    Close(Option<(u16, String)>),
+}
+
+mod opcode {
+    pub const CONTINUATION: u8 = 0x0;
+    pub const TEXT: u8 = 0x1;
+    pub const BINARY: u8 = 0x2;
+    pub const CLOSE: u8 = 0x8;
+    pub const PING: u8 = 0x9;
+    pub const PONG: u8 = 0xA;
+}
+
+/// Close codes we send (RFC 6455 §7.4.1).
+pub mod close_code {
+    pub const GOING_AWAY: u16 = 1001;
+    pub const PROTOCOL_ERROR: u16 = 1002;
+    pub const INVALID_DATA: u16 = 1007;
+    pub const TOO_BIG: u16 = 1009;
+}
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+type Io = TokioIo<Upgraded>;
+
+/// The first frame in `buf`, and how many bytes it took; None until all of
+/// it has arrived.
+fn parse_frame(buf: &[u8]) -> Result<Option<(Frame, usize)>, u16> {
+    let [first, second, ..] = *buf else {
+        return Ok(None);
+    };
+    let fin = first & 0x80 != 0;
+    let opcode = first & 0x0F;
+    let masked = second & 0x80 != 0;
+
+    // No extensions are negotiated, so RSV bits must be zero; clients must mask.
+    if first & 0x70 != 0 || !masked {
+        return Err(close_code::PROTOCOL_ERROR);
+    }
+
+    let (len, mut at) = match second & 0x7F {
+        126 if buf.len() >= 4 => (u16::from_be_bytes([buf[2], buf[3]]) as u64, 4),
+        127 if buf.len() >= 10 => (u64::from_be_bytes(buf[2..10].try_into().unwrap()), 10),
+        126 | 127 => return Ok(None),
+        n => (n as u64, 2),
+    };
+
+    let is_control = opcode & 0x8 != 0;
+    if is_control && (len > 125 || !fin) {
+        return Err(close_code::PROTOCOL_ERROR);
+    }
+    if len > MAX_MESSAGE_SIZE as u64 {
+        return Err(close_code::TOO_BIG);
+    }
+
+    let len = len as usize;
+    if buf.len() < at + 4 + len {
+        return Ok(None);
+    }
+    let mask = [buf[at], buf[at + 1], buf[at + 2], buf[at + 3]];
+    at += 4;
+    let payload = buf[at..at + len]
+        .iter()
+        .enumerate()
+        .map(|(i, byte)| byte ^ mask[i % 4])
+        .collect();
+
+    Ok(Some((
+        Frame {
+            fin,
+            opcode,
+            payload,
+        },
+        at + len,
+    )))
+}
+
+/// The next frame, reading into `buf` until it has one. Cancel-safe: what
+/// was read stays in `buf` for the next call.
+async fn read_frame(io: &mut ReadHalf<Io>, buf: &mut Vec<u8>) -> Result<Frame, u16> {
+    loop {
+        if let Some((frame, used)) = parse_frame(buf)? {
+            buf.drain(..used);
+            return Ok(frame);
+        }
+        buf.reserve(8 * 1024);
+        match io.read_buf(buf).await {
+            Ok(0) | Err(_) => return Err(close_code::GOING_AWAY),
+            Ok(_) => {}
+        }
+    }
+}
+
+fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(payload.len() + 10);
+    // Server frames are never masked and never fragmented here.
+    out.push(0x80 | opcode);
+    match payload.len() {
+        n if n < 126 => out.push(n as u8),
+        n if n <= u16::MAX as usize => {
+            out.push(126);
+            out.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        n => {
+            out.push(127);
+            out.extend_from_slice(&(n as u64).to_be_bytes());
+        }
+    }
+    out.extend_from_slice(payload);
+    out
+}
+
+fn close_payload(code: u16, reason: &str) -> Vec<u8> {
+    let mut payload = code.to_be_bytes().to_vec();
+    payload.extend_from_slice(reason.as_bytes());
+    payload
+}
+
+// ---------------------------------------------------------------------------
+// Socket
+// ---------------------------------------------------------------------------
+
+/// State the read half, the write half and the keep-alive task share.
+struct Liveness {
+    last_read: std::sync::Mutex<Instant>,
+    closed: AtomicBool,
+    /// Wakes a recv() waiting on a peer that stopped answering.
+    timed_out: Notify,
+}
+
+impl Liveness {
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+    }
+}
+
+/// An upgraded connection. Split it to read and write from different tasks.
+pub struct WebSocket {
+    receiver: WsReceiver,
+    protocol: Option<&'static str>,
+}
+
+impl WebSocket {
+    fn new(
+        upgraded: Upgraded,
+        protocol: Option<&'static str>,
+        keep_alive: Option<Duration>,
+    ) -> Self {
+        let (reader, writer) = tokio::io::split(TokioIo::new(upgraded));
+        let sender = WsSender {
+            writer: Arc::new(Mutex::new(writer)),
+        };
+        let liveness = Arc::new(Liveness {
+            last_read: std::sync::Mutex::new(Instant::now()),
+            closed: AtomicBool::new(false),
+            timed_out: Notify::new(),
+        });
+        if let Some(every) = keep_alive {
+            tokio::spawn(ping_while_idle(
+                every,
+                Arc::downgrade(&liveness),
+                Arc::downgrade(&sender.writer),
+            ));
+        }
+        Self {
+            receiver: WsReceiver {
+                reader,
+                buf: Vec::new(),
+                fragments: None,
+                sender,
+                liveness,
+            },
+            protocol,
+        }
+    }
+
+    /// The subprotocol agreed on in the handshake, if any.
+    pub fn protocol(&self) -> Option<&'static str> {
+        self.protocol
+    }
+
+    /// See WsReceiver::recv.
+    pub async fn recv(&mut self) -> Option<Message> {
+        self.receiver.recv().await
+    }
+
+    /// `&mut`, like recv(): a WebSocket isn't shared. Split it for a
+    /// sender that is.
+    pub async fn send(&mut self, message: Message) -> io::Result<()> {
+        self.receiver.sender.send(message).await
+    }
+
+    pub fn split(self) -> (WsSender, WsReceiver) {
+        (self.receiver.sender.clone(), self.receiver)
+    }
+}
+
+/// Pings a connection nothing has been read from for `every`, and closes
+/// one still silent a second `every` later. Holds the socket weakly: ends
+/// when the socket is dropped or closed.
+async fn ping_while_idle(
+    every: Duration,
+    liveness: Weak<Liveness>,
+    writer: Weak<Mutex<WriteHalf<Io>>>,
+) {
+    let mut ticks = tokio::time::interval_at(Instant::now() + every, every);
+    loop {
+        ticks.tick().await;
+        let (Some(liveness), Some(writer)) = (liveness.upgrade(), writer.upgrade()) else {
+            return;
+        };
+        if liveness.is_closed() {
+            return;
+        }
+        let sender = WsSender { writer };
+        let idle = liveness
+            .last_read
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .elapsed();
+        if idle >= every * 2 {
+            liveness.close();
+            let _ = sender.close(close_code::GOING_AWAY, "ping timeout").await;
+            liveness.timed_out.notify_one();
+            return;
+        }
+        if idle >= every && sender.send(Message::Ping(Vec::new())).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Cloneable write half. Writes are serialised so frames never interleave.
+#[derive(Clone)]
+pub struct WsSender {
+    writer: Arc<Mutex<WriteHalf<Io>>>,
+}
+
+impl WsSender {
+    pub async fn send(&self, message: Message) -> io::Result<()> {
+        let (code, payload) = match message {
+            Message::Text(text) => (opcode::TEXT, text.into_bytes()),
+            Message::Binary(data) => (opcode::BINARY, data),
+            Message::Ping(data) => (opcode::PING, data),
+            Message::Pong(data) => (opcode::PONG, data),
+            Message::Close(None) => (opcode::CLOSE, Vec::new()),
+            Message::Close(Some((code, reason))) => (opcode::CLOSE, close_payload(code, &reason)),
+        };
+        if code & 0x8 != 0 && payload.len() > 125 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a ping, pong or close payload is at most 125 bytes",
+            ));
+        }
+        let frame = encode_frame(code, &payload);
+
+        let mut writer = self.writer.lock().await;
+        writer.write_all(&frame).await?;
+        writer.flush().await
+    }
+
+    pub async fn send_text(&self, text: impl Into<String>) -> io::Result<()> {
+        self.send(Message::Text(text.into())).await
+    }
+
+    pub async fn close(&self, code: u16, reason: &str) -> io::Result<()> {
+        self.send(Message::Close(Some((code, reason.to_string()))))
+            .await
+    }
+}
+
+/// Read half. Answers pings and completes the close handshake by itself.
+pub struct WsReceiver {
+    reader: ReadHalf<Io>,
+    /// Bytes read but not yet a whole frame.
+    buf: Vec<u8>,
+    /// The kind and data of a fragmented message, so far.
+    fragments: Option<(u8, Vec<u8>)>,
+    sender: WsSender,
+    liveness: Arc<Liveness>,
+}
+
+impl WsReceiver {
+    /// Next message, or None once the connection is closed: by either side,
+    /// a protocol error, or a keep-alive timeout.
+    ///
+    /// Cancel-safe, so it can be a `tokio::select!` branch: a frame cut
+    /// short is finished by the next call, and replies (pongs, the close
+    /// echo) are written by a task of their own.
+    pub async fn recv(&mut self) -> Option<Message> {
+        while !self.liveness.is_closed() {
+            let read = tokio::select! {
+                read = read_frame(&mut self.reader, &mut self.buf) => read,
+                // The keep-alive task has sent the close frame already.
+                () = self.liveness.timed_out.notified() => return None,
+            };
+            *self
+                .liveness
+                .last_read
+                .lock()
+                .unwrap_or_else(|e| e.into_inner()) = Instant::now();
+            let frame = match read {
+                Ok(frame) => frame,
+                Err(code) => {
+                    self.liveness.close();
+                    if code != close_code::GOING_AWAY {
+                        self.reply(Message::Close(Some((code, String::new()))));
+                    }
+                    return None;
+                }
+            };
+
+            match frame.opcode {
+                opcode::PING => {
+                    self.reply(Message::Pong(frame.payload.clone()));
+                    return Some(Message::Ping(frame.payload));
+                }
+                opcode::PONG => return Some(Message::Pong(frame.payload)),
+                opcode::CLOSE => {
+                    self.liveness.close();
+                    // Echo the close frame, as the protocol requires.
+                    self.reply(Message::Close(None));
+                    let close = (frame.payload.len() >= 2).then(|| {
+                        let code = u16::from_be_bytes([frame.payload[0], frame.payload[1]]);
+                        let reason = String::from_utf8_lossy(&frame.payload[2..]).into_owned();
+                        (code, reason)
+                    });
+                    return Some(Message::Close(close));
+                }
+                opcode::TEXT | opcode::BINARY | opcode::CONTINUATION => {
+                    let (kind, mut data) = match (self.fragments.take(), frame.opcode) {
+                        (None, opcode::CONTINUATION) | (Some(_), opcode::TEXT | opcode::BINARY) => {
+                            self.fail(close_code::PROTOCOL_ERROR);
+                            return None;
+                        }
+                        (None, kind) => (kind, Vec::new()),
+                        (Some(started), _) => started,
+                    };
+
+                    data.extend_from_slice(&frame.payload);
+                    if data.len() > MAX_MESSAGE_SIZE {
+                        self.fail(close_code::TOO_BIG);
+                        return None;
+                    }
+                    if !frame.fin {
+                        self.fragments = Some((kind, data));
+                        continue;
+                    }
+
+                    if kind == opcode::BINARY {
+                        return Some(Message::Binary(data));
+                    }
+                    match String::from_utf8(data) {
+                        Ok(text) => return Some(Message::Text(text)),
+                        Err(_) => {
+                            self.fail(close_code::INVALID_DATA);
+                            return None;
+                        }
+                    }
+                }
+                _ => {
+                    self.fail(close_code::PROTOCOL_ERROR);
+                    return None;
+                }
+            }
+        }
+
+        None
+    }
+
+    fn fail(&mut self, code: u16) {
+        self.liveness.close();
+        self.reply(Message::Close(Some((code, String::new()))));
+    }
+
+    /// Writes `message` from a task of its own: a write cut short by a
+    /// cancelled recv() would leave half a frame on the wire.
+    fn reply(&self, message: Message) {
+        let sender = self.sender.clone();
+        tokio::spawn(async move {
+            let _ = sender.send(message).await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustapi_core::{BodyVariant, PathParams};
+    use std::convert::Infallible;
+    use tokio::io::DuplexStream;
+
+    /// The key and accept value from RFC 6455 §1.3.
+    const SAMPLE_KEY: &str = "dGhlIHNhbXBsZSBub25jZQ==";
+    const SAMPLE_ACCEPT: &str = "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=";
+
+    /// A client frame: masked, as clients must send them.
+    fn client_frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x37, 0xfa, 0x21, 0x3d];
+        let mut frame = vec![if fin { 0x80 } else { 0 } | opcode];
+        match payload.len() {
+            n if n < 126 => frame.push(0x80 | n as u8),
+            n if n <= u16::MAX as usize => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(n as u16).to_be_bytes());
+            }
+            n => {
+                frame.push(0x80 | 127);
+                frame.extend_from_slice(&(n as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        frame
+    }
+
+    fn parsed(buf: &[u8]) -> (bool, u8, Vec<u8>, usize) {
+        let (frame, used) = parse_frame(buf).unwrap().expect("a whole frame");
+        (frame.fin, frame.opcode, frame.payload, used)
+    }
+
+    #[test]
+    fn accept_key_matches_the_rfc_example() {
+        assert_eq!(accept_key(SAMPLE_KEY.as_bytes()), SAMPLE_ACCEPT);
+    }
+
+    #[test]
+    fn masked_frames_are_unmasked() {
+        // RFC 6455 §5.7: a masked "Hello".
+        let hello = [
+            0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+        ];
+        assert_eq!(
+            parsed(&hello),
+            (true, opcode::TEXT, b"Hello".to_vec(), hello.len())
+        );
+    }
+
+    #[test]
+    fn unmasked_client_frames_are_a_protocol_error() {
+        // RFC 6455 §5.7: the same "Hello", unmasked, as a server sends it.
+        let hello = [0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f];
+        assert_eq!(parse_frame(&hello).err(), Some(close_code::PROTOCOL_ERROR));
+        // And a server frame is exactly that.
+        assert_eq!(encode_frame(opcode::TEXT, b"Hello"), hello);
+    }
+
+    #[test]
+    fn extended_lengths_both_ways() {
+        for len in [125, 126, 256, u16::MAX as usize, u16::MAX as usize + 1] {
+            let payload: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let frame = client_frame(true, opcode::BINARY, &payload);
+            let header = match len {
+                0..=125 => 2,
+                126..=65535 => 4,
+                _ => 10,
+            };
+            assert_eq!(frame.len(), header + 4 + len);
+            assert_eq!(
+                parsed(&frame),
+                (true, opcode::BINARY, payload.clone(), frame.len()),
+                "{len}"
+            );
+
+            let encoded = encode_frame(opcode::BINARY, &payload);
+            assert_eq!(encoded.len(), header + len, "{len}");
+            let marker = match header {
+                2 => len as u8,
+                4 => 126,
+                _ => 127,
+            };
+            assert_eq!(encoded[1], marker, "{len}");
+        }
+    }
+
+    #[test]
+    fn partial_frames_wait_for_more() {
+        let frame = client_frame(true, opcode::TEXT, &[b'x'; 300]);
+        for cut in 0..frame.len() {
+            assert!(parse_frame(&frame[..cut]).unwrap().is_none(), "{cut}");
+        }
+        // A second frame behind the first is left for the next call.
+        let mut two = frame.clone();
+        two.extend(client_frame(true, opcode::PING, b""));
+        assert_eq!(parsed(&two).3, frame.len());
+    }
+
+    #[test]
+    fn control_frames_must_be_short_and_whole() {
+        let long_ping = client_frame(true, opcode::PING, &[0; 126]);
+        assert_eq!(
+            parse_frame(&long_ping).err(),
+            Some(close_code::PROTOCOL_ERROR)
+        );
+        let fragmented_ping = client_frame(false, opcode::PING, b"hi");
+        assert_eq!(
+            parse_frame(&fragmented_ping).err(),
+            Some(close_code::PROTOCOL_ERROR)
+        );
+        let max_ping = client_frame(true, opcode::PING, &[0; 125]);
+        assert_eq!(parsed(&max_ping).2.len(), 125);
+    }
+
+    #[test]
+    fn oversized_frames_are_refused_before_their_payload() {
+        // Only the header: the length alone is enough to refuse it.
+        let mut header = vec![0x82, 0x80 | 127];
+        header.extend_from_slice(&(MAX_MESSAGE_SIZE as u64 + 1).to_be_bytes());
+        assert_eq!(parse_frame(&header).err(), Some(close_code::TOO_BIG));
+    }
+
+    #[test]
+    fn reserved_bits_are_a_protocol_error() {
+        let mut frame = client_frame(true, opcode::TEXT, b"hi");
+        frame[0] |= 0x40;
+        assert_eq!(parse_frame(&frame).err(), Some(close_code::PROTOCOL_ERROR));
+    }
+
+    // -- Over a connection ---------------------------------------------------
+
+    async fn echo(ws: WebSocketUpgrade) -> Response {
+        ws.keep_alive(None).on_upgrade(|mut socket| async move {
+            while let Some(message) = socket.recv().await {
+                if matches!(message, Message::Text(_) | Message::Binary(_)) {
+                    let _ = socket.send(message).await;
+                }
+            }
+        })
+    }
+
+    /// A client end, already past the handshake, of a connection to `echo`.
+    async fn connect() -> DuplexStream {
+        let dispatcher = RustApi::new().route("/ws", get(echo)).request_dispatcher();
+        let (mut client, server) = tokio::io::duplex(256 * 1024);
+        let service =
+            hyper::service::service_fn(move |req: hyper::Request<hyper::body::Incoming>| {
+                let dispatcher = dispatcher.clone();
+                async move {
+                    let (parts, body) = req.into_parts();
+                    let request = Request::new(
+                        parts,
+                        BodyVariant::Streaming(body),
+                        dispatcher.state_ref(),
+                        PathParams::new(),
+                    );
+                    Ok::<_, Infallible>(dispatcher.dispatch(request).await)
+                }
+            });
+        tokio::spawn(
+            hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(server), service)
+                .with_upgrades(),
+        );
+
+        let request = format!(
+            "GET /ws HTTP/1.1\r\nHost: x\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\
+             Sec-WebSocket-Version: 13\r\nSec-WebSocket-Key: {SAMPLE_KEY}\r\n\r\n"
+        );
+        client.write_all(request.as_bytes()).await.unwrap();
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(client.read_u8().await.unwrap());
+        }
+        let head = String::from_utf8(head).unwrap().to_ascii_lowercase();
+        assert!(head.starts_with("http/1.1 101"), "{head}");
+        assert!(
+            head.contains(&format!(
+                "sec-websocket-accept: {}",
+                SAMPLE_ACCEPT.to_ascii_lowercase()
+            )),
+            "{head}"
+        );
+        client
+    }
+
+    /// The next frame from the server: opcode and payload.
+    async fn server_frame(client: &mut DuplexStream) -> (u8, Vec<u8>) {
+        let first = client.read_u8().await.unwrap();
+        let second = client.read_u8().await.unwrap();
+        assert_eq!(second & 0x80, 0, "server frames are not masked");
+        let len = match second {
+            126 => client.read_u16().await.unwrap() as usize,
+            127 => client.read_u64().await.unwrap() as usize,
+            n => n as usize,
+        };
+        let mut payload = vec![0; len];
+        client.read_exact(&mut payload).await.unwrap();
+        (first & 0x0F, payload)
+    }
+
+    fn close_code_of(frame: (u8, Vec<u8>)) -> u16 {
+        assert_eq!(frame.0, opcode::CLOSE);
+        u16::from_be_bytes([frame.1[0], frame.1[1]])
+    }
+
+    #[tokio::test]
+    async fn fragments_are_joined_around_a_ping() {
+        let mut client = connect().await;
+        client
+            .write_all(&client_frame(false, opcode::TEXT, b"Hel"))
+            .await
+            .unwrap();
+        // Control frames may come between the fragments.
+        client
+            .write_all(&client_frame(true, opcode::PING, b"?"))
+            .await
+            .unwrap();
+        client
+            .write_all(&client_frame(false, opcode::CONTINUATION, b"lo, "))
+            .await
+            .unwrap();
+        client
+            .write_all(&client_frame(true, opcode::CONTINUATION, b"world"))
+            .await
+            .unwrap();
+
+        // The pong is written by a task of its own, so either may come first.
+        let mut frames = vec![
+            server_frame(&mut client).await,
+            server_frame(&mut client).await,
+        ];
+        frames.sort();
+        assert_eq!(
+            frames,
+            [
+                (opcode::TEXT, b"Hello, world".to_vec()),
+                (opcode::PONG, b"?".to_vec()),
+            ]
+        );
+
+        // And a large binary message, with a 16-bit length each way.
+        let data: Vec<u8> = (0..40_000).map(|i| i as u8).collect();
+        client
+            .write_all(&client_frame(true, opcode::BINARY, &data))
+            .await
+            .unwrap();
+        assert_eq!(server_frame(&mut client).await, (opcode::BINARY, data));
+    }
+
+    #[tokio::test]
+    async fn a_continuation_without_a_start_is_a_protocol_error() {
+        let mut client = connect().await;
+        client
+            .write_all(&client_frame(true, opcode::CONTINUATION, b"orphan"))
+            .await
+            .unwrap();
+        assert_eq!(
+            close_code_of(server_frame(&mut client).await),
+            close_code::PROTOCOL_ERROR
+        );
+    }
+
+    #[tokio::test]
+    async fn a_new_message_inside_a_fragmented_one_is_a_protocol_error() {
+        let mut client = connect().await;
+        client
+            .write_all(&client_frame(false, opcode::TEXT, b"one"))
+            .await
+            .unwrap();
+        client
+            .write_all(&client_frame(true, opcode::TEXT, b"two"))
+            .await
+            .unwrap();
+        assert_eq!(
+            close_code_of(server_frame(&mut client).await),
+            close_code::PROTOCOL_ERROR
+        );
+    }
+
+    #[tokio::test]
+    async fn fragments_over_the_size_limit_are_refused() {
+        // Each frame is within the limit; the message they add up to isn't.
+        let mut client = connect().await;
+        let half = vec![b'x'; MAX_MESSAGE_SIZE / 2 + 1];
+        client
+            .write_all(&client_frame(false, opcode::BINARY, &half))
+            .await
+            .unwrap();
+        client
+            .write_all(&client_frame(true, opcode::CONTINUATION, &half))
+            .await
+            .unwrap();
+        assert_eq!(
+            close_code_of(server_frame(&mut client).await),
+            close_code::TOO_BIG
+        );
+    }
+
+    #[tokio::test]
+    async fn an_oversized_frame_is_refused() {
+        let mut client = connect().await;
+        let data = vec![b'x'; MAX_MESSAGE_SIZE + 1];
+        client
+            .write_all(&client_frame(true, opcode::BINARY, &data))
+            .await
+            .unwrap();
+        assert_eq!(
+            close_code_of(server_frame(&mut client).await),
+            close_code::TOO_BIG
+        );
+    }
+
+    #[tokio::test]
+    async fn a_long_ping_is_a_protocol_error() {
+        let mut client = connect().await;
+        client
+            .write_all(&client_frame(true, opcode::PING, &[0; 126]))
+            .await
+            .unwrap();
+        assert_eq!(
+            close_code_of(server_frame(&mut client).await),
+            close_code::PROTOCOL_ERROR
+        );
+    }
+
+    #[tokio::test]
+    async fn handshake_needs_version_13_and_a_key() {
+        async fn status(headers: &[(&str, &str)]) -> StatusCode {
+            let dispatcher = RustApi::new().route("/ws", get(echo)).request_dispatcher();
+            let mut builder = http::Request::get("/ws");
+            for (name, value) in headers {
+                builder = builder.header(*name, *value);
+            }
+            let (parts, ()) = builder.body(()).unwrap().into_parts();
+            let request = Request::new(
+                parts,
+                BodyVariant::Buffered(Default::default()),
+                dispatcher.state_ref(),
+                PathParams::new(),
+            );
+            dispatcher.dispatch(request).await.status()
+        }
+        let upgrade = [
+            ("connection", "keep-alive, Upgrade"),
+            ("upgrade", "websocket"),
+        ];
+
+        assert_eq!(status(&[]).await, StatusCode::BAD_REQUEST);
+        let old_version = [
+            upgrade[0],
+            upgrade[1],
+            ("sec-websocket-version", "8"),
+            ("sec-websocket-key", SAMPLE_KEY),
+        ];
+        assert_eq!(status(&old_version).await, StatusCode::BAD_REQUEST);
+        let no_key = [upgrade[0], upgrade[1], ("sec-websocket-version", "13")];
+        assert_eq!(status(&no_key).await, StatusCode::BAD_REQUEST);
+    }
+}
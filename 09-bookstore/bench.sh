@@ -0,0 +1,36 @@
+#!/usr/bin/env bash
+# JSON backend comparison on GET /books: serde_json (default) vs simd-json.
+#
+# Usage: ./09-bookstore/bench.sh [books] [connections] [duration]
+# Needs: oha (cargo install oha)
+#
+# Json<T> picks its backend at compile time through rustapi-rs's `simd-json`
+# feature, so the same handlers are built twice — nothing in the code changes.
+
+set -euo pipefail
+
+BOOKS="${1:-2000}"
+CONNECTIONS="${2:-64}"
+DURATION="${3:-30s}"
+URL="http://127.0.0.1:3000/books"
+
+for backend in serde_json simd-json; do
+    if [ "$backend" = simd-json ]; then
+        cargo build -p bookstore --release --features rustapi-rs/simd-json
+    else
+        cargo build -p bookstore --release
+    fi
+
+    BENCH_EXTRA_BOOKS="$BOOKS" ./target/release/bookstore >/dev/null 2>&1 &
+    pid=$!
+    sleep 1
+
+    echo "== $backend ($BOOKS books, $(curl -s "$URL" | wc -c) bytes/response, c=$CONNECTIONS, $DURATION) =="
+    # Warm up, then measure.
+    oha -z 5s -c "$CONNECTIONS" --no-tui "$URL" >/dev/null
+    oha -z "$DURATION" -c "$CONNECTIONS" --no-tui "$URL" \
+        | grep -E 'Requests/sec|50.00%|99.00%'
+
+    kill "$pid"
+    wait "$pid" 2>/dev/null || true
+done
@@ -0,0 +1,102 @@
+// ---------------------------------------------------------------------------
+// #[derive(FromRef)]
+// ---------------------------------------------------------------------------
+//
+// For a struct of named fields, generates
+//
+//   impl FromRef<AppState> for FieldType { .. clones the field .. }   per field
+//   impl StateParts for AppState { .. registers each field as state .. }
+//
+// against the traits in bookstore's src/from_ref.rs, found at
+// `crate::from_ref`. A field marked `#[from_ref(skip)]` gets neither: it is
+// only reachable through the whole struct.
+//
+// NOTE: two fields of the same type would be two impls of the same trait.
+//       That is a compile error, and State<T> couldn't tell them apart
+//       anyway: wrap one in a newtype.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields};
+
+#[proc_macro_derive(FromRef, attributes(from_ref))]
+pub fn derive_from_ref(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "#[derive(FromRef)] doesn't support generic structs",
+        ));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "#[derive(FromRef)] needs named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "#[derive(FromRef)] only works on structs",
+            ))
+        }
+    };
+
+    let mut impls = Vec::new();
+    let mut parts = Vec::new();
+    for field in fields {
+        if skipped(field)? {
+            continue;
+        }
+        let ident = field.ident.as_ref().expect("named fields have idents");
+        let ty = &field.ty;
+        impls.push(quote! {
+            impl crate::from_ref::FromRef<#name> for #ty {
+                fn from_ref(state: &#name) -> Self {
+                    ::std::clone::Clone::clone(&state.#ident)
+                }
+            }
+        });
+        parts.push(quote! {
+            let app = app.state(<#ty as crate::from_ref::FromRef<#name>>::from_ref(self));
+        });
+    }
+
+    Ok(quote! {
+        #(#impls)*
+
+        impl crate::from_ref::StateParts for #name {
+            fn register_parts(&self, app: ::rustapi_rs::RustApi) -> ::rustapi_rs::RustApi {
+                #(#parts)*
+                app
+            }
+        }
+    })
+}
+
+/// Whether `field` has `#[from_ref(skip)]`.
+fn skipped(field: &syn::Field) -> syn::Result<bool> {
+    let mut skip = false;
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("from_ref")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                skip = true;
+                Ok(())
+            } else {
+                Err(meta.error("expected `skip`"))
+            }
+        })?;
+    }
+    Ok(skip)
+}
@@ -0,0 +1,353 @@
+// ---------------------------------------------------------------------------
+// One body, several media types
+// ---------------------------------------------------------------------------
+//
+// `Json<T>` takes JSON and documents `application/json`. A form on a web page
+// posts `application/x-www-form-urlencoded`, or `multipart/form-data` once it
+// has a file input. To take all of them on one route, list the formats the
+// route accepts:
+//
+//   Accepts<NewBook, (AsJson, AsForm, AsMultipart)>
+//
+// The request's Content-Type picks the decoder; anything not listed is a 415
+// naming the ones that are. In the spec, `requestBody.content` lists every
+// format with T's schema, so /docs offers each of them.
+//
+// NOTE: multipart text fields are decoded like a form (so `price_cents=1299`
+//       becomes a number); file parts are skipped. For uploads, see the
+//       streaming Multipart in 15-file-upload.
+//
+// A multipart body is also held to the app's MultipartConfig, part by part
+// as it's parsed, with 413 for whichever limit is hit first:
+//
+//   max_fields      how many parts, files included
+//   max_size        all parts' contents together
+//   max_file_size   any one part, file or text
+//
+// These sit under the request body limit: a thousand one-byte parts fit in
+// any body limit, and are exactly what max_fields is for.
+
+use crate::form::Form;
+use rustapi_openapi::schema::{RustApiSchema, SchemaCtx};
+use rustapi_openapi::{MediaType, OpenApiSpec, Operation, OperationModifier, RequestBody};
+use rustapi_rs::prelude::*;
+use rustapi_rs::FromRequest;
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::marker::PhantomData;
+
+/// A request body format: its media type and how to decode it.
+pub trait BodyFormat {
+    const MEDIA_TYPE: &'static str;
+
+    fn decode<T: DeserializeOwned + Send>(
+        req: &mut Request,
+    ) -> impl Future<Output = Result<T>> + Send;
+}
+
+/// `application/json`
+pub struct AsJson;
+
+/// `application/x-www-form-urlencoded`
+pub struct AsForm;
+
+/// `multipart/form-data` — text fields only, within the app's
+/// `MultipartConfig` limits.
+pub struct AsMultipart;
+
+fn invalid(format: &str, err: impl std::fmt::Display) -> ApiError {
+    ApiError::bad_request(format!("Invalid {} body: {}", format, err))
+}
+
+fn too_large(message: String) -> ApiError {
+    ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large", message)
+}
+
+async fn body(req: &mut Request) -> Result<bytes::Bytes> {
+    req.load_body().await?;
+    req.take_body()
+        .ok_or_else(|| ApiError::internal("Body already consumed"))
+}
+
+impl BodyFormat for AsJson {
+    const MEDIA_TYPE: &'static str = "application/json";
+
+    async fn decode<T: DeserializeOwned + Send>(req: &mut Request) -> Result<T> {
+        Json::<T>::from_request(req).await.map(|Json(value)| value)
+    }
+}
+
+impl BodyFormat for AsForm {
+    const MEDIA_TYPE: &'static str = crate::form::MEDIA_TYPE;
+
+    async fn decode<T: DeserializeOwned + Send>(req: &mut Request) -> Result<T> {
+        Form::<T>::from_request(req).await.map(|Form(value)| value)
+    }
+}
+
+impl BodyFormat for AsMultipart {
+    const MEDIA_TYPE: &'static str = "multipart/form-data";
+
+    async fn decode<T: DeserializeOwned + Send>(req: &mut Request) -> Result<T> {
+        let config = req
+            .state()
+            .get::<MultipartConfig>()
+            .cloned()
+            .unwrap_or_default();
+        let boundary = req
+            .headers()
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| multer::parse_boundary(value).ok())
+            .ok_or_else(|| ApiError::bad_request("Missing boundary in Content-Type"))?;
+        let body = body(req).await?;
+
+        // Parsed with multer part by part, so a body of ten thousand tiny
+        // parts is turned away at the first one over max_fields.
+        let per_part = multer::SizeLimit::new().per_field(config.max_file_size as u64);
+        let mut multipart = multer::Multipart::with_constraints(
+            futures_util::stream::once(async move { Ok::<_, Infallible>(body) }),
+            boundary,
+            multer::Constraints::new().size_limit(per_part),
+        );
+
+        let (mut parts, mut total) = (0, 0);
+        let mut pairs = Vec::new();
+        while let Some(field) = multipart.next_field().await.map_err(multipart_error)? {
+            parts += 1;
+            if parts > config.max_fields {
+                return Err(too_large(format!(
+                    "Multipart body has more than {} parts",
+                    config.max_fields
+                )));
+            }
+            let name = field.name().map(str::to_string);
+            let is_file = field.file_name().is_some();
+            let data = field.bytes().await.map_err(multipart_error)?;
+            total += data.len();
+            if total > config.max_size {
+                return Err(too_large(format!(
+                    "Multipart parts exceed {} bytes in total",
+                    config.max_size
+                )));
+            }
+            if is_file {
+                continue;
+            }
+            let (Some(name), Ok(value)) = (name, String::from_utf8(data.to_vec())) else {
+                continue;
+            };
+            pairs.push((name, value));
+        }
+
+        // Re-encoded as a form, so field values get the same conversions.
+        let form = serde_urlencoded::to_string(&pairs).map_err(|err| invalid("multipart", err))?;
+        serde_urlencoded::from_str(&form).map_err(|err| invalid("multipart", err))
+    }
+}
+
+fn multipart_error(err: multer::Error) -> ApiError {
+    match err {
+        multer::Error::FieldSizeExceeded { limit, field_name } => too_large(format!(
+            "Multipart part {} exceeds {} bytes",
+            field_name.as_deref().unwrap_or("(unnamed)"),
+            limit
+        )),
+        err => invalid("multipart", err),
+    }
+}
+
+/// `T`, decoded from whichever of `Formats` (a tuple of [`BodyFormat`]s)
+/// matches the request's Content-Type.
+///
+/// Destructure it in the handler: `Accepts(book, _): Accepts<NewBook, (AsJson, AsForm)>`.
+pub struct Accepts<T, Formats>(pub T, pub PhantomData<fn() -> Formats>);
+
+/// The media type without parameters: `multipart/form-data; boundary=x`
+/// is `multipart/form-data`.
+fn essence(req: &Request) -> String {
+    req.headers()
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+fn unsupported(got: &str, accepted: &[&str]) -> ApiError {
+    let got = if got.is_empty() {
+        "no Content-Type"
+    } else {
+        got
+    };
+    ApiError::new(
+        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        "unsupported_media_type",
+        format!("Expected one of: {} (got {})", accepted.join(", "), got),
+    )
+}
+
+macro_rules! accepts {
+    ($($format:ident),+) => {
+        impl<T, $($format: BodyFormat),+> FromRequest for Accepts<T, ($($format,)+)>
+        where
+            T: DeserializeOwned + Send,
+        {
+            async fn from_request(req: &mut Request) -> Result<Self> {
+                let essence = essence(req);
+                $(
+                    if essence == $format::MEDIA_TYPE {
+                        return Ok(Accepts($format::decode(req).await?, PhantomData));
+                    }
+                )+
+                Err(unsupported(&essence, &[$($format::MEDIA_TYPE),+]))
+            }
+        }
+
+        impl<T: RustApiSchema, $($format: BodyFormat),+> OperationModifier
+            for Accepts<T, ($($format,)+)>
+        {
+            fn update_operation(op: &mut Operation) {
+                let mut ctx = SchemaCtx::new();
+                let schema = T::schema(&mut ctx);
+                let mut content = BTreeMap::new();
+                $(
+                    content.insert(
+                        $format::MEDIA_TYPE.to_string(),
+                        MediaType {
+                            schema: Some(schema.clone()),
+                            example: None,
+                        },
+                    );
+                )+
+                op.request_body = Some(RequestBody {
+                    description: None,
+                    required: Some(true),
+                    content,
+                });
+            }
+
+            fn register_components(spec: &mut OpenApiSpec) {
+                spec.register_in_place::<T>();
+            }
+        }
+    };
+}
+
+accepts!(A);
+accepts!(A, B);
+accepts!(A, B, C);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustapi_core::{BodyVariant, PathParams};
+    use std::sync::Arc;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Pair {
+        title: String,
+        price_cents: u64,
+    }
+
+    type Multipart = Accepts<Pair, (AsJson, AsMultipart)>;
+
+    const BOUNDARY: &str = "X-BOUNDARY";
+
+    /// A multipart/form-data body with one text part per entry.
+    fn multipart(parts: &[(&str, &str)]) -> String {
+        let mut body = String::new();
+        for (name, value) in parts {
+            body.push_str(&format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                BOUNDARY, name, value
+            ));
+        }
+        body + &format!("--{}--\r\n", BOUNDARY)
+    }
+
+    async fn decode(config: MultipartConfig, content_type: &str, body: String) -> Result<Pair> {
+        let (parts, ()) = http::Request::builder()
+            .method("POST")
+            .uri("/books")
+            .header(http::header::CONTENT_TYPE, content_type)
+            .body(())
+            .unwrap()
+            .into_parts();
+        let mut state = http::Extensions::new();
+        state.insert(config);
+        let mut req = Request::new(
+            parts,
+            BodyVariant::Buffered(body.into()),
+            Arc::new(state),
+            PathParams::new(),
+        );
+        Multipart::from_request(&mut req)
+            .await
+            .map(|Accepts(pair, _)| pair)
+    }
+
+    fn form_data() -> String {
+        format!("multipart/form-data; boundary={}", BOUNDARY)
+    }
+
+    #[tokio::test]
+    async fn text_parts_decode_like_a_form() {
+        let body = multipart(&[("title", "Dune"), ("price_cents", "1299")]);
+        let pair = decode(MultipartConfig::new(), &form_data(), body)
+            .await
+            .unwrap();
+        assert_eq!(
+            pair,
+            Pair {
+                title: "Dune".to_string(),
+                price_cents: 1299
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn too_many_parts_is_413() {
+        let config = MultipartConfig::new().max_fields(2);
+        let body = multipart(&[("title", "Dune"), ("price_cents", "1299"), ("extra", "x")]);
+        let err = decode(config, &form_data(), body).await.unwrap_err();
+        assert_eq!(err.status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(err.message.contains("more than 2 parts"), "{}", err.message);
+    }
+
+    #[tokio::test]
+    async fn size_limits_are_413() {
+        let body = multipart(&[("title", "Dune"), ("price_cents", "1299")]);
+        let err = decode(
+            MultipartConfig::new().max_size(6),
+            &form_data(),
+            body.clone(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status, StatusCode::PAYLOAD_TOO_LARGE);
+
+        let err = decode(MultipartConfig::new().max_file_size(3), &form_data(), body)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(err.message.contains("exceeds 3 bytes"), "{}", err.message);
+    }
+
+    #[tokio::test]
+    async fn unlisted_media_type_is_415() {
+        let err = decode(MultipartConfig::new(), "text/plain", String::new())
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert!(
+            err.message
+                .contains("application/json, multipart/form-data (got text/plain)"),
+            "{}",
+            err.message
+        );
+    }
+}
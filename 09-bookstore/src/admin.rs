@@ -0,0 +1,110 @@
+// ---------------------------------------------------------------------------
+// Operator-only routes
+// ---------------------------------------------------------------------------
+//
+// Some routes are for whoever runs the bookstore, not its customers:
+// subscribing a URL to webhooks makes this server send requests to it, and
+// the delivery log shows where they went. Taking `Admin` puts a handler
+// behind a bearer token:
+//
+//   async fn subscribe(_: Admin, ..) -> ..
+//
+//   Authorization: Bearer <ADMIN_TOKEN>    the handler runs
+//   missing or wrong                       401 "Missing or wrong admin token"
+//
+// The token comes from ADMIN_TOKEN. Without it, each process picks a random
+// one and prints it at startup, so a demo run still has a way in.
+//
+// NOTE: the comparison takes the same time however much of the token
+//       matched, so timing doesn't leak it a byte at a time.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ring::rand::{SecureRandom, SystemRandom};
+use rustapi_openapi::{MediaType, Operation, OperationModifier, ResponseSpec, SchemaRef};
+use rustapi_rs::prelude::*;
+use rustapi_rs::FromRequestParts;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// The bearer token `Admin` checks. Register it as state.
+#[derive(Clone)]
+pub struct AdminToken(Arc<str>);
+
+impl AdminToken {
+    pub fn new(token: impl Into<Arc<str>>) -> Self {
+        Self(token.into())
+    }
+
+    /// ADMIN_TOKEN, or a random token printed to stdout when it's unset.
+    pub fn from_env() -> Self {
+        match std::env::var("ADMIN_TOKEN") {
+            Ok(token) if !token.is_empty() => Self::new(token),
+            _ => {
+                let mut bytes = [0u8; 24];
+                SystemRandom::new()
+                    .fill(&mut bytes)
+                    .expect("system randomness is available");
+                let token = URL_SAFE_NO_PAD.encode(bytes);
+                println!("ADMIN_TOKEN is not set; this run's token is {}", token);
+                Self::new(token)
+            }
+        }
+    }
+
+    fn matches(&self, given: &str) -> bool {
+        given.len() == self.0.len()
+            && given
+                .bytes()
+                .zip(self.0.bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    }
+}
+
+/// Proof that the request carried the admin token.
+#[derive(Debug, Clone, Copy)]
+pub struct Admin;
+
+impl FromRequestParts for Admin {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        let token = req
+            .state()
+            .get::<AdminToken>()
+            .ok_or_else(|| ApiError::internal("AdminToken is not registered as state"))?;
+        let given = req
+            .headers()
+            .get(http::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        if token.matches(given) {
+            Ok(Admin)
+        } else {
+            Err(ApiError::unauthorized("Missing or wrong admin token"))
+        }
+    }
+}
+
+impl OperationModifier for Admin {
+    fn update_operation(op: &mut Operation) {
+        let mut content = BTreeMap::new();
+        content.insert(
+            "application/json".to_string(),
+            MediaType {
+                schema: Some(SchemaRef::Ref {
+                    reference: "#/components/schemas/ErrorSchema".to_string(),
+                }),
+                example: None,
+            },
+        );
+        op.responses.insert(
+            "401".to_string(),
+            ResponseSpec {
+                description: "Missing or wrong admin token".to_string(),
+                content,
+                headers: BTreeMap::new(),
+            },
+        );
+    }
+}
@@ -0,0 +1,215 @@
+// ---------------------------------------------------------------------------
+// Batch operations
+// ---------------------------------------------------------------------------
+//
+// One request, many items, one result per item. What happens when some items
+// fail is the caller's choice, spelled out in the query string:
+//
+//   best-effort (default)  valid items are applied, invalid ones reported.
+//                          201 if everything succeeded, otherwise
+//                          207 Multi-Status.
+//   ?atomic=true           nothing is applied unless every item is valid.
+//                          201 if everything succeeded, otherwise 422 — the
+//                          valid items are reported as 424 Failed Dependency.
+//
+// Every item is validated before any is applied, so an atomic batch never
+// leaves half its items behind.
+
+use rustapi_openapi::schema::{RustApiSchema, SchemaCtx};
+use rustapi_openapi::{MediaType, OpenApiSpec, Operation, ResponseModifier, ResponseSpec};
+use rustapi_rs::prelude::*;
+use std::collections::BTreeMap;
+
+/// Query string of a batch endpoint.
+#[derive(Debug, Default, Deserialize, Schema)]
+pub struct BatchOptions {
+    /// All-or-nothing: apply no item unless every item is valid.
+    #[serde(default)]
+    pub atomic: bool,
+}
+
+/// Why one item was not applied, as reported to the client.
+#[derive(Debug, Clone, Serialize, Schema)]
+pub struct ItemError {
+    pub code: String,
+    pub message: String,
+}
+
+/// A failed item check: the per-item status plus the error to report.
+#[derive(Debug)]
+pub struct Rejection {
+    status: StatusCode,
+    error: ItemError,
+}
+
+impl Rejection {
+    pub fn new(status: StatusCode, code: &str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            error: ItemError {
+                code: code.to_string(),
+                message: message.into(),
+            },
+        }
+    }
+
+    /// 422 — the item itself is invalid.
+    pub fn invalid(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNPROCESSABLE_ENTITY, "invalid", message)
+    }
+
+    /// 409 — the item clashes with existing data or an earlier item.
+    pub fn conflict(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::CONFLICT, "conflict", message)
+    }
+
+    fn not_applied() -> Self {
+        Self::new(
+            StatusCode::FAILED_DEPENDENCY,
+            "not_applied",
+            "Valid, but not applied because another item in this atomic batch failed",
+        )
+    }
+}
+
+// A single-item endpoint can run the same checks and answer with the same error.
+impl From<Rejection> for ApiError {
+    fn from(rejection: Rejection) -> Self {
+        ApiError::new(
+            rejection.status,
+            rejection.error.code,
+            rejection.error.message,
+        )
+    }
+}
+
+/// Outcome of the item at `index` in the request array.
+#[derive(Debug, Serialize, Schema)]
+pub struct ItemResult<T: RustApiSchema> {
+    pub index: usize,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ItemError>,
+}
+
+/// Response of a batch endpoint: a summary plus one result per item, in
+/// request order.
+#[derive(Debug, Serialize, Schema)]
+pub struct BatchReport<T: RustApiSchema> {
+    pub atomic: bool,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub results: Vec<ItemResult<T>>,
+}
+
+impl<T: RustApiSchema> BatchReport<T> {
+    /// Validates every item with `check`, then — if the mode allows — applies
+    /// the valid ones with `apply`, in order.
+    ///
+    /// `check` sees the items one by one, so it can also reject clashes
+    /// between items of the same batch.
+    pub fn run<I>(
+        options: &BatchOptions,
+        items: Vec<I>,
+        mut check: impl FnMut(&I) -> Result<(), Rejection>,
+        mut apply: impl FnMut(I) -> T,
+    ) -> Self {
+        let checked: Vec<_> = items
+            .into_iter()
+            .map(|item| check(&item).map(|()| item))
+            .collect();
+        let any_failed = checked.iter().any(Result::is_err);
+        let apply_valid = !(options.atomic && any_failed);
+
+        let results: Vec<_> = checked
+            .into_iter()
+            .enumerate()
+            .map(|(index, outcome)| match outcome {
+                Ok(item) if apply_valid => ItemResult {
+                    index,
+                    status: StatusCode::CREATED.as_u16(),
+                    data: Some(apply(item)),
+                    error: None,
+                },
+                Ok(_) => ItemResult::failed(index, Rejection::not_applied()),
+                Err(rejection) => ItemResult::failed(index, rejection),
+            })
+            .collect();
+
+        let succeeded = results.iter().filter(|r| r.data.is_some()).count();
+        Self {
+            atomic: options.atomic,
+            succeeded,
+            failed: results.len() - succeeded,
+            results,
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match (self.failed, self.atomic) {
+            (0, _) => StatusCode::CREATED,
+            (_, false) => StatusCode::MULTI_STATUS,
+            (_, true) => StatusCode::UNPROCESSABLE_ENTITY,
+        }
+    }
+}
+
+impl<T: RustApiSchema> ItemResult<T> {
+    fn failed(index: usize, rejection: Rejection) -> Self {
+        Self {
+            index,
+            status: rejection.status.as_u16(),
+            data: None,
+            error: Some(rejection.error),
+        }
+    }
+}
+
+impl<T: RustApiSchema + Serialize> IntoResponse for BatchReport<T> {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let mut response = Json(self).into_response();
+        *response.status_mut() = status;
+        response
+    }
+}
+
+impl<T: RustApiSchema> ResponseModifier for BatchReport<T> {
+    fn update_response(op: &mut Operation) {
+        let mut ctx = SchemaCtx::new();
+        let schema = Self::schema(&mut ctx);
+
+        let outcomes = [
+            ("201", "Every item was applied"),
+            (
+                "207",
+                "Best-effort batch: some items failed, the rest were applied",
+            ),
+            ("422", "Atomic batch: some items failed, none were applied"),
+        ];
+        for (status, description) in outcomes {
+            let mut content = BTreeMap::new();
+            content.insert(
+                "application/json".to_string(),
+                MediaType {
+                    schema: Some(schema.clone()),
+                    example: None,
+                },
+            );
+            op.responses.insert(
+                status.to_string(),
+                ResponseSpec {
+                    description: description.to_string(),
+                    content,
+                    headers: BTreeMap::new(),
+                },
+            );
+        }
+    }
+
+    fn register_components(spec: &mut OpenApiSpec) {
+        spec.register_in_place::<Self>();
+    }
+}
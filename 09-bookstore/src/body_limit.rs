@@ -0,0 +1,224 @@
+// ---------------------------------------------------------------------------
+// Request body limits, per route
+// ---------------------------------------------------------------------------
+//
+// The limit RustApi::run() installs judges a request by its Content-Length
+// alone, and one limit covers every route. BodyLimitLayer replaces it:
+//
+//   RustApi::auto()
+//       .route_meta(RouteMeta::new().route(Method::POST, "/webhooks", BodyLimit(16 * 1024)))?
+//       .default_body_limit(1024 * 1024)
+//
+// A route's `BodyLimit` metadata (src/route_meta.rs) wins over the default,
+// up or down: an upload route can take 50 MB while a form takes 16 KiB.
+//
+//   Content-Length over the limit   413 at once; the body is never read
+//   no Content-Length (chunked)     read here up to the limit, then put back
+//                                   as an ordinary buffered body; the first
+//                                   byte past it is a 413
+//
+// So an endless stream costs at most `limit` bytes of memory, and every body
+// extractor — Json, Form, Bytes, String — then works unchanged, within the
+// route's limit. A declared length under the limit passes straight through:
+// hyper won't read past it, and ContentLengthLayer checks it is honest.
+//
+// NOTE: `default_body_limit` turns the built-in limit off. That one runs
+//       outermost, before any route is matched, so it would cap every route
+//       at the default and an upload route could never go higher.
+
+use crate::route_meta::RouteMetaRequestExt;
+use bytes::Bytes;
+use http::header::CONTENT_LENGTH;
+use http_body_util::{BodyExt, LengthLimitError, Limited};
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_core::{BodyVariant, PathParams};
+use rustapi_rs::prelude::*;
+use std::future::Future;
+use std::pin::Pin;
+
+/// Route metadata: this route's body limit in bytes, instead of the default.
+#[derive(Debug, Clone, Copy)]
+pub struct BodyLimit(pub usize);
+
+#[derive(Clone)]
+pub struct BodyLimitLayer {
+    default: usize,
+}
+
+impl BodyLimitLayer {
+    /// `max_bytes` for routes without a `BodyLimit` of their own.
+    pub fn new(max_bytes: usize) -> Self {
+        Self { default: max_bytes }
+    }
+}
+
+/// `RustApi::auto().default_body_limit(..)`
+pub trait BodyLimitExt {
+    /// Caps every request body at `max_bytes`, or at its route's `BodyLimit`,
+    /// in place of the built-in Content-Length check. Call it after
+    /// `route_meta`, which it reads.
+    fn default_body_limit(self, max_bytes: usize) -> Self;
+}
+
+impl BodyLimitExt for RustApi {
+    fn default_body_limit(self, max_bytes: usize) -> Self {
+        self.no_body_limit().layer(BodyLimitLayer::new(max_bytes))
+    }
+}
+
+fn payload_too_large(limit: usize) -> Response {
+    ApiError::new(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        "payload_too_large",
+        format!("Request body exceeds limit of {} bytes", limit),
+    )
+    .into_response()
+}
+
+/// The same request, with `body` as its (buffered) body.
+pub fn with_body(mut req: Request, body: Bytes) -> Request {
+    let mut builder = http::Request::builder()
+        .method(req.method().clone())
+        .uri(req.uri().clone())
+        .version(req.version());
+    if let Some(headers) = builder.headers_mut() {
+        *headers = req.headers().clone();
+    }
+    if let Some(extensions) = builder.extensions_mut() {
+        *extensions = std::mem::take(req.extensions_mut());
+    }
+    let (parts, ()) = builder
+        .body(())
+        .expect("parts copied from a valid request")
+        .into_parts();
+
+    Request::new(
+        parts,
+        BodyVariant::Buffered(body),
+        req.state().clone(),
+        PathParams::clone(req.path_params()),
+    )
+}
+
+impl MiddlewareLayer for BodyLimitLayer {
+    fn call(
+        &self,
+        mut req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let limit = req
+            .route_meta::<BodyLimit>()
+            .map_or(self.default, |limit| limit.0);
+
+        Box::pin(async move {
+            if let Some(declared) = req.headers().get(CONTENT_LENGTH) {
+                // One that isn't a number is ContentLengthLayer's 400.
+                let declared = declared
+                    .to_str()
+                    .ok()
+                    .and_then(|value| value.trim().parse::<usize>().ok());
+                if declared.is_some_and(|length| length > limit) {
+                    return payload_too_large(limit);
+                }
+                return next(req).await;
+            }
+            // No stream: already buffered (in-process dispatch) or no body.
+            let Some(stream) = req.take_stream() else {
+                return next(req).await;
+            };
+
+            let body = match Limited::new(stream, limit).collect().await {
+                Ok(collected) => collected.to_bytes(),
+                Err(err) if err.is::<LengthLimitError>() => return payload_too_large(limit),
+                Err(err) => return ApiError::bad_request(err.to_string()).into_response(),
+            };
+            next(with_body(req, body)).await
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::server::conn::http1;
+    use hyper::service::service_fn;
+    use hyper_util::rt::TokioIo;
+    use std::convert::Infallible;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const LIMIT: usize = 16;
+
+    /// Sends `raw` over an in-memory HTTP/1 connection to a 16-byte
+    /// BodyLimitLayer, in front of a handler that echoes the body's length.
+    /// Returns the raw response.
+    async fn exchange(raw: &str) -> String {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let service = service_fn(|req: hyper::Request<hyper::body::Incoming>| async move {
+            let (parts, body) = req.into_parts();
+            let req = Request::new(
+                parts,
+                BodyVariant::Streaming(body),
+                Arc::new(http::Extensions::new()),
+                PathParams::new(),
+            );
+            let next: BoxedNext = Arc::new(|mut req: Request| {
+                Box::pin(async move {
+                    let body = req.take_body().unwrap_or_default();
+                    format!("read {} bytes", body.len()).into_response()
+                })
+            });
+            Ok::<_, Infallible>(BodyLimitLayer::new(LIMIT).call(req, next).await)
+        });
+        tokio::spawn(http1::Builder::new().serve_connection(TokioIo::new(server), service));
+
+        let (mut read, mut write) = tokio::io::split(client);
+        write.write_all(raw.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        read.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    fn chunked(chunks: &[&str]) -> String {
+        let mut raw = "POST /upload HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\
+                       Transfer-Encoding: chunked\r\n\r\n"
+            .to_string();
+        for chunk in chunks {
+            raw.push_str(&format!("{:x}\r\n{}\r\n", chunk.len(), chunk));
+        }
+        raw + "0\r\n\r\n"
+    }
+
+    #[tokio::test]
+    async fn chunked_body_over_the_limit_is_413() {
+        let response = exchange(&chunked(&["0123456789", "0123456789"])).await;
+        assert!(response.starts_with("HTTP/1.1 413"), "{}", response);
+        assert!(
+            response.contains("exceeds limit of 16 bytes"),
+            "{}",
+            response
+        );
+    }
+
+    #[tokio::test]
+    async fn chunked_body_within_the_limit_is_buffered() {
+        let response = exchange(&chunked(&["01234567", "89abcdef"])).await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.ends_with("read 16 bytes"), "{}", response);
+    }
+
+    #[tokio::test]
+    async fn declared_length_over_the_limit_is_413_unread() {
+        // Only the headers are sent: the 413 mustn't wait for the body.
+        let response = exchange(
+            "POST /upload HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\
+             Content-Length: 17\r\n\r\n",
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 413"), "{}", response);
+    }
+}
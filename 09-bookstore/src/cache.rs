@@ -0,0 +1,136 @@
+// ---------------------------------------------------------------------------
+// Per-response caching policy
+// ---------------------------------------------------------------------------
+//
+// A global cache layer gives every response the same policy. Catalogue data
+// can sit in shared caches for minutes; anything about *this* customer must
+// never leave their browser. CacheControl builds the header value, Cached
+// attaches it to one response.
+
+use http::header::{HeaderValue, CACHE_CONTROL};
+use rustapi_openapi::{OpenApiSpec, Operation, ResponseModifier};
+use rustapi_rs::prelude::*;
+use std::fmt;
+
+/// A `Cache-Control` response header value.
+///
+/// ```ignore
+/// CacheControl::public().max_age(300).stale_while_revalidate(60)
+/// // public, max-age=300, stale-while-revalidate=60
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheControl {
+    public: bool,
+    private: bool,
+    no_store: bool,
+    must_revalidate: bool,
+    max_age: Option<u32>,
+    stale_while_revalidate: Option<u32>,
+}
+
+impl CacheControl {
+    /// Any cache may store the response, shared ones (CDNs, proxies) included.
+    pub fn public() -> Self {
+        Self {
+            public: true,
+            ..Self::default()
+        }
+    }
+
+    /// Only the client's own cache may store the response.
+    pub fn private() -> Self {
+        Self {
+            private: true,
+            ..Self::default()
+        }
+    }
+
+    /// Must not be stored at all, not even by the client.
+    pub fn no_store(mut self) -> Self {
+        self.no_store = true;
+        self
+    }
+
+    /// Fresh for `seconds` after the response was generated.
+    pub fn max_age(mut self, seconds: u32) -> Self {
+        self.max_age = Some(seconds);
+        self
+    }
+
+    /// Once stale, must not be served without revalidating.
+    pub fn must_revalidate(mut self) -> Self {
+        self.must_revalidate = true;
+        self
+    }
+
+    /// Once stale, may still be served for `seconds` while a refresh runs in
+    /// the background.
+    pub fn stale_while_revalidate(mut self, seconds: u32) -> Self {
+        self.stale_while_revalidate = Some(seconds);
+        self
+    }
+}
+
+impl fmt::Display for CacheControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut directives = Vec::new();
+        if self.public {
+            directives.push("public".to_string());
+        }
+        if self.private {
+            directives.push("private".to_string());
+        }
+        if self.no_store {
+            directives.push("no-store".to_string());
+        }
+        if let Some(seconds) = self.max_age {
+            directives.push(format!("max-age={}", seconds));
+        }
+        if self.must_revalidate {
+            directives.push("must-revalidate".to_string());
+        }
+        if let Some(seconds) = self.stale_while_revalidate {
+            directives.push(format!("stale-while-revalidate={}", seconds));
+        }
+        f.write_str(&directives.join(", "))
+    }
+}
+
+/// Wraps a response and sets its `Cache-Control` header.
+///
+/// Return it inside `Result<Cached<_>, ApiError>` so errors keep the
+/// framework's defaults instead of being cached with the success policy.
+pub struct Cached<T>(pub T, pub CacheControl);
+
+impl<T: IntoResponse> IntoResponse for Cached<T> {
+    fn into_response(self) -> Response {
+        let Cached(inner, policy) = self;
+        let mut response = inner.into_response();
+        let value = HeaderValue::from_str(&policy.to_string()).expect("directives are plain ASCII");
+        response.headers_mut().insert(CACHE_CONTROL, value);
+        response
+    }
+}
+
+impl<T: ResponseModifier> ResponseModifier for Cached<T> {
+    fn update_response(op: &mut Operation) {
+        T::update_response(op);
+        // The policy is chosen at runtime, so only the header itself is documented.
+        for (status, response) in op.responses.iter_mut() {
+            if status.starts_with('2') {
+                // NOTE: rustapi_openapi doesn't export its Header type by name;
+                //       serde fills it in from the map's value type.
+                let header = serde_json::from_value(serde_json::json!({
+                    "description": "Caching policy for this response",
+                    "schema": { "type": "string" }
+                }))
+                .expect("valid OpenAPI header object");
+                response.headers.insert("Cache-Control".to_string(), header);
+            }
+        }
+    }
+
+    fn register_components(spec: &mut OpenApiSpec) {
+        T::register_components(spec);
+    }
+}
@@ -0,0 +1,244 @@
+// ---------------------------------------------------------------------------
+// Canary releases
+// ---------------------------------------------------------------------------
+//
+// A new implementation of a route goes to a share of clients first. The
+// layer decides who gets which; the handler just asks:
+//
+//   CanaryLayer::new("search-v2", 10).route(Method::GET, "/books/search")
+//
+//   async fn search_books(variant: Variant, ...) -> ... {
+//       match variant {
+//           Variant::Stable => ...,
+//           Variant::Canary => ...,
+//       }
+//   }
+//
+// RustAPI picks the handler before any layer runs, so the layer can't send
+// the request to another route. It puts the `Variant` in the request
+// extensions instead, and the handler branches on it.
+//
+// Who gets the canary, in order:
+//
+//   X-Canary: canary | stable   forces it, for testing either side by hand
+//   the client cookie           SHA-256 of the experiment name and the
+//                               cookie's value, mapped to a bucket 0..100;
+//                               buckets below `percent` get the canary
+//
+// A client without the cookie is given one (random, a year long), so every
+// later request lands in the same bucket: no flip-flopping between the two
+// implementations mid-session. Raising `percent` only adds clients to the
+// canary, it never moves one back. The experiment name is part of the hash,
+// so the first 10% of one experiment aren't the first 10% of every other.
+//
+// Every response from a watched route says what it got, `X-Canary: stable`
+// or `X-Canary: canary`, so a bug report can say which one it was.
+//
+// FlagsLayer (src/flags.rs) buckets by the same cookie. Whichever layer
+// sees a new client first gives it the id; the other buckets by that id,
+// and the client gets one cookie.
+//
+// NOTE: routes are matched by method and exact path, like SloLayer. One
+//       layer is one experiment; a second experiment on the same route
+//       would overwrite the first one's `Variant`.
+
+use http::header::{COOKIE, SET_COOKIE};
+use http::{HeaderName, HeaderValue, Method};
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_openapi::{Operation, OperationModifier, Parameter, SchemaRef};
+use rustapi_rs::prelude::*;
+use rustapi_rs::FromRequestParts;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// Request header that forces a variant, and response header that reports it.
+pub const CANARY_HEADER: HeaderName = HeaderName::from_static("x-canary");
+
+/// The cookie clients are bucketed by.
+pub const CLIENT_COOKIE: &str = "bookstore_client";
+
+/// How long the client cookie lives.
+const CLIENT_COOKIE_MAX_AGE: u32 = 365 * 24 * 60 * 60;
+
+/// Longest cookie value hashed as is; anything longer is treated as absent.
+const MAX_CLIENT_ID: usize = 64;
+
+/// Which implementation a request gets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Variant {
+    #[default]
+    Stable,
+    Canary,
+}
+
+impl Variant {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Stable => "stable",
+            Self::Canary => "canary",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            v if v.eq_ignore_ascii_case("stable") => Some(Self::Stable),
+            v if v.eq_ignore_ascii_case("canary") => Some(Self::Canary),
+            _ => None,
+        }
+    }
+}
+
+/// The variant `CanaryLayer` assigned. Stable on routes it doesn't watch.
+impl FromRequestParts for Variant {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        Ok(req
+            .extensions()
+            .get::<Variant>()
+            .copied()
+            .unwrap_or_default())
+    }
+}
+
+impl OperationModifier for Variant {
+    fn update_operation(op: &mut Operation) {
+        op.parameters.push(Parameter {
+            name: CANARY_HEADER.to_string(),
+            location: "header".to_string(),
+            description: Some(
+                "Force `stable` or `canary`; without it, the client cookie decides".to_string(),
+            ),
+            required: false,
+            deprecated: None,
+            schema: Some(SchemaRef::Inline(
+                serde_json::json!({ "type": "string", "enum": ["stable", "canary"] }),
+            )),
+        });
+    }
+}
+
+#[derive(Clone)]
+pub struct CanaryLayer {
+    experiment: Arc<str>,
+    percent: u8,
+    routes: Vec<(Method, String)>,
+    rng: SystemRandom,
+}
+
+impl CanaryLayer {
+    /// `percent` (0–100) of clients get the canary of `experiment`.
+    pub fn new(experiment: &str, percent: u8) -> Self {
+        Self {
+            experiment: experiment.into(),
+            percent: percent.min(100),
+            routes: Vec::new(),
+            rng: SystemRandom::new(),
+        }
+    }
+
+    /// Assign a variant on `method path`.
+    pub fn route(mut self, method: Method, path: &str) -> Self {
+        self.routes.push((method, path.to_string()));
+        self
+    }
+}
+
+/// The client's bucket, 0..100, for `experiment` (an experiment or a flag).
+pub fn bucket(experiment: &str, client_id: &str) -> u8 {
+    let mut ctx = digest::Context::new(&digest::SHA256);
+    ctx.update(experiment.as_bytes());
+    ctx.update(b"\0");
+    ctx.update(client_id.as_bytes());
+    let hash = ctx.finish();
+    let head = u32::from_be_bytes(hash.as_ref()[..4].try_into().unwrap());
+    (head % 100) as u8
+}
+
+/// An id a layer gave a new client on this request. Layers further in use
+/// it instead of giving out one of their own, so the client gets one cookie.
+#[derive(Clone)]
+struct AssignedClientId(String);
+
+/// The client's id: its cookie, else the id an outer layer just gave it,
+/// else a new one. `true` when new: the caller sends it back with
+/// `client_cookie_header`.
+pub fn client_id(req: &mut Request, rng: &SystemRandom) -> Option<(String, bool)> {
+    if let Some(id) = client_cookie(req) {
+        return Some((id, false));
+    }
+    if let Some(AssignedClientId(id)) = req.extensions().get::<AssignedClientId>() {
+        return Some((id.clone(), false));
+    }
+    let mut bytes = [0u8; 16];
+    rng.fill(&mut bytes).ok()?;
+    let id: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    req.extensions_mut().insert(AssignedClientId(id.clone()));
+    Some((id, true))
+}
+
+/// `Set-Cookie` for an id `client_id` gave out.
+pub fn client_cookie_header(id: &str) -> Option<HeaderValue> {
+    let cookie = format!(
+        "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Lax",
+        CLIENT_COOKIE, id, CLIENT_COOKIE_MAX_AGE
+    );
+    HeaderValue::try_from(cookie).ok()
+}
+
+fn client_cookie(req: &Request) -> Option<String> {
+    req.headers()
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == CLIENT_COOKIE)
+        .map(|(_, value)| value.to_string())
+        .filter(|value| !value.is_empty() && value.len() <= MAX_CLIENT_ID)
+}
+
+impl MiddlewareLayer for CanaryLayer {
+    fn call(
+        &self,
+        mut req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let watched = self
+            .routes
+            .iter()
+            .any(|(method, path)| method == req.method() && path == req.path());
+        if !watched {
+            return Box::pin(next(req));
+        }
+
+        let forced = req
+            .headers()
+            .get(&CANARY_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Variant::parse);
+        // A new client is bucketed by the id it is about to be given.
+        let client = client_id(&mut req, &self.rng);
+        let variant = forced.unwrap_or_else(|| match &client {
+            Some((id, _)) if bucket(&self.experiment, id) < self.percent => Variant::Canary,
+            _ => Variant::Stable,
+        });
+        let new_id = client.and_then(|(id, new)| new.then_some(id));
+
+        req.extensions_mut().insert(variant);
+        Box::pin(async move {
+            let mut response = next(req).await;
+            let headers = response.headers_mut();
+            headers.insert(CANARY_HEADER, HeaderValue::from_static(variant.as_str()));
+            if let Some(cookie) = new_id.as_deref().and_then(client_cookie_header) {
+                headers.append(SET_COOKIE, cookie);
+            }
+            response
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,320 @@
+// ---------------------------------------------------------------------------
+// Response compression
+// ---------------------------------------------------------------------------
+//
+// JSON compresses well: a 2000-book `GET /books` shrinks to a fraction of its
+// size. CompressionLayer picks an encoding from Accept-Encoding and
+// compresses responses that are worth it:
+//
+//   .layer(CompressionLayer::new().min_size(1024).algorithms(&[Algorithm::Gzip, Algorithm::Deflate]))
+//
+// The encoding is the one with the highest q-value among `algorithms`; ties
+// go to the order given. `identity;q=0` or a missing header don't force
+// anything: the response is simply sent as it is.
+//
+// Left alone, with reasons:
+//
+//   streamed bodies          SSE, NDJSON, `Render::Streamed`: compressing
+//                            means buffering, and their point is not to
+//   text/event-stream        even if it were buffered
+//   images, video, zip, ...  only the types in COMPRESSIBLE are tried; the
+//                            rest are compressed already
+//   under `min_size`         the gzip framing alone is 18 bytes
+//   Content-Encoding set     compressed by the handler
+//   Cache-Control:           the response asks intermediaries not to
+//     no-transform           change it; we are one
+//   204, 206, 304, HEAD      no body, or a byte range of the plain one
+//   no gain                  output not smaller than the input: sent plain
+//
+// Every response that could have been compressed gets
+// `Vary: Accept-Encoding`, whichever way it went, so caches don't hand a
+// gzip body to a client that never asked for one. A strong ETag on a
+// compressed response is made weak: the bytes differ from the plain one's.
+//
+// NOTE: the encoders are flate2's, at its default level; brotli has no
+//       encoder here (see "Compression" in the README).
+
+use bytes::Bytes;
+use flate2::write::{GzEncoder, ZlibEncoder};
+use http::header::{
+    ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, ETAG, VARY,
+};
+use http::{HeaderValue, Method};
+use http_body_util::BodyExt;
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_rs::prelude::*;
+use rustapi_rs::ResponseBody;
+use std::future::Future;
+use std::io::Write;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// Content types worth compressing: text and text-like structured data.
+/// A `+json` or `+xml` suffix counts too.
+const COMPRESSIBLE: &[&str] = &[
+    "text/",
+    "application/json",
+    "application/javascript",
+    "application/xml",
+    "application/x-ndjson",
+    "image/svg+xml",
+];
+
+/// Never compressed, even when buffered.
+const STREAMING: &[&str] = &["text/event-stream"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Gzip,
+    /// zlib-wrapped DEFLATE, which is what `deflate` means in HTTP.
+    Deflate,
+}
+
+impl Algorithm {
+    pub fn content_encoding(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Deflate => "deflate",
+        }
+    }
+
+    pub(crate) fn encode(self, data: &[u8]) -> Vec<u8> {
+        let level = flate2::Compression::default();
+        let encoded = match self {
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), level);
+                encoder.write_all(data).and_then(|()| encoder.finish())
+            }
+            Self::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), level);
+                encoder.write_all(data).and_then(|()| encoder.finish())
+            }
+        };
+        encoded.expect("writing to a Vec doesn't fail")
+    }
+}
+
+#[derive(Clone)]
+pub struct CompressionLayer {
+    min_size: usize,
+    algorithms: Arc<[Algorithm]>,
+}
+
+impl Default for CompressionLayer {
+    fn default() -> Self {
+        Self {
+            min_size: 1024,
+            algorithms: Arc::new([Algorithm::Gzip, Algorithm::Deflate]),
+        }
+    }
+}
+
+impl CompressionLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bodies shorter than this are sent as they are. Default 1024 bytes.
+    pub fn min_size(mut self, bytes: usize) -> Self {
+        self.min_size = bytes;
+        self
+    }
+
+    /// The encodings offered, in server preference order (breaks q-value
+    /// ties). Default gzip, then deflate.
+    pub fn algorithms(mut self, algorithms: &[Algorithm]) -> Self {
+        self.algorithms = algorithms.into();
+        self
+    }
+
+    fn negotiate(&self, accept_encoding: &str) -> Option<Algorithm> {
+        negotiate(&self.algorithms, accept_encoding)
+    }
+}
+
+/// The best of `algorithms` for this Accept-Encoding, if any.
+pub(crate) fn negotiate(algorithms: &[Algorithm], accept_encoding: &str) -> Option<Algorithm> {
+    let entries: Vec<(String, f32)> = accept_encoding
+        .split(',')
+        .filter_map(|element| {
+            let mut parts = element.split(';').map(str::trim);
+            let coding = parts.next().filter(|coding| !coding.is_empty())?;
+            let mut q = 1.0;
+            for param in parts {
+                if let Some((name, value)) = param.split_once('=') {
+                    if name.trim().eq_ignore_ascii_case("q") {
+                        q = value
+                            .trim()
+                            .parse()
+                            .ok()
+                            .filter(|q| (0.0..=1.0).contains(q))?;
+                    }
+                }
+            }
+            Some((coding.to_ascii_lowercase(), q))
+        })
+        .collect();
+
+    // An algorithm's own entry wins over `*`.
+    let quality = |name: &str| {
+        let named = entries.iter().find(|(coding, _)| coding == name);
+        let any = entries.iter().find(|(coding, _)| coding == "*");
+        named.or(any).map_or(0.0, |(_, q)| *q)
+    };
+    let mut best: Option<(Algorithm, f32)> = None;
+    for &algorithm in algorithms {
+        let q = quality(algorithm.content_encoding());
+        if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+            best = Some((algorithm, q));
+        }
+    }
+    best.map(|(algorithm, _)| algorithm)
+}
+
+fn header(response: &Response, name: http::header::HeaderName) -> &str {
+    response
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+}
+
+/// Whether `response` is a candidate at all, whatever the client accepts.
+fn compressible(response: &Response, head: bool) -> bool {
+    let status = response.status();
+    let content_type = header(response, CONTENT_TYPE).to_ascii_lowercase();
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+
+    !head
+        && status != StatusCode::NO_CONTENT
+        && status != StatusCode::PARTIAL_CONTENT
+        && status != StatusCode::NOT_MODIFIED
+        && matches!(response.body(), ResponseBody::Full(_))
+        && !response.headers().contains_key(CONTENT_ENCODING)
+        && !header(response, CACHE_CONTROL)
+            .split(',')
+            .any(|directive| directive.trim().eq_ignore_ascii_case("no-transform"))
+        && !STREAMING.contains(&essence)
+        && (COMPRESSIBLE
+            .iter()
+            .any(|prefix| essence.starts_with(prefix))
+            || essence.ends_with("+json")
+            || essence.ends_with("+xml"))
+}
+
+fn add_vary(response: &mut Response) {
+    let vary = header(response, VARY);
+    if vary
+        .split(',')
+        .any(|name| name.trim() == "*" || name.trim().eq_ignore_ascii_case("accept-encoding"))
+    {
+        return;
+    }
+    let value = if vary.is_empty() {
+        "Accept-Encoding".to_string()
+    } else {
+        format!("{}, Accept-Encoding", vary)
+    };
+    if let Ok(value) = HeaderValue::try_from(value) {
+        response.headers_mut().insert(VARY, value);
+    }
+}
+
+impl MiddlewareLayer for CompressionLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let algorithm = req
+            .headers()
+            .get_all(ACCEPT_ENCODING)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+        let algorithm = self.negotiate(&algorithm);
+        let head = req.method() == Method::HEAD;
+        let min_size = self.min_size;
+
+        Box::pin(async move {
+            let mut response = next(req).await;
+            if !compressible(&response, head) {
+                return response;
+            }
+            add_vary(&mut response);
+            let Some(algorithm) = algorithm else {
+                return response;
+            };
+
+            let (mut parts, body) = response.into_parts();
+            // A Full body: already in memory, and collecting it can't fail.
+            let plain = match body.collect().await {
+                Ok(collected) => collected.to_bytes(),
+                Err(err) => return err.into_response(),
+            };
+            if plain.len() < min_size {
+                return Response::from_parts(parts, ResponseBody::new(plain));
+            }
+            let encoded = algorithm.encode(&plain);
+            if encoded.len() >= plain.len() {
+                return Response::from_parts(parts, ResponseBody::new(plain));
+            }
+
+            parts.headers.insert(
+                CONTENT_ENCODING,
+                HeaderValue::from_static(algorithm.content_encoding()),
+            );
+            parts.headers.remove(CONTENT_LENGTH);
+            if let Some(etag) = parts.headers.get(ETAG).and_then(|v| v.to_str().ok()) {
+                if !etag.starts_with("W/") {
+                    if let Ok(weak) = HeaderValue::try_from(format!("W/{}", etag)) {
+                        parts.headers.insert(ETAG, weak);
+                    }
+                }
+            }
+            Response::from_parts(parts, ResponseBody::new(Bytes::from(encoded)))
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::{GzDecoder, ZlibDecoder};
+    use std::io::Read;
+
+    #[test]
+    fn both_encodings_round_trip_and_shrink_json() {
+        let books: Vec<_> = (0..500)
+            .map(|id| serde_json::json!({ "id": id, "title": format!("Book {id}") }))
+            .collect();
+        let json = serde_json::to_vec(&books).unwrap();
+
+        let gzip = Algorithm::Gzip.encode(&json);
+        assert_eq!(gzip[..2], [0x1f, 0x8b]);
+        let mut decoded = Vec::new();
+        GzDecoder::new(&gzip[..]).read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, json);
+
+        // `deflate` in HTTP is the zlib framing: CMF 0x78, then FLG.
+        let zlib = Algorithm::Deflate.encode(&json);
+        assert_eq!(zlib[0], 0x78);
+        let mut decoded = Vec::new();
+        ZlibDecoder::new(&zlib[..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, json);
+
+        assert!(
+            gzip.len() < json.len() / 4,
+            "{} of {}",
+            gzip.len(),
+            json.len()
+        );
+    }
+}
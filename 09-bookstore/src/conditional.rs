@@ -0,0 +1,318 @@
+// ---------------------------------------------------------------------------
+// Conditional GETs in the handler
+// ---------------------------------------------------------------------------
+//
+// ETagLayer (src/etag.rs) answers If-None-Match after the handler has built
+// the whole body. A handler that knows its resource's version up front can
+// answer before doing that work:
+//
+//   async fn get_book(.., conditions: Preconditions) -> Result<Conditional<..>, ApiError> {
+//       let etag = ..; let modified = ..;
+//       if let Some(not_modified) = precondition(&etag, modified, &conditions) {
+//           return Ok(not_modified.into());
+//       }
+//       Ok(Conditional::fresh(body, etag, modified))
+//   }
+//
+//   If-None-Match sent              304 if it names the ETag (weak comparison,
+//                                   `*` matches anything); If-Modified-Since
+//                                   is then ignored (RFC 9110 §13.2.2)
+//   only If-Modified-Since sent     304 if last_modified is no later
+//   neither, or a date that         None: build the full response
+//     doesn't parse
+//
+// Both the 304 and the full 200 carry ETag and Last-Modified, so the client
+// has them to send next time. ETagLayer keeps an ETag a handler set.
+//
+// NOTE: only for GET and HEAD. For writes, If-Match and If-Unmodified-Since
+//       decide a 412 instead, which this doesn't cover.
+
+use http::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use http::{HeaderMap, HeaderValue};
+use rustapi_openapi::{
+    OpenApiSpec, Operation, OperationModifier, Parameter, ResponseModifier, ResponseSpec, SchemaRef,
+};
+use rustapi_rs::prelude::*;
+use rustapi_rs::{FromRequestParts, ResponseBody};
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+/// Whether `If-None-Match` names `etag`, by weak comparison.
+pub(crate) fn none_match_fails(if_none_match: &HeaderValue, etag: &HeaderValue) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let Ok(etag) = etag.to_str() else {
+        return false;
+    };
+    if_none_match.to_str().is_ok_and(|list| {
+        list.split(',')
+            .any(|tag| tag.trim() == "*" || opaque(tag) == opaque(etag))
+    })
+}
+
+/// Whether `last_modified` is no later than `If-Modified-Since`. An
+/// unparseable date means "modified": the full response is the safe one.
+pub(crate) fn unmodified_since(
+    if_modified_since: &HeaderValue,
+    last_modified: Option<SystemTime>,
+) -> bool {
+    let since = if_modified_since
+        .to_str()
+        .ok()
+        .and_then(|value| httpdate::parse_http_date(value).ok());
+    match (last_modified, since) {
+        // HTTP dates have whole seconds; the resource's time may not.
+        (Some(modified), Some(since)) => httpdate::HttpDate::from(modified) <= since.into(),
+        _ => false,
+    }
+}
+
+/// The `If-None-Match` and `If-Modified-Since` headers of a request.
+#[derive(Debug, Clone, Default)]
+pub struct Preconditions {
+    if_none_match: Option<HeaderValue>,
+    if_modified_since: Option<HeaderValue>,
+}
+
+impl Preconditions {
+    /// Whether a client holding `etag` / `last_modified` already has the
+    /// current representation.
+    pub fn unchanged(&self, etag: &HeaderValue, last_modified: Option<SystemTime>) -> bool {
+        match (&self.if_none_match, &self.if_modified_since) {
+            (Some(wanted), _) => none_match_fails(wanted, etag),
+            (None, Some(since)) => unmodified_since(since, last_modified),
+            (None, None) => false,
+        }
+    }
+}
+
+impl FromRequestParts for Preconditions {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        Ok(Self {
+            if_none_match: req.headers().get(IF_NONE_MATCH).cloned(),
+            if_modified_since: req.headers().get(IF_MODIFIED_SINCE).cloned(),
+        })
+    }
+}
+
+impl OperationModifier for Preconditions {
+    fn update_operation(op: &mut Operation) {
+        for (name, description) in [
+            (
+                "If-None-Match",
+                "ETags of copies the client has; 304 if one is current",
+            ),
+            (
+                "If-Modified-Since",
+                "304 if unchanged since; ignored with If-None-Match",
+            ),
+        ] {
+            op.parameters.push(Parameter {
+                name: name.to_string(),
+                location: "header".to_string(),
+                description: Some(description.to_string()),
+                required: false,
+                deprecated: None,
+                schema: Some(SchemaRef::Inline(serde_json::json!({ "type": "string" }))),
+            });
+        }
+    }
+}
+
+/// A resource's ETag and Last-Modified, as response headers.
+#[derive(Debug, Clone)]
+pub struct Validators {
+    etag: HeaderValue,
+    last_modified: Option<SystemTime>,
+}
+
+impl Validators {
+    fn apply(&self, headers: &mut HeaderMap) {
+        headers.insert(ETAG, self.etag.clone());
+        if let Some(modified) = self.last_modified {
+            let date = httpdate::fmt_http_date(modified);
+            headers.insert(
+                LAST_MODIFIED,
+                HeaderValue::try_from(date).expect("an HTTP date is a valid header value"),
+            );
+        }
+    }
+}
+
+/// `304 Not Modified`: the client's copy is current. No body.
+#[derive(Debug, Clone)]
+pub struct NotModified(Validators);
+
+impl IntoResponse for NotModified {
+    fn into_response(self) -> Response {
+        let mut response = Response::new(ResponseBody::empty());
+        *response.status_mut() = StatusCode::NOT_MODIFIED;
+        self.0.apply(response.headers_mut());
+        response
+    }
+}
+
+/// The 304 to return if `req`'s conditions say the client already has the
+/// representation tagged `etag`, last changed at `last_modified`.
+pub fn precondition(
+    etag: &HeaderValue,
+    last_modified: Option<SystemTime>,
+    req: &Preconditions,
+) -> Option<NotModified> {
+    req.unchanged(etag, last_modified).then(|| {
+        NotModified(Validators {
+            etag: etag.clone(),
+            last_modified,
+        })
+    })
+}
+
+/// A handler's answer to a conditional GET: the 304, or `T` with its
+/// validators.
+pub enum Conditional<T> {
+    NotModified(NotModified),
+    Fresh(T, Validators),
+}
+
+impl<T> Conditional<T> {
+    /// `body`, sent with `etag` and `last_modified`.
+    pub fn fresh(body: T, etag: HeaderValue, last_modified: Option<SystemTime>) -> Self {
+        Self::Fresh(
+            body,
+            Validators {
+                etag,
+                last_modified,
+            },
+        )
+    }
+}
+
+impl<T> From<NotModified> for Conditional<T> {
+    fn from(not_modified: NotModified) -> Self {
+        Self::NotModified(not_modified)
+    }
+}
+
+impl<T: IntoResponse> IntoResponse for Conditional<T> {
+    fn into_response(self) -> Response {
+        match self {
+            Self::NotModified(not_modified) => not_modified.into_response(),
+            Self::Fresh(body, validators) => {
+                let mut response = body.into_response();
+                if response.status().is_success() {
+                    validators.apply(response.headers_mut());
+                }
+                response
+            }
+        }
+    }
+}
+
+impl<T: ResponseModifier> ResponseModifier for Conditional<T> {
+    fn update_response(op: &mut Operation) {
+        T::update_response(op);
+        // NOTE: rustapi_openapi doesn't export its Header type by name;
+        //       serde fills it in from the map's value type.
+        let header = |description: &str| {
+            serde_json::from_value(serde_json::json!({
+                "description": description,
+                "schema": { "type": "string" }
+            }))
+            .expect("valid OpenAPI header object")
+        };
+        let mut headers = BTreeMap::new();
+        headers.insert("ETag".to_string(), header("Current version"));
+        headers.insert("Last-Modified".to_string(), header("Last change"));
+
+        if let Some(ok) = op.responses.get_mut("200") {
+            ok.headers.extend(headers.clone());
+        }
+        op.responses.insert(
+            "304".to_string(),
+            ResponseSpec {
+                description: "Not modified: the client's copy is current".to_string(),
+                content: BTreeMap::new(),
+                headers,
+            },
+        );
+    }
+
+    fn register_components(spec: &mut OpenApiSpec) {
+        T::register_components(spec);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    const ETAG_VALUE: &str = "\"v7\"";
+
+    fn modified() -> SystemTime {
+        // 2024-01-01T00:00:00.5Z: not on a whole second.
+        UNIX_EPOCH + Duration::from_millis(1_704_067_200_500)
+    }
+
+    fn conditions(
+        if_none_match: Option<&'static str>,
+        since: Option<&'static str>,
+    ) -> Preconditions {
+        Preconditions {
+            if_none_match: if_none_match.map(HeaderValue::from_static),
+            if_modified_since: since.map(HeaderValue::from_static),
+        }
+    }
+
+    fn answer(conditions: &Preconditions) -> Response {
+        let etag = HeaderValue::from_static(ETAG_VALUE);
+        match precondition(&etag, Some(modified()), conditions) {
+            Some(not_modified) => Conditional::<&str>::from(not_modified),
+            None => Conditional::fresh("the book", etag, Some(modified())),
+        }
+        .into_response()
+    }
+
+    #[test]
+    fn if_none_match_with_the_etag_is_304() {
+        for header in ["\"v7\"", "W/\"v7\"", "\"v6\", \"v7\"", "*"] {
+            let response = answer(&conditions(Some(header), None));
+            assert_eq!(response.status(), StatusCode::NOT_MODIFIED, "{}", header);
+            assert_eq!(response.headers()[ETAG], ETAG_VALUE);
+            assert_eq!(
+                response.headers()[LAST_MODIFIED],
+                "Mon, 01 Jan 2024 00:00:00 GMT"
+            );
+        }
+    }
+
+    #[test]
+    fn if_none_match_with_another_etag_is_200() {
+        let response = answer(&conditions(Some("\"v6\""), None));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[ETAG], ETAG_VALUE);
+    }
+
+    #[test]
+    fn if_modified_since_not_before_the_change_is_304() {
+        // The same second as the change, with its fraction dropped.
+        let same = answer(&conditions(None, Some("Mon, 01 Jan 2024 00:00:00 GMT")));
+        assert_eq!(same.status(), StatusCode::NOT_MODIFIED);
+        let later = answer(&conditions(None, Some("Tue, 02 Jan 2024 00:00:00 GMT")));
+        assert_eq!(later.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[test]
+    fn if_modified_since_before_the_change_is_200() {
+        let earlier = answer(&conditions(None, Some("Sun, 31 Dec 2023 23:59:59 GMT")));
+        assert_eq!(earlier.status(), StatusCode::OK);
+        let unparseable = answer(&conditions(None, Some("yesterday")));
+        assert_eq!(unparseable.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn if_none_match_overrides_if_modified_since() {
+        let stale_tag = conditions(Some("\"v6\""), Some("Tue, 02 Jan 2024 00:00:00 GMT"));
+        assert_eq!(answer(&stale_tag).status(), StatusCode::OK);
+        assert_eq!(answer(&conditions(None, None)).status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,144 @@
+// ---------------------------------------------------------------------------
+// 201 Created with Location
+// ---------------------------------------------------------------------------
+//
+// RustAPI's `Created<T>` answers 201 with a JSON body. REST clients also
+// expect to be told *where* the new resource lives, in a `Location` header,
+// so they can fetch it again without knowing the URL scheme.
+//
+// The location comes from a TypedPath — the route template as a type — and
+// ensure_mounted() checks at startup that the template is really a route.
+// Rename `/books/{id}` in the handler but not in BookPath and the app
+// refuses to start, instead of handing out dead links.
+
+use http::header::{HeaderValue, LOCATION};
+use rustapi_openapi::schema::{RustApiSchema, SchemaCtx};
+use rustapi_openapi::{MediaType, OpenApiSpec, Operation, ResponseModifier, ResponseSpec};
+use rustapi_rs::prelude::*;
+use std::collections::BTreeMap;
+
+/// `201 Created`, the new resource as JSON and its URL in `Location`.
+pub struct CreatedAt<T> {
+    location: String,
+    body: T,
+}
+
+impl<T> CreatedAt<T> {
+    /// `body`, created at `path` — `CreatedAt::at(&BookPath { id }, book)`.
+    pub fn at<P: TypedPath>(path: &P, body: T) -> Self {
+        Self {
+            location: path.to_uri(),
+            body,
+        }
+    }
+}
+
+/// Fails unless `P::PATH` is one of `app`'s routes.
+pub fn ensure_mounted<P: TypedPath>(app: &RustApi) -> Result<(), String> {
+    if app.openapi_spec().paths.contains_key(P::PATH) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Location headers point at {}, but no route is mounted there",
+            P::PATH
+        ))
+    }
+}
+
+impl<T: Serialize> IntoResponse for CreatedAt<T> {
+    fn into_response(self) -> Response {
+        let mut response = Created(self.body).into_response();
+        match HeaderValue::try_from(self.location) {
+            Ok(location) => {
+                response.headers_mut().insert(LOCATION, location);
+            }
+            // A TypedPath renders field values verbatim; ours are numbers.
+            Err(err) => error!("Dropped invalid Location header: {}", err),
+        }
+        response
+    }
+}
+
+impl<T: RustApiSchema> ResponseModifier for CreatedAt<T> {
+    fn update_response(op: &mut Operation) {
+        let mut ctx = SchemaCtx::new();
+        let mut content = BTreeMap::new();
+        content.insert(
+            "application/json".to_string(),
+            MediaType {
+                schema: Some(T::schema(&mut ctx)),
+                example: None,
+            },
+        );
+
+        // NOTE: rustapi_openapi doesn't export its Header type by name;
+        //       serde fills it in from the map's value type.
+        let mut headers = BTreeMap::new();
+        headers.insert(
+            "Location".to_string(),
+            serde_json::from_value(serde_json::json!({
+                "description": "URL of the created resource",
+                "schema": { "type": "string", "format": "uri-reference" }
+            }))
+            .expect("valid OpenAPI header object"),
+        );
+
+        op.responses.insert(
+            "201".to_string(),
+            ResponseSpec {
+                description: "Created".to_string(),
+                content,
+                headers,
+            },
+        );
+    }
+
+    fn register_components(spec: &mut OpenApiSpec) {
+        spec.register_in_place::<T>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Book, BookPath};
+    use http_body_util::BodyExt;
+
+    #[derive(Serialize)]
+    struct Stub {
+        id: u64,
+    }
+
+    async fn stub() -> &'static str {
+        "stub"
+    }
+
+    #[tokio::test]
+    async fn answers_201_with_location_and_body() {
+        let response = CreatedAt::at(&BookPath { id: 42 }, Stub { id: 42 }).into_response();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()[LOCATION], "/books/42");
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!({ "id": 42 })
+        );
+    }
+
+    #[test]
+    fn documents_the_location_header() {
+        let mut op = Operation::new();
+        CreatedAt::<Book>::update_response(&mut op);
+        assert!(op.responses["201"].headers.contains_key("Location"));
+    }
+
+    #[test]
+    fn location_template_must_be_a_route() {
+        let mounted = RustApi::new().route("/books/{id}", get(stub));
+        assert!(ensure_mounted::<BookPath>(&mounted).is_ok());
+
+        let renamed = RustApi::new().route("/book/{id}", get(stub));
+        assert!(ensure_mounted::<BookPath>(&renamed).is_err());
+    }
+}
@@ -0,0 +1,191 @@
+// ---------------------------------------------------------------------------
+// Opaque pagination cursors
+// ---------------------------------------------------------------------------
+//
+// `?page=3` means "skip 40 rows": an insert on page 1 shifts everything, so
+// the client sees a book twice or never, and the database still reads and
+// throws away the 40 rows. Keyset pagination says "after this row" instead —
+// the sort key and id of the last item seen — which stays put under inserts
+// and is an index seek.
+//
+// The position goes to the client as an opaque token:
+//
+//   base64url( JSON position ‖ HMAC-SHA256(key, JSON position) )
+//
+// Opaque, so clients can't build one and the format can change; signed, so
+// an edited cursor is a 400 rather than a query with attacker-chosen values.
+// Cursor<T> reads `?cursor=`, within the QueryLimit that Query<T> checks;
+// CursorKey::encode() makes the next one.
+//
+// NOTE: without CURSOR_SECRET the key is random per process, so cursors stop
+//       working on restart (400, start from the first page). Share the secret
+//       across instances behind a load balancer.
+
+use crate::query::QueryLimit;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ring::hmac;
+use rustapi_openapi::{Operation, OperationModifier, Parameter, SchemaRef};
+use rustapi_rs::prelude::*;
+use rustapi_rs::FromRequestParts;
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
+
+const TAG_LEN: usize = 32;
+
+/// Signs and checks cursors. Put one in app state.
+#[derive(Clone)]
+pub struct CursorKey(Arc<hmac::Key>);
+
+impl CursorKey {
+    pub fn from_secret(secret: &[u8]) -> Self {
+        Self(Arc::new(hmac::Key::new(hmac::HMAC_SHA256, secret)))
+    }
+
+    /// `CURSOR_SECRET` if set, else a random key for this process.
+    pub fn from_env() -> Self {
+        match std::env::var("CURSOR_SECRET") {
+            Ok(secret) if !secret.is_empty() => Self::from_secret(secret.as_bytes()),
+            _ => {
+                let rng = ring::rand::SystemRandom::new();
+                let key = hmac::Key::generate(hmac::HMAC_SHA256, &rng)
+                    .expect("system randomness is available");
+                Self(Arc::new(key))
+            }
+        }
+    }
+
+    /// The token for `position`, to hand back as `next_cursor`.
+    pub fn encode<T: Serialize>(&self, position: &T) -> String {
+        let mut token = serde_json::to_vec(position).expect("cursor position should serialize");
+        let tag = hmac::sign(&self.0, &token);
+        token.extend_from_slice(tag.as_ref());
+        URL_SAFE_NO_PAD.encode(token)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, token: &str) -> Result<T> {
+        let invalid = || ApiError::bad_request("Invalid cursor; start again without one");
+        let raw = URL_SAFE_NO_PAD.decode(token).map_err(|_| invalid())?;
+        if raw.len() <= TAG_LEN {
+            return Err(invalid());
+        }
+        let (position, tag) = raw.split_at(raw.len() - TAG_LEN);
+        hmac::verify(&self.0, position, tag).map_err(|_| invalid())?;
+        serde_json::from_slice(position).map_err(|_| invalid())
+    }
+}
+
+/// The position from `?cursor=`: None on the first page, or rejected with
+/// 400 if the token was edited, truncated or signed with another key.
+pub struct Cursor<T>(pub Option<T>);
+
+/// The one parameter Cursor<T> reads; the handler's Query<T> has the rest.
+#[derive(Deserialize)]
+struct CursorParam {
+    cursor: Option<String>,
+}
+
+impl<T: DeserializeOwned> FromRequestParts for Cursor<T> {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        // Through QueryLimit like Query<T>, so a query string that is too
+        // long or malformed (`?cursor=a&cursor=b`) is a 400 rather than a
+        // silent first page.
+        let CursorParam { cursor } = QueryLimit::of(req).parse(req.query_string().unwrap_or(""))?;
+        let Some(token) = cursor else {
+            return Ok(Self(None));
+        };
+
+        let key = req
+            .state()
+            .get::<CursorKey>()
+            .ok_or_else(|| ApiError::internal("CursorKey is not in app state"))?;
+        key.decode(&token).map(|position| Self(Some(position)))
+    }
+}
+
+impl<T> OperationModifier for Cursor<T> {
+    fn update_operation(op: &mut Operation) {
+        op.parameters.push(Parameter {
+            name: "cursor".to_string(),
+            location: "query".to_string(),
+            description: Some(
+                "`next_cursor` from the previous page; omit for the first page.".to_string(),
+            ),
+            required: false,
+            deprecated: None,
+            schema: Some(SchemaRef::Inline(serde_json::json!({ "type": "string" }))),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustapi_core::{BodyVariant, PathParams};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct After {
+        id: u64,
+    }
+
+    fn key() -> CursorKey {
+        CursorKey::from_secret(b"test secret")
+    }
+
+    fn extract(query: &str) -> Result<Option<After>> {
+        let (parts, ()) = http::Request::get(format!("/books/page?{query}"))
+            .body(())
+            .unwrap()
+            .into_parts();
+        let mut state = http::Extensions::new();
+        state.insert(key());
+        state.insert(QueryLimit(4));
+        let req = Request::new(
+            parts,
+            BodyVariant::Buffered(Default::default()),
+            Arc::new(state),
+            PathParams::new(),
+        );
+        Cursor::<After>::from_request_parts(&req).map(|Cursor(after)| after)
+    }
+
+    fn assert_bad_request(query: &str) {
+        let err = extract(query).expect_err(query);
+        assert_eq!(err.status, StatusCode::BAD_REQUEST, "{query}");
+    }
+
+    #[test]
+    fn a_cursor_round_trips_next_to_other_parameters() {
+        let token = key().encode(&After { id: 7 });
+        assert_eq!(
+            extract(&format!("limit=5&cursor={token}")).unwrap(),
+            Some(After { id: 7 })
+        );
+        assert_eq!(extract("limit=5").unwrap(), None);
+    }
+
+    #[test]
+    fn an_edited_or_foreign_cursor_is_a_400() {
+        let token = key().encode(&After { id: 7 });
+        let mut edited = token.clone().into_bytes();
+        edited[2] ^= 1;
+        assert_bad_request(&format!("cursor={}", String::from_utf8(edited).unwrap()));
+        assert_bad_request(&format!("cursor={}", &token[..token.len() - 4]));
+        assert_bad_request("cursor=");
+        let foreign = CursorKey::from_secret(b"another secret").encode(&After { id: 7 });
+        assert_bad_request(&format!("cursor={foreign}"));
+    }
+
+    #[test]
+    fn a_malformed_query_is_a_400_not_the_first_page() {
+        let token = key().encode(&After { id: 7 });
+        assert_bad_request(&format!("cursor={token}&cursor={token}"));
+        // Over the QueryLimit in state, before the cursor is looked at.
+        let err = extract(&format!("a=1&b=2&c=3&d=4&cursor={token}")).unwrap_err();
+        assert!(
+            err.message.contains("Too many query parameters"),
+            "{}",
+            err.message
+        );
+    }
+}
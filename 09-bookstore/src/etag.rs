@@ -0,0 +1,152 @@
+// ---------------------------------------------------------------------------
+// ETags and conditional GETs
+// ---------------------------------------------------------------------------
+//
+// A client that already has a response can ask "has it changed?" instead of
+// downloading it again. ETagLayer answers that for every GET and HEAD:
+//
+//   .layer(ETagLayer::new())
+//
+//   200 without an ETag        hash the body: ETag: "q3c1XWkYp0Gt0lJ2oIqk6w"
+//   200 with one               kept as it is (serve_static sets its own)
+//   If-None-Match matches      304, no body
+//   If-Modified-Since, and     304 if Last-Modified is no later; only asked
+//     no If-None-Match           when the response has Last-Modified
+//
+// If-None-Match uses the weak comparison (RFC 9110 §13.1.2): `W/"x"` and
+// `"x"` are the same tag, so one cached copy revalidates whether or not a
+// compression layer outside this one weakened it. A 304 keeps the headers
+// the 200 would have had — ETag, Cache-Control, Vary, Set-Cookie — minus
+// those that describe the body.
+//
+// Left alone: other methods, non-200 statuses, streamed bodies (hashing them
+// means buffering them) and routes with `NoETag` in their route metadata.
+//
+// NOTE: the handler still runs; the 304 saves the transfer, not the work.
+//       A handler that can tell its version cheaply (an updated_at column)
+//       should answer the conditions itself with `precondition`
+//       (src/conditional.rs) before it builds the body.
+
+use crate::conditional::{none_match_fails, unmodified_since};
+use crate::route_meta::RouteMetaRequestExt;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use http::header::{
+    CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE,
+    IF_NONE_MATCH, LAST_MODIFIED, TRANSFER_ENCODING,
+};
+use http::{HeaderValue, Method};
+use http_body_util::BodyExt;
+use ring::digest;
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_rs::prelude::*;
+use rustapi_rs::ResponseBody;
+use std::future::Future;
+use std::pin::Pin;
+
+/// Route metadata: leave this route's responses without an ETag.
+#[derive(Debug, Clone, Copy)]
+pub struct NoETag;
+
+#[derive(Clone, Default)]
+pub struct ETagLayer {
+    weak: bool,
+}
+
+impl ETagLayer {
+    /// Strong ETags: the same tag means the same bytes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Weak ETags (`W/"..."`) for the tags this layer computes: the same tag
+    /// means the same document, not necessarily the same bytes.
+    pub fn weak(mut self) -> Self {
+        self.weak = true;
+        self
+    }
+}
+
+fn etag_of(body: &[u8], weak: bool) -> HeaderValue {
+    let hash = digest::digest(&digest::SHA256, body);
+    let tag = URL_SAFE_NO_PAD.encode(&hash.as_ref()[..16]);
+    let prefix = if weak { "W/" } else { "" };
+    HeaderValue::try_from(format!("{prefix}\"{tag}\"")).expect("base64 is a valid header value")
+}
+
+fn not_modified(mut parts: http::response::Parts) -> Response {
+    parts.status = StatusCode::NOT_MODIFIED;
+    for name in [
+        CONTENT_TYPE,
+        CONTENT_LENGTH,
+        CONTENT_ENCODING,
+        CONTENT_RANGE,
+        TRANSFER_ENCODING,
+    ] {
+        parts.headers.remove(name);
+    }
+    Response::from_parts(parts, ResponseBody::empty())
+}
+
+impl MiddlewareLayer for ETagLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let conditional = req.method() == Method::GET || req.method() == Method::HEAD;
+        if !conditional || req.route_meta::<NoETag>().is_some() {
+            return next(req);
+        }
+        let if_none_match = req.headers().get(IF_NONE_MATCH).cloned();
+        let if_modified_since = req.headers().get(IF_MODIFIED_SINCE).cloned();
+        let weak = self.weak;
+
+        Box::pin(async move {
+            let response = next(req).await;
+            if response.status() != StatusCode::OK {
+                return response;
+            }
+
+            let (mut parts, body) = response.into_parts();
+            let body = match parts.headers.get(ETAG) {
+                Some(_) => body,
+                None if matches!(body, ResponseBody::Streaming(_)) => {
+                    return Response::from_parts(parts, body);
+                }
+                None => {
+                    // A Full body: already in memory, and collecting it can't fail.
+                    let bytes = match body.collect().await {
+                        Ok(collected) => collected.to_bytes(),
+                        Err(err) => return err.into_response(),
+                    };
+                    parts.headers.insert(ETAG, etag_of(&bytes, weak));
+                    ResponseBody::new(bytes)
+                }
+            };
+
+            // If-None-Match, when sent, decides alone (RFC 9110 §13.2.2).
+            let etag = parts.headers.get(ETAG).expect("set above");
+            let unchanged = match (&if_none_match, &if_modified_since) {
+                (Some(wanted), _) => none_match_fails(wanted, etag),
+                (None, Some(since)) => {
+                    let last_modified = parts
+                        .headers
+                        .get(LAST_MODIFIED)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| httpdate::parse_http_date(value).ok());
+                    unmodified_since(since, last_modified)
+                }
+                (None, None) => false,
+            };
+            if unchanged {
+                return not_modified(parts);
+            }
+            Response::from_parts(parts, body)
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
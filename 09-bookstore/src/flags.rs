@@ -0,0 +1,276 @@
+// ---------------------------------------------------------------------------
+// Feature flags, evaluated per request
+// ---------------------------------------------------------------------------
+//
+// A flag is on for some users and off for the rest. FlagsLayer looks up who
+// is asking; the handler asks about a flag by name:
+//
+//   .layer(FlagsLayer::new(StaticFlags::from(flags)).user_id(signed_in_user))
+//
+//   async fn home(flags: Flags, view: View) -> Result<Page> {
+//       if flags.enabled("new_ui") { .. } else { .. }
+//   }
+//
+// A FlagSet says, for each flag, who gets it:
+//
+//   {"new_ui": {"percent": 50, "users": ["ada"]}, "gift_wrap": {"percent": 100}}
+//
+//   `users`     these user ids always get it
+//   `percent`   of everyone else: SHA-256 of the flag name and the user id
+//               (or the `bookstore_client` cookie, for a client that isn't
+//               signed in) mapped to a bucket 0..100; buckets below
+//               `percent` get it
+//   neither     off; so is a flag the set doesn't name
+//
+// Bucketing is src/canary.rs's: the same client stays on the same side of
+// a flag, raising `percent` only adds clients, and each flag splits them
+// differently. A client without the cookie is given one.
+//
+// The FlagSet comes from a FlagProvider:
+//
+//   StaticFlags    one set, fixed at startup
+//   RemoteFlags    the last set fetched by a task of yours (poll()), kept
+//                  when a fetch fails; replace() swaps it by hand
+//
+// Cheap per request: the layer takes an Arc of the current set and puts it
+// in the request extensions, with the user. The flags themselves are
+// evaluated the first time anything asks, once, and the Flags extractor and
+// View share the result. A View adds `{{flags}}` to every template context:
+// the names of the flags that are on, space-separated, for a `class`.
+//
+// NOTE: a provider's set is taken once per request. A replace() while a
+//       request runs doesn't change the flags that request sees.
+
+use crate::canary::{bucket, client_cookie_header, client_id};
+use http::header::SET_COOKIE;
+use ring::rand::SystemRandom;
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_openapi::{Operation, OperationModifier};
+use rustapi_rs::prelude::*;
+use rustapi_rs::FromRequestParts;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::Duration;
+
+/// Who gets a flag.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Rule {
+    /// Share of clients, 0–100, that get it.
+    #[serde(default)]
+    pub percent: u8,
+    /// User ids that always get it.
+    #[serde(default)]
+    pub users: HashSet<String>,
+}
+
+/// Every flag, by name, and who gets it.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct FlagSet {
+    rules: HashMap<String, Rule>,
+}
+
+impl FlagSet {
+    /// A set from JSON: `{"name": {"percent": 10, "users": ["id"]}}`.
+    pub fn from_json(json: &str) -> std::result::Result<Self, String> {
+        serde_json::from_str(json).map_err(|err| format!("Invalid flags: {}", err))
+    }
+}
+
+/// Where FlagsLayer gets the current FlagSet, once per request.
+pub trait FlagProvider: Send + Sync + 'static {
+    fn current(&self) -> Arc<FlagSet>;
+}
+
+/// The same FlagSet for the life of the process.
+pub struct StaticFlags(Arc<FlagSet>);
+
+impl From<FlagSet> for StaticFlags {
+    fn from(set: FlagSet) -> Self {
+        Self(Arc::new(set))
+    }
+}
+
+impl FlagProvider for StaticFlags {
+    fn current(&self) -> Arc<FlagSet> {
+        self.0.clone()
+    }
+}
+
+/// A FlagSet kept up to date from somewhere else. Clones share it.
+#[derive(Clone)]
+pub struct RemoteFlags {
+    current: Arc<RwLock<Arc<FlagSet>>>,
+}
+
+impl RemoteFlags {
+    /// Starts with `initial`, until the first fetch or replace().
+    pub fn new(initial: FlagSet) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(initial))),
+        }
+    }
+
+    /// Requests that start from now on see `set`.
+    pub fn replace(&self, set: FlagSet) {
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(set);
+    }
+
+    /// Spawns a task that calls `fetch` every `every` and replaces the set
+    /// with what it returns. A failed fetch is logged; the last set stays.
+    pub fn poll<F, Fut>(&self, every: Duration, fetch: F)
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = std::result::Result<FlagSet, String>> + Send,
+    {
+        let flags = self.clone();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(every);
+            loop {
+                ticks.tick().await;
+                match fetch().await {
+                    Ok(set) => flags.replace(set),
+                    Err(err) => warn!("Fetching feature flags failed, keeping the last: {}", err),
+                }
+            }
+        });
+    }
+}
+
+impl FlagProvider for RemoteFlags {
+    fn current(&self) -> Arc<FlagSet> {
+        self.current
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+/// The flags of one request. Clones share the evaluation.
+#[derive(Clone, Default)]
+pub struct Flags(Arc<Evaluation>);
+
+#[derive(Default)]
+struct Evaluation {
+    set: Arc<FlagSet>,
+    user: Option<String>,
+    /// What percentages bucket by: the user, else the client cookie.
+    subject: Option<String>,
+    /// Names of the flags that are on, sorted; filled in on first use.
+    enabled: OnceLock<Vec<String>>,
+}
+
+impl Flags {
+    /// Whether flag `name` is on for this request.
+    pub fn enabled(&self, name: &str) -> bool {
+        self.all()
+            .binary_search_by(|flag| flag.as_str().cmp(name))
+            .is_ok()
+    }
+
+    /// Every flag that is on for this request, sorted by name.
+    pub fn all(&self) -> &[String] {
+        let eval = &*self.0;
+        eval.enabled.get_or_init(|| {
+            let mut enabled: Vec<String> = eval
+                .set
+                .rules
+                .iter()
+                .filter(|(name, rule)| {
+                    let listed = eval
+                        .user
+                        .as_ref()
+                        .is_some_and(|user| rule.users.contains(user));
+                    let bucketed = rule.percent >= 100
+                        || eval
+                            .subject
+                            .as_ref()
+                            .is_some_and(|subject| bucket(name, subject) < rule.percent);
+                    listed || bucketed
+                })
+                .map(|(name, _)| name.clone())
+                .collect();
+            enabled.sort();
+            enabled
+        })
+    }
+}
+
+/// The flags FlagsLayer put on the request; all off without one.
+impl FromRequestParts for Flags {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        Ok(req.extensions().get::<Flags>().cloned().unwrap_or_default())
+    }
+}
+
+// Flags come from the cookie and the session, not from anything a client
+// documents per route.
+impl OperationModifier for Flags {
+    fn update_operation(_op: &mut Operation) {}
+}
+
+type UserIdFn = fn(&Request) -> Option<String>;
+
+#[derive(Clone)]
+pub struct FlagsLayer {
+    provider: Arc<dyn FlagProvider>,
+    user_id: Option<UserIdFn>,
+    rng: SystemRandom,
+}
+
+impl FlagsLayer {
+    pub fn new(provider: impl FlagProvider) -> Self {
+        Self {
+            provider: Arc::new(provider),
+            user_id: None,
+            rng: SystemRandom::new(),
+        }
+    }
+
+    /// How to tell the signed-in user, for `users` and for bucketing. Without
+    /// it, or when it returns None, the client cookie buckets.
+    pub fn user_id(mut self, user_id: UserIdFn) -> Self {
+        self.user_id = Some(user_id);
+        self
+    }
+}
+
+impl MiddlewareLayer for FlagsLayer {
+    fn call(
+        &self,
+        mut req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let user = self.user_id.and_then(|user_id| user_id(&req));
+        let mut new_id = None;
+        let subject = match &user {
+            Some(user) => Some(user.clone()),
+            None => client_id(&mut req, &self.rng).map(|(id, new)| {
+                if new {
+                    new_id = Some(id.clone());
+                }
+                id
+            }),
+        };
+
+        req.extensions_mut().insert(Flags(Arc::new(Evaluation {
+            set: self.provider.current(),
+            user,
+            subject,
+            enabled: OnceLock::new(),
+        })));
+        Box::pin(async move {
+            let mut response = next(req).await;
+            if let Some(cookie) = new_id.as_deref().and_then(client_cookie_header) {
+                response.headers_mut().append(SET_COOKIE, cookie);
+            }
+            response
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
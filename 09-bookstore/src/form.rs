@@ -0,0 +1,87 @@
+// ---------------------------------------------------------------------------
+// Form<T>
+// ---------------------------------------------------------------------------
+//
+// An HTML `<form method="post">` sends its fields as the body,
+// `application/x-www-form-urlencoded`:
+//
+//   name=Ada&email=ada%40example.com&message=Do+you+stock+Rust+books%3F
+//
+// Form<T> is Json<T> for that body: it checks the Content-Type, decodes the
+// body with serde_urlencoded and documents `requestBody` with T's schema.
+//
+//   wrong or missing Content-Type   415 unsupported_media_type
+//   a field missing or mistyped     400 "Invalid form body: missing field `email`"
+//
+// Values arrive as text; serde turns `price_cents=1299` into a number the way
+// Query<T> does. Nested objects and arrays don't fit the encoding.
+
+use rustapi_openapi::schema::{RustApiSchema, SchemaCtx};
+use rustapi_openapi::{MediaType, OpenApiSpec, Operation, OperationModifier, RequestBody};
+use rustapi_rs::prelude::*;
+use rustapi_rs::FromRequest;
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+
+pub const MEDIA_TYPE: &str = "application/x-www-form-urlencoded";
+
+/// `T`, decoded from a URL-encoded form body.
+pub struct Form<T>(pub T);
+
+impl<T: DeserializeOwned + Send> FromRequest for Form<T> {
+    async fn from_request(req: &mut Request) -> Result<Self> {
+        let content_type = req
+            .headers()
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        // `application/x-www-form-urlencoded; charset=UTF-8` is fine too.
+        let essence = content_type.split(';').next().unwrap_or_default().trim();
+        if !essence.eq_ignore_ascii_case(MEDIA_TYPE) {
+            return Err(ApiError::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "unsupported_media_type",
+                format!(
+                    "Expected {} (got {})",
+                    MEDIA_TYPE,
+                    if essence.is_empty() {
+                        "no Content-Type"
+                    } else {
+                        essence
+                    }
+                ),
+            ));
+        }
+
+        req.load_body().await?;
+        let body = req
+            .take_body()
+            .ok_or_else(|| ApiError::internal("Body already consumed"))?;
+        serde_urlencoded::from_bytes(&body)
+            .map(Form)
+            .map_err(|err| ApiError::bad_request(format!("Invalid form body: {}", err)))
+    }
+}
+
+impl<T: RustApiSchema> OperationModifier for Form<T> {
+    fn update_operation(op: &mut Operation) {
+        let mut ctx = SchemaCtx::new();
+        let mut content = BTreeMap::new();
+        content.insert(
+            MEDIA_TYPE.to_string(),
+            MediaType {
+                schema: Some(T::schema(&mut ctx)),
+                example: None,
+            },
+        );
+        op.request_body = Some(RequestBody {
+            description: None,
+            required: Some(true),
+            content,
+        });
+    }
+
+    fn register_components(spec: &mut OpenApiSpec) {
+        spec.register_in_place::<T>();
+    }
+}
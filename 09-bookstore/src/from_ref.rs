@@ -0,0 +1,57 @@
+// ---------------------------------------------------------------------------
+// One composite state, extracted a piece at a time
+// ---------------------------------------------------------------------------
+//
+// State<T> looks state up by type, so every piece a handler wants on its own
+// has to be registered on its own:
+//
+//   RustApi::auto().state(catalogue).state(views).state(cursors).state(..)
+//
+// With #[derive(FromRef)] (bookstore-derive) the pieces live in one struct,
+// registered once:
+//
+//   #[derive(Clone, FromRef)]
+//   struct AppState { catalogue: Catalogue, views: Views, cursors: CursorKey }
+//
+//   RustApi::auto().state_parts(AppState { .. })
+//
+//   async fn page(State(cursors): State<CursorKey>, ..)   // one piece
+//   async fn admin(State(app): State<AppState>, ..)       // all of them
+//
+// The derive writes `impl FromRef<AppState> for CursorKey` (and so on) plus
+// `impl StateParts for AppState`, which `state_parts` uses to register the
+// struct and every field's FromRef value. Handlers and extractors keep using
+// State<T> and `req.state().get::<T>()` unchanged.
+//
+//   #[from_ref(skip)]         the field is only reachable through AppState
+//   two fields of one type    compile error: give one a newtype
+//
+// NOTE: the pieces are cloned once, at startup. Put anything that changes
+//       behind an Arc (as Catalogue and Views do), so the copies share it.
+
+use rustapi_rs::prelude::*;
+
+/// `Self`, taken out of a composite state `T`.
+pub trait FromRef<T> {
+    fn from_ref(input: &T) -> Self;
+}
+
+/// A composite state whose fields can be extracted on their own. Derived
+/// with #[derive(FromRef)].
+pub trait StateParts: Clone + Send + Sync + 'static {
+    /// Registers every field's FromRef value as state.
+    fn register_parts(&self, app: RustApi) -> RustApi;
+}
+
+/// `RustApi::auto().state_parts(AppState { .. })`
+pub trait StatePartsExt {
+    /// Registers `state`, and each of its parts, as state.
+    fn state_parts<S: StateParts>(self, state: S) -> Self;
+}
+
+impl StatePartsExt for RustApi {
+    fn state_parts<S: StateParts>(self, state: S) -> Self {
+        let app = state.register_parts(self);
+        app.state(state)
+    }
+}
@@ -0,0 +1,163 @@
+// ---------------------------------------------------------------------------
+// HTML pages, buffered or streamed
+// ---------------------------------------------------------------------------
+//
+// A page is a head, one fragment per row and a tail. `HtmlPage` renders it
+// one of two ways:
+//
+//   Render::Buffered   (default) the whole page is built into one String,
+//                      then sent with a Content-Length
+//   Render::Streamed   the head goes out at once; rows are rendered in
+//                      batches as the client reads, sent chunked
+//
+//   HtmlPage::new("<ul>", books, |book| format!("<li>{}</li>", escape(&book.title)), "</ul>")
+//       .render(Render::Streamed)
+//
+// Streaming buys time-to-first-byte and flat memory on large pages: the
+// browser starts parsing and fetching stylesheets while rows are still being
+// rendered, and only one batch is ever held as a String. What it costs:
+//
+//   - no Content-Length, so no download progress and no exact size in logs
+//   - the status and headers are sent before the first row is rendered; a
+//     row that fails can't turn the page into a 500 any more. Do anything
+//     that can fail (queries, permission checks) before building the page.
+//   - rows are rendered after the handler returns, so the data must be
+//     owned: clone a snapshot rather than render from under a lock held by
+//     a client that might read slowly
+//
+// NOTE: the body is a plain stream; anything between here and the client
+//       that buffers responses (a compression layer, some proxies) gives up
+//       the time-to-first-byte, though not the correctness.
+
+use bytes::Bytes;
+use http::header::{HeaderValue, CONTENT_TYPE};
+use rustapi_openapi::{MediaType, Operation, ResponseModifier, ResponseSpec, SchemaRef};
+use rustapi_rs::prelude::*;
+use rustapi_rs::ResponseBody;
+use std::collections::BTreeMap;
+
+/// Rows rendered per chunk when streaming.
+const BATCH: usize = 64;
+
+/// How an `HtmlPage` is sent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Render {
+    /// Rendered in full, then sent with a Content-Length.
+    #[default]
+    Buffered,
+    /// Sent chunked, a batch of rows at a time, as it is rendered.
+    Streamed,
+}
+
+type Rows = Box<dyn Iterator<Item = String> + Send>;
+
+/// An HTML page of rows between a fixed head and tail.
+pub struct HtmlPage {
+    head: String,
+    rows: Rows,
+    tail: String,
+    render: Render,
+}
+
+impl HtmlPage {
+    /// A page that renders `items` with `row`, one fragment each. Nothing is
+    /// rendered until the response is sent.
+    pub fn new<T, F>(
+        head: impl Into<String>,
+        items: Vec<T>,
+        mut row: F,
+        tail: impl Into<String>,
+    ) -> Self
+    where
+        T: Send + 'static,
+        F: FnMut(&T) -> String + Send + 'static,
+    {
+        Self {
+            head: head.into(),
+            rows: Box::new(items.into_iter().map(move |item| row(&item))),
+            tail: tail.into(),
+            render: Render::default(),
+        }
+    }
+
+    pub fn render(mut self, render: Render) -> Self {
+        self.render = render;
+        self
+    }
+}
+
+/// `text` with the five HTML-significant characters escaped.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl IntoResponse for HtmlPage {
+    fn into_response(self) -> Response {
+        let HtmlPage {
+            head,
+            rows,
+            tail,
+            render,
+        } = self;
+
+        if render == Render::Buffered {
+            let mut page = head;
+            page.extend(rows);
+            page.push_str(&tail);
+            return Html(page).into_response();
+        }
+
+        // Each poll renders one batch; the next waits until hyper has
+        // written this one.
+        let mut rows = rows.peekable();
+        let batches = std::iter::from_fn(move || {
+            rows.peek()?;
+            Some(rows.by_ref().take(BATCH).collect::<String>())
+        });
+        let chunks = std::iter::once(head)
+            .chain(batches)
+            .chain(std::iter::once(tail))
+            .map(|chunk| Ok::<_, ApiError>(Bytes::from(chunk)));
+
+        let mut response = Response::new(ResponseBody::from_stream(futures_util::stream::iter(
+            chunks,
+        )));
+        response.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("text/html; charset=utf-8"),
+        );
+        response
+    }
+}
+
+impl ResponseModifier for HtmlPage {
+    fn update_response(op: &mut Operation) {
+        let mut content = BTreeMap::new();
+        content.insert(
+            "text/html".to_string(),
+            MediaType {
+                schema: Some(SchemaRef::Inline(serde_json::json!({ "type": "string" }))),
+                example: None,
+            },
+        );
+        op.responses.insert(
+            "200".to_string(),
+            ResponseSpec {
+                description: "HTML page".to_string(),
+                content,
+                headers: BTreeMap::new(),
+            },
+        );
+    }
+}
@@ -0,0 +1,152 @@
+// ---------------------------------------------------------------------------
+// Incoming webhooks
+// ---------------------------------------------------------------------------
+//
+// The receiving end of src/webhooks.rs: events POSTed to us by someone who
+// shares a secret with us, signed the Standard Webhooks way.
+//
+//   RustApi::auto().state(WebhookVerifier::new("whsec_...")?)
+//
+//   async fn inbox(
+//       State(verifier): State<WebhookVerifier>,
+//       signature: WebhookSignature,
+//       JsonWithRaw { value, raw }: JsonWithRaw<InboundEvent>,
+//   ) -> Result<..> {
+//       verifier.verify(&signature, &raw)?;
+//       ..
+//   }
+//
+// The HMAC is over the body's exact bytes, which is why the body comes in as
+// JsonWithRaw<T> (src/json_raw.rs).
+//
+//   webhook-* header missing               400 "Missing webhook-signature header"
+//   timestamp more than 5 minutes off      401 "Webhook timestamp out of tolerance"
+//   no `v1,` signature matches             401 "Invalid webhook signature"
+//
+// `webhook-signature` may list several signatures, space-separated, while
+// the sender rotates secrets; one match is enough. Signatures are compared
+// in constant time.
+//
+// NOTE: the timestamp check stops a captured request from being replayed
+//       later. Within the tolerance, dedupe on `webhook-id`.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ring::hmac;
+use rustapi_openapi::{
+    MediaType, Operation, OperationModifier, Parameter, ResponseSpec, SchemaRef,
+};
+use rustapi_rs::prelude::*;
+use rustapi_rs::FromRequestParts;
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How far `webhook-timestamp` may be from now, either way.
+const TOLERANCE: Duration = Duration::from_secs(5 * 60);
+
+const HEADERS: [&str; 3] = ["webhook-id", "webhook-timestamp", "webhook-signature"];
+
+/// Checks signatures made with one shared secret. Clone it into state.
+#[derive(Clone)]
+pub struct WebhookVerifier {
+    key: hmac::Key,
+}
+
+impl WebhookVerifier {
+    /// `secret` is base64, with or without the `whsec_` prefix.
+    pub fn new(secret: &str) -> std::result::Result<Self, base64::DecodeError> {
+        let secret = STANDARD.decode(secret.strip_prefix("whsec_").unwrap_or(secret))?;
+        Ok(Self {
+            key: hmac::Key::new(hmac::HMAC_SHA256, &secret),
+        })
+    }
+
+    /// Ok if one of `signature`'s signatures is ours over `body`, and it was
+    /// made recently enough.
+    pub fn verify(&self, signature: &WebhookSignature, body: &[u8]) -> Result<()> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        let fresh = signature
+            .timestamp
+            .parse::<u64>()
+            .is_ok_and(|sent| now.abs_diff(sent) <= TOLERANCE.as_secs());
+        if !fresh {
+            return Err(ApiError::unauthorized("Webhook timestamp out of tolerance"));
+        }
+
+        let mut signed = format!("{}.{}.", signature.id, signature.timestamp).into_bytes();
+        signed.extend_from_slice(body);
+        let matches = signature
+            .signatures
+            .split_whitespace()
+            .filter_map(|entry| entry.strip_prefix("v1,"))
+            .filter_map(|tag| STANDARD.decode(tag).ok())
+            .any(|tag| hmac::verify(&self.key, &signed, &tag).is_ok());
+        if matches {
+            Ok(())
+        } else {
+            Err(ApiError::unauthorized("Invalid webhook signature"))
+        }
+    }
+}
+
+/// The `webhook-*` headers of a request, not yet checked.
+#[derive(Debug, Clone)]
+pub struct WebhookSignature {
+    pub id: String,
+    pub timestamp: String,
+    /// Space-separated `v1,<base64>` entries.
+    pub signatures: String,
+}
+
+impl FromRequestParts for WebhookSignature {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+                .ok_or_else(|| ApiError::bad_request(format!("Missing {} header", name)))
+        };
+        Ok(Self {
+            id: header(HEADERS[0])?,
+            timestamp: header(HEADERS[1])?,
+            signatures: header(HEADERS[2])?,
+        })
+    }
+}
+
+impl OperationModifier for WebhookSignature {
+    fn update_operation(op: &mut Operation) {
+        for name in HEADERS {
+            op.parameters.push(Parameter {
+                name: name.to_string(),
+                location: "header".to_string(),
+                description: None,
+                required: true,
+                deprecated: None,
+                schema: Some(SchemaRef::Inline(serde_json::json!({ "type": "string" }))),
+            });
+        }
+
+        let mut content = BTreeMap::new();
+        content.insert(
+            "application/json".to_string(),
+            MediaType {
+                schema: Some(SchemaRef::Ref {
+                    reference: "#/components/schemas/ErrorSchema".to_string(),
+                }),
+                example: None,
+            },
+        );
+        op.responses.insert(
+            "401".to_string(),
+            ResponseSpec {
+                description: "Stale timestamp or invalid signature".to_string(),
+                content,
+                headers: BTreeMap::new(),
+            },
+        );
+    }
+}
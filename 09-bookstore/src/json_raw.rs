@@ -0,0 +1,57 @@
+// ---------------------------------------------------------------------------
+// JsonWithRaw<T>: the parsed body and the bytes it was parsed from
+// ---------------------------------------------------------------------------
+//
+// A signed request is signed over its bytes, not over what they mean. Once
+// Json<T> has parsed the body the bytes are gone, and serializing T again
+// won't give them back: key order, whitespace, `1.0` vs `1`, escapes and
+// unknown fields all differ. JsonWithRaw<T> keeps both:
+//
+//   async fn inbox(JsonWithRaw { value, raw }: JsonWithRaw<Event>) -> .. {
+//       verifier.verify(&signature, &raw)?;   // the exact bytes
+//       handle(value)                          // the parsed struct
+//   }
+//
+// The body is read once; `raw` is a cheap, reference-counted Bytes. Errors
+// and OpenAPI are Json<T>'s:
+//
+//   body isn't JSON, or not a T   400 "Invalid JSON: ..."
+//
+// NOTE: the body is parsed before the handler can check a signature. Keep
+//       the route's BodyLimit small, so an unsigned request can't make the
+//       server parse much.
+
+use bytes::Bytes;
+use rustapi_openapi::schema::RustApiSchema;
+use rustapi_openapi::{OpenApiSpec, Operation, OperationModifier};
+use rustapi_rs::prelude::*;
+use rustapi_rs::FromRequest;
+use serde::de::DeserializeOwned;
+
+/// `T`, decoded from a JSON body, and the body itself.
+pub struct JsonWithRaw<T> {
+    pub value: T,
+    pub raw: Bytes,
+}
+
+impl<T: DeserializeOwned + Send> FromRequest for JsonWithRaw<T> {
+    async fn from_request(req: &mut Request) -> Result<Self> {
+        req.load_body().await?;
+        let raw = req
+            .take_body()
+            .ok_or_else(|| ApiError::internal("Body already consumed"))?;
+        let value = serde_json::from_slice(&raw)
+            .map_err(|err| ApiError::bad_request(format!("Invalid JSON: {}", err)))?;
+        Ok(Self { value, raw })
+    }
+}
+
+impl<T: RustApiSchema> OperationModifier for JsonWithRaw<T> {
+    fn update_operation(op: &mut Operation) {
+        Json::<T>::update_operation(op);
+    }
+
+    fn register_components(spec: &mut OpenApiSpec) {
+        Json::<T>::register_components(spec);
+    }
+}
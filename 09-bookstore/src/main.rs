@@ -0,0 +1,1200 @@
+// Run with: cargo run -p bookstore
+// Then visit: http://127.0.0.1:3000/docs
+//
+// Lesson: let the type system reject bad input. A route segment that can only
+//         be one of a few words becomes an enum, parsed before the handler runs
+//         and documented as a string enum in OpenAPI.
+
+mod accepts;
+mod admin;
+mod batch;
+mod body_limit;
+mod cache;
+mod canary;
+mod compression;
+mod conditional;
+mod content_length;
+mod created;
+mod cursor;
+mod etag;
+mod flags;
+mod form;
+mod from_ref;
+mod html_page;
+mod inbox;
+mod json_raw;
+mod merge_patch;
+mod models;
+mod openapi_meta;
+mod patch;
+mod path_enum;
+mod query;
+mod redirect;
+mod route_meta;
+mod slo;
+mod state;
+mod streamed;
+mod valid;
+mod views;
+mod webhooks;
+
+use accepts::{Accepts, AsForm, AsJson, AsMultipart};
+use admin::{Admin, AdminToken};
+use batch::{BatchOptions, BatchReport, Rejection};
+use body_limit::{BodyLimit, BodyLimitExt};
+use bookstore_derive::FromRef;
+use cache::{CacheControl, Cached};
+use canary::{CanaryLayer, Variant};
+use compression::{Algorithm, CompressionLayer};
+use conditional::{precondition, Conditional, Preconditions};
+use content_length::ContentLengthLayer;
+use created::{ensure_mounted, CreatedAt};
+use cursor::{Cursor, CursorKey};
+use etag::{ETagLayer, NoETag};
+use flags::{FlagSet, Flags, FlagsLayer, RemoteFlags, StaticFlags};
+use form::Form;
+use from_ref::StatePartsExt;
+use html_page::{escape, HtmlPage, Render};
+use inbox::{WebhookSignature, WebhookVerifier};
+use json_raw::JsonWithRaw;
+use merge_patch::PatchBody;
+use models::{Account, Book, BookFormat, BookPatch, BookPath, InboundEvent, NewBook, PriceChange};
+use openapi_meta::{Contact, License, OpenApiInfo, OpenApiMetaExt};
+use path_enum::{EnumPath, PathEnum};
+use query::{Query, QueryLimit, RawQuery, DEFAULT_QUERY_LIMIT};
+use redirect::Redirect;
+use route_meta::{RouteMeta, RouteMetaExt};
+use rustapi_rs::prelude::*;
+use rustapi_rs::Validate;
+use rustapi_rs::{description, errors, get, patch, post, summary, tag};
+use rustapi_validate::v2::ValidationErrors;
+use slo::{Slo, SloLayer};
+use state::{assert_state, State};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use streamed::Streamed;
+use tokio::sync::RwLock;
+use valid::{field_errors, Valid, ValidationFormat};
+use views::{Page, View, Views};
+use webhooks::{Delivery, RetryPolicy, Subscription, WebhookDispatcher};
+
+// ---------------------------------------------------------------------------
+// State
+// ---------------------------------------------------------------------------
+
+/// Everything the handlers share, registered once with `state_parts`. Each
+/// field can also be extracted on its own, as `State<CursorKey>` and so on.
+#[derive(Clone, FromRef)]
+struct AppState {
+    catalogue: Catalogue,
+    views: Views,
+    cursors: CursorKey,
+    webhooks: WebhookDispatcher,
+    inbox: WebhookVerifier,
+    /// Read by Admin, for the operator-only routes.
+    admin: AdminToken,
+    /// Read by Multipart, from the state, rather than by handlers.
+    multipart: MultipartConfig,
+    /// Read by Valid<T>: failed rules, in the client's language.
+    validation: ValidationFormat,
+    /// Read by Query<T> and RawQuery: how many parameters they parse.
+    query_limit: QueryLimit,
+}
+
+/// The books, and what has been done to them.
+#[derive(Clone)]
+struct Catalogue {
+    books: Arc<RwLock<Vec<Book>>>,
+    /// Edits per book id, updated under the `books` write lock. A book with
+    /// none is as it was added.
+    revisions: Arc<Mutex<HashMap<u64, Revision>>>,
+    /// When this store was created: the Last-Modified of unedited books. A
+    /// restart starts over, and may reuse ids for other books.
+    created: SystemTime,
+}
+
+/// How often a book was edited, and when last.
+#[derive(Debug, Clone, Copy)]
+struct Revision {
+    number: u32,
+    at: SystemTime,
+}
+
+impl Catalogue {
+    /// Book `id`'s ETag and Last-Modified. Call with `books` locked, so an
+    /// edit can't land in between.
+    fn book_version(&self, id: u64) -> (http::HeaderValue, SystemTime) {
+        let revision = self
+            .revisions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&id)
+            .copied();
+        let (number, modified) = revision.map_or((0, self.created), |r| (r.number, r.at));
+
+        let epoch = self.created.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let tag = format!("\"{:x}-{}-{}\"", epoch.as_secs(), id, number);
+        let etag = http::HeaderValue::try_from(tag).expect("digits are a valid header value");
+        (etag, modified)
+    }
+
+    /// Records an edit of book `id`. Call with `books` write-locked.
+    fn book_edited(&self, id: u64) {
+        let mut revisions = self.revisions.lock().unwrap_or_else(|e| e.into_inner());
+        let revision = revisions.entry(id).or_insert(Revision {
+            number: 0,
+            at: self.created,
+        });
+        revision.number += 1;
+        revision.at = SystemTime::now();
+    }
+
+    /// A fresh store with a few books in every format, plus `extra` generated
+    /// ones to give the list endpoint a realistic payload when benchmarking.
+    fn seeded(extra: usize) -> Self {
+        let seed = [
+            (
+                "The Rust Programming Language",
+                "Steve Klabnik",
+                BookFormat::Paperback,
+                3999,
+            ),
+            (
+                "Rust for Rustaceans",
+                "Jon Gjengset",
+                BookFormat::Ebook,
+                2999,
+            ),
+            (
+                "Zero To Production In Rust",
+                "Luca Palmieri",
+                BookFormat::Ebook,
+                3499,
+            ),
+            (
+                "Programming Rust",
+                "Jim Blandy",
+                BookFormat::Hardcover,
+                5999,
+            ),
+        ];
+
+        let generated = (1..=extra).map(|n| {
+            (
+                format!("Generated Volume {n}"),
+                format!("Author {}", n % 97),
+                BookFormat::VARIANTS[n % BookFormat::VARIANTS.len()].1,
+                1000 + (n % 5000) as u32,
+            )
+        });
+
+        let books = seed
+            .into_iter()
+            .map(|(title, author, format, price)| {
+                (title.to_string(), author.to_string(), format, price)
+            })
+            .chain(generated)
+            .zip(1u64..)
+            .map(|((title, author, format, price_cents), id)| Book {
+                id,
+                title,
+                subtitle: None,
+                author,
+                format,
+                price_cents,
+            })
+            .collect();
+
+        Self {
+            books: Arc::new(RwLock::new(books)),
+            revisions: Arc::default(),
+            created: SystemTime::now(),
+        }
+    }
+}
+
+/// `?q=` for `/books/search`.
+#[derive(Debug, Deserialize, Schema, Validate)]
+struct SearchParams {
+    /// Matched case-insensitively against title and author; 2 to 100
+    /// characters.
+    #[validate(length(min = 2, max = 100))]
+    q: String,
+}
+
+/// `?stream=` for `/books.html`.
+#[derive(Debug, Deserialize, Schema)]
+struct CatalogueParams {
+    /// Send rows as they are rendered instead of all at once. Default false.
+    stream: Option<bool>,
+}
+
+/// `?buffer=` for `/reports/inventory.csv`.
+#[derive(Debug, Deserialize, Schema)]
+struct ReportParams {
+    /// Build the whole report before sending it, with a Content-Length.
+    /// Default false: rows are sent as they are written.
+    buffer: Option<bool>,
+}
+
+/// `?limit=` for `/books/page`; the cursor is read by `Cursor<T>`.
+#[derive(Debug, Deserialize, Schema)]
+struct PageParams {
+    /// 1 to 100; defaults to 20.
+    limit: Option<usize>,
+}
+
+/// Where a page of `/books/page` ended: books sort by title, then id.
+#[derive(Debug, Serialize, Deserialize)]
+struct AfterBook {
+    title: String,
+    id: u64,
+}
+
+#[derive(Debug, Serialize, Schema)]
+struct BookPage {
+    items: Vec<Book>,
+    /// Pass back as `?cursor=` for the next page; null on the last one.
+    next_cursor: Option<String>,
+}
+
+/// What the form on `GET /contact` posts.
+#[derive(Debug, Deserialize, Schema, Validate)]
+struct ContactForm {
+    /// 1 to 100 characters.
+    #[validate(length(min = 1, max = 100))]
+    name: String,
+    #[validate(email)]
+    email: String,
+    /// At least 10 characters.
+    #[validate(length(min = 10, max = 5000))]
+    message: String,
+}
+
+/// Body of `POST /webhooks`.
+#[derive(Debug, Deserialize, Schema)]
+struct NewSubscription {
+    /// Receives a signed POST for every event.
+    url: String,
+}
+
+/// Base64 of `bookstore-demo-inbox-key`; set INBOX_SECRET to your own.
+const DEMO_INBOX_SECRET: &str = "whsec_Ym9va3N0b3JlLWRlbW8taW5ib3gta2V5";
+
+/// Same limit RustApi::run() installs by default; routes may set their own.
+const BODY_LIMIT: usize = rustapi_core::DEFAULT_BODY_LIMIT;
+
+/// Largest batch `POST /books/batch` accepts.
+const MAX_BATCH_SIZE: usize = 100;
+
+/// How long a rendered /home and /about stay cached. Home shows the stock,
+/// so it goes stale sooner; a new book changes its context anyway.
+const HOME_TTL: Duration = Duration::from_secs(60);
+const ABOUT_TTL: Duration = Duration::from_secs(3600);
+
+const STORE_NAME: &str = "RustAPI Books";
+
+/// Feature flags when neither FLAGS nor FLAGS_URL is set: half of clients,
+/// and always Ada, get the new home page.
+const DEFAULT_FLAGS: &str = r#"{"new_ui": {"percent": 50, "users": ["ada"]}}"#;
+
+/// The signed-in user, for feature flags. A stand-in: a real app reads its
+/// session here, not a header anyone can send.
+fn signed_in_user(req: &Request) -> Option<String> {
+    req.headers()
+        .get("x-user-id")
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+}
+
+/// Titles are unique, ignoring case.
+fn title_key(title: &str) -> String {
+    title.trim().to_lowercase()
+}
+
+fn check_new_book(book: &NewBook, taken: &HashSet<String>) -> Result<(), Rejection> {
+    if let Some(problem) = book.problem() {
+        return Err(Rejection::invalid(problem));
+    }
+    if taken.contains(&title_key(&book.title)) {
+        return Err(Rejection::conflict(format!(
+            "A book titled '{}' already exists",
+            book.title
+        )));
+    }
+    Ok(())
+}
+
+fn insert_book(books: &mut Vec<Book>, new: NewBook) -> Book {
+    let id = books.iter().map(|book| book.id).max().unwrap_or(0) + 1;
+    let book = Book {
+        id,
+        title: new.title,
+        subtitle: new.subtitle,
+        author: new.author,
+        format: new.format,
+        price_cents: new.price_cents,
+    };
+    books.push(book.clone());
+    book
+}
+
+// ---------------------------------------------------------------------------
+// Handlers
+// ---------------------------------------------------------------------------
+
+#[get("/books")]
+#[tag("books")]
+#[summary("List books")]
+async fn list_books(State(state): State<Catalogue>) -> Cached<Json<Vec<Book>>> {
+    Cached(
+        Json(state.books.read().await.clone()),
+        CacheControl::public().max_age(60).must_revalidate(),
+    )
+}
+
+#[get("/books.html")]
+#[tag("books")]
+#[summary("The catalogue as an HTML table")]
+#[description(
+    "Rendered in full by default. With `?stream=true` the table is sent as it \
+     is rendered, in chunks: the first rows arrive sooner, but there is no \
+     Content-Length."
+)]
+async fn catalogue_page(
+    State(state): State<Catalogue>,
+    Query(params): Query<CatalogueParams>,
+) -> HtmlPage {
+    // A snapshot: a streamed page is rendered while the client reads, and
+    // the lock shouldn't wait on a slow one.
+    let books = state.books.read().await.clone();
+    let render = match params.stream {
+        Some(true) => Render::Streamed,
+        _ => Render::Buffered,
+    };
+
+    HtmlPage::new(
+        format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>Catalogue</title><link rel="stylesheet" href="/assets/style.css"></head>
+<body>
+  <h1>Catalogue</h1>
+  <p>{} books</p>
+  <table>
+    <tr><th>Title</th><th>Author</th><th>Format</th><th>Price</th></tr>
+"#,
+            books.len()
+        ),
+        books,
+        |book: &Book| {
+            format!(
+                "    <tr><td><a href=\"{}\">{}</a></td><td>{}</td><td>{}</td><td>{}.{:02}</td></tr>\n",
+                BookPath { id: book.id }.to_uri(),
+                escape(&book.title),
+                escape(&book.author),
+                book.format,
+                book.price_cents / 100,
+                book.price_cents % 100
+            )
+        },
+        "  </table>\n</body>\n</html>\n",
+    )
+    .render(render)
+}
+
+#[get("/reports/inventory.csv")]
+#[tag("reports")]
+#[summary("Every book, with its edit count, as CSV")]
+#[description(
+    "Streamed by default: rows go out as they are written, chunked. With \
+     `?buffer=true` the report is built in full first and sent with a \
+     Content-Length, compressed if the client accepts it."
+)]
+async fn inventory_report(
+    State(state): State<Catalogue>,
+    Query(params): Query<ReportParams>,
+) -> Result<Streamed> {
+    // A snapshot, as for /books.html: rows are written while the client reads.
+    let books = state.books.read().await.clone();
+    let revisions = state
+        .revisions
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+
+    let header = "id,title,author,format,price,revisions\n".to_string();
+    let rows = books.chunks(64).map(|batch| {
+        batch
+            .iter()
+            .map(|book| {
+                format!(
+                    "{},{},{},{},{}.{:02},{}\n",
+                    book.id,
+                    csv_field(&book.title),
+                    csv_field(&book.author),
+                    book.format,
+                    book.price_cents / 100,
+                    book.price_cents % 100,
+                    revisions.get(&book.id).map_or(0, |r| r.number)
+                )
+            })
+            .collect::<String>()
+    });
+    let chunks: Vec<String> = std::iter::once(header).chain(rows).collect();
+    let csv = Streamed::new(futures_util::stream::iter(
+        chunks.into_iter().map(Ok::<_, std::convert::Infallible>),
+    ))
+    .content_type("text/csv; charset=utf-8");
+
+    if params.buffer == Some(true) {
+        csv.buffered().await
+    } else {
+        Ok(csv)
+    }
+}
+
+/// `value` as one CSV field: quoted, with quotes doubled, if it needs to be.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[get("/books/page")]
+#[tag("books")]
+#[summary("List books a page at a time")]
+#[description(
+    "Sorted by title. Follow `next_cursor` for the next page; unlike offsets, \
+     a cursor never skips or repeats a book when others are added meanwhile."
+)]
+#[errors(400 = "The cursor was edited, truncated or has expired")]
+async fn page_books(
+    State(state): State<Catalogue>,
+    State(cursors): State<CursorKey>,
+    Query(params): Query<PageParams>,
+    Cursor(after): Cursor<AfterBook>,
+) -> Json<BookPage> {
+    let limit = params.limit.unwrap_or(20).clamp(1, 100);
+    let books = state.books.read().await;
+
+    // A database does this with an index: WHERE (title, id) > ($1, $2)
+    // ORDER BY title, id LIMIT $3.
+    let mut rest: Vec<(String, &Book)> = books
+        .iter()
+        .map(|book| (title_key(&book.title), book))
+        .filter(|(title, book)| {
+            after
+                .as_ref()
+                .is_none_or(|after| (title, book.id) > (&after.title, after.id))
+        })
+        .collect();
+    rest.sort_by(|(a, x), (b, y)| (a, x.id).cmp(&(b, y.id)));
+
+    let next_cursor = (rest.len() > limit).then(|| {
+        let (title, last) = &rest[limit - 1];
+        cursors.encode(&AfterBook {
+            title: title.clone(),
+            id: last.id,
+        })
+    });
+    Json(BookPage {
+        items: rest
+            .into_iter()
+            .take(limit)
+            .map(|(_, book)| book.clone())
+            .collect(),
+        next_cursor,
+    })
+}
+
+#[get("/books/search")]
+#[tag("books")]
+#[summary("Search books by title or author")]
+#[description(
+    "Watched by a latency SLO: p99 under `SEARCH_SLO_MS` (default 200 ms). `SEARCH_CANARY_PERCENT` of clients (default 10) get the word-by-word search; the `X-Canary` response header says which one answered."
+)]
+async fn search_books(
+    State(state): State<Catalogue>,
+    Valid(Query(params)): Valid<Query<SearchParams>>,
+    variant: Variant,
+) -> Json<Vec<Book>> {
+    let books = state.books.read().await;
+    Json(match variant {
+        Variant::Stable => search_phrase(&books, &params.q),
+        Variant::Canary => search_words(&books, &params.q),
+    })
+}
+
+/// The stable search: `q` as one phrase, in title or author.
+fn search_phrase(books: &[Book], q: &str) -> Vec<Book> {
+    let needle = q.to_lowercase();
+    books
+        .iter()
+        .filter(|book| {
+            book.title.to_lowercase().contains(&needle)
+                || book.author.to_lowercase().contains(&needle)
+        })
+        .cloned()
+        .collect()
+}
+
+/// The canary: every word of `q` somewhere in title or author, so
+/// "rust klabnik" finds The Rust Programming Language. Books with more of
+/// the words in the title come first.
+fn search_words(books: &[Book], q: &str) -> Vec<Book> {
+    let words: Vec<String> = q.split_whitespace().map(str::to_lowercase).collect();
+    let mut hits: Vec<(usize, &Book)> = books
+        .iter()
+        .filter_map(|book| {
+            let title = book.title.to_lowercase();
+            let author = book.author.to_lowercase();
+            words
+                .iter()
+                .all(|word| title.contains(word) || author.contains(word))
+                .then(|| {
+                    (
+                        words.iter().filter(|word| title.contains(*word)).count(),
+                        book,
+                    )
+                })
+        })
+        .collect();
+    // Stable sort: equal ranks keep catalogue order.
+    hits.sort_by_key(|(rank, _)| std::cmp::Reverse(*rank));
+    hits.into_iter().map(|(_, book)| book.clone()).collect()
+}
+
+#[get("/books/latest")]
+#[tag("books")]
+#[summary("307 Temporary Redirect to the newest book")]
+#[errors(404 = "No books yet")]
+async fn latest_book(State(state): State<Catalogue>) -> Result<Redirect, ApiError> {
+    // Temporary: the target moves whenever a book is added.
+    let books = state.books.read().await;
+    let id = books
+        .iter()
+        .map(|book| book.id)
+        .max()
+        .ok_or_else(|| ApiError::not_found("No books yet"))?;
+    Ok(Redirect::temporary(&BookPath { id }.to_uri()))
+}
+
+#[get("/books/{id}")]
+#[tag("books")]
+#[summary("Get a book by ID")]
+async fn get_book(
+    State(state): State<Catalogue>,
+    Path(id): Path<u64>,
+    conditions: Preconditions,
+) -> Result<Conditional<Cached<Json<Book>>>, ApiError> {
+    // Book details rarely change: let CDNs keep them for five minutes and
+    // serve a stale copy for one more while they refresh.
+    let policy = CacheControl::public()
+        .max_age(300)
+        .stale_while_revalidate(60);
+
+    let books = state.books.read().await;
+    let book = books
+        .iter()
+        .find(|book| book.id == id)
+        .ok_or_else(|| ApiError::not_found("Book not found"))?;
+
+    // Answered before the book is cloned and serialized.
+    let (etag, modified) = state.book_version(book.id);
+    if let Some(not_modified) = precondition(&etag, Some(modified), &conditions) {
+        return Ok(not_modified.into());
+    }
+    let body = Cached(Json(book.clone()), policy);
+    Ok(Conditional::fresh(body, etag, Some(modified)))
+}
+
+#[patch("/books/{id}")]
+#[tag("books")]
+#[summary("Change some of a book's fields")]
+#[description(
+    "Takes the fields to change as `application/json`, or as a JSON Merge Patch (RFC 7396, `application/merge-patch+json`). Fields left out stay as they are. `subtitle: null` removes the subtitle; the other fields can't be null (422)."
+)]
+#[errors(404 = "Book not found", 409 = "Another book has that title", 422 = "A field is null or invalid")]
+async fn update_book(
+    State(state): State<Catalogue>,
+    Path(id): Path<u64>,
+    body: PatchBody<BookPatch, Book>,
+) -> Result<Json<Book>, ApiError> {
+    if let PatchBody::Json(patch) = &body {
+        if let Some(problem) = patch.problem() {
+            return Err(Rejection::invalid(problem).into());
+        }
+    }
+    let mut books = state.books.write().await;
+    let index = books
+        .iter()
+        .position(|book| book.id == id)
+        .ok_or_else(|| ApiError::not_found("Book not found"))?;
+
+    let before = books[index].clone();
+    let after = match body {
+        PatchBody::Json(patch) => {
+            let mut after = before.clone();
+            patch.apply(&mut after);
+            after
+        }
+        PatchBody::Merge(patch) => patch.apply(&before)?,
+    };
+    if after.id != id {
+        return Err(Rejection::invalid("id can't be changed").into());
+    }
+    // The same rules as a new book, against every other book's title.
+    let taken = books
+        .iter()
+        .filter(|book| book.id != id)
+        .map(|book| title_key(&book.title))
+        .collect();
+    let edited = NewBook {
+        title: after.title.clone(),
+        subtitle: after.subtitle.clone(),
+        author: after.author.clone(),
+        format: after.format,
+        price_cents: after.price_cents,
+    };
+    check_new_book(&edited, &taken)?;
+
+    // A patch that changes nothing leaves cached copies valid.
+    if after != before {
+        books[index] = after.clone();
+        state.book_edited(id);
+    }
+    Ok(Json(after))
+}
+
+#[post("/books")]
+#[tag("books")]
+#[summary("Add a book")]
+#[description("Takes the book as JSON, as a URL-encoded form or as multipart form data.")]
+async fn add_book(
+    State(state): State<Catalogue>,
+    State(webhooks): State<WebhookDispatcher>,
+    Accepts(new, _): Accepts<NewBook, (AsJson, AsForm, AsMultipart)>,
+) -> Result<CreatedAt<Book>, ApiError> {
+    let mut books = state.books.write().await;
+    let taken = books.iter().map(|book| title_key(&book.title)).collect();
+    check_new_book(&new, &taken)?;
+    let book = insert_book(&mut books, new);
+    webhooks.dispatch("book.created", &book);
+    Ok(CreatedAt::at(&BookPath { id: book.id }, book))
+}
+
+#[post("/books/batch")]
+#[tag("books")]
+#[summary("Add many books at once")]
+#[description(
+    "Returns one result per item, in request order. Best-effort by default: valid books are added and the response is 207 if any item failed. With `?atomic=true` nothing is added unless every item is valid (422 otherwise)."
+)]
+async fn add_books(
+    State(state): State<Catalogue>,
+    State(webhooks): State<WebhookDispatcher>,
+    Query(options): Query<BatchOptions>,
+    Json(items): Json<Vec<NewBook>>,
+) -> Result<BatchReport<Book>, ApiError> {
+    if items.is_empty() {
+        return Err(ApiError::bad_request(
+            "Batch must contain at least one book",
+        ));
+    }
+    if items.len() > MAX_BATCH_SIZE {
+        return Err(ApiError::bad_request(format!(
+            "Batch of {} books exceeds the limit of {}",
+            items.len(),
+            MAX_BATCH_SIZE
+        )));
+    }
+
+    // One write lock for the whole batch: checks and inserts see the same
+    // catalogue, and no other request can slip in between.
+    let mut books = state.books.write().await;
+    let mut taken: HashSet<_> = books.iter().map(|book| title_key(&book.title)).collect();
+
+    Ok(BatchReport::run(
+        &options,
+        items,
+        |book| {
+            check_new_book(book, &taken)?;
+            // Later items with the same title clash with this one.
+            taken.insert(title_key(&book.title));
+            Ok(())
+        },
+        // Only runs for books that are added, so no event for a rolled-back batch.
+        |book| {
+            let book = insert_book(&mut books, book);
+            webhooks.dispatch("book.created", &book);
+            book
+        },
+    ))
+}
+
+#[derive(Serialize)]
+struct HomeContext {
+    store: &'static str,
+    books: usize,
+    latest: String,
+}
+
+#[get("/home")]
+#[tag("pages")]
+#[summary("Home page, rendered from templates/home.html and cached")]
+#[description("Clients with the `new_ui` feature flag get the new layout, templates/home-v2.html.")]
+async fn home(State(state): State<Catalogue>, view: View, flags: Flags) -> Result<Page, ApiError> {
+    let context = {
+        let books = state.books.read().await;
+        HomeContext {
+            store: STORE_NAME,
+            books: books.len(),
+            latest: books
+                .iter()
+                .max_by_key(|book| book.id)
+                .map(|book| book.title.clone())
+                .unwrap_or_default(),
+        }
+    };
+    let template = if flags.enabled("new_ui") {
+        "home-v2.html"
+    } else {
+        "home.html"
+    };
+    view.render(template, &context, HOME_TTL)
+}
+
+#[get("/about")]
+#[tag("pages")]
+#[summary("About page, rendered from templates/about.html and cached")]
+async fn about(view: View) -> Result<Page, ApiError> {
+    view.render(
+        "about.html",
+        &serde_json::json!({ "store": STORE_NAME }),
+        ABOUT_TTL,
+    )
+}
+
+#[get("/contact")]
+#[tag("contact")]
+#[summary("Contact form")]
+async fn contact_page() -> Html<&'static str> {
+    Html(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>Contact the bookstore</title><link rel="stylesheet" href="/assets/style.css"></head>
+<body>
+  <h1>Contact us</h1>
+  <form method="post" action="/contact">
+    <p><label>Name <input name="name" required></label></p>
+    <p><label>Email <input name="email" type="email" required></label></p>
+    <p><label>Message <textarea name="message" required></textarea></label></p>
+    <button>Send</button>
+  </form>
+</body>
+</html>"#,
+    )
+}
+
+#[post("/contact")]
+#[tag("contact")]
+#[summary("Send the contact form; 303 See Other to /contact/thanks")]
+#[errors(
+    400 = "A field is missing",
+    415 = "Not application/x-www-form-urlencoded"
+)]
+async fn contact_post(Valid(Form(form)): Valid<Form<ContactForm>>) -> Redirect {
+    info!(
+        "Message from {} <{}>: {} bytes",
+        form.name,
+        form.email,
+        form.message.len()
+    );
+    // POST-redirect-GET: reloading the thank-you page doesn't resend the form.
+    Redirect::to("/contact/thanks")
+}
+
+#[get("/contact/thanks")]
+#[tag("contact")]
+#[summary("Where a sent contact form lands")]
+async fn contact_thanks() -> Html<&'static str> {
+    Html("<!DOCTYPE html><p>Thanks! We'll answer by email.</p>")
+}
+
+#[get("/")]
+#[tag("books")]
+#[summary("302 Found to the API docs")]
+async fn index() -> Redirect {
+    Redirect::found("/docs")
+}
+
+#[get("/catalogue")]
+#[tag("books")]
+#[summary("Old name of /books; 308 Permanent Redirect there, query string and all")]
+async fn catalogue(RawQuery(query): RawQuery) -> Redirect {
+    match query {
+        Some(query) => Redirect::permanent(&format!("/books?{}", query)),
+        None => Redirect::permanent("/books"),
+    }
+}
+
+#[post("/webhooks")]
+#[tag("webhooks")]
+#[summary("Subscribe to book events")]
+#[description(
+    "`url` gets a signed POST (Standard Webhooks headers) for every `book.created` event, \
+     retried with backoff. The secret to verify signatures is only returned here. \
+     Needs `Authorization: Bearer <ADMIN_TOKEN>`."
+)]
+#[errors(
+    400 = "`url` is not an http(s) URL, or its host isn't a public address",
+    409 = "The subscriber limit is reached"
+)]
+async fn subscribe(
+    _: Admin,
+    State(webhooks): State<WebhookDispatcher>,
+    Json(new): Json<NewSubscription>,
+) -> Result<Created<Subscription>, ApiError> {
+    Ok(Created(webhooks.subscribe(new.url).await?))
+}
+
+#[post("/webhooks/inbox")]
+#[tag("webhooks")]
+#[summary("Receive a signed event from a distributor")]
+#[description(
+    "Standard Webhooks headers, signed with INBOX_SECRET over the exact body. \
+     `price.changed` sets a book's price; other event types are acknowledged and dropped."
+)]
+#[errors(404 = "`price.changed` for a book that doesn't exist")]
+async fn inbox(
+    State(state): State<Catalogue>,
+    State(verifier): State<WebhookVerifier>,
+    signature: WebhookSignature,
+    JsonWithRaw { value: event, raw }: JsonWithRaw<InboundEvent>,
+) -> Result<NoContent, ApiError> {
+    // Over the bytes as sent: re-serializing `event` wouldn't match them.
+    verifier.verify(&signature, &raw)?;
+    info!("Webhook {} received: {}", signature.id, event.event_type);
+    if event.event_type != "price.changed" {
+        return Ok(NoContent);
+    }
+
+    let change: PriceChange = serde_json::from_value(event.data)
+        .map_err(|err| ApiError::bad_request(format!("Invalid price.changed data: {}", err)))?;
+    let mut books = state.books.write().await;
+    let book = books
+        .iter_mut()
+        .find(|book| book.id == change.book_id)
+        .ok_or_else(|| ApiError::not_found(format!("Book {} not found", change.book_id)))?;
+    book.price_cents = change.price_cents;
+    state.book_edited(change.book_id);
+    Ok(NoContent)
+}
+
+#[get("/webhooks/deliveries")]
+#[tag("webhooks")]
+#[summary("Recent webhook deliveries")]
+#[description(
+    "The last 100 deliveries, oldest first: delivered, failed, or pending a retry. \
+     Needs `Authorization: Bearer <ADMIN_TOKEN>`."
+)]
+async fn deliveries(_: Admin, State(webhooks): State<WebhookDispatcher>) -> Json<Vec<Delivery>> {
+    Json(webhooks.deliveries())
+}
+
+#[get("/books/format/{format}")]
+#[tag("books")]
+#[summary("List books in one format")]
+#[description(
+    "`format` is one of `hardcover`, `paperback` or `ebook`; anything else is a 400 listing the valid values."
+)]
+async fn books_by_format(
+    State(state): State<Catalogue>,
+    EnumPath(format): EnumPath<BookFormat>,
+) -> Json<Vec<Book>> {
+    let books = state.books.read().await;
+    Json(
+        books
+            .iter()
+            .filter(|book| book.format == format)
+            .cloned()
+            .collect(),
+    )
+}
+
+#[get("/me")]
+#[tag("account")]
+#[summary("The signed-in customer's account")]
+async fn me() -> Cached<Json<Account>> {
+    // Personal data: never in a shared cache, and not written to disk either.
+    Cached(
+        Json(Account {
+            name: "Ada Lovelace".to_string(),
+            email: "ada@example.com".to_string(),
+            loyalty_points: 120,
+        }),
+        CacheControl::private().no_store(),
+    )
+}
+
+/// Valid<T>'s 422, its messages in the first language of Accept-Language
+/// that rustapi has ("en" or "tr"), else in English.
+fn localized_validation(req: &Request, errors: ValidationErrors) -> ApiError {
+    let locale = req
+        .headers()
+        .get(http::header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            value
+                .split(',')
+                .map(|tag| tag.split(';').next().unwrap_or_default().trim())
+                .map(|tag| tag.split('-').next().unwrap_or_default())
+                .find(|lang| ["en", "tr"].contains(&lang.to_ascii_lowercase().as_str()))
+                .map(str::to_ascii_lowercase)
+        });
+    ApiError::validation(field_errors(&errors, locale.as_deref()))
+}
+
+// ---------------------------------------------------------------------------
+// Entry point
+// ---------------------------------------------------------------------------
+
+/// SIGHUP re-reads TEMPLATES_DIR and drops every cached page. A reload
+/// that fails changes nothing.
+#[cfg(unix)]
+fn reload_on_sighup(views: Views) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let Ok(mut sighup) = signal(SignalKind::hangup()) else {
+        warn!("Can't listen for SIGHUP; restart to reload templates");
+        return;
+    };
+    tokio::spawn(async move {
+        while sighup.recv().await.is_some() {
+            match views.reload() {
+                Ok(count) => info!("SIGHUP: reloaded {} template(s)", count),
+                Err(err) => warn!("SIGHUP: reload failed, keeping the old templates: {}", err),
+            }
+        }
+    });
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Only bench.sh sets this; the normal catalogue is the four seeded books.
+    let extra_books = match std::env::var("BENCH_EXTRA_BOOKS") {
+        Ok(n) => n.parse()?,
+        Err(_) => 0,
+    };
+
+    let search_slo = match std::env::var("SEARCH_SLO_MS") {
+        Ok(ms) => Duration::from_millis(ms.parse()?),
+        Err(_) => Duration::from_millis(200),
+    };
+    let lookup_slo = match std::env::var("LOOKUP_SLO_MS") {
+        Ok(ms) => Duration::from_millis(ms.parse()?),
+        Err(_) => Duration::from_millis(50),
+    };
+
+    let query_limit = match std::env::var("QUERY_LIMIT") {
+        Ok(limit) => limit.parse()?,
+        Err(_) => DEFAULT_QUERY_LIMIT,
+    };
+
+    let search_canary_percent = match std::env::var("SEARCH_CANARY_PERCENT") {
+        Ok(percent) => percent.parse()?,
+        Err(_) => 10,
+    };
+
+    // FLAGS is fixed for the life of the process. FLAGS_URL serves the same
+    // JSON, fetched again every FLAGS_POLL_SECS; FLAGS is the set until then.
+    let initial_flags =
+        FlagSet::from_json(&std::env::var("FLAGS").unwrap_or_else(|_| DEFAULT_FLAGS.to_string()))?;
+    let flags = match std::env::var("FLAGS_URL") {
+        Ok(url) => {
+            let every = match std::env::var("FLAGS_POLL_SECS") {
+                Ok(secs) => Duration::from_secs(secs.parse()?),
+                Err(_) => Duration::from_secs(30),
+            };
+            let client = reqwest::Client::builder()
+                .timeout(Duration::from_secs(5))
+                .build()?;
+            let remote = RemoteFlags::new(initial_flags);
+            remote.poll(every, move || {
+                let request = client.get(&url).send();
+                async move {
+                    let body = request
+                        .await
+                        .and_then(|response| response.error_for_status())
+                        .map_err(|err| err.to_string())?
+                        .text()
+                        .await
+                        .map_err(|err| err.to_string())?;
+                    FlagSet::from_json(&body)
+                }
+            });
+            FlagsLayer::new(remote)
+        }
+        Err(_) => FlagsLayer::new(StaticFlags::from(initial_flags)),
+    };
+
+    println!("Starting bookstore example…");
+    println!(" -> GET  http://127.0.0.1:3000/books");
+    println!(" -> POST http://127.0.0.1:3000/books");
+    println!(" -> POST http://127.0.0.1:3000/books/batch[?atomic=true]");
+    println!(" -> GET  http://127.0.0.1:3000/books.html[?stream=true]");
+    println!(" -> GET  http://127.0.0.1:3000/books/page[?limit=20&cursor=…]");
+    println!(" -> GET  http://127.0.0.1:3000/reports/inventory.csv[?buffer=true]");
+    println!(" -> GET  http://127.0.0.1:3000/books/search?q=rust   (X-Canary: canary for v2)");
+    println!(" -> GET  http://127.0.0.1:3000/books/{{id}}");
+    println!(" -> GET  http://127.0.0.1:3000/books/latest   (307 to the newest book)");
+    println!(" -> GET  http://127.0.0.1:3000/books/format/{{hardcover|paperback|ebook}}");
+    println!(" -> GET  http://127.0.0.1:3000/me");
+    println!(" -> GET  http://127.0.0.1:3000/contact   (HTML form, posts Form<T>)");
+    println!(" -> GET  http://127.0.0.1:3000/home      (cached template; X-Preview: 1 skips it)");
+    println!("          the new_ui flag picks the new layout (X-User-Id: ada always gets it)");
+    println!(" -> GET  http://127.0.0.1:3000/about");
+    println!(" -> GET  http://127.0.0.1:3000/catalogue (308 to /books)");
+    println!(" -> POST http://127.0.0.1:3000/webhooks   (Bearer ADMIN_TOKEN)");
+    println!(" -> GET  http://127.0.0.1:3000/webhooks/deliveries   (Bearer ADMIN_TOKEN)");
+    println!(" -> POST http://127.0.0.1:3000/webhooks/inbox   (signed with INBOX_SECRET)");
+    println!(" -> GET  http://127.0.0.1:3000/assets/style.css");
+    println!(" -> GET  http://127.0.0.1:3000/docs");
+
+    // Swap the log line for a pager, a webhook, ... — spawned, as it does I/O.
+    let slos = SloLayer::new(|breach| {
+        warn!(
+            "SLO breached: {} p{} = {:?} (target {:?}, {} samples)",
+            breach.route,
+            breach.quantile * 100.0,
+            breach.observed,
+            breach.target,
+            breach.samples
+        )
+    });
+
+    // Per-route settings for the layers, by route template.
+    let meta = RouteMeta::new()
+        .route(
+            http::Method::GET,
+            "/books/search",
+            Slo::p99(search_slo)
+                .window(Duration::from_secs(60))
+                .min_samples(20)
+                .cooldown(Duration::from_secs(60)),
+        )
+        .route(
+            http::Method::GET,
+            "/books/{id}",
+            Slo::p99(lookup_slo).cooldown(Duration::from_secs(60)),
+        )
+        // A subscription is a URL and a few event names.
+        .route(http::Method::POST, "/webhooks", BodyLimit(16 * 1024))
+        // Parsed before its signature is checked: keep it small.
+        .route(http::Method::POST, "/webhooks/inbox", BodyLimit(16 * 1024))
+        // no-store: nothing keeps a copy to revalidate.
+        .route(http::Method::GET, "/me", NoETag);
+
+    // Rendered pages are cached until their TTL or the next SIGHUP.
+    let templates = std::env::var("TEMPLATES_DIR")
+        .unwrap_or_else(|_| concat!(env!("CARGO_MANIFEST_DIR"), "/templates").to_string());
+    // Editors check a template change with `X-Preview: 1` before a reload.
+    let views = Views::load(templates)?.bypass_header("x-preview");
+    #[cfg(unix)]
+    reload_on_sighup(views.clone());
+
+    let inbox_secret =
+        std::env::var("INBOX_SECRET").unwrap_or_else(|_| DEMO_INBOX_SECRET.to_string());
+
+    // Subscribers must be public hosts, unless a local receiver is allowed.
+    let mut webhooks = WebhookDispatcher::new(RetryPolicy::default());
+    if std::env::var("WEBHOOK_ALLOW_PRIVATE").is_ok_and(|value| value == "1") {
+        warn!("WEBHOOK_ALLOW_PRIVATE=1: webhooks may target loopback and private addresses");
+        webhooks = webhooks.allow_private();
+    }
+
+    let state = AppState {
+        catalogue: Catalogue::seeded(extra_books),
+        views,
+        cursors: CursorKey::from_env(),
+        webhooks,
+        inbox: WebhookVerifier::new(&inbox_secret)?,
+        admin: AdminToken::from_env(),
+        // Multipart books are four short fields; far below BODY_LIMIT.
+        multipart: MultipartConfig::new()
+            .max_fields(16)
+            .max_size(16 * 1024)
+            .max_file_size(4 * 1024),
+        validation: ValidationFormat::new(localized_validation),
+        query_limit: QueryLimit(query_limit),
+    };
+
+    // Docs off here: they're mounted last, by docs_with_metadata, once the
+    // metadata below is declared (RustApi::auto() would mount them first).
+    let app = RustApi::config()
+        .docs_enabled(false)
+        .build()
+        .state_parts(state)
+        // Stylesheet for the HTML pages; sets its own ETag and Last-Modified.
+        .serve_static("/assets", concat!(env!("CARGO_MANIFEST_DIR"), "/assets"))
+        // Outermost: every layer below can read the matched route.
+        .route_meta(meta)?
+        // Content-Length or chunked, per route: BodyLimit in `meta`, else this.
+        .default_body_limit(BODY_LIMIT)
+        // ...and this one checks that a declared Content-Length is what arrives.
+        .layer(ContentLengthLayer::new())
+        .layer(slos)
+        // JSON lists and the HTML catalogue; streamed pages pass through.
+        .layer(
+            CompressionLayer::new()
+                .min_size(1024)
+                .algorithms(&[Algorithm::Gzip, Algorithm::Deflate]),
+        )
+        // Inside compression, so tags hash the plain body. Weak: gzip and plain
+        // bodies are the same document and get the same tag.
+        .layer(ETagLayer::new().weak())
+        // Every route; a new client gets its bookstore_client cookie here.
+        .layer(flags.user_id(signed_in_user))
+        .layer(
+            CanaryLayer::new("search-v2", search_canary_percent)
+                .route(http::Method::GET, "/books/search"),
+        )
+        .openapi_metadata(OpenApiInfo {
+            title: "Bookstore API".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            description: Some(
+                "Books, accounts and webhooks for a small online bookstore.".to_string(),
+            ),
+            contact: Some(Contact {
+                name: Some("Bookstore team".to_string()),
+                email: Some("api@bookstore.example".to_string()),
+                ..Contact::default()
+            }),
+            license: Some(License {
+                name: "MIT OR Apache-2.0".to_string(),
+                identifier: Some("MIT OR Apache-2.0".to_string()),
+            }),
+        })
+        .openapi_server("http://127.0.0.1:3000", "This process")
+        .openapi_server("https://api.bookstore.example", "Production")
+        .openapi_tag("books", "Browse, search, add and edit books")
+        .openapi_tag("account", "The signed-in customer")
+        .openapi_tag("webhooks", "Subscriptions, deliveries and incoming events")
+        .openapi_tag("reports", "Exports for the back office")
+        .openapi_tag("pages", "HTML pages for browsers")
+        .openapi_tag("contact", "The contact form")
+        .docs_with_metadata("/docs")
+        .dashboard(DashboardConfig::new());
+
+    // Every State<T> a handler takes, registered by `state_parts` above.
+    assert_state(&app);
+    // POST /books answers with a Location built from BookPath.
+    ensure_mounted::<BookPath>(&app)?;
+
+    app.run("127.0.0.1:3000").await
+}
@@ -0,0 +1,348 @@
+// ---------------------------------------------------------------------------
+// JSON Merge Patch (RFC 7396)
+// ---------------------------------------------------------------------------
+//
+// `application/merge-patch+json` is the standard partial update: the body
+// looks like the resource, with only the members to change.
+//
+//   {"subtitle": null}                    remove subtitle
+//   {"price_cents": 2499}                 set price_cents
+//   {"shipping": {"city": "Leeds"}}       merge into shipping: its other
+//                                         members stay
+//   {"tags": ["rust"]}                    arrays are replaced, not merged
+//
+// MergePatch<T> is the extractor. It holds the patch; the handler applies it
+// to the resource it loaded and gets the merged T back:
+//
+//   async fn update(Path(id): Path<u64>, patch: MergePatch<Book>) -> Result<Json<Book>> {
+//       let book = load(id)?;
+//       let merged = patch.apply(&book)?;
+//       ..
+//   }
+//
+//   Content-Type isn't merge-patch+json   415 unsupported_media_type
+//   body isn't JSON                       400 "Invalid JSON: ..."
+//   merged value isn't a T                422 "Patched value is invalid: missing field `title`"
+//
+// A route that also takes a plain JSON body takes PatchBody<P, T>: P for
+// `application/json`, MergePatch<T> for merge patches. Both are documented.
+//
+// NOTE: a null can only remove a member, so a merge patch can't set a value
+//       to null. A T whose `Option` fields must be kept as explicit nulls
+//       needs JSON Patch (RFC 6902) instead.
+
+use rustapi_openapi::schema::{RustApiSchema, SchemaCtx, SchemaRef};
+use rustapi_openapi::{MediaType, OpenApiSpec, Operation, OperationModifier, RequestBody};
+use rustapi_rs::prelude::*;
+use rustapi_rs::FromRequest;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+
+pub const MEDIA_TYPE: &str = "application/merge-patch+json";
+
+/// A merge patch for a `T`, not yet applied.
+pub struct MergePatch<T> {
+    patch: Value,
+    target: PhantomData<fn() -> T>,
+}
+
+impl<T> MergePatch<T> {
+    /// A patch from a JSON document already parsed.
+    pub fn from_value(patch: Value) -> Self {
+        Self {
+            patch,
+            target: PhantomData,
+        }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> MergePatch<T> {
+    /// `target` with the patch merged in; 422 if the result isn't a `T`.
+    pub fn apply(&self, target: &T) -> Result<T> {
+        let mut merged = serde_json::to_value(target)
+            .map_err(|err| ApiError::internal(format!("Can't patch this value: {}", err)))?;
+        merge(&mut merged, &self.patch);
+        serde_json::from_value(merged).map_err(|err| {
+            ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "invalid",
+                format!("Patched value is invalid: {}", err),
+            )
+        })
+    }
+}
+
+/// Merges `patch` into `target`, as RFC 7396's MergePatch does.
+pub fn merge(target: &mut Value, patch: &Value) {
+    let Value::Object(members) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    let Value::Object(target) = target else {
+        unreachable!("made an object above");
+    };
+    for (name, value) in members {
+        if value.is_null() {
+            target.remove(name);
+        } else {
+            merge(target.entry(name.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+/// The Content-Type's media type, without parameters.
+fn essence(req: &Request) -> String {
+    req.headers()
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+impl<T> FromRequest for MergePatch<T> {
+    async fn from_request(req: &mut Request) -> Result<Self> {
+        let essence = essence(req);
+        if essence != MEDIA_TYPE {
+            return Err(ApiError::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "unsupported_media_type",
+                format!(
+                    "Expected {} (got {})",
+                    MEDIA_TYPE,
+                    if essence.is_empty() {
+                        "no Content-Type"
+                    } else {
+                        &essence
+                    }
+                ),
+            ));
+        }
+
+        req.load_body().await?;
+        let body = req
+            .take_body()
+            .ok_or_else(|| ApiError::internal("Body already consumed"))?;
+        serde_json::from_slice(&body)
+            .map(Self::from_value)
+            .map_err(|err| ApiError::bad_request(format!("Invalid JSON: {}", err)))
+    }
+}
+
+// Any part of a T, so no schema more exact than "an object".
+impl<T> OperationModifier for MergePatch<T> {
+    fn update_operation(op: &mut Operation) {
+        let mut content = BTreeMap::new();
+        content.insert(
+            MEDIA_TYPE.to_string(),
+            MediaType {
+                schema: Some(SchemaRef::Inline(serde_json::json!({ "type": "object" }))),
+                example: None,
+            },
+        );
+        op.request_body = Some(RequestBody {
+            description: None,
+            required: Some(true),
+            content,
+        });
+    }
+}
+
+/// A PATCH body for a `T`: a `P` sent as `application/json`, or a merge
+/// patch. The Content-Type picks.
+pub enum PatchBody<P, T> {
+    Json(P),
+    Merge(MergePatch<T>),
+}
+
+impl<P: DeserializeOwned + Send, T> FromRequest for PatchBody<P, T> {
+    async fn from_request(req: &mut Request) -> Result<Self> {
+        if essence(req) == MEDIA_TYPE {
+            MergePatch::from_request(req).await.map(Self::Merge)
+        } else {
+            Json::from_request(req)
+                .await
+                .map(|Json(patch)| Self::Json(patch))
+        }
+    }
+}
+
+// P describes a merge patch of T too: every member optional, and null
+// where it may be removed.
+impl<P: RustApiSchema, T> OperationModifier for PatchBody<P, T> {
+    fn update_operation(op: &mut Operation) {
+        Json::<P>::update_operation(op);
+        if let Some(body) = op.request_body.as_mut() {
+            body.content.insert(
+                MEDIA_TYPE.to_string(),
+                MediaType {
+                    schema: Some(P::schema(&mut SchemaCtx::new())),
+                    example: None,
+                },
+            );
+        }
+    }
+
+    fn register_components(spec: &mut OpenApiSpec) {
+        Json::<P>::register_components(spec);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Book, BookFormat};
+    use serde_json::json;
+
+    /// RFC 7396 Appendix A: original, patch, result.
+    fn rfc_examples() -> Vec<(Value, Value, Value)> {
+        vec![
+            (json!({"a": "b"}), json!({"a": "c"}), json!({"a": "c"})),
+            (
+                json!({"a": "b"}),
+                json!({"b": "c"}),
+                json!({"a": "b", "b": "c"}),
+            ),
+            (json!({"a": "b"}), json!({"a": null}), json!({})),
+            (
+                json!({"a": "b", "b": "c"}),
+                json!({"a": null}),
+                json!({"b": "c"}),
+            ),
+            (json!({"a": ["b"]}), json!({"a": "c"}), json!({"a": "c"})),
+            (json!({"a": "c"}), json!({"a": ["b"]}), json!({"a": ["b"]})),
+            (
+                json!({"a": {"b": "c"}}),
+                json!({"a": {"b": "d", "c": null}}),
+                json!({"a": {"b": "d"}}),
+            ),
+            (
+                json!({"a": [{"b": "c"}]}),
+                json!({"a": [1]}),
+                json!({"a": [1]}),
+            ),
+            (json!(["a", "b"]), json!(["c", "d"]), json!(["c", "d"])),
+            (json!({"a": "b"}), json!(["c"]), json!(["c"])),
+            (json!({"a": "foo"}), json!(null), json!(null)),
+            (json!({"a": "foo"}), json!("bar"), json!("bar")),
+            (
+                json!({"e": null}),
+                json!({"a": 1}),
+                json!({"e": null, "a": 1}),
+            ),
+            (
+                json!([1, 2]),
+                json!({"a": "b", "c": null}),
+                json!({"a": "b"}),
+            ),
+            (
+                json!({}),
+                json!({"a": {"bb": {"ccc": null}}}),
+                json!({"a": {"bb": {}}}),
+            ),
+        ]
+    }
+
+    #[test]
+    fn rfc_7396_appendix_a() {
+        for (original, patch, expected) in rfc_examples() {
+            let mut merged = original.clone();
+            merge(&mut merged, &patch);
+            assert_eq!(merged, expected, "{} + {}", original, patch);
+        }
+    }
+
+    fn book() -> Book {
+        Book {
+            id: 1,
+            title: "The Rust Programming Language".to_string(),
+            subtitle: Some("Second Edition".to_string()),
+            author: "Steve Klabnik".to_string(),
+            format: BookFormat::Paperback,
+            price_cents: 3999,
+        }
+    }
+
+    #[test]
+    fn null_removes_an_option_field() {
+        let merged = MergePatch::<Book>::from_value(json!({"subtitle": null, "price_cents": 2499}))
+            .apply(&book())
+            .unwrap();
+        let expected = Book {
+            subtitle: None,
+            price_cents: 2499,
+            ..book()
+        };
+        assert_eq!(merged, expected);
+    }
+
+    #[test]
+    fn null_on_a_required_field_is_422() {
+        let err = MergePatch::<Book>::from_value(json!({"title": null}))
+            .apply(&book())
+            .unwrap_err();
+        assert_eq!(err.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(
+            err.message.contains("missing field `title`"),
+            "{}",
+            err.message
+        );
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Customer {
+        name: String,
+        shipping: Address,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Address {
+        street: String,
+        city: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        note: Option<String>,
+    }
+
+    fn customer() -> Customer {
+        Customer {
+            name: "Ada".to_string(),
+            shipping: Address {
+                street: "1 Analytical Row".to_string(),
+                city: "London".to_string(),
+                note: Some("Ring twice".to_string()),
+            },
+        }
+    }
+
+    #[test]
+    fn nested_objects_merge_member_by_member() {
+        let merged = MergePatch::<Customer>::from_value(
+            json!({"shipping": {"city": "Leeds", "note": null}}),
+        )
+        .apply(&customer())
+        .unwrap();
+        let expected = Customer {
+            shipping: Address {
+                city: "Leeds".to_string(),
+                note: None,
+                ..customer().shipping
+            },
+            ..customer()
+        };
+        assert_eq!(merged, expected);
+    }
+
+    #[test]
+    fn an_empty_patch_changes_nothing() {
+        let merged = MergePatch::<Customer>::from_value(json!({}))
+            .apply(&customer())
+            .unwrap();
+        assert_eq!(merged, customer());
+    }
+}
@@ -0,0 +1,272 @@
+// ---------------------------------------------------------------------------
+// Models
+// ---------------------------------------------------------------------------
+
+use crate::patch::Patch;
+use crate::path_enum::PathEnum;
+use rustapi_openapi::schema::{JsonSchema2020, RustApiSchema, SchemaCtx, SchemaRef};
+use rustapi_rs::prelude::*;
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Schema)]
+pub struct Book {
+    pub id: u64,
+    pub title: String,
+    pub subtitle: Option<String>,
+    pub author: String,
+    pub format: BookFormat,
+    pub price_cents: u32,
+}
+
+/// The URL of one book, as a value: `BookPath { id: 5 }.to_uri()` is
+/// `/books/5`. `Location` headers are built from this instead of `format!`.
+#[derive(Debug, Serialize, Deserialize, TypedPath)]
+#[typed_path("/books/{id}")]
+pub struct BookPath {
+    pub id: u64,
+}
+
+/// Request body for adding a book — one, or one item of a batch.
+#[derive(Debug, Clone, Deserialize, Schema)]
+pub struct NewBook {
+    pub title: String,
+    pub subtitle: Option<String>,
+    pub author: String,
+    pub format: BookFormat,
+    pub price_cents: u32,
+}
+
+impl NewBook {
+    /// Why this book can't be added as-is, if it can't.
+    pub fn problem(&self) -> Option<&'static str> {
+        if self.title.trim().is_empty() {
+            Some("title must not be empty")
+        } else if self.author.trim().is_empty() {
+            Some("author must not be empty")
+        } else if self.price_cents == 0 {
+            Some("price_cents must be greater than zero")
+        } else {
+            None
+        }
+    }
+}
+
+/// Request body for `PATCH /books/{id}`: only the fields to change.
+/// `subtitle` may be null, to remove it; the other fields can't be.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct BookPatch {
+    pub title: Patch<String>,
+    pub subtitle: Patch<String>,
+    pub author: Patch<String>,
+    pub format: Patch<BookFormat>,
+    pub price_cents: Patch<u32>,
+}
+
+impl BookPatch {
+    /// Why this patch can't be applied as-is, if it can't.
+    pub fn problem(&self) -> Option<&'static str> {
+        if self.title.is_null() {
+            Some("title can't be null")
+        } else if self.author.is_null() {
+            Some("author can't be null")
+        } else if self.format.is_null() {
+            Some("format can't be null")
+        } else if self.price_cents.is_null() {
+            Some("price_cents can't be null")
+        } else if self
+            .title
+            .value()
+            .is_some_and(|title| title.trim().is_empty())
+        {
+            Some("title must not be empty")
+        } else if self
+            .author
+            .value()
+            .is_some_and(|author| author.trim().is_empty())
+        {
+            Some("author must not be empty")
+        } else if self.price_cents.value() == Some(&0) {
+            Some("price_cents must be greater than zero")
+        } else {
+            None
+        }
+    }
+
+    /// Applies the fields present. Check `problem()` first.
+    pub fn apply(self, book: &mut Book) {
+        self.title.apply_required(&mut book.title);
+        self.subtitle.apply(&mut book.subtitle);
+        self.author.apply_required(&mut book.author);
+        self.format.apply_required(&mut book.format);
+        self.price_cents.apply_required(&mut book.price_cents);
+    }
+}
+
+// NOTE: written by hand because #[derive(Schema)] makes every field that
+//       isn't an Option required, and none of these is.
+impl RustApiSchema for BookPatch {
+    fn schema(ctx: &mut SchemaCtx) -> SchemaRef {
+        let name = Self::name();
+        if !ctx.components.contains_key(name.as_ref()) {
+            let fields = [
+                ("title", Patch::<String>::schema(ctx)),
+                ("subtitle", Patch::<String>::schema(ctx)),
+                ("author", Patch::<String>::schema(ctx)),
+                ("format", Patch::<BookFormat>::schema(ctx)),
+                ("price_cents", Patch::<u32>::schema(ctx)),
+            ];
+            let properties = fields
+                .into_iter()
+                .map(|(field, schema)| {
+                    let schema = match schema {
+                        SchemaRef::Schema(schema) => *schema,
+                        SchemaRef::Ref { reference } => {
+                            let mut schema = JsonSchema2020::new();
+                            schema.reference = Some(reference);
+                            schema
+                        }
+                        SchemaRef::Inline(value) => {
+                            serde_json::from_value(value).unwrap_or_default()
+                        }
+                    };
+                    (field.to_string(), schema)
+                })
+                .collect();
+
+            let mut schema = JsonSchema2020::object();
+            schema.properties = Some(properties);
+            ctx.components.insert(name.to_string(), schema);
+        }
+        SchemaRef::Ref {
+            reference: format!("#/components/schemas/{}", name),
+        }
+    }
+
+    fn component_name() -> Option<&'static str> {
+        Some("BookPatch")
+    }
+
+    fn name() -> Cow<'static, str> {
+        Cow::Borrowed("BookPatch")
+    }
+}
+
+/// The signed-in customer. Stubbed — this example has no authentication.
+#[derive(Debug, Clone, Serialize, Schema)]
+pub struct Account {
+    pub name: String,
+    pub email: String,
+    pub loyalty_points: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BookFormat {
+    Hardcover,
+    Paperback,
+    Ebook,
+}
+
+impl PathEnum for BookFormat {
+    const PARAM: &'static str = "format";
+    const VARIANTS: &'static [(&'static str, Self)] = &[
+        ("hardcover", Self::Hardcover),
+        ("paperback", Self::Paperback),
+        ("ebook", Self::Ebook),
+    ];
+}
+
+// FromStr keeps plain `Path<BookFormat>` and `Query` working too.
+impl FromStr for BookFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::from_param(s).ok_or_else(|| Self::unknown(s))
+    }
+}
+
+impl fmt::Display for BookFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_param())
+    }
+}
+
+// NOTE: written by hand because #[derive(Schema)] lists variant idents
+//       ("Hardcover") and ignores #[serde(rename_all)].
+impl RustApiSchema for BookFormat {
+    fn schema(ctx: &mut SchemaCtx) -> SchemaRef {
+        let name = Self::name();
+        if !ctx.components.contains_key(name.as_ref()) {
+            let mut schema = JsonSchema2020::string();
+            schema.enum_values = Some(
+                Self::VARIANTS
+                    .iter()
+                    .map(|(value, _)| (*value).into())
+                    .collect(),
+            );
+            ctx.components.insert(name.to_string(), schema);
+        }
+        SchemaRef::Ref {
+            reference: format!("#/components/schemas/{}", name),
+        }
+    }
+
+    fn component_name() -> Option<&'static str> {
+        Some("BookFormat")
+    }
+
+    fn name() -> Cow<'static, str> {
+        Cow::Borrowed("BookFormat")
+    }
+}
+
+/// An event a distributor POSTs to `/webhooks/inbox`, in the shape
+/// bookstore sends its own.
+#[derive(Debug, Deserialize)]
+pub struct InboundEvent {
+    /// `price.changed`; other types are accepted and ignored.
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub data: serde_json::Value,
+}
+
+/// `data` of a `price.changed` event.
+#[derive(Debug, Deserialize)]
+pub struct PriceChange {
+    pub book_id: u64,
+    pub price_cents: u32,
+}
+
+// NOTE: written by hand because #[derive(Schema)] ignores #[serde(rename)],
+//       and the field is `type` on the wire.
+impl RustApiSchema for InboundEvent {
+    fn schema(ctx: &mut SchemaCtx) -> SchemaRef {
+        let name = Self::name();
+        if !ctx.components.contains_key(name.as_ref()) {
+            let mut schema = JsonSchema2020::object();
+            schema.properties = Some(
+                [
+                    ("type".to_string(), JsonSchema2020::string()),
+                    ("data".to_string(), JsonSchema2020::default()),
+                ]
+                .into(),
+            );
+            schema.required = Some(vec!["type".to_string(), "data".to_string()]);
+            ctx.components.insert(name.to_string(), schema);
+        }
+        SchemaRef::Ref {
+            reference: format!("#/components/schemas/{}", name),
+        }
+    }
+
+    fn component_name() -> Option<&'static str> {
+        Some("InboundEvent")
+    }
+
+    fn name() -> Cow<'static, str> {
+        Cow::Borrowed("InboundEvent")
+    }
+}
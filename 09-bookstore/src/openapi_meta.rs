@@ -0,0 +1,275 @@
+// ---------------------------------------------------------------------------
+// OpenAPI metadata: info, servers, tags
+// ---------------------------------------------------------------------------
+//
+// RustApi::openapi_info() sets a title, a version and a description. The
+// rest of what Swagger UI shows above the operations (contact, license,
+// servers, tag descriptions) has no setter. These add it:
+//
+//   RustApi::config()
+//       .docs_enabled(false)
+//       .build()
+//       .openapi_metadata(OpenApiInfo { title, version, description,
+//                                       contact, license })
+//       .openapi_server("https://api.example.com", "Production")
+//       .openapi_tag("books", "Browse, add and edit books")
+//       .docs_with_metadata("/docs")
+//
+//   openapi_metadata(info)        replaces `info` whole
+//   openapi_server(url, about)    appends to `servers`; the first is the
+//                                 default in "Try it out"
+//   openapi_tag(name, about)      appends to `tags`: Swagger UI shows the
+//                                 groups in this order, with the text under
+//                                 each heading; undeclared tags follow
+//   docs_with_metadata(path)      serves Swagger UI and
+//                                 `{path}/openapi.json` with all of the
+//                                 above in; call it last, after every route
+//
+// As with 03-jwt-auth's docs_with_security: the spec is fixed when the docs
+// are mounted, so RustApi::auto() (which mounts them at once) would serve it
+// without any of this. Build the app with docs off instead; the calls
+// collect what they're given in the app state, and the docs are mounted
+// with it written in, once.
+
+use rustapi_rs::prelude::*;
+use rustapi_rs::ResponseBody;
+use serde_json::Value;
+
+/// The spec's `info`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenApiInfo {
+    pub title: String,
+    pub version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contact: Option<Contact>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<License>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Contact {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+
+/// `identifier` is an SPDX expression, like `MIT OR Apache-2.0`.
+#[derive(Debug, Clone, Serialize)]
+pub struct License {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identifier: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Server {
+    url: String,
+    description: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Tag {
+    name: String,
+    description: String,
+}
+
+/// Everything declared so far, kept in the app state until the docs are
+/// mounted.
+#[derive(Debug, Clone, Default)]
+struct OpenApiMetadata {
+    info: Option<OpenApiInfo>,
+    servers: Vec<Server>,
+    tags: Vec<Tag>,
+}
+
+pub trait OpenApiMetaExt {
+    /// The spec's `info`: title, version, description, contact, license.
+    fn openapi_metadata(self, info: OpenApiInfo) -> Self;
+
+    /// A server "Try it out" can send requests to.
+    fn openapi_server(self, url: &str, description: &str) -> Self;
+
+    /// A tag's description, and its place among the groups in Swagger UI.
+    fn openapi_tag(self, name: &str, description: &str) -> Self;
+
+    /// Serves Swagger UI at `path` and the spec, with everything declared
+    /// above, at `{path}/openapi.json`.
+    fn docs_with_metadata(self, path: &str) -> Self;
+}
+
+/// Applies `change` to the metadata in the state.
+fn update(app: RustApi, change: impl FnOnce(&mut OpenApiMetadata)) -> RustApi {
+    let mut metadata = app
+        .router()
+        .state_ref()
+        .get::<OpenApiMetadata>()
+        .cloned()
+        .unwrap_or_default();
+    change(&mut metadata);
+    app.state(metadata)
+}
+
+impl OpenApiMetaExt for RustApi {
+    fn openapi_metadata(self, info: OpenApiInfo) -> Self {
+        // The served spec gets the whole `info` when the docs are mounted;
+        // this keeps openapi_spec() in step for code that reads it.
+        let app = self.openapi_info(&info.title, &info.version, info.description.as_deref());
+        update(app, |metadata| metadata.info = Some(info))
+    }
+
+    fn openapi_server(self, url: &str, description: &str) -> Self {
+        update(self, |metadata| {
+            metadata.servers.push(Server {
+                url: url.to_string(),
+                description: description.to_string(),
+            })
+        })
+    }
+
+    fn openapi_tag(self, name: &str, description: &str) -> Self {
+        update(self, |metadata| {
+            metadata.tags.retain(|tag| tag.name != name);
+            metadata.tags.push(Tag {
+                name: name.to_string(),
+                description: description.to_string(),
+            })
+        })
+    }
+
+    fn docs_with_metadata(self, path: &str) -> Self {
+        let metadata = self
+            .router()
+            .state_ref()
+            .get::<OpenApiMetadata>()
+            .cloned()
+            .unwrap_or_default();
+        let mut spec = self.openapi_spec().to_json();
+        write_metadata(&mut spec, &metadata);
+        let json = serde_json::to_string_pretty(&spec).unwrap_or_else(|_| "{}".to_string());
+
+        let path = path.trim_end_matches('/');
+        let openapi_url = format!("{}/openapi.json", path);
+        let spec_handler = move || {
+            let json = json.clone();
+            async move {
+                let mut response = Response::new(ResponseBody::from(json));
+                response.headers_mut().insert(
+                    http::header::CONTENT_TYPE,
+                    http::HeaderValue::from_static("application/json"),
+                );
+                response
+            }
+        };
+        let url = openapi_url.clone();
+        let docs_handler = move || {
+            let url = url.clone();
+            async move { rustapi_openapi::swagger_ui_html(&url).map(ResponseBody::Full) }
+        };
+
+        self.route(&openapi_url, get(spec_handler))
+            .route(path, get(docs_handler))
+    }
+}
+
+/// Writes the declared metadata into `spec`, replacing what it had.
+fn write_metadata(spec: &mut Value, metadata: &OpenApiMetadata) {
+    let Some(object) = spec.as_object_mut() else {
+        return;
+    };
+    if let Some(info) = &metadata.info {
+        object.insert(
+            "info".to_string(),
+            serde_json::to_value(info).unwrap_or_default(),
+        );
+    }
+    if !metadata.servers.is_empty() {
+        object.insert(
+            "servers".to_string(),
+            serde_json::to_value(&metadata.servers).unwrap_or_default(),
+        );
+    }
+    if !metadata.tags.is_empty() {
+        object.insert(
+            "tags".to_string(),
+            serde_json::to_value(&metadata.tags).unwrap_or_default(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+    use rustapi_core::{BodyVariant, PathParams};
+
+    async fn stub() -> &'static str {
+        "stub"
+    }
+
+    async fn get_json(app: &RequestDispatcher, path: &str) -> Value {
+        let (parts, ()) = http::Request::get(path).body(()).unwrap().into_parts();
+        let request = Request::new(
+            parts,
+            BodyVariant::Buffered(Default::default()),
+            app.state_ref(),
+            PathParams::new(),
+        );
+        let response = app.dispatch(request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn served_spec_carries_the_metadata() {
+        let app = RustApi::new()
+            .route("/books", get(stub))
+            .openapi_metadata(OpenApiInfo {
+                title: "Bookstore API".to_string(),
+                version: "1.2.3".to_string(),
+                description: None,
+                contact: None,
+                license: Some(License {
+                    name: "MIT".to_string(),
+                    identifier: Some("MIT".to_string()),
+                }),
+            })
+            .openapi_server("https://api.bookstore.example", "Production")
+            .openapi_tag("books", "Browse books")
+            .openapi_tag("account", "The signed-in customer")
+            // Declared again: replaced, and moved to the end.
+            .openapi_tag("books", "Browse, search and edit books")
+            .docs_with_metadata("/docs/")
+            .request_dispatcher();
+
+        let spec = get_json(&app, "/docs/openapi.json").await;
+        assert_eq!(spec["info"]["title"], "Bookstore API");
+        assert_eq!(spec["info"]["license"]["identifier"], "MIT");
+        assert_eq!(
+            spec["servers"],
+            serde_json::json!([
+                { "url": "https://api.bookstore.example", "description": "Production" }
+            ])
+        );
+        assert_eq!(spec["tags"][0]["name"], "account");
+        assert_eq!(
+            spec["tags"][1]["description"],
+            "Browse, search and edit books"
+        );
+        assert!(spec["paths"].get("/books").is_some());
+    }
+
+    #[test]
+    fn nothing_declared_leaves_the_spec_alone() {
+        let mut spec = serde_json::json!({ "openapi": "3.1.0", "info": { "title": "x" } });
+        let before = spec.clone();
+        write_metadata(&mut spec, &OpenApiMetadata::default());
+        assert_eq!(spec, before);
+    }
+}
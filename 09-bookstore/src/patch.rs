@@ -0,0 +1,92 @@
+// ---------------------------------------------------------------------------
+// PATCH bodies: absent, null or a value
+// ---------------------------------------------------------------------------
+//
+// An `Option<T>` field reads `{}` and `{"subtitle": null}` both as None, but
+// in a partial update they mean different things: "leave it" and "clear it".
+// Patch<T> keeps the three apart:
+//
+//   {}                         Patch::Absent     leave the field as it is
+//   {"subtitle": null}         Patch::Null       clear it
+//   {"subtitle": "2nd ed."}    Patch::Value(..)  set it
+//
+// It works with `Json<T>` like any field type, given `#[serde(default)]` on
+// the struct:
+//
+//   #[derive(Deserialize)]
+//   #[serde(default)]
+//   struct BookPatch { subtitle: Patch<String>, .. }
+//
+// In OpenAPI a Patch<T> is T's schema made nullable, like Option<T>. The
+// struct holding it should list no field as required (src/models.rs).
+//
+// NOTE: without `#[serde(default)]`, serde hands a missing field to the
+//       type as a null, and Absent would never be seen: every omitted field
+//       would clear.
+
+use rustapi_openapi::schema::{RustApiSchema, SchemaCtx, SchemaRef};
+use serde::{Deserialize, Deserializer};
+use std::borrow::Cow;
+
+/// One field of a partial update.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Patch<T> {
+    /// Not in the body: leave the field alone.
+    #[default]
+    Absent,
+    /// `null`: clear the field.
+    Null,
+    /// A new value.
+    Value(T),
+}
+
+impl<T> Patch<T> {
+    pub fn is_null(&self) -> bool {
+        matches!(self, Self::Null)
+    }
+
+    /// The new value, if the patch sets one.
+    pub fn value(&self) -> Option<&T> {
+        match self {
+            Self::Value(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Applies the patch to a field that may be empty: Null clears it.
+    pub fn apply(self, field: &mut Option<T>) {
+        match self {
+            Self::Absent => {}
+            Self::Null => *field = None,
+            Self::Value(value) => *field = Some(value),
+        }
+    }
+
+    /// Applies the patch to a field that always has a value. Null must have
+    /// been rejected before; here it leaves the field alone.
+    pub fn apply_required(self, field: &mut T) {
+        if let Self::Value(value) = self {
+            *field = value;
+        }
+    }
+}
+
+// Only called for a field that is in the body; Absent comes from Default.
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Patch<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match Option::<T>::deserialize(deserializer)? {
+            Some(value) => Self::Value(value),
+            None => Self::Null,
+        })
+    }
+}
+
+impl<T: RustApiSchema> RustApiSchema for Patch<T> {
+    fn schema(ctx: &mut SchemaCtx) -> SchemaRef {
+        Option::<T>::schema(ctx)
+    }
+
+    fn name() -> Cow<'static, str> {
+        Option::<T>::name()
+    }
+}
@@ -0,0 +1,81 @@
+// ---------------------------------------------------------------------------
+// Enum path parameters
+// ---------------------------------------------------------------------------
+//
+// `Path<BookFormat>` already parses through FromStr, but Path's OpenAPI hook
+// is the same no-op for every T, so /docs would show a free-form string.
+// EnumPath<T> parses the same way *and* publishes the allowed values as a
+// string enum on the parameter.
+
+use rustapi_openapi::{Operation, OperationModifier, Parameter, SchemaRef};
+use rustapi_rs::prelude::*;
+use rustapi_rs::FromRequestParts;
+
+/// A closed set of values that can fill a `{placeholder}` in a route.
+pub trait PathEnum: Sized + Copy + 'static {
+    /// Name of the placeholder, e.g. `"format"` for `/books/format/{format}`.
+    const PARAM: &'static str;
+    /// Every accepted spelling and the value it maps to.
+    const VARIANTS: &'static [(&'static str, Self)];
+
+    fn from_param(value: &str) -> Option<Self> {
+        Self::VARIANTS
+            .iter()
+            .find(|(name, _)| *name == value)
+            .map(|(_, variant)| *variant)
+    }
+
+    fn as_param(self) -> &'static str
+    where
+        Self: PartialEq,
+    {
+        Self::VARIANTS
+            .iter()
+            .find(|(_, variant)| *variant == self)
+            .map(|(name, _)| *name)
+            .expect("every variant is listed in VARIANTS")
+    }
+
+    /// "Unknown format 'x', expected one of: a, b, c"
+    fn unknown(value: &str) -> String {
+        let valid: Vec<_> = Self::VARIANTS.iter().map(|(name, _)| *name).collect();
+        format!(
+            "Unknown {} '{}', expected one of: {}",
+            Self::PARAM,
+            value,
+            valid.join(", ")
+        )
+    }
+}
+
+pub struct EnumPath<T>(pub T);
+
+impl<T: PathEnum> FromRequestParts for EnumPath<T> {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        let value = req
+            .path_param(T::PARAM)
+            .ok_or_else(|| ApiError::internal(format!("Route has no {{{}}} segment", T::PARAM)))?;
+
+        T::from_param(value)
+            .map(EnumPath)
+            .ok_or_else(|| ApiError::bad_request(T::unknown(value)))
+    }
+}
+
+impl<T: PathEnum> OperationModifier for EnumPath<T> {
+    fn update_operation(op: &mut Operation) {
+        let values: Vec<_> = T::VARIANTS.iter().map(|(name, _)| *name).collect();
+        // Added before RustAPI fills in path params, so ours wins over the
+        // default `{"type": "string"}`.
+        op.parameters.push(Parameter {
+            name: T::PARAM.to_string(),
+            location: "path".to_string(),
+            description: None,
+            required: true,
+            deprecated: None,
+            schema: Some(SchemaRef::Inline(
+                serde_json::json!({ "type": "string", "enum": values }),
+            )),
+        });
+    }
+}
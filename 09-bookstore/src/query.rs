@@ -0,0 +1,178 @@
+// ---------------------------------------------------------------------------
+// Query<T> and RawQuery, with a cap on parameters
+// ---------------------------------------------------------------------------
+//
+// serde_urlencoded decodes every pair of a query string before the handler
+// sees any of it, and a map or a `flatten`ed struct keeps them all:
+// `?a=1&a=1&…` fifty thousand times is that much work for one request.
+// These extractors count the pairs first:
+//
+//   up to the limit         Query<T> parses as rustapi's does
+//   more than the limit     400 "Too many query parameters (at most 256)"
+//
+// The limit is a QueryLimit in the state, or DEFAULT_QUERY_LIMIT:
+//
+//   RustApi::auto().state(QueryLimit(64))
+//
+//   Query<T>     a drop-in for rustapi's: import it instead of the
+//                prelude's (`use query::Query;` wins over the glob)
+//   RawQuery     the query string as sent, undecoded, for a handler that
+//                passes it on
+//
+// Counting stops at the first pair past the limit, and nothing is decoded
+// before the count passes.
+//
+// NOTE: empty pairs (`a=1&&b=2`) aren't counted; serde_urlencoded skips
+//       them too. The URI as a whole is bounded by hyper's read buffer.
+
+use rustapi_openapi::{Operation, OperationModifier};
+use rustapi_rs::prelude::*;
+use rustapi_rs::FromRequestParts;
+use serde::de::DeserializeOwned;
+use std::ops::Deref;
+
+/// Most query parameters a request may carry, unless a QueryLimit says
+/// otherwise. Far more than any form or filter here sends.
+pub const DEFAULT_QUERY_LIMIT: usize = 256;
+
+/// The most query parameters Query<T> and RawQuery accept. Register as
+/// state to replace DEFAULT_QUERY_LIMIT.
+#[derive(Debug, Clone, Copy)]
+pub struct QueryLimit(pub usize);
+
+impl Default for QueryLimit {
+    fn default() -> Self {
+        Self(DEFAULT_QUERY_LIMIT)
+    }
+}
+
+impl QueryLimit {
+    /// 400 if `query` has more than the limit's pairs.
+    pub fn check(self, query: &str) -> Result<()> {
+        let over = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .nth(self.0)
+            .is_some();
+        if over {
+            return Err(ApiError::bad_request(format!(
+                "Too many query parameters (at most {})",
+                self.0
+            )));
+        }
+        Ok(())
+    }
+
+    /// `query` decoded as a `T`, once it passed check().
+    pub fn parse<T: DeserializeOwned>(self, query: &str) -> Result<T> {
+        self.check(query)?;
+        serde_urlencoded::from_str(query)
+            .map_err(|e| ApiError::bad_request(format!("Invalid query string: {}", e)))
+    }
+
+    /// The app's limit, or the default.
+    pub fn of(req: &Request) -> Self {
+        req.state().get::<QueryLimit>().copied().unwrap_or_default()
+    }
+}
+
+/// The query string decoded as a `T`.
+#[derive(Debug, Clone)]
+pub struct Query<T>(pub T);
+
+impl<T: DeserializeOwned> FromRequestParts for Query<T> {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        QueryLimit::of(req)
+            .parse(req.query_string().unwrap_or(""))
+            .map(Query)
+    }
+}
+
+impl<T> OperationModifier for Query<T>
+where
+    rustapi_rs::Query<T>: OperationModifier,
+{
+    fn update_operation(op: &mut Operation) {
+        rustapi_rs::Query::<T>::update_operation(op);
+    }
+}
+
+impl<T> Deref for Query<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+/// The query string as sent, without the `?`; None if there was none.
+#[derive(Debug, Clone)]
+pub struct RawQuery(pub Option<String>);
+
+impl FromRequestParts for RawQuery {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        let query = req.query_string();
+        if let Some(query) = query {
+            QueryLimit::of(req).check(query)?;
+        }
+        Ok(Self(query.map(str::to_string)))
+    }
+}
+
+// Whatever was sent; nothing to document.
+impl OperationModifier for RawQuery {
+    fn update_operation(_op: &mut Operation) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// `count` distinct parameters: `p0=0&p1=1&…`.
+    fn params(count: usize) -> String {
+        (0..count)
+            .map(|i| format!("p{i}={i}"))
+            .collect::<Vec<_>>()
+            .join("&")
+    }
+
+    fn assert_rejected(limit: QueryLimit, query: &str) {
+        let err = limit
+            .parse::<HashMap<String, String>>(query)
+            .expect_err("over the limit");
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        assert!(err.message.contains("Too many query parameters"));
+    }
+
+    #[test]
+    fn thousands_of_parameters_are_rejected() {
+        assert_rejected(QueryLimit::default(), &params(5000));
+        // The same string as a raw query: RawQuery only counts.
+        assert!(QueryLimit::default().check(&params(5000)).is_err());
+    }
+
+    #[test]
+    fn exactly_the_default_limit_parses() {
+        let parsed: HashMap<String, String> = QueryLimit::default()
+            .parse(&params(DEFAULT_QUERY_LIMIT))
+            .unwrap();
+        assert_eq!(parsed.len(), DEFAULT_QUERY_LIMIT);
+    }
+
+    #[test]
+    fn one_past_the_limit_is_rejected() {
+        assert_rejected(QueryLimit(3), "a=1&b=2&c=3&d=4");
+    }
+
+    #[test]
+    fn a_repeated_name_counts_every_time() {
+        // A map keeps one `a`, but each pair is still decoded.
+        assert_rejected(QueryLimit(3), "a=1&a=2&a=3&a=4");
+    }
+
+    #[test]
+    fn empty_pairs_are_not_counted() {
+        assert!(QueryLimit(2).check("a=1&&&b=2&").is_ok());
+    }
+}
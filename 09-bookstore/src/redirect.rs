@@ -0,0 +1,123 @@
+// ---------------------------------------------------------------------------
+// Redirects
+// ---------------------------------------------------------------------------
+//
+// RustAPI's `Redirect` knows 301, 302 and 307, and panics on a URI that isn't
+// a valid header value. This one covers the four a web app actually picks
+// from, and never panics:
+//
+//   Redirect::to(uri)          303 See Other    after a form POST: the browser
+//                                               follows with a GET
+//   Redirect::found(uri)       302 Found        legacy; browsers turn a POST
+//                                               into a GET, other clients may not
+//   Redirect::temporary(uri)   307              same method and body, once
+//   Redirect::permanent(uri)   308              same method and body, for good;
+//                                               clients may cache it
+//
+// The URI is percent-encoded on the way into `Location`: spaces, quotes,
+// non-ASCII and the like become %XX. Characters with a meaning in a URI
+// (`/ ? # & =` ...) and existing %XX escapes are left alone, so
+// `/books/by title` becomes `/books/by%20title` and an already-encoded URI
+// comes out unchanged.
+
+use http::header::{HeaderValue, LOCATION};
+use rustapi_openapi::{Operation, ResponseModifier, ResponseSpec};
+use rustapi_rs::prelude::*;
+use rustapi_rs::ResponseBody;
+use std::collections::BTreeMap;
+
+/// A 3xx answer with a `Location` header and no body.
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    status: StatusCode,
+    location: HeaderValue,
+}
+
+impl Redirect {
+    fn new(status: StatusCode, uri: &str) -> Self {
+        let location =
+            HeaderValue::try_from(encode_uri(uri)).expect("an encoded URI is visible ASCII only");
+        Self { status, location }
+    }
+
+    /// 303 See Other: "the result is over there, GET it". The
+    /// POST-redirect-GET answer to a form submission.
+    pub fn to(uri: &str) -> Self {
+        Self::new(StatusCode::SEE_OTHER, uri)
+    }
+
+    /// 308 Permanent Redirect: the resource moved for good.
+    pub fn permanent(uri: &str) -> Self {
+        Self::new(StatusCode::PERMANENT_REDIRECT, uri)
+    }
+
+    /// 307 Temporary Redirect: repeat this request, method and body, there.
+    pub fn temporary(uri: &str) -> Self {
+        Self::new(StatusCode::TEMPORARY_REDIRECT, uri)
+    }
+
+    /// 302 Found, for clients that predate 303 and 307.
+    pub fn found(uri: &str) -> Self {
+        Self::new(StatusCode::FOUND, uri)
+    }
+}
+
+/// Whether `byte` may appear as is in a URI (RFC 3986 unreserved and
+/// reserved characters).
+fn allowed_in_uri(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"-._~:/?#[]@!$&'()*+,;=".contains(&byte)
+}
+
+/// Percent-encodes what can't appear in a URI, keeping valid %XX escapes.
+fn encode_uri(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut encoded = String::with_capacity(uri.len());
+    for (i, &byte) in bytes.iter().enumerate() {
+        let escape = byte == b'%'
+            && bytes.len() > i + 2
+            && bytes[i + 1].is_ascii_hexdigit()
+            && bytes[i + 2].is_ascii_hexdigit();
+        if allowed_in_uri(byte) || escape {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+impl IntoResponse for Redirect {
+    fn into_response(self) -> Response {
+        let mut response = Response::new(ResponseBody::empty());
+        *response.status_mut() = self.status;
+        response.headers_mut().insert(LOCATION, self.location);
+        response
+    }
+}
+
+impl ResponseModifier for Redirect {
+    fn update_response(op: &mut Operation) {
+        // NOTE: the status is picked at runtime, so the spec can only say
+        //       "some 3xx"; the route's summary should say which. Every
+        //       operation starts out with a blank 200, which a redirect never is.
+        op.responses.remove("200");
+        let mut headers = BTreeMap::new();
+        headers.insert(
+            "Location".to_string(),
+            serde_json::from_value(serde_json::json!({
+                "description": "Where to go instead",
+                "schema": { "type": "string", "format": "uri-reference" }
+            }))
+            .expect("valid OpenAPI header object"),
+        );
+
+        op.responses.insert(
+            "3XX".to_string(),
+            ResponseSpec {
+                description: "Redirect".to_string(),
+                content: BTreeMap::new(),
+                headers,
+            },
+        );
+    }
+}
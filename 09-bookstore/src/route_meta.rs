@@ -0,0 +1,187 @@
+// ---------------------------------------------------------------------------
+// Per-route metadata for layers
+// ---------------------------------------------------------------------------
+//
+// Some layers need settings that belong to one route: its latency SLO, its
+// rate limit, whether it is public. Declared next to the routes, keyed by
+// their templates:
+//
+//   let meta = RouteMeta::new()
+//       .route(Method::GET, "/books/search", Slo::p99(Duration::from_millis(200)))
+//       .route(Method::GET, "/books/{id}", Slo::p99(Duration::from_millis(50)));
+//
+//   RustApi::auto().route_meta(meta)?.layer(SloLayer::new(..))
+//
+// and read by any layer added after it:
+//
+//   fn call(&self, req: Request, next: BoxedNext) -> ... {
+//       if let Some(slo) = req.route_meta::<Slo>() { ... }
+//       let label = req.matched_route().map(|route| route.template());  // "/books/{id}"
+//   }
+//
+// RustAPI picks the route only after every layer has run, so there is no
+// match to read yet when they do. `route_meta` adds the outermost layer,
+// which matches the path itself — with matchit and the app's own registered
+// routes, so `/books/search` beats `/books/{id}` exactly as it does for the
+// router — and puts a `MatchedRoute` in the request extensions.
+//
+// Every matched request gets one, with or without metadata, so layers can
+// also label by template instead of raw path: one label for `/books/{id}`,
+// not one per id. Requests that match no route (404, 405) get none.
+//
+// `route_meta` fails at startup if metadata names a route that isn't
+// mounted, like ensure_mounted(): a typo in a template would otherwise turn
+// an SLO off without a word.
+//
+// NOTE: call it before `.layer(..)`; layers added earlier run before it and
+//       see nothing. Routes mounted after it are not matched.
+
+use http::{Extensions, Method};
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_rs::prelude::*;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// Metadata to attach, per method and route template.
+#[derive(Default)]
+pub struct RouteMeta {
+    routes: HashMap<(Method, String), Extensions>,
+}
+
+impl RouteMeta {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attaches `value` to `method template`. A route holds one value per
+    /// type; attaching another of the same type replaces it.
+    pub fn route<T>(mut self, method: Method, template: &str, value: T) -> Self
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        self.routes
+            .entry((method, template.to_string()))
+            .or_default()
+            .insert(value);
+        self
+    }
+}
+
+/// The route a request matched, and the metadata declared on it.
+#[derive(Debug)]
+pub struct MatchedRoute {
+    method: Method,
+    template: String,
+    meta: Extensions,
+}
+
+impl MatchedRoute {
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// `/books/{id}`, as the route was declared.
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    /// The `T` declared on this route, if any.
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.meta.get::<T>()
+    }
+}
+
+/// Typed access to the matched route, for layers and extractors.
+pub trait RouteMetaRequestExt {
+    /// None before `route_meta`'s layer has run, or if no route matched.
+    fn matched_route(&self) -> Option<&MatchedRoute>;
+
+    /// The `T` declared on the matched route.
+    fn route_meta<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.matched_route()?.get::<T>()
+    }
+}
+
+impl RouteMetaRequestExt for Request {
+    fn matched_route(&self) -> Option<&MatchedRoute> {
+        self.extensions()
+            .get::<Arc<MatchedRoute>>()
+            .map(Arc::as_ref)
+    }
+}
+
+/// One path pattern, and its route per method.
+type Methods = HashMap<Method, Arc<MatchedRoute>>;
+
+#[derive(Clone)]
+struct RouteMetaLayer {
+    matcher: Arc<matchit::Router<Methods>>,
+}
+
+impl MiddlewareLayer for RouteMetaLayer {
+    fn call(
+        &self,
+        mut req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let matched = self
+            .matcher
+            .at(req.path())
+            .ok()
+            .and_then(|found| found.value.get(req.method()).cloned());
+        if let Some(matched) = matched {
+            req.extensions_mut().insert(matched);
+        }
+        Box::pin(next(req))
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+/// `RustApi::auto().route_meta(meta)?`
+pub trait RouteMetaExt: Sized {
+    /// Matches every request against the app's routes and attaches `meta`.
+    /// Fails if `meta` names a route that isn't mounted.
+    fn route_meta(self, meta: RouteMeta) -> Result<Self, String>;
+}
+
+impl RouteMetaExt for RustApi {
+    fn route_meta(self, mut meta: RouteMeta) -> Result<Self, String> {
+        let mut matcher = matchit::Router::new();
+        for (pattern, info) in self.router().registered_routes() {
+            let methods: Methods = info
+                .methods
+                .iter()
+                .map(|method| {
+                    let key = (method.clone(), info.path.clone());
+                    let route = MatchedRoute {
+                        method: method.clone(),
+                        template: info.path.clone(),
+                        meta: meta.routes.remove(&key).unwrap_or_default(),
+                    };
+                    (method.clone(), Arc::new(route))
+                })
+                .collect();
+            // The patterns come from a matchit router already; they fit.
+            matcher
+                .insert(pattern.clone(), methods)
+                .map_err(|err| format!("Can't match route {}: {}", info.path, err))?;
+        }
+
+        // Whatever is left names no mounted route.
+        if let Some((method, template)) = meta.routes.into_keys().next() {
+            return Err(format!(
+                "Route metadata is declared for {} {}, but no such route is mounted",
+                method, template
+            ));
+        }
+
+        Ok(self.layer(RouteMetaLayer {
+            matcher: Arc::new(matcher),
+        }))
+    }
+}
@@ -0,0 +1,232 @@
+// ---------------------------------------------------------------------------
+// Latency SLOs
+// ---------------------------------------------------------------------------
+//
+// A route declares "p99 under 200ms over the last minute" as route metadata
+// (src/route_meta.rs); SloLayer measures it in-process and calls a hook when
+// the window breaches it. What the hook does — log, page someone, flip a
+// feature flag — is up to the app.
+//
+//   RouteMeta::new().route(Method::GET, "/books/{id}", Slo::p99(Duration::from_millis(50)))
+//
+// A templated route has one window for all its paths, and breaches name the
+// template: `GET /books/{id}`, not `GET /books/3`.
+//
+// Cost per request on a watched route: one short mutex hold to push a sample
+// into a bounded window. The percentile itself is computed at most once per
+// EVALUATE_EVERY, and unwatched routes only pay for an extensions lookup.
+//
+// The hook is debounced: after it fires for a route, it stays silent for that
+// route's cooldown even if the SLO is still breached, so one slow minute is
+// one alert and not thousands.
+
+use crate::route_meta::RouteMetaRequestExt;
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_rs::prelude::*;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often a watched route's percentile is recomputed.
+const EVALUATE_EVERY: Duration = Duration::from_secs(1);
+
+/// A latency objective: `quantile` of requests within `target`.
+#[derive(Debug, Clone)]
+pub struct Slo {
+    quantile: f64,
+    target: Duration,
+    window: Duration,
+    max_samples: usize,
+    min_samples: usize,
+    cooldown: Duration,
+}
+
+impl Slo {
+    /// 99% of requests faster than `target`, over a one-minute window.
+    pub fn p99(target: Duration) -> Self {
+        Self::quantile(0.99, target)
+    }
+
+    /// `quantile` (0.0–1.0) of requests faster than `target`.
+    pub fn quantile(quantile: f64, target: Duration) -> Self {
+        Self {
+            quantile: quantile.clamp(0.0, 1.0),
+            target,
+            window: Duration::from_secs(60),
+            max_samples: 2048,
+            min_samples: 20,
+            cooldown: Duration::from_secs(300),
+        }
+    }
+
+    /// How far back the rolling window reaches (default one minute).
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Don't judge a window with fewer samples than this (default 20) — one
+    /// slow request out of three says little about the p99.
+    pub fn min_samples(mut self, min_samples: usize) -> Self {
+        self.min_samples = min_samples.max(1);
+        self
+    }
+
+    /// Quiet period after a breach is reported (default five minutes).
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+}
+
+/// What the breach hook is told.
+#[derive(Debug, Clone)]
+pub struct SloBreach {
+    /// `GET /books/{id}`
+    pub route: String,
+    pub quantile: f64,
+    /// The measured latency at `quantile`.
+    pub observed: Duration,
+    pub target: Duration,
+    /// Requests in the window the percentile was taken over.
+    pub samples: usize,
+}
+
+type BreachHook = Arc<dyn Fn(&SloBreach) + Send + Sync>;
+
+struct Window {
+    samples: VecDeque<(Instant, Duration)>,
+    last_evaluated: Option<Instant>,
+    last_fired: Option<Instant>,
+}
+
+struct WatchedRoute {
+    /// `GET /books/{id}`
+    route: String,
+    slo: Slo,
+    window: Mutex<Window>,
+}
+
+impl WatchedRoute {
+    /// Records one request; returns a breach if this sample tipped the window
+    /// over the objective and the route isn't cooling down.
+    fn record(&self, now: Instant, elapsed: Duration) -> Option<SloBreach> {
+        let mut window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+
+        window.samples.push_back((now, elapsed));
+        while window.samples.len() > self.slo.max_samples {
+            window.samples.pop_front();
+        }
+        while let Some((at, _)) = window.samples.front() {
+            if now.duration_since(*at) <= self.slo.window {
+                break;
+            }
+            window.samples.pop_front();
+        }
+
+        let due = window
+            .last_evaluated
+            .is_none_or(|at| now.duration_since(at) >= EVALUATE_EVERY);
+        if !due || window.samples.len() < self.slo.min_samples {
+            return None;
+        }
+        window.last_evaluated = Some(now);
+
+        let mut latencies: Vec<_> = window.samples.iter().map(|(_, d)| *d).collect();
+        let rank = ((latencies.len() as f64 * self.slo.quantile).ceil() as usize)
+            .clamp(1, latencies.len());
+        let (_, observed, _) = latencies.select_nth_unstable(rank - 1);
+        let observed = *observed;
+        if observed <= self.slo.target {
+            return None;
+        }
+
+        let cooling = window
+            .last_fired
+            .is_some_and(|at| now.duration_since(at) < self.slo.cooldown);
+        if cooling {
+            return None;
+        }
+        window.last_fired = Some(now);
+
+        Some(SloBreach {
+            route: self.route.clone(),
+            quantile: self.slo.quantile,
+            observed,
+            target: self.slo.target,
+            samples: latencies.len(),
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct SloLayer {
+    /// Created on a route's first request, keyed by `METHOD template`.
+    routes: Arc<Mutex<HashMap<String, Arc<WatchedRoute>>>>,
+    on_breach: BreachHook,
+}
+
+impl SloLayer {
+    /// Calls `on_breach` whenever a route breaches the `Slo` in its metadata
+    /// (at most once per cooldown). It runs outside any lock, on the
+    /// request's task: keep it quick and spawn a task for anything that does
+    /// I/O.
+    pub fn new(on_breach: impl Fn(&SloBreach) + Send + Sync + 'static) -> Self {
+        Self {
+            routes: Arc::new(Mutex::new(HashMap::new())),
+            on_breach: Arc::new(on_breach),
+        }
+    }
+
+    /// The window for the request's route, if its metadata has an `Slo`.
+    fn watched(&self, req: &Request) -> Option<Arc<WatchedRoute>> {
+        let slo = req.route_meta::<Slo>()?;
+        let matched = req.matched_route()?;
+        let route = format!("{} {}", matched.method(), matched.template());
+
+        let mut routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+        let watched = routes.entry(route.clone()).or_insert_with(|| {
+            Arc::new(WatchedRoute {
+                route,
+                slo: slo.clone(),
+                window: Mutex::new(Window {
+                    samples: VecDeque::new(),
+                    last_evaluated: None,
+                    last_fired: None,
+                }),
+            })
+        });
+        Some(watched.clone())
+    }
+}
+
+impl MiddlewareLayer for SloLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let watched = self.watched(&req);
+        let on_breach = self.on_breach.clone();
+
+        Box::pin(async move {
+            let Some(watched) = watched else {
+                return next(req).await;
+            };
+
+            let started = Instant::now();
+            let response = next(req).await;
+
+            if let Some(breach) = watched.record(Instant::now(), started.elapsed()) {
+                on_breach(&breach);
+            }
+            response
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
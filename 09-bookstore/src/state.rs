@@ -0,0 +1,106 @@
+// ---------------------------------------------------------------------------
+// State<T>, checked at startup
+// ---------------------------------------------------------------------------
+//
+// Registered state is a map by type: register any number of values, each of
+// its own type, and extract each on its own:
+//
+//   RustApi::auto().state(db).state(templates).state(config)
+//
+//   async fn page(State(db): State<Db>, State(views): State<Views>) -> ..
+//
+// rustapi's State<T> finds a missing value only when a request needs it,
+// and answers that request with a 500. This State<T> is a drop-in for it
+// that also notes, as routes are built, every T a handler extracts;
+// assert_state() then fails the start instead:
+//
+//   let app = RustApi::auto().state(db).state(templates);
+//   assert_state(&app);   // after the last .state()
+//
+//   thread 'main' panicked at src/main.rs:..:
+//   State<bookstore::Config> is extracted by a handler but was never
+//   registered: add .state(..) with a bookstore::Config
+//
+// Import it instead of the prelude's (`use state::State;` wins over the
+// glob) and handlers don't change.
+//
+// NOTE: only handler arguments are seen. An extractor that reads
+//       `req.state().get::<T>()` itself (Valid<T>, Multipart) decides
+//       alone what a missing value means.
+
+use http::Extensions;
+use rustapi_openapi::{Operation, OperationModifier};
+use rustapi_rs::prelude::*;
+use rustapi_rs::FromRequestParts;
+use std::any::type_name;
+use std::ops::Deref;
+use std::sync::Mutex;
+
+/// A `T` registered with `.state()`, by type.
+#[derive(Debug, Clone)]
+pub struct State<T>(pub T);
+
+/// A type some handler extracts, and how to tell whether it's registered.
+struct Requested {
+    type_name: &'static str,
+    registered: fn(&Extensions) -> bool,
+}
+
+/// Filled in by `update_operation`, which runs as each route is built.
+static REQUESTED: Mutex<Vec<Requested>> = Mutex::new(Vec::new());
+
+fn registered<T: Send + Sync + 'static>(state: &Extensions) -> bool {
+    state.get::<T>().is_some()
+}
+
+impl<T: Clone + Send + Sync + 'static> FromRequestParts for State<T> {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        req.state().get::<T>().cloned().map(State).ok_or_else(|| {
+            ApiError::internal(format!(
+                "State of type `{}` not found. Did you forget to call .state()?",
+                type_name::<T>()
+            ))
+        })
+    }
+}
+
+impl<T: Send + Sync + 'static> OperationModifier for State<T> {
+    fn update_operation(_op: &mut Operation) {
+        let mut requested = REQUESTED.lock().unwrap_or_else(|e| e.into_inner());
+        if !requested.iter().any(|r| r.type_name == type_name::<T>()) {
+            requested.push(Requested {
+                type_name: type_name::<T>(),
+                registered: registered::<T>,
+            });
+        }
+    }
+}
+
+impl<T> Deref for State<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+/// Panics, naming each one, if a handler extracts a State<T> that `app`
+/// has no value for. Call it after the last `.state()`.
+#[track_caller]
+pub fn assert_state(app: &RustApi) {
+    let state = app.router().state_ref();
+    let requested = REQUESTED.lock().unwrap_or_else(|e| e.into_inner());
+    let missing: Vec<String> = requested
+        .iter()
+        .filter(|r| !(r.registered)(&state))
+        .map(|r| {
+            format!(
+                "State<{}> is extracted by a handler but was never registered: add .state(..) with a {}",
+                r.type_name, r.type_name
+            )
+        })
+        .collect();
+    if !missing.is_empty() {
+        panic!("{}", missing.join("\n"));
+    }
+}
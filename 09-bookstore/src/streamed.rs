@@ -0,0 +1,139 @@
+// ---------------------------------------------------------------------------
+// Streamed: a response body produced a chunk at a time
+// ---------------------------------------------------------------------------
+//
+// Every response is sent one of two ways, and the body decides which:
+//
+//   ResponseBody::Full        Json, Html, String, ... and every error: built
+//     (the default)           in full, sent with a Content-Length
+//   ResponseBody::Streaming   sent as it is produced; chunked on HTTP/1.1,
+//                             plain DATA frames on HTTP/2
+//
+// A handler that produces its body incrementally (a report, an export)
+// returns Streamed to opt into the second; one that wants the
+// Content-Length anyway buffers the same stream first:
+//
+//   async fn report(..) -> Result<Streamed> {
+//       let csv = Streamed::new(rows).content_type("text/csv");
+//       if params.buffer { csv.buffered().await } else { Ok(csv) }
+//   }
+//
+//                      streamed                   .buffered()
+//   first byte         after the first chunk      after the last
+//   memory             one chunk                  the whole body
+//   Content-Length     no                         yes
+//   a chunk fails      logged, connection cut     500, nothing sent yet
+//   compressed         no: CompressionLayer       yes, if big enough
+//                      leaves streams alone
+//   ETag / 304         no: ETagLayer can't hash   yes
+//                      a stream
+//
+// A cut connection is the only way to tell a streaming client that the body
+// is incomplete: the 200 and its headers are long gone. A chunked body then
+// ends without its terminating chunk, which curl reports as an error.
+//
+// NOTE: HtmlPage (src/html_page.rs), Sse and 04-sse-stream's NdJson are
+//       streams too, with a format of their own; Streamed is for bytes
+//       that are already formatted.
+
+use bytes::Bytes;
+use futures_util::{Stream, TryStreamExt};
+use http::header::{HeaderValue, CONTENT_TYPE};
+use rustapi_openapi::{MediaType, Operation, ResponseModifier, ResponseSpec, SchemaRef};
+use rustapi_rs::prelude::*;
+use rustapi_rs::ResponseBody;
+use std::collections::BTreeMap;
+use std::pin::Pin;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+type Chunks = Pin<Box<dyn Stream<Item = std::result::Result<Bytes, BoxError>> + Send>>;
+
+enum Body {
+    Stream(Chunks),
+    Whole(Bytes),
+}
+
+/// A body from a stream of chunks, sent as they come unless `buffered`.
+pub struct Streamed {
+    body: Body,
+    content_type: HeaderValue,
+}
+
+impl Streamed {
+    /// `application/octet-stream` until `content_type` says otherwise.
+    pub fn new<S, B, E>(chunks: S) -> Self
+    where
+        S: Stream<Item = std::result::Result<B, E>> + Send + 'static,
+        B: Into<Bytes> + 'static,
+        E: Into<BoxError> + 'static,
+    {
+        Self {
+            body: Body::Stream(Box::pin(chunks.map_ok(Into::into).map_err(Into::into))),
+            content_type: HeaderValue::from_static("application/octet-stream"),
+        }
+    }
+
+    pub fn content_type(mut self, content_type: &'static str) -> Self {
+        self.content_type = HeaderValue::from_static(content_type);
+        self
+    }
+
+    /// Reads the whole stream now, so it is sent with a Content-Length. A
+    /// chunk that fails fails the request with a 500, before anything is
+    /// sent.
+    pub async fn buffered(self) -> Result<Self> {
+        let Body::Stream(chunks) = self.body else {
+            return Ok(self);
+        };
+        let parts: Vec<Bytes> = chunks.try_collect().await.map_err(|err| {
+            error!("Buffering a streamed body failed: {}", err);
+            ApiError::internal("Failed to produce the response body")
+        })?;
+        Ok(Self {
+            body: Body::Whole(parts.concat().into()),
+            content_type: self.content_type,
+        })
+    }
+}
+
+impl IntoResponse for Streamed {
+    fn into_response(self) -> Response {
+        let body = match self.body {
+            Body::Whole(bytes) => ResponseBody::from(bytes),
+            Body::Stream(chunks) => ResponseBody::from_stream(chunks.map_err(|err| {
+                // Too late for a status: ending the body with an error
+                // makes hyper cut the connection.
+                error!("Streamed body failed: {}", err);
+                ApiError::internal("Streamed body failed")
+            })),
+        };
+        let mut response = Response::new(body);
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, self.content_type);
+        response
+    }
+}
+
+impl ResponseModifier for Streamed {
+    fn update_response(op: &mut Operation) {
+        let mut content = BTreeMap::new();
+        content.insert(
+            "*/*".to_string(),
+            MediaType {
+                schema: Some(SchemaRef::Inline(
+                    serde_json::json!({ "type": "string", "format": "binary" }),
+                )),
+                example: None,
+            },
+        );
+        op.responses.insert(
+            "200".to_string(),
+            ResponseSpec {
+                description: "Streamed, or buffered with a Content-Length".to_string(),
+                content,
+                headers: BTreeMap::new(),
+            },
+        );
+    }
+}
@@ -0,0 +1,148 @@
+// ---------------------------------------------------------------------------
+// Valid<E>: an extractor, then its rules
+// ---------------------------------------------------------------------------
+//
+// Wrap Json, Form or Query in Valid and the value is checked before the
+// handler runs:
+//
+//   #[derive(Deserialize, Schema, Validate)]
+//   struct ContactForm {
+//       #[validate(length(min = 1, max = 100))]
+//       name: String,
+//       #[validate(email)]
+//       email: String,
+//   }
+//
+//   async fn contact(Valid(Form(form)): Valid<Form<ContactForm>>) -> ..
+//
+//   the inner extractor fails           its own error (400, 415, ...)
+//   a rule fails                        422, every failed rule listed
+//
+//   {"error":{"type":"validation_error","message":"Request validation failed",
+//             "fields":[{"field":"email","code":"email","message":"Invalid email format"},
+//                       {"field":"name","code":"length","message":"Length must be at least 1 characters"}]}}
+//
+// Fields are sorted by name, then rule, so the list is stable between
+// requests. Each entry is one rule: a field that breaks two is listed twice.
+//
+// The rules are rustapi-validate's, #[derive(Validate)]: the `validator`
+// crate's attributes, same names, checked by rustapi's own trait. To change
+// the body, register a ValidationFormat:
+//
+//   RustApi::auto().state(ValidationFormat::new(|req, errors| ..ApiError..))
+//
+// It sees the request, so it can pick a language (see main.rs) or a status.
+//
+// NOTE: a Query<T> wrapped in Valid still documents its parameters; the
+//       rules themselves aren't in the OpenAPI schema, so say them in the
+//       field's doc comment.
+
+use rustapi_openapi::{
+    MediaType, OpenApiSpec, Operation, OperationModifier, ResponseSpec, SchemaRef,
+};
+use rustapi_rs::prelude::*;
+use rustapi_rs::{FieldError, FromRequest, FromRequestParts};
+use rustapi_validate::v2::ValidationErrors;
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::form::Form;
+use crate::query::Query;
+
+/// An extracted value that passed its `#[validate(..)]` rules.
+pub struct Valid<E>(pub E);
+
+/// Turns failed rules into the response. Register as state to replace the
+/// default, `ApiError::validation` with untranslated messages.
+#[derive(Clone)]
+pub struct ValidationFormat(Arc<FormatFn>);
+
+type FormatFn = dyn Fn(&Request, ValidationErrors) -> ApiError + Send + Sync;
+
+impl ValidationFormat {
+    pub fn new(
+        format: impl Fn(&Request, ValidationErrors) -> ApiError + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(format))
+    }
+}
+
+/// One entry per failed rule, sorted by field, then rule; messages in
+/// `locale` (rustapi ships "en" and "tr"), or the default.
+pub fn field_errors(errors: &ValidationErrors, locale: Option<&str>) -> Vec<FieldError> {
+    let mut fields: Vec<FieldError> = errors
+        .fields
+        .iter()
+        .flat_map(|(field, rules)| {
+            rules.iter().map(move |rule| FieldError {
+                field: field.clone(),
+                code: rule.code.clone(),
+                message: rule.interpolate_with_locale(locale),
+            })
+        })
+        .collect();
+    fields.sort_by(|a, b| (&a.field, &a.code).cmp(&(&b.field, &b.code)));
+    fields
+}
+
+/// Checks `value`, formatting a failure with the registered ValidationFormat.
+fn check<T: V2Validate>(req: &Request, value: T) -> Result<T> {
+    match value.validate() {
+        Ok(()) => Ok(value),
+        Err(errors) => Err(match req.state().get::<ValidationFormat>() {
+            Some(ValidationFormat(format)) => format(req, errors),
+            None => ApiError::validation(field_errors(&errors, None)),
+        }),
+    }
+}
+
+impl<T: DeserializeOwned + V2Validate + Send> FromRequest for Valid<Json<T>> {
+    async fn from_request(req: &mut Request) -> Result<Self> {
+        let Json(value) = Json::<T>::from_request(req).await?;
+        check(req, value).map(|value| Self(Json(value)))
+    }
+}
+
+impl<T: DeserializeOwned + V2Validate + Send> FromRequest for Valid<Form<T>> {
+    async fn from_request(req: &mut Request) -> Result<Self> {
+        let Form(value) = Form::<T>::from_request(req).await?;
+        check(req, value).map(|value| Self(Form(value)))
+    }
+}
+
+impl<T: DeserializeOwned + V2Validate> FromRequestParts for Valid<Query<T>> {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        let Query(value) = Query::<T>::from_request_parts(req)?;
+        check(req, value).map(|value| Self(Query(value)))
+    }
+}
+
+impl<E: OperationModifier> OperationModifier for Valid<E> {
+    fn update_operation(op: &mut Operation) {
+        E::update_operation(op);
+
+        let mut content = BTreeMap::new();
+        content.insert(
+            "application/json".to_string(),
+            MediaType {
+                schema: Some(SchemaRef::Ref {
+                    reference: "#/components/schemas/ValidationErrorSchema".to_string(),
+                }),
+                example: None,
+            },
+        );
+        op.responses.insert(
+            "422".to_string(),
+            ResponseSpec {
+                description: "A field broke one of its rules".to_string(),
+                content,
+                headers: BTreeMap::new(),
+            },
+        );
+    }
+
+    fn register_components(spec: &mut OpenApiSpec) {
+        E::register_components(spec);
+    }
+}
@@ -0,0 +1,425 @@
+// ---------------------------------------------------------------------------
+// Template views with a rendered-and-compressed page cache
+// ---------------------------------------------------------------------------
+//
+// Pages like /home and /about change rarely but are hit constantly. Every
+// request would render the template, then CompressionLayer would gzip the
+// result: the same work, over and over. A View keeps the finished bytes:
+//
+//   async fn home(State(state): State<AppState>, view: View) -> Result<Page> {
+//       let context = HomeContext { .. };
+//       view.render("home.html", &context, HOME_TTL)
+//   }
+//
+// The templates are the `*.html` files of one directory, with `{{name}}`
+// placeholders filled from the context's fields, HTML-escaped, plus
+// `{{flags}}`: the request's feature flags that are on (src/flags.rs),
+// unless the context has a `flags` of its own. A rendered page is cached by
+// template and a hash of the context, flags included, for the TTL the
+// handler passes, so a context that changes (a new book) is a new entry.
+// Each cached page keeps its plain bytes plus a gzip and a deflate copy,
+// compressed the first time a client asks for that encoding.
+//
+//   X-View-Cache: hit      served from the cache; nothing rendered
+//   X-View-Cache: miss     rendered now, cached for next time
+//   X-View-Cache: bypass   the request sent `X-Preview` (Views::bypass_header):
+//                          the template re-read from disk, rendered and
+//                          compressed fresh, not cached — a preview of an
+//                          edit before the reload that publishes it
+//
+// Views::reload() re-reads the directory, all or nothing, and starts an
+// empty cache with the new templates: no page rendered from an old template
+// is served after it. main() calls it on SIGHUP.
+//
+// Responses carry Content-Encoding when compressed, so CompressionLayer
+// leaves them alone, and `Vary: Accept-Encoding` either way.
+//
+// NOTE: a bypass costs a full render; anyone can send the header. Strip it
+//       at the proxy for outside traffic, or check a token, if renders are
+//       expensive.
+//
+// NOTE: there's no brotli encoder in this example (see src/compression.rs);
+//       one would be a third lazily filled copy next to gzip and deflate.
+
+use crate::compression::{self, Algorithm};
+use crate::flags::Flags;
+use crate::html_page::escape;
+use bytes::Bytes;
+use http::header::{
+    HeaderName, HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_TYPE, VARY,
+};
+use rustapi_openapi::{
+    MediaType, Operation, OperationModifier, Parameter, ResponseModifier, ResponseSpec, SchemaRef,
+};
+use rustapi_rs::prelude::*;
+use rustapi_rs::{FromRequestParts, ResponseBody};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+
+/// Past this many cached pages, expired ones are swept out; if none are,
+/// new pages aren't cached until some expire.
+const MAX_PAGES: usize = 1024;
+
+/// A piece of a parsed template.
+enum Part {
+    Text(String),
+    Field(String),
+}
+
+/// A template, split at its placeholders when loaded.
+struct Template(Vec<Part>);
+
+impl Template {
+    fn parse(source: &str) -> std::result::Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut rest = source;
+        while let Some(start) = rest.find("{{") {
+            parts.push(Part::Text(rest[..start].to_string()));
+            let after = &rest[start + 2..];
+            let end = after.find("}}").ok_or("`{{` without a closing `}}`")?;
+            let name = after[..end].trim();
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(format!("bad placeholder `{{{{{}}}}}`", &after[..end]));
+            }
+            parts.push(Part::Field(name.to_string()));
+            rest = &after[end + 2..];
+        }
+        parts.push(Part::Text(rest.to_string()));
+        Ok(Self(parts))
+    }
+
+    fn render(&self, context: &serde_json::Map<String, serde_json::Value>) -> Result<String> {
+        let mut page = String::new();
+        for part in &self.0 {
+            match part {
+                Part::Text(text) => page.push_str(text),
+                Part::Field(name) => match context.get(name) {
+                    Some(serde_json::Value::String(text)) => page.push_str(&escape(text)),
+                    Some(serde_json::Value::Null) => {}
+                    Some(value @ (serde_json::Value::Number(_) | serde_json::Value::Bool(_))) => {
+                        page.push_str(&value.to_string())
+                    }
+                    Some(_) => {
+                        return Err(ApiError::internal(format!(
+                            "`{name}` is a list or an object; templates take plain values"
+                        )))
+                    }
+                    None => {
+                        return Err(ApiError::internal(format!(
+                            "`{name}` is not in the context"
+                        )))
+                    }
+                },
+            }
+        }
+        Ok(page)
+    }
+}
+
+/// A rendered page, and its compressed copies once asked for. A copy is
+/// None when compressing didn't make it smaller.
+struct Rendered {
+    plain: Bytes,
+    gzip: OnceLock<Option<Bytes>>,
+    deflate: OnceLock<Option<Bytes>>,
+    expires: Instant,
+}
+
+impl Rendered {
+    fn new(plain: String, ttl: Duration) -> Self {
+        Self {
+            plain: Bytes::from(plain),
+            gzip: OnceLock::new(),
+            deflate: OnceLock::new(),
+            expires: Instant::now() + ttl,
+        }
+    }
+
+    /// The body for `encoding`, and the encoding it actually has.
+    fn body(&self, encoding: Option<Algorithm>) -> (Bytes, Option<Algorithm>) {
+        let Some(algorithm) = encoding else {
+            return (self.plain.clone(), None);
+        };
+        let copy = match algorithm {
+            Algorithm::Gzip => &self.gzip,
+            Algorithm::Deflate => &self.deflate,
+        };
+        let encoded = copy.get_or_init(|| {
+            let encoded = algorithm.encode(&self.plain);
+            (encoded.len() < self.plain.len()).then(|| Bytes::from(encoded))
+        });
+        match encoded {
+            Some(encoded) => (encoded.clone(), Some(algorithm)),
+            None => (self.plain.clone(), None),
+        }
+    }
+}
+
+/// One load of the templates, and the pages rendered from it. A reload
+/// replaces the whole thing, cache included.
+struct Generation {
+    templates: HashMap<String, Template>,
+    pages: Mutex<HashMap<(String, u64), Arc<Rendered>>>,
+}
+
+impl Generation {
+    /// Every `*.html` in `dir`. Any file that can't be read or parsed fails
+    /// the whole load.
+    fn load(dir: &Path) -> std::result::Result<Self, String> {
+        let entries =
+            std::fs::read_dir(dir).map_err(|err| format!("{}: {}", dir.display(), err))?;
+        let mut templates = HashMap::new();
+        for entry in entries {
+            let path = entry
+                .map_err(|err| format!("{}: {}", dir.display(), err))?
+                .path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("html") {
+                continue;
+            }
+            let source = std::fs::read_to_string(&path)
+                .map_err(|err| format!("{}: {}", path.display(), err))?;
+            let template =
+                Template::parse(&source).map_err(|err| format!("{}: {}", path.display(), err))?;
+            let name = path.file_name().and_then(|name| name.to_str());
+            templates.insert(name.unwrap_or_default().to_string(), template);
+        }
+        Ok(Self {
+            templates,
+            pages: Mutex::new(HashMap::new()),
+        })
+    }
+}
+
+/// The templates and their page cache. Put it in the app state; handlers
+/// take a `View`.
+#[derive(Clone)]
+pub struct Views {
+    dir: PathBuf,
+    current: Arc<RwLock<Arc<Generation>>>,
+    algorithms: Arc<[Algorithm]>,
+    bypass: HeaderName,
+}
+
+impl Views {
+    /// Loads the templates in `dir`.
+    pub fn load(dir: impl Into<PathBuf>) -> std::result::Result<Self, String> {
+        let dir = dir.into();
+        let generation = Generation::load(&dir)?;
+        Ok(Self {
+            dir,
+            current: Arc::new(RwLock::new(Arc::new(generation))),
+            algorithms: Arc::new([Algorithm::Gzip, Algorithm::Deflate]),
+            bypass: HeaderName::from_static("x-preview"),
+        })
+    }
+
+    /// The request header that skips the cache. Default `X-Preview`.
+    pub fn bypass_header(mut self, name: &'static str) -> Self {
+        self.bypass = HeaderName::from_static(name);
+        self
+    }
+
+    /// Re-reads the templates and drops every cached page, or changes
+    /// nothing if a template fails. Returns how many templates there are.
+    pub fn reload(&self) -> std::result::Result<usize, String> {
+        let generation = Generation::load(&self.dir)?;
+        let count = generation.templates.len();
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(generation);
+        Ok(count)
+    }
+}
+
+/// Renders templates for one request, in the encoding it accepts.
+pub struct View {
+    generation: Arc<Generation>,
+    encoding: Option<Algorithm>,
+    /// The template directory, for a request that bypasses the cache.
+    preview: Option<PathBuf>,
+    /// Filled in as `{{flags}}`.
+    flags: Flags,
+}
+
+impl View {
+    /// Template `name` filled from `context`'s fields: from the cache if it
+    /// was rendered with the same context in the last `ttl`, else rendered
+    /// now and cached for `ttl`. A zero `ttl` never caches.
+    pub fn render<C: Serialize>(&self, name: &str, context: &C, ttl: Duration) -> Result<Page> {
+        let fresh;
+        let template = match &self.preview {
+            Some(dir) => {
+                let path = dir.join(name);
+                fresh = std::fs::read_to_string(&path)
+                    .map_err(|err| err.to_string())
+                    .and_then(|source| Template::parse(&source))
+                    .map_err(|err| ApiError::internal(format!("{}: {}", path.display(), err)))?;
+                &fresh
+            }
+            None => self
+                .generation
+                .templates
+                .get(name)
+                .ok_or_else(|| ApiError::internal(format!("No template {name}")))?,
+        };
+        let mut context = match serde_json::to_value(context) {
+            Ok(serde_json::Value::Object(fields)) => fields,
+            _ => {
+                return Err(ApiError::internal(
+                    "A template context must serialize to an object",
+                ))
+            }
+        };
+        context
+            .entry("flags")
+            .or_insert_with(|| self.flags.all().join(" ").into());
+        // serde_json's Map is sorted, so equal contexts hash the same.
+        let mut hasher = DefaultHasher::new();
+        serde_json::Value::Object(context.clone())
+            .to_string()
+            .hash(&mut hasher);
+        let key = (name.to_string(), hasher.finish());
+
+        if self.preview.is_none() {
+            let cached = self
+                .generation
+                .pages
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get(&key)
+                .filter(|page| page.expires > Instant::now())
+                .cloned();
+            // Outside the lock: the first request for an encoding compresses.
+            if let Some(page) = cached {
+                return Ok(Page::new(page.body(self.encoding), "hit"));
+            }
+        }
+
+        let page = Arc::new(Rendered::new(template.render(&context)?, ttl));
+        let body = page.body(self.encoding);
+        if self.preview.is_some() {
+            return Ok(Page::new(body, "bypass"));
+        }
+        if !ttl.is_zero() {
+            let mut pages = self
+                .generation
+                .pages
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            if pages.len() >= MAX_PAGES {
+                let now = Instant::now();
+                pages.retain(|_, page| page.expires > now);
+            }
+            if pages.len() < MAX_PAGES {
+                pages.insert(key, page);
+            }
+        }
+        Ok(Page::new(body, "miss"))
+    }
+}
+
+impl FromRequestParts for View {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        let views = req
+            .state()
+            .get::<Views>()
+            .ok_or_else(|| ApiError::internal("No Views in the app state"))?;
+        let accept_encoding = req
+            .headers()
+            .get_all(ACCEPT_ENCODING)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+        let generation = views
+            .current
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        Ok(Self {
+            generation,
+            encoding: compression::negotiate(&views.algorithms, &accept_encoding),
+            preview: req
+                .headers()
+                .contains_key(&views.bypass)
+                .then(|| views.dir.clone()),
+            flags: Flags::from_request_parts(req)?,
+        })
+    }
+}
+
+impl OperationModifier for View {
+    fn update_operation(op: &mut Operation) {
+        op.parameters.push(Parameter {
+            name: "X-Preview".to_string(),
+            location: "header".to_string(),
+            description: Some("Any value: render fresh, skipping the page cache".to_string()),
+            required: false,
+            deprecated: None,
+            schema: Some(SchemaRef::Inline(serde_json::json!({ "type": "string" }))),
+        });
+    }
+}
+
+/// A rendered page, plain or compressed, as a response.
+pub struct Page {
+    body: Bytes,
+    encoding: Option<Algorithm>,
+    cache: &'static str,
+}
+
+impl Page {
+    fn new((body, encoding): (Bytes, Option<Algorithm>), cache: &'static str) -> Self {
+        Self {
+            body,
+            encoding,
+            cache,
+        }
+    }
+}
+
+impl IntoResponse for Page {
+    fn into_response(self) -> Response {
+        let mut response = Response::new(ResponseBody::new(self.body));
+        let headers = response.headers_mut();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("text/html; charset=utf-8"),
+        );
+        headers.insert(VARY, HeaderValue::from_static("Accept-Encoding"));
+        if let Some(algorithm) = self.encoding {
+            headers.insert(
+                CONTENT_ENCODING,
+                HeaderValue::from_static(algorithm.content_encoding()),
+            );
+        }
+        headers.insert(
+            HeaderName::from_static("x-view-cache"),
+            HeaderValue::from_static(self.cache),
+        );
+        response
+    }
+}
+
+impl ResponseModifier for Page {
+    fn update_response(op: &mut Operation) {
+        let mut content = BTreeMap::new();
+        content.insert(
+            "text/html".to_string(),
+            MediaType {
+                schema: Some(SchemaRef::Inline(serde_json::json!({ "type": "string" }))),
+                example: None,
+            },
+        );
+        op.responses.insert(
+            "200".to_string(),
+            ResponseSpec {
+                description: "Rendered page; X-View-Cache says whether it was cached".to_string(),
+                content,
+                headers: BTreeMap::new(),
+            },
+        );
+    }
+}
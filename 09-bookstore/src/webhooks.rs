@@ -0,0 +1,487 @@
+// ---------------------------------------------------------------------------
+// Outgoing webhooks
+// ---------------------------------------------------------------------------
+//
+// Subscribers register a URL and get a secret back. Each event is POSTed to
+// every subscriber as JSON, signed the Standard Webhooks way
+// (https://www.standardwebhooks.com):
+//
+//   webhook-id:         msg_2x8Kq...        same on every retry: dedupe on it
+//   webhook-timestamp:  1790812800          Unix seconds, fresh per attempt
+//   webhook-signature:  v1,<base64 HMAC-SHA256(secret, "{id}.{timestamp}.{body}")>
+//
+//   {"type":"book.created","timestamp":1790812800,"data":{...the book...}}
+//
+// Delivery runs on its own task, so the request that caused the event never
+// waits for a subscriber. A network error, 408, 429 or 5xx is retried with
+// exponential backoff (1s, 2s, 4s, ... up to RetryPolicy::max_delay); any
+// other 4xx means the subscriber won't accept it, and it isn't retried.
+// The last MAX_DELIVERIES outcomes are kept for GET /webhooks/deliveries.
+//
+// A subscriber URL makes this server send requests wherever it points, so
+// it must point at the public internet:
+//
+//   not http or https                              400
+//   host resolves to a loopback, private,          400 (169.254.169.254, the
+//   link-local or otherwise non-public address         cloud metadata service,
+//                                                      is link-local)
+//   MAX_SUBSCRIBERS already registered             409
+//
+// The host is checked again at every delivery, by the client's own resolver,
+// so a name that resolves elsewhere later (DNS rebinding) is refused then.
+// Redirects aren't followed: a 3xx is a failed delivery. allow_private() lifts
+// the address check, for a receiver on the same machine during development.
+//
+// NOTE: deliveries live in memory. A restart drops pending retries; a real
+//       deployment keeps an outbox in the database and delivers from that.
+
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use rustapi_rs::prelude::*;
+use std::collections::VecDeque;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Delivery outcomes kept for inspection, newest last.
+const MAX_DELIVERIES: usize = 100;
+
+/// How long one attempt may take before it counts as failed.
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Subscribers kept at once; each event is one delivery per subscriber.
+pub const MAX_SUBSCRIBERS: usize = 100;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Attempts in total, the first included.
+    pub max_attempts: u32,
+    /// Wait before the first retry; doubled for each one after.
+    pub first_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            first_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait after failed attempt number `attempt` (1-based).
+    fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.first_delay.saturating_mul(factor).min(self.max_delay)
+    }
+}
+
+/// Returned once, when a subscriber registers: the secret isn't shown again.
+#[derive(Debug, Clone, Serialize, Schema)]
+pub struct Subscription {
+    pub id: u64,
+    pub url: String,
+    /// `whsec_` + base64 key; verify `webhook-signature` with it.
+    pub secret: String,
+}
+
+struct Subscriber {
+    id: u64,
+    url: String,
+    key: hmac::Key,
+}
+
+#[derive(Debug, Clone, Serialize, Schema)]
+pub struct Delivery {
+    pub event_id: String,
+    pub event_type: String,
+    pub subscriber_id: u64,
+    pub url: String,
+    /// `pending` (retrying), `delivered` or `failed` (gave up).
+    pub state: String,
+    pub attempts: u32,
+    /// Status of the last response, if there was one.
+    pub last_status: Option<u16>,
+    pub last_error: Option<String>,
+}
+
+/// Sends signed events to every subscriber. Clone it into state.
+#[derive(Clone)]
+pub struct WebhookDispatcher {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    client: reqwest::Client,
+    retry: RetryPolicy,
+    allow_private: bool,
+    rng: SystemRandom,
+    next_subscriber: AtomicU64,
+    subscribers: RwLock<Vec<Arc<Subscriber>>>,
+    deliveries: Mutex<VecDeque<Delivery>>,
+}
+
+impl WebhookDispatcher {
+    pub fn new(retry: RetryPolicy) -> Self {
+        Self::build(retry, false)
+    }
+
+    /// Lets subscribers point at loopback and private addresses. For a
+    /// receiver on the same machine or network; never in production.
+    pub fn allow_private(self) -> Self {
+        Self::build(self.inner.retry, true)
+    }
+
+    fn build(retry: RetryPolicy, allow_private: bool) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(ATTEMPT_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none());
+        let client = if allow_private {
+            client
+        } else {
+            client.dns_resolver(Arc::new(PublicOnly))
+        };
+        Self {
+            inner: Arc::new(Inner {
+                client: client.build().expect("HTTP client should build"),
+                retry,
+                allow_private,
+                rng: SystemRandom::new(),
+                next_subscriber: AtomicU64::new(1),
+                subscribers: RwLock::new(Vec::new()),
+                deliveries: Mutex::new(VecDeque::new()),
+            }),
+        }
+    }
+
+    fn random(&self, len: usize) -> Vec<u8> {
+        let mut bytes = vec![0; len];
+        self.inner
+            .rng
+            .fill(&mut bytes)
+            .expect("system randomness is available");
+        bytes
+    }
+
+    /// Adds a subscriber for every future event, once `url` is known to
+    /// point at a public address.
+    pub async fn subscribe(&self, url: String) -> Result<Subscription> {
+        let parsed = reqwest::Url::parse(&url)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+            .ok_or_else(|| ApiError::bad_request("url must be an http or https URL"))?;
+        if !self.inner.allow_private {
+            check_public(&parsed).await?;
+        }
+
+        let secret = self.random(24);
+        let mut subscribers = self
+            .inner
+            .subscribers
+            .write()
+            .expect("subscribers poisoned");
+        if subscribers.len() >= MAX_SUBSCRIBERS {
+            return Err(ApiError::conflict(format!(
+                "Already {} subscribers; no more are taken",
+                MAX_SUBSCRIBERS
+            )));
+        }
+        let subscriber = Subscriber {
+            id: self.inner.next_subscriber.fetch_add(1, Ordering::Relaxed),
+            url,
+            key: hmac::Key::new(hmac::HMAC_SHA256, &secret),
+        };
+        let subscription = Subscription {
+            id: subscriber.id,
+            url: subscriber.url.clone(),
+            secret: format!("whsec_{}", STANDARD.encode(&secret)),
+        };
+        subscribers.push(Arc::new(subscriber));
+        Ok(subscription)
+    }
+
+    /// Latest delivery outcomes, oldest first.
+    pub fn deliveries(&self) -> Vec<Delivery> {
+        let deliveries = self.inner.deliveries.lock().expect("deliveries poisoned");
+        deliveries.iter().cloned().collect()
+    }
+
+    /// Queues `data` as a `event_type` event for every subscriber. Returns
+    /// immediately; delivery and retries happen in the background.
+    pub fn dispatch<E: Serialize>(&self, event_type: &str, data: &E) {
+        let subscribers = self
+            .inner
+            .subscribers
+            .read()
+            .expect("subscribers poisoned")
+            .clone();
+        if subscribers.is_empty() {
+            return;
+        }
+
+        let event_id = format!("msg_{}", URL_SAFE_NO_PAD.encode(self.random(12)));
+        let body = serde_json::json!({
+            "type": event_type,
+            "timestamp": unix_now(),
+            "data": data,
+        })
+        .to_string();
+
+        for subscriber in subscribers {
+            self.record(Delivery {
+                event_id: event_id.clone(),
+                event_type: event_type.to_string(),
+                subscriber_id: subscriber.id,
+                url: subscriber.url.clone(),
+                state: "pending".to_string(),
+                attempts: 0,
+                last_status: None,
+                last_error: None,
+            });
+            let dispatcher = self.clone();
+            let (event_id, body) = (event_id.clone(), body.clone());
+            tokio::spawn(async move { dispatcher.deliver(&subscriber, &event_id, body).await });
+        }
+    }
+
+    async fn deliver(&self, subscriber: &Subscriber, event_id: &str, body: String) {
+        let retry = self.inner.retry;
+        for attempt in 1..=retry.max_attempts {
+            let timestamp = unix_now().to_string();
+            let signed = format!("{event_id}.{timestamp}.{body}");
+            let signature = hmac::sign(&subscriber.key, signed.as_bytes());
+
+            let outcome = self
+                .inner
+                .client
+                .post(&subscriber.url)
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header("webhook-id", event_id)
+                .header("webhook-timestamp", &timestamp)
+                .header(
+                    "webhook-signature",
+                    format!("v1,{}", STANDARD.encode(signature.as_ref())),
+                )
+                .body(body.clone())
+                .send()
+                .await;
+
+            let (status, error, retryable) = match outcome {
+                Ok(response) if response.status().is_success() => {
+                    self.update(event_id, subscriber.id, |delivery| {
+                        delivery.state = "delivered".to_string();
+                        delivery.attempts = attempt;
+                        delivery.last_status = Some(response.status().as_u16());
+                        delivery.last_error = None;
+                    });
+                    return;
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    let retryable = status.is_server_error()
+                        || status == StatusCode::REQUEST_TIMEOUT
+                        || status == StatusCode::TOO_MANY_REQUESTS;
+                    (Some(status.as_u16()), format!("HTTP {status}"), retryable)
+                }
+                Err(err) => (None, err.to_string(), true),
+            };
+
+            let gave_up = !retryable || attempt == retry.max_attempts;
+            warn!(
+                "Webhook {} to {} failed (attempt {}/{}): {}",
+                event_id, subscriber.url, attempt, retry.max_attempts, error
+            );
+            self.update(event_id, subscriber.id, |delivery| {
+                delivery.state = if gave_up { "failed" } else { "pending" }.to_string();
+                delivery.attempts = attempt;
+                delivery.last_status = status;
+                delivery.last_error = Some(error);
+            });
+            if gave_up {
+                return;
+            }
+            tokio::time::sleep(retry.delay(attempt)).await;
+        }
+    }
+
+    fn record(&self, delivery: Delivery) {
+        let mut deliveries = self.inner.deliveries.lock().expect("deliveries poisoned");
+        if deliveries.len() == MAX_DELIVERIES {
+            deliveries.pop_front();
+        }
+        deliveries.push_back(delivery);
+    }
+
+    fn update(&self, event_id: &str, subscriber_id: u64, change: impl FnOnce(&mut Delivery)) {
+        let mut deliveries = self.inner.deliveries.lock().expect("deliveries poisoned");
+        // Gone if newer deliveries pushed it out; the outcome is still logged.
+        if let Some(delivery) = deliveries
+            .iter_mut()
+            .find(|d| d.event_id == event_id && d.subscriber_id == subscriber_id)
+        {
+            change(delivery);
+        }
+    }
+}
+
+/// Ok if every address `url`'s host resolves to is public.
+async fn check_public(url: &reqwest::Url) -> Result<()> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| ApiError::bad_request("url has no host"))?;
+    // IPv6 literals come bracketed; lookup_host wants them bare.
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|_| ApiError::bad_request(format!("url's host {} doesn't resolve", host)))?
+        .collect();
+    match addrs.iter().find(|addr| !is_public(addr.ip())) {
+        None if !addrs.is_empty() => Ok(()),
+        None => Err(ApiError::bad_request(format!(
+            "url's host {} doesn't resolve",
+            host
+        ))),
+        Some(addr) => Err(ApiError::bad_request(format!(
+            "url's host {} resolves to {}, which isn't a public address",
+            host,
+            addr.ip()
+        ))),
+    }
+}
+
+/// False for loopback, private, link-local, shared (CGNAT), unspecified,
+/// multicast, broadcast and documentation addresses, in IPv4 or IPv6.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(mapped) => is_public_v4(mapped),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_multicast()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        // 0.0.0.0/8 "this network" and 100.64.0.0/10 shared address space.
+        || a == 0
+        || (a == 100 && (64..128).contains(&b)))
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        || ip.is_unique_local()
+        || ip.is_unicast_link_local()
+        // 2001:db8::/32, documentation.
+        || (first == 0x2001 && ip.segments()[1] == 0x0db8))
+}
+
+/// The system resolver, minus every address that isn't public: a request to
+/// a host that only has such addresses fails to connect.
+struct PublicOnly;
+
+impl Resolve for PublicOnly {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn rejected(dispatcher: &WebhookDispatcher, url: &str) -> StatusCode {
+        match dispatcher.subscribe(url.to_string()).await {
+            Ok(_) => panic!("{} was accepted", url),
+            Err(err) => err.status,
+        }
+    }
+
+    #[test]
+    fn only_public_addresses_are_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fe80::1",
+            "fd00::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{} counted as public", ip);
+        }
+        for ip in ["93.184.216.34", "1.1.1.1", "2606:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{} counted as private", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn subscribe_rejects_private_and_non_http_targets() {
+        let dispatcher = WebhookDispatcher::new(RetryPolicy::default());
+        for url in [
+            "http://127.0.0.1:4000/hook",
+            "http://localhost/hook",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://10.0.0.5/hook",
+            "http://[::1]/hook",
+        ] {
+            assert_eq!(rejected(&dispatcher, url).await, StatusCode::BAD_REQUEST);
+        }
+        assert_eq!(
+            rejected(&dispatcher, "ftp://example.com/hook").await,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[tokio::test]
+    async fn subscribers_are_capped() {
+        let dispatcher = WebhookDispatcher::new(RetryPolicy::default()).allow_private();
+        for _ in 0..MAX_SUBSCRIBERS {
+            dispatcher
+                .subscribe("http://127.0.0.1:4000/hook".to_string())
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            rejected(&dispatcher, "http://127.0.0.1:4000/hook").await,
+            StatusCode::CONFLICT
+        );
+    }
+}
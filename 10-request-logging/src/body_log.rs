@@ -0,0 +1,118 @@
+// ---------------------------------------------------------------------------
+// Body logging
+// ---------------------------------------------------------------------------
+//
+// An access log line per request, including (a prefix of) the request body.
+// The body comes from TeedBody, so this layer must be added *after*
+// TeeBodyLayer. Without it, the line is logged with `body=<not buffered>`.
+//
+// With `.slow_only(threshold)` fast, successful requests are not logged at
+// all: only those that took at least `threshold`, plus every 4xx/5xx. The
+// line always carries the measured duration.
+//
+// Behind CorrelationLayer, the line also carries the request id (`id=…`),
+// the same one the client gets in X-Request-Id and in error bodies.
+
+use crate::request_id::RequestId;
+use crate::tee::TeedBody;
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_rs::prelude::*;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+#[derive(Clone)]
+pub struct BodyLogLayer {
+    max_logged: usize,
+    slow_threshold: Option<Duration>,
+}
+
+impl BodyLogLayer {
+    /// Log every request, with at most `max_logged` bytes of each body.
+    pub fn new(max_logged: usize) -> Self {
+        Self {
+            max_logged,
+            slow_threshold: None,
+        }
+    }
+
+    /// Only log requests that take at least `threshold`, and errors.
+    pub fn slow_only(mut self, threshold: Duration) -> Self {
+        self.slow_threshold = Some(threshold);
+        self
+    }
+}
+
+/// `"{...}"`, `"{...}…(+1200 bytes)"` or `<empty>`.
+fn preview(body: &[u8], max: usize) -> String {
+    if body.is_empty() {
+        return "<empty>".to_string();
+    }
+    let shown = &body[..body.len().min(max)];
+    let mut text = format!("{:?}", String::from_utf8_lossy(shown));
+    if body.len() > max {
+        text.push_str(&format!("…(+{} bytes)", body.len() - max));
+    }
+    text
+}
+
+impl MiddlewareLayer for BodyLogLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let max_logged = self.max_logged;
+        let slow_threshold = self.slow_threshold;
+
+        Box::pin(async move {
+            let method = req.method().clone();
+            let path = req.path().to_owned();
+            let id = match req.extensions().get::<RequestId>() {
+                Some(RequestId(id)) => id.clone(),
+                None => "-".to_string(),
+            };
+            // A clone of the handle, not of the bytes.
+            let body = match req.extensions().get::<TeedBody>() {
+                Some(TeedBody(bytes)) => preview(bytes, max_logged),
+                None => "<not buffered>".to_string(),
+            };
+
+            let started = Instant::now();
+            let response = next(req).await;
+            let elapsed = started.elapsed();
+            let status = response.status();
+
+            let slow = slow_threshold.filter(|threshold| elapsed >= *threshold);
+            let failed = status.is_client_error() || status.is_server_error();
+
+            if let Some(threshold) = slow {
+                warn!(
+                    "{} {} -> {} in {:?} (slow, threshold {:?}) id={} body={}",
+                    method,
+                    path,
+                    status.as_u16(),
+                    elapsed,
+                    threshold,
+                    id,
+                    body
+                );
+            } else if slow_threshold.is_none() || failed {
+                info!(
+                    "{} {} -> {} in {:?} id={} body={}",
+                    method,
+                    path,
+                    status.as_u16(),
+                    elapsed,
+                    id,
+                    body
+                );
+            }
+            response
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
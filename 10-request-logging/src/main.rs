@@ -0,0 +1,147 @@
+// Run with: cargo run -p request-logging
+//           LOG_SLOWER_THAN_MS=500 cargo run -p request-logging   (slow requests and errors only)
+//           REQUEST_ID_IN_ERRORS=false ACCEPT_REQUEST_ID=false cargo run -p request-logging
+//           REQUEST_ID_HEADER=x-correlation-id REQUEST_ID_PREFIX=web-1 cargo run -p request-logging
+// Then visit: http://127.0.0.1:3000/docs
+//
+// Lesson: a request body can only be read once. To log it *and* hand it to
+//         Json<T>, buffer it in one layer and share the buffer with everyone
+//         downstream.
+
+mod body_log;
+mod request_id;
+mod stats;
+mod tee;
+
+use body_log::BodyLogLayer;
+use request_id::{CorrelationLayer, RequestId};
+use rustapi_rs::prelude::*;
+use rustapi_rs::{get, post, summary, tag};
+use stats::{RequestStats, RequestStatsLayer, StatsSnapshot};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tee::TeeBodyLayer;
+
+/// Same limit RustApi::run() installs by default.
+const BODY_LIMIT: usize = rustapi_core::DEFAULT_BODY_LIMIT;
+
+// ---------------------------------------------------------------------------
+// Models
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Deserialize, Schema)]
+struct NewOrder {
+    item: String,
+    quantity: u32,
+}
+
+#[derive(Debug, Serialize, Schema)]
+struct Order {
+    item: String,
+    quantity: u32,
+    status: String,
+    /// The request that placed it; quote it when asking about the order.
+    request_id: String,
+}
+
+// ---------------------------------------------------------------------------
+// Handlers
+// ---------------------------------------------------------------------------
+
+#[post("/orders")]
+#[tag("orders")]
+#[summary("Place an order")]
+async fn create_order(request_id: RequestId, Json(order): Json<NewOrder>) -> Json<Order> {
+    // Json<T> reads the buffered body; the log line has already got its copy.
+    info!(request_id = %request_id, item = %order.item, "order accepted");
+    Json(Order {
+        item: order.item,
+        quantity: order.quantity,
+        status: "accepted".to_string(),
+        request_id: request_id.0,
+    })
+}
+
+#[get("/slow")]
+#[tag("orders")]
+#[summary("Takes a second")]
+async fn slow() -> &'static str {
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    "done"
+}
+
+#[get("/")]
+#[tag("status")]
+#[summary("Index with live traffic numbers")]
+async fn index(stats: RequestStats) -> String {
+    let snapshot = stats.snapshot();
+    format!(
+        "request-logging example: {} requests served, {} in flight, {:.2} ms average",
+        snapshot.total_requests, snapshot.active_requests, snapshot.avg_latency_ms
+    )
+}
+
+#[get("/stats")]
+#[tag("status")]
+#[summary("Request counts and latency, overall and per route")]
+async fn stats(stats: RequestStats) -> Json<StatsSnapshot> {
+    Json(stats.snapshot())
+}
+
+// ---------------------------------------------------------------------------
+// Entry point
+// ---------------------------------------------------------------------------
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    tracing_subscriber::fmt().with_target(false).init();
+
+    // Unset: log every request. Set: only those slower than this, and errors.
+    let slow_threshold = match std::env::var("LOG_SLOWER_THAN_MS") {
+        Ok(ms) => Some(Duration::from_millis(ms.parse()?)),
+        Err(_) => None,
+    };
+
+    // Both default to true; see src/request_id.rs.
+    let flag = |name: &str| match std::env::var(name) {
+        Ok(value) => value.parse::<bool>(),
+        Err(_) => Ok(true),
+    };
+    let mut correlation = CorrelationLayer::new()
+        .in_error_body(flag("REQUEST_ID_IN_ERRORS")?)
+        .accept_incoming(flag("ACCEPT_REQUEST_ID")?);
+    if let Ok(name) = std::env::var("REQUEST_ID_HEADER") {
+        correlation = correlation.header(http::HeaderName::try_from(name)?);
+    }
+    // Ids that say which instance served the request: web-1-0000002a.
+    if let Ok(prefix) = std::env::var("REQUEST_ID_PREFIX") {
+        let counter = AtomicU64::new(0);
+        correlation = correlation.generator(move || {
+            format!("{}-{:08x}", prefix, counter.fetch_add(1, Ordering::Relaxed))
+        });
+    }
+
+    println!("Starting request-logging example…");
+    println!(" -> POST http://127.0.0.1:3000/orders");
+    println!(" -> GET  http://127.0.0.1:3000/slow");
+    println!(" -> GET  http://127.0.0.1:3000/stats");
+    println!(" -> GET  http://127.0.0.1:3000/docs");
+
+    let app = RustApi::auto();
+    let stats = RequestStats::for_app(&app);
+    let body_log = match slow_threshold {
+        Some(threshold) => BodyLogLayer::new(256).slow_only(threshold),
+        None => BodyLogLayer::new(256),
+    };
+
+    // Layers run in the order they're added: the request id first, so every
+    // later layer and every error carries it; stats next so its latency covers
+    // the rest of the stack; the tee before the log.
+    app.body_limit(BODY_LIMIT)
+        .layer(correlation)
+        .layer(RequestStatsLayer::new(stats))
+        .layer(TeeBodyLayer::new(BODY_LIMIT))
+        .layer(body_log)
+        .run("127.0.0.1:3000")
+        .await
+}
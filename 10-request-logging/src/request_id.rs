@@ -0,0 +1,225 @@
+// ---------------------------------------------------------------------------
+// Request IDs, in the log and in error bodies
+// ---------------------------------------------------------------------------
+//
+// "My request failed" is hard to act on. "Request 4f1c… failed" is a grep.
+// This layer gives every request an id and makes sure the client ends up
+// holding it:
+//
+//   X-Request-Id     on every response
+//   "request_id"     added to every JSON error body (4xx/5xx) next to the
+//                    framework's "error_id"
+//   id=…             in BodyLogLayer's access log line
+//
+// Handlers take the id as an argument, to log it or put it in a response:
+//
+//   async fn create_order(request_id: RequestId, ..) -> .. {
+//       info!(request_id = %request_id, "order accepted");
+//
+// That's this module's RequestId. rustapi's own RequestId can't be a handler
+// argument (the route macros want an OperationModifier it doesn't have); the
+// layer stores both, so rustapi's TracingLayer still finds its own. It does
+// what rustapi's RequestIdLayer does and more; don't add both.
+//
+// When a proxy or the client already sent an X-Request-Id, that one is kept,
+// so a single id follows the request through every hop. Only short ids of
+// `[A-Za-z0-9._-]` are taken; anything else gets a fresh id.
+//
+//   CorrelationLayer::new().in_error_body(false)   header only
+//   CorrelationLayer::new().accept_incoming(false) always generate
+//   CorrelationLayer::new().header(HeaderName::from_static("x-correlation-id"))
+//                                                  read and echo that header
+//   CorrelationLayer::new().generator(|| ..)       fresh ids from a closure
+//                                                  instead of a UUID
+//
+// NOTE: the layer only sees responses from itself inwards. Errors returned
+//       by run()'s own BodyLimitLayer, which sits outside every user layer,
+//       carry an error_id but no request_id.
+
+use bytes::Bytes;
+use http::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use http::HeaderName;
+use http::HeaderValue;
+use http_body::Body as _;
+use http_body_util::BodyExt;
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_openapi::{Operation, OperationModifier};
+use rustapi_rs::prelude::*;
+use rustapi_rs::{FromRequestParts, ResponseBody};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest incoming id accepted.
+const MAX_INCOMING_LEN: usize = 128;
+
+/// Error bodies larger than this are passed through untouched.
+const MAX_ERROR_BODY: usize = 64 * 1024;
+
+/// The id CorrelationLayer gave this request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromRequestParts for RequestId {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        req.extensions().get::<RequestId>().cloned().ok_or_else(|| {
+            ApiError::internal("No request id; is CorrelationLayer in front of this route?")
+        })
+    }
+}
+
+impl OperationModifier for RequestId {
+    fn update_operation(_op: &mut Operation) {}
+}
+
+type Generator = dyn Fn() -> String + Send + Sync;
+
+#[derive(Clone)]
+pub struct CorrelationLayer {
+    in_error_body: bool,
+    accept_incoming: bool,
+    header: HeaderName,
+    generator: Option<Arc<Generator>>,
+}
+
+impl CorrelationLayer {
+    pub fn new() -> Self {
+        Self {
+            in_error_body: true,
+            accept_incoming: true,
+            header: HeaderName::from_static(REQUEST_ID_HEADER),
+            generator: None,
+        }
+    }
+
+    /// Whether JSON error bodies get a `request_id` field. Default true.
+    pub fn in_error_body(mut self, enabled: bool) -> Self {
+        self.in_error_body = enabled;
+        self
+    }
+
+    /// Whether an incoming `X-Request-Id` is reused. Default true.
+    pub fn accept_incoming(mut self, enabled: bool) -> Self {
+        self.accept_incoming = enabled;
+        self
+    }
+
+    /// The header an incoming id is read from and the id is sent back in.
+    /// Default `X-Request-Id`.
+    pub fn header(mut self, name: HeaderName) -> Self {
+        self.header = name;
+        self
+    }
+
+    /// Makes fresh ids with `generator` instead of a UUID. Ids that aren't
+    /// a valid header value are still used, but not echoed.
+    pub fn generator<F>(mut self, generator: F) -> Self
+    where
+        F: Fn() -> String + Send + Sync + 'static,
+    {
+        self.generator = Some(Arc::new(generator));
+        self
+    }
+
+    fn id_for(&self, req: &Request) -> String {
+        let incoming = req
+            .headers()
+            .get(&self.header)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| self.accept_incoming && is_acceptable(id));
+        match (incoming, &self.generator) {
+            (Some(id), _) => id.to_string(),
+            (None, Some(generate)) => generate(),
+            (None, None) => rustapi_rs::RequestId::new().0,
+        }
+    }
+}
+
+fn is_acceptable(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_INCOMING_LEN
+        && id
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || b"._-".contains(&byte))
+}
+
+/// Whether `response` is an error with a small JSON body.
+fn is_json_error(response: &Response) -> bool {
+    let status = response.status();
+    let headers = response.headers();
+    let json = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    // Content-Length isn't set until the server writes the response.
+    let small = response
+        .body()
+        .size_hint()
+        .upper()
+        .is_some_and(|length| length <= MAX_ERROR_BODY as u64);
+    (status.is_client_error() || status.is_server_error()) && json && small
+}
+
+/// `body` with `"request_id": id` added, if it's a JSON object with an
+/// `error` field; None otherwise.
+fn with_request_id(body: &[u8], id: &str) -> Option<Bytes> {
+    let mut value: serde_json::Value = serde_json::from_slice(body).ok()?;
+    let object = value.as_object_mut().filter(|o| o.contains_key("error"))?;
+    object.insert("request_id".to_string(), id.into());
+    serde_json::to_vec(&value).ok().map(Bytes::from)
+}
+
+async fn add_to_error_body(response: Response, id: &str) -> Response {
+    let (mut parts, body) = response.into_parts();
+    let body = match body.collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(err) => return err.into_response(),
+    };
+    let body = match with_request_id(&body, id) {
+        Some(rewritten) => {
+            parts.headers.insert(CONTENT_LENGTH, rewritten.len().into());
+            rewritten
+        }
+        None => body,
+    };
+    Response::from_parts(parts, ResponseBody::new(body))
+}
+
+impl MiddlewareLayer for CorrelationLayer {
+    fn call(
+        &self,
+        mut req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let id = self.id_for(&req);
+        req.extensions_mut().insert(RequestId(id.clone()));
+        req.extensions_mut()
+            .insert(rustapi_rs::RequestId::from_string(id.clone()));
+        let in_error_body = self.in_error_body;
+        let header = self.header.clone();
+
+        Box::pin(async move {
+            let mut response = next(req).await;
+            if in_error_body && is_json_error(&response) {
+                response = add_to_error_body(response, &id).await;
+            }
+            if let Ok(value) = HeaderValue::try_from(id) {
+                response.headers_mut().insert(header, value);
+            }
+            response
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,292 @@
+// ---------------------------------------------------------------------------
+// Live request statistics
+// ---------------------------------------------------------------------------
+//
+// RequestStatsLayer counts every request with a handful of atomics — no locks
+// on the hot path. Handlers read the numbers through the RequestStats
+// extractor, e.g. to report live traffic from a status endpoint.
+//
+// Layers run before routing, so the layer doesn't know which route a request
+// will hit. It matches the path against the app's registered routes instead
+// (`/books/{id}`), which also keeps the number of counters fixed: a path no
+// route matches is counted under "other".
+//
+// The routes come from the router, not the OpenAPI spec. The spec leaves out
+// the docs themselves, so `/docs` and `/docs/openapi.json` would land in
+// "other" and hide the stray paths that bucket is there to show.
+
+use http::Method;
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_openapi::{Operation, OperationModifier};
+use rustapi_rs::prelude::*;
+use rustapi_rs::FromRequestParts;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+struct Counter {
+    requests: AtomicU64,
+    total_micros: AtomicU64,
+}
+
+impl Counter {
+    fn record(&self, elapsed: Duration) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.total_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// (requests, average latency in ms)
+    fn read(&self) -> (u64, f64) {
+        let requests = self.requests.load(Ordering::Relaxed);
+        let micros = self.total_micros.load(Ordering::Relaxed);
+        let avg_ms = if requests == 0 {
+            0.0
+        } else {
+            micros as f64 / requests as f64 / 1000.0
+        };
+        (requests, avg_ms)
+    }
+}
+
+struct RouteCounter {
+    method: Method,
+    template: String,
+    /// `None` for a `{placeholder}` segment.
+    segments: Vec<Option<String>>,
+    counter: Counter,
+}
+
+impl RouteCounter {
+    fn matches(&self, method: &Method, path: &str) -> bool {
+        let mut parts = path.trim_matches('/').split('/');
+        *method == self.method
+            && self
+                .segments
+                .iter()
+                .all(|segment| match (segment, parts.next()) {
+                    (Some(literal), Some(part)) => literal == part,
+                    (None, Some(part)) => !part.is_empty(),
+                    (_, None) => false,
+                })
+            && parts.next().is_none()
+    }
+}
+
+#[derive(Default)]
+struct StatsInner {
+    all: Counter,
+    active: AtomicU64,
+    routes: Vec<RouteCounter>,
+    other: Counter,
+}
+
+/// Shared request counters. Cheap to clone; every clone sees the same numbers.
+#[derive(Clone)]
+pub struct RequestStats(Arc<StatsInner>);
+
+#[derive(Debug, Serialize, Schema)]
+pub struct RouteStats {
+    /// `"GET /books/{id}"`, or `"other"` for paths no route matches.
+    pub route: String,
+    pub requests: u64,
+    pub avg_latency_ms: f64,
+}
+
+#[derive(Debug, Serialize, Schema)]
+pub struct StatsSnapshot {
+    /// Completed requests.
+    pub total_requests: u64,
+    /// Requests being handled right now, including the one asking.
+    pub active_requests: u64,
+    pub avg_latency_ms: f64,
+    pub routes: Vec<RouteStats>,
+}
+
+impl RequestStats {
+    /// Counters for every route registered on `app`, the docs included.
+    pub fn for_app(app: &RustApi) -> Self {
+        let mut routes: Vec<RouteCounter> = app
+            .router()
+            .registered_routes()
+            .values()
+            .flat_map(|info| {
+                info.methods.iter().map(|method| RouteCounter {
+                    method: method.clone(),
+                    template: info.path.clone(),
+                    segments: info
+                        .path
+                        .trim_matches('/')
+                        .split('/')
+                        .map(|s| (!s.starts_with('{')).then(|| s.to_string()))
+                        .collect(),
+                    counter: Counter::default(),
+                })
+            })
+            .collect();
+        // `/books/format/{format}` must win over `/books/{id}`: try the routes
+        // with the most literal segments first.
+        routes.sort_by_key(|route| {
+            std::cmp::Reverse(route.segments.iter().filter(|s| s.is_some()).count())
+        });
+
+        Self(Arc::new(StatsInner {
+            routes,
+            ..StatsInner::default()
+        }))
+    }
+
+    fn record(&self, method: &Method, path: &str, elapsed: Duration) {
+        self.0.all.record(elapsed);
+        match self.0.routes.iter().find(|r| r.matches(method, path)) {
+            Some(route) => route.counter.record(elapsed),
+            None => self.0.other.record(elapsed),
+        }
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        let (total_requests, avg_latency_ms) = self.0.all.read();
+
+        let mut routes: Vec<_> = self
+            .0
+            .routes
+            .iter()
+            .map(|route| {
+                (
+                    format!("{} {}", route.method, route.template),
+                    &route.counter,
+                )
+            })
+            .chain(std::iter::once(("other".to_string(), &self.0.other)))
+            .map(|(route, counter)| {
+                let (requests, avg_latency_ms) = counter.read();
+                RouteStats {
+                    route,
+                    requests,
+                    avg_latency_ms,
+                }
+            })
+            .collect();
+        routes.sort_by(|a, b| a.route.cmp(&b.route));
+
+        StatsSnapshot {
+            total_requests,
+            active_requests: self.0.active.load(Ordering::Relaxed),
+            avg_latency_ms,
+            routes,
+        }
+    }
+}
+
+/// Handlers get the stats the layer is recording into.
+impl FromRequestParts for RequestStats {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        req.extensions()
+            .get::<RequestStats>()
+            .cloned()
+            .ok_or_else(|| ApiError::internal("RequestStatsLayer is not installed"))
+    }
+}
+
+impl OperationModifier for RequestStats {
+    fn update_operation(_op: &mut Operation) {}
+}
+
+/// Decrements `active` however the request ends.
+struct Active(RequestStats);
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        self.0 .0.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Clone)]
+pub struct RequestStatsLayer {
+    stats: RequestStats,
+}
+
+impl RequestStatsLayer {
+    pub fn new(stats: RequestStats) -> Self {
+        Self { stats }
+    }
+}
+
+impl MiddlewareLayer for RequestStatsLayer {
+    fn call(
+        &self,
+        mut req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let stats = self.stats.clone();
+
+        Box::pin(async move {
+            let method = req.method().clone();
+            let path = req.path().to_owned();
+            req.extensions_mut().insert(stats.clone());
+
+            stats.0.active.fetch_add(1, Ordering::Relaxed);
+            let _active = Active(stats.clone());
+            let started = Instant::now();
+
+            let response = next(req).await;
+            stats.record(&method, &path, started.elapsed());
+            response
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn book() -> &'static str {
+        "book"
+    }
+
+    fn requests(stats: &RequestStats, route: &str) -> u64 {
+        let snapshot = stats.snapshot();
+        let found = snapshot.routes.iter().find(|r| r.route == route);
+        found.map_or(0, |r| r.requests)
+    }
+
+    #[test]
+    fn docs_are_counted_under_their_own_routes() {
+        let app = RustApi::new().route("/books/{id}", get(book)).docs("/docs");
+        let stats = RequestStats::for_app(&app);
+        for path in [
+            "/docs",
+            "/docs/openapi.json",
+            "/books/7",
+            "/books/8",
+            "/nope",
+        ] {
+            stats.record(&Method::GET, path, Duration::ZERO);
+        }
+
+        assert_eq!(requests(&stats, "GET /docs"), 1);
+        assert_eq!(requests(&stats, "GET /docs/openapi.json"), 1);
+        assert_eq!(requests(&stats, "GET /books/{id}"), 2);
+        assert_eq!(requests(&stats, "other"), 1);
+        assert_eq!(stats.snapshot().total_requests, 5);
+    }
+
+    #[test]
+    fn literal_segments_win_over_placeholders() {
+        let app = RustApi::new()
+            .route("/books/{id}", get(book))
+            .route("/books/search", get(book));
+        let stats = RequestStats::for_app(&app);
+        stats.record(&Method::GET, "/books/search", Duration::ZERO);
+
+        assert_eq!(requests(&stats, "GET /books/search"), 1);
+        assert_eq!(requests(&stats, "GET /books/{id}"), 0);
+    }
+}
@@ -0,0 +1,114 @@
+// ---------------------------------------------------------------------------
+// Body tee
+// ---------------------------------------------------------------------------
+//
+// A request body is a stream: whoever reads it first gets it, and `Json<T>`
+// wants to be that reader. To log the body *and* parse it, TeeBodyLayer reads
+// the stream once into `Bytes`, puts those bytes back as the request body and
+// leaves a cheap handle to the same buffer in the request extensions.
+//
+// `Bytes` is reference counted, so the logger's copy and the extractor's copy
+// share one allocation — nothing is read or copied twice.
+
+use bytes::Bytes;
+use http_body_util::{BodyExt, LengthLimitError, Limited};
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_core::{BodyVariant, PathParams};
+use rustapi_rs::prelude::*;
+use std::future::Future;
+use std::pin::Pin;
+
+/// The buffered request body, readable by any later layer or handler.
+#[derive(Clone)]
+pub struct TeedBody(pub Bytes);
+
+#[derive(Clone)]
+pub struct TeeBodyLayer {
+    limit: usize,
+}
+
+impl TeeBodyLayer {
+    /// Buffer bodies up to `limit` bytes; anything larger is a 413.
+    ///
+    /// Use the same value as the app's body limit — `BodyLimitLayer` only
+    /// checks Content-Length, this layer also stops chunked bodies.
+    pub fn new(limit: usize) -> Self {
+        Self { limit }
+    }
+}
+
+fn payload_too_large(limit: usize) -> Response {
+    ApiError::new(
+        http::StatusCode::PAYLOAD_TOO_LARGE,
+        "payload_too_large",
+        format!("Request body exceeds limit of {} bytes", limit),
+    )
+    .into_response()
+}
+
+/// Reads the body into memory, stopping as soon as it passes `limit`.
+async fn buffer(req: &mut Request, limit: usize) -> std::result::Result<Bytes, Response> {
+    let Some(stream) = req.take_stream() else {
+        // Already buffered (e.g. in-process dispatch): share what's there.
+        return Ok(req
+            .try_clone()
+            .and_then(|mut copy| copy.take_body())
+            .unwrap_or_default());
+    };
+
+    match Limited::new(stream, limit).collect().await {
+        Ok(collected) => Ok(collected.to_bytes()),
+        Err(err) if err.is::<LengthLimitError>() => Err(payload_too_large(limit)),
+        Err(err) => Err(ApiError::bad_request(err.to_string()).into_response()),
+    }
+}
+
+/// The same request, with `body` as its (buffered) body.
+fn with_body(mut req: Request, body: Bytes) -> Request {
+    let mut builder = http::Request::builder()
+        .method(req.method().clone())
+        .uri(req.uri().clone())
+        .version(req.version());
+    if let Some(headers) = builder.headers_mut() {
+        *headers = req.headers().clone();
+    }
+    if let Some(extensions) = builder.extensions_mut() {
+        *extensions = std::mem::take(req.extensions_mut());
+    }
+    let (parts, ()) = builder
+        .body(())
+        .expect("parts copied from a valid request")
+        .into_parts();
+
+    Request::new(
+        parts,
+        BodyVariant::Buffered(body),
+        req.state().clone(),
+        PathParams::clone(req.path_params()),
+    )
+}
+
+impl MiddlewareLayer for TeeBodyLayer {
+    fn call(
+        &self,
+        mut req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let limit = self.limit;
+
+        Box::pin(async move {
+            let body = match buffer(&mut req, limit).await {
+                Ok(body) => body,
+                Err(response) => return response,
+            };
+
+            let mut req = with_body(req, body.clone());
+            req.extensions_mut().insert(TeedBody(body));
+            next(req).await
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,127 @@
+// ---------------------------------------------------------------------------
+// Deprecated endpoints
+// ---------------------------------------------------------------------------
+//
+// Phasing out an endpoint is announced twice: to people reading the docs
+// (`deprecated: true` on the OpenAPI operation) and to programs calling it
+// (response headers they can alert on):
+//
+//   Deprecation: @1790812800                        (RFC 9745 — since when)
+//   Sunset: Thu, 01 Apr 2027 00:00:00 GMT           (RFC 8594 — gone after)
+//   Link: </v2/users>; rel="successor-version"     (what to use instead)
+//
+// Wrap the handler's return type in Deprecated<T> and both happen: the
+// headers on every response, the flag in the spec via ResponseModifier —
+// the one hook a return type has into its operation.
+
+use http::header::{HeaderValue, LINK};
+use rustapi_openapi::{OpenApiSpec, Operation, ResponseModifier};
+use rustapi_rs::prelude::*;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// When and how an endpoint goes away. Declared once, as a `static`, and
+/// shared by every response of the endpoint.
+#[derive(Debug)]
+pub struct Deprecation {
+    /// Deprecated since (Unix seconds).
+    pub since: u64,
+    /// Removed after (Unix seconds), if that's decided.
+    pub sunset: Option<u64>,
+    /// Path of the replacement, if there is one.
+    pub successor: Option<&'static str>,
+}
+
+impl Deprecation {
+    fn headers(&self) -> Vec<(&'static str, String)> {
+        let mut headers = vec![("deprecation", format!("@{}", self.since))];
+        if let Some(sunset) = self.sunset {
+            let at = UNIX_EPOCH + Duration::from_secs(sunset);
+            headers.push(("sunset", httpdate::fmt_http_date(at)));
+        }
+        if let Some(successor) = self.successor {
+            headers.push((
+                LINK.as_str(),
+                format!("<{successor}>; rel=\"successor-version\""),
+            ));
+        }
+        headers
+    }
+}
+
+/// `T`, marked deprecated — in the response headers and in the spec.
+///
+/// ```ignore
+/// static OLD_LISTING: Deprecation = Deprecation { since: .., sunset: .., successor: .. };
+///
+/// async fn list_users() -> Deprecated<Json<Vec<User>>> {
+///     Deprecated::new(&OLD_LISTING, Json(users()))
+/// }
+/// ```
+pub struct Deprecated<T> {
+    deprecation: &'static Deprecation,
+    inner: T,
+}
+
+impl<T> Deprecated<T> {
+    pub fn new(deprecation: &'static Deprecation, inner: T) -> Self {
+        Self { deprecation, inner }
+    }
+}
+
+impl<T: IntoResponse> IntoResponse for Deprecated<T> {
+    fn into_response(self) -> Response {
+        let mut response = self.inner.into_response();
+        for (name, value) in self.deprecation.headers() {
+            match HeaderValue::try_from(value) {
+                Ok(value) => {
+                    response.headers_mut().insert(name, value);
+                }
+                Err(err) => error!("Dropped invalid {} header: {}", name, err),
+            }
+        }
+        response
+    }
+}
+
+impl<T: ResponseModifier> ResponseModifier for Deprecated<T> {
+    fn update_response(op: &mut Operation) {
+        T::update_response(op);
+        op.deprecated = Some(true);
+
+        // NOTE: the dates live in a static the spec can't see; the headers
+        //       are documented by name and format only.
+        let documented = [
+            (
+                "Deprecation",
+                "When the endpoint was deprecated (RFC 9745), e.g. `@1790812800`",
+            ),
+            (
+                "Sunset",
+                "When the endpoint will be removed (RFC 8594), as an HTTP-date",
+            ),
+            (
+                "Link",
+                "The replacement endpoint, with `rel=\"successor-version\"`",
+            ),
+        ];
+        for (status, response) in op.responses.iter_mut() {
+            if !status.starts_with('2') {
+                continue;
+            }
+            for (name, description) in documented {
+                response.headers.insert(
+                    name.to_string(),
+                    serde_json::from_value(serde_json::json!({
+                        "description": description,
+                        "schema": { "type": "string" }
+                    }))
+                    .expect("valid OpenAPI header object"),
+                );
+            }
+        }
+    }
+
+    fn register_components(spec: &mut OpenApiSpec) {
+        T::register_components(spec);
+    }
+}
@@ -0,0 +1,150 @@
+// ---------------------------------------------------------------------------
+// Caching for /docs
+// ---------------------------------------------------------------------------
+//
+// Every visit to /docs fetches the page and the whole spec again, although
+// neither changes while the process runs. This layer lets browsers keep them:
+//
+//   /docs                Cache-Control: public, max-age=300
+//   /docs/openapi.json   Cache-Control: no-cache   (always revalidate, so a
+//                                                   deploy shows up at once)
+//
+// Both get an ETag, a hash of the body. A request whose If-None-Match names
+// it gets a bodyless 304. Once the ETag of a path is known, the 304 is sent
+// without running the layers and handler behind this one.
+//
+// The ETag is weak (W/"..."), so it stays valid when a compression layer
+// outside this one gzips the body: the gzipped and plain bodies count as the
+// same document.
+//
+// NOTE: Swagger UI's scripts and styles come from unpkg, pinned to a version.
+//       unpkg serves them compressed and cacheable for a year, so there is
+//       nothing to do for those here.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use http::header::{CACHE_CONTROL, CONTENT_LENGTH, ETAG, IF_NONE_MATCH};
+use http::HeaderValue;
+use http_body_util::BodyExt;
+use ring::digest;
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_rs::prelude::*;
+use rustapi_rs::ResponseBody;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+
+/// Paths served by Swagger UI, and how long a browser may keep each one.
+const CACHED: [(&str, &str); 2] = [
+    ("/docs", "public, max-age=300"),
+    ("/docs/openapi.json", "no-cache"),
+];
+
+#[derive(Clone, Default)]
+pub struct DocsCacheLayer {
+    /// Last ETag seen per path.
+    etags: Arc<RwLock<HashMap<&'static str, HeaderValue>>>,
+}
+
+impl DocsCacheLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Builder-style sugar: `RustApi::auto().docs_cache()`.
+pub trait DocsCacheExt {
+    fn docs_cache(self) -> Self;
+}
+
+impl DocsCacheExt for RustApi {
+    fn docs_cache(self) -> Self {
+        self.layer(DocsCacheLayer::new())
+    }
+}
+
+fn etag_of(body: &[u8]) -> HeaderValue {
+    let hash = digest::digest(&digest::SHA256, body);
+    let tag = URL_SAFE_NO_PAD.encode(&hash.as_ref()[..16]);
+    HeaderValue::try_from(format!("W/\"{tag}\"")).expect("base64 is a valid header value")
+}
+
+/// Whether `If-None-Match` names `etag`. Weak comparison, as RFC 9110 asks
+/// for with If-None-Match: `W/` prefixes are ignored.
+fn matches(if_none_match: &HeaderValue, etag: &HeaderValue) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let Ok(wanted) = etag.to_str() else {
+        return false;
+    };
+    if_none_match.to_str().is_ok_and(|list| {
+        list.split(',')
+            .any(|tag| tag.trim() == "*" || opaque(tag) == opaque(wanted))
+    })
+}
+
+fn not_modified(etag: HeaderValue, cache_control: &'static str) -> Response {
+    let mut response = Response::new(ResponseBody::empty());
+    *response.status_mut() = StatusCode::NOT_MODIFIED;
+    response.headers_mut().insert(ETAG, etag);
+    response
+        .headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static(cache_control));
+    response
+}
+
+impl MiddlewareLayer for DocsCacheLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let path = req.path().trim_end_matches('/');
+        let cached = CACHED.iter().find(|(docs_path, _)| *docs_path == path);
+        let Some(&(path, cache_control)) = cached.filter(|_| req.method() == http::Method::GET)
+        else {
+            return next(req);
+        };
+        let etags = self.etags.clone();
+        let if_none_match = req.headers().get(IF_NONE_MATCH).cloned();
+
+        Box::pin(async move {
+            let known = etags.read().expect("etags poisoned").get(path).cloned();
+            if let (Some(wanted), Some(etag)) = (&if_none_match, known) {
+                if matches(wanted, &etag) {
+                    return not_modified(etag, cache_control);
+                }
+            }
+
+            let response = next(req).await;
+            if response.status() != StatusCode::OK {
+                return response;
+            }
+            let (mut parts, body) = response.into_parts();
+            let body = match body.collect().await {
+                Ok(collected) => collected.to_bytes(),
+                Err(err) => return err.into_response(),
+            };
+
+            let etag = etag_of(&body);
+            etags
+                .write()
+                .expect("etags poisoned")
+                .insert(path, etag.clone());
+            if if_none_match.is_some_and(|wanted| matches(&wanted, &etag)) {
+                return not_modified(etag, cache_control);
+            }
+
+            parts.headers.remove(CONTENT_LENGTH);
+            parts.headers.insert(ETAG, etag);
+            parts
+                .headers
+                .insert(CACHE_CONTROL, HeaderValue::from_static(cache_control));
+            Response::from_parts(parts, ResponseBody::new(body))
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
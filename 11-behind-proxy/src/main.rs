@@ -0,0 +1,177 @@
+// Run with: cargo run -p behind-proxy
+// Then visit: http://127.0.0.1:3000/docs
+//
+// Lesson: the address you bind is not always the URL clients use. When a
+//         gateway publishes the API under another host or prefix, say so in
+//         the spec's `servers` list — not by rewriting every path.
+
+mod deprecation;
+mod docs_cache;
+mod original_uri;
+mod server_header;
+mod servers;
+
+use deprecation::{Deprecated, Deprecation};
+use docs_cache::DocsCacheExt;
+use original_uri::{OriginalUri, TrustedProxies};
+use rustapi_rs::prelude::*;
+use rustapi_rs::{description, get, summary, tag};
+use server_header::ServerHeaderExt;
+use servers::OpenApiServersExt;
+
+// ---------------------------------------------------------------------------
+// Models
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Serialize, Schema)]
+struct User {
+    id: u64,
+    name: String,
+}
+
+#[derive(Debug, Deserialize, Schema)]
+struct PageParams {
+    /// 1-based; defaults to 1.
+    page: Option<usize>,
+    /// Defaults to 10, at most 100.
+    per_page: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Schema)]
+struct UserPage {
+    items: Vec<User>,
+    page: usize,
+    per_page: usize,
+    total: usize,
+}
+
+#[derive(Debug, Serialize, Schema)]
+struct RequestUri {
+    /// The full URL the client requested.
+    original: String,
+    /// Where this app's routes live, as seen by the client.
+    base: String,
+    /// What reached this process.
+    received: String,
+}
+
+fn users() -> Vec<User> {
+    vec![
+        User {
+            id: 1,
+            name: "Alice".to_string(),
+        },
+        User {
+            id: 2,
+            name: "Bob".to_string(),
+        },
+    ]
+}
+
+// ---------------------------------------------------------------------------
+// Handlers
+// ---------------------------------------------------------------------------
+
+/// The unpaginated listing is on its way out in favor of `/v2/users`.
+static UNPAGED_LISTING: Deprecation = Deprecation {
+    since: 1_790_812_800,        // 2026-10-01
+    sunset: Some(1_806_537_600), // 2027-04-01
+    successor: Some("/v2/users"),
+};
+
+#[get("/users")]
+#[tag("users")]
+#[summary("List users")]
+#[description("Deprecated: returns every user at once. Use `GET /v2/users`.")]
+async fn list_users() -> Deprecated<Json<Vec<User>>> {
+    Deprecated::new(&UNPAGED_LISTING, Json(users()))
+}
+
+#[get("/v2/users")]
+#[tag("users")]
+#[summary("List users, one page at a time")]
+async fn list_users_paged(Query(params): Query<PageParams>) -> Json<UserPage> {
+    let page = params.page.unwrap_or(1).max(1);
+    let per_page = params.per_page.unwrap_or(10).clamp(1, 100);
+    let all = users();
+    Json(UserPage {
+        total: all.len(),
+        items: all
+            .into_iter()
+            .skip((page - 1).saturating_mul(per_page))
+            .take(per_page)
+            .collect(),
+        page,
+        per_page,
+    })
+}
+
+#[get("/users/{id}")]
+#[tag("users")]
+#[summary("Get a user by ID")]
+async fn get_user(Path(id): Path<u64>) -> Result<Json<User>, ApiError> {
+    users()
+        .into_iter()
+        .find(|user| user.id == id)
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found("User not found"))
+}
+
+#[get("/me")]
+#[tag("users")]
+#[summary("Redirect to the signed-in user")]
+#[description("Stubbed as user 1. The `Location` is absolute and includes the gateway's prefix.")]
+async fn me(uri: OriginalUri) -> Redirect {
+    Redirect::to(&uri.join("/users/1"))
+}
+
+#[get("/request-uri")]
+#[tag("debug")]
+#[summary("Show the reconstructed request URL")]
+async fn request_uri(uri: OriginalUri) -> Json<RequestUri> {
+    Json(RequestUri {
+        original: uri.to_string(),
+        base: uri.base(),
+        received: uri.path_and_query,
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Entry point
+// ---------------------------------------------------------------------------
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    println!("Starting behind-proxy example…");
+    println!(" -> GET  http://127.0.0.1:3000/users         (deprecated)");
+    println!(" -> GET  http://127.0.0.1:3000/v2/users?page=1&per_page=10");
+    println!(" -> GET  http://127.0.0.1:3000/me           (absolute redirect)");
+    println!(" -> GET  http://127.0.0.1:3000/request-uri");
+    println!(" -> GET  http://127.0.0.1:3000/docs/openapi.json  (see \"servers\")");
+
+    // OriginalUri reads the forwarded headers only behind this many proxies.
+    let trusted_proxies = TrustedProxies::from_env();
+
+    // No Server header unless SERVER_HEADER names one.
+    let server_header = std::env::var("SERVER_HEADER").ok();
+
+    // Order matters: Swagger UI preselects the first server.
+    //   /api/v1                        — same host, behind the gateway
+    //   https://api.example.com/api/v1 — the public URL
+    //   http://127.0.0.1:3000          — this process, no gateway
+    RustApi::auto()
+        // First layer added, so outermost of ours: it sees every response
+        // after the others. run() still puts its body limit in front; that
+        // 413 carries no Server header of its own.
+        .server_header(server_header.as_deref())
+        // Outside openapi_servers, so the ETag is of the spec as served.
+        .docs_cache()
+        .openapi_servers([
+            "/api/v1",
+            "https://api.example.com/api/v1",
+            "http://127.0.0.1:3000",
+        ])
+        .state(trusted_proxies)
+        .run("127.0.0.1:3000")
+        .await
+}
@@ -0,0 +1,329 @@
+// ---------------------------------------------------------------------------
+// Original request URI
+// ---------------------------------------------------------------------------
+//
+// `req.uri()` is what reached *this* process: usually just `/users/1`. The
+// client asked for `https://api.example.com/api/v1/users/1`; the scheme, host
+// and `/api/v1` were consumed by the gateway on the way in. A `Location`
+// header or an absolute link built from `req.uri()` alone points at the
+// internal address.
+//
+// OriginalUri puts the public URL back together:
+//
+//   scheme    Forwarded `proto=`   -> X-Forwarded-Proto  -> "http"
+//   authority Forwarded `host=`    -> X-Forwarded-Host   -> Host -> URI authority
+//   prefix    X-Forwarded-Prefix   -> ""
+//   path      the request URI, untouched (RustAPI's nest() matches prefixes
+//             without rewriting it)
+//
+// The forwarded headers are only read once the app says how many proxies it
+// sits behind, as state:
+//
+//   RustApi::auto().state(TrustedProxies::hops(1))
+//
+//   no TrustedProxies, or hops(0)   forwarded headers ignored: Host and the
+//                                   URI only
+//   hops(n)                         the n-th entry from the right of each
+//                                   header: the one the outermost of your n
+//                                   proxies wrote
+//
+// Each proxy appends to these lists, so everything left of that entry came
+// from the client and can say anything. A header with fewer than n entries
+// wasn't written by the outermost proxy, and is ignored.
+//
+// What's read is checked before it's used: a scheme other than http or
+// https, a host that isn't `host[:port]`, or a prefix that isn't a plain
+// absolute path (no `//`, `..`, `\`, query or fragment) is a 400. Built
+// into a Location, any of those would send the client somewhere else.
+
+use http::header::{FORWARDED, HOST};
+use http::HeaderMap;
+use rustapi_openapi::{Operation, OperationModifier};
+use rustapi_rs::prelude::*;
+use rustapi_rs::FromRequestParts;
+use std::fmt;
+
+/// How many proxies in front of the app write the forwarded headers.
+/// Register it as state; without it, none are trusted.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TrustedProxies {
+    hops: usize,
+}
+
+impl TrustedProxies {
+    /// Clients reach the app directly: forwarded headers are ignored.
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// `hops` proxies, one behind the other, each appending to the headers.
+    pub fn hops(hops: usize) -> Self {
+        Self { hops }
+    }
+
+    /// TRUSTED_PROXY_HOPS, or none when it's unset or not a number.
+    pub fn from_env() -> Self {
+        std::env::var("TRUSTED_PROXY_HOPS")
+            .ok()
+            .and_then(|hops| hops.parse().ok())
+            .map_or_else(Self::none, Self::hops)
+    }
+}
+
+const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+const X_FORWARDED_HOST: &str = "x-forwarded-host";
+const X_FORWARDED_PREFIX: &str = "x-forwarded-prefix";
+
+/// The URL the client actually requested, before the gateway took its share.
+#[derive(Debug, Clone)]
+pub struct OriginalUri {
+    /// `http` or `https`.
+    pub scheme: String,
+    /// `host[:port]` as the client addressed it.
+    pub authority: String,
+    /// The path the gateway stripped, e.g. `/api/v1`; empty when there is none.
+    pub prefix: String,
+    /// Path and query as this process received them.
+    pub path_and_query: String,
+}
+
+impl OriginalUri {
+    /// `https://api.example.com`
+    pub fn origin(&self) -> String {
+        format!("{}://{}", self.scheme, self.authority)
+    }
+
+    /// `https://api.example.com/api/v1` — where the app's own routes live.
+    pub fn base(&self) -> String {
+        format!("{}{}", self.origin(), self.prefix)
+    }
+
+    /// Absolute URL for one of this app's routes: `join("/users/1")`.
+    pub fn join(&self, path: &str) -> String {
+        format!("{}{}", self.base(), path)
+    }
+}
+
+impl fmt::Display for OriginalUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.base(), self.path_and_query)
+    }
+}
+
+impl FromRequestParts for OriginalUri {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        let headers = req.headers();
+        let uri = req.uri();
+        let hops = req
+            .state()
+            .get::<TrustedProxies>()
+            .map_or(0, |trusted| trusted.hops);
+        let forwarded = trusted_forwarded(headers, hops);
+
+        let scheme = forwarded
+            .proto
+            .or_else(|| trusted_value(headers, X_FORWARDED_PROTO, hops))
+            .or_else(|| uri.scheme_str().map(str::to_string))
+            .unwrap_or_else(|| "http".to_string())
+            .to_ascii_lowercase();
+        if scheme != "http" && scheme != "https" {
+            return Err(ApiError::bad_request(
+                "Forwarded scheme must be http or https",
+            ));
+        }
+
+        let authority = forwarded
+            .host
+            .or_else(|| trusted_value(headers, X_FORWARDED_HOST, hops))
+            .or_else(|| {
+                headers
+                    .get(HOST)
+                    .and_then(|host| host.to_str().ok())
+                    .map(str::to_string)
+            })
+            .or_else(|| uri.authority().map(|a| a.to_string()))
+            .ok_or_else(|| ApiError::bad_request("Cannot tell which host was requested"))?;
+        if !is_host(&authority) {
+            return Err(ApiError::bad_request("Requested host is not host[:port]"));
+        }
+
+        let prefix = trusted_value(headers, X_FORWARDED_PREFIX, hops)
+            .map(|p| p.trim_end_matches('/').to_string())
+            .unwrap_or_default();
+        if !is_prefix(&prefix) {
+            return Err(ApiError::bad_request(
+                "Forwarded prefix is not a plain path",
+            ));
+        }
+
+        let path_and_query = uri
+            .path_and_query()
+            .map(|pq| pq.as_str().to_string())
+            .unwrap_or_else(|| "/".to_string());
+
+        Ok(Self {
+            scheme,
+            authority,
+            prefix,
+            path_and_query,
+        })
+    }
+}
+
+// Nothing for the client to send — the gateway supplies it.
+impl OperationModifier for OriginalUri {
+    fn update_operation(_op: &mut Operation) {}
+}
+
+// ---------------------------------------------------------------------------
+// Header parsing
+// ---------------------------------------------------------------------------
+
+/// `proto` and `host` from the trusted element of an RFC 7239 `Forwarded`
+/// header: `Forwarded: for=203.0.113.7;proto=https;host=api.example.com`.
+#[derive(Default)]
+struct Forwarded {
+    proto: Option<String>,
+    host: Option<String>,
+}
+
+fn trusted_forwarded(headers: &HeaderMap, hops: usize) -> Forwarded {
+    let mut forwarded = Forwarded::default();
+    let Some(element) = trusted_value(headers, FORWARDED.as_str(), hops) else {
+        return forwarded;
+    };
+
+    for pair in element.split(';') {
+        let Some((key, value)) = pair.split_once('=') else {
+            continue;
+        };
+        let value = value.trim().trim_matches('"').to_string();
+        if value.is_empty() {
+            continue;
+        }
+        match key.trim().to_ascii_lowercase().as_str() {
+            "proto" => forwarded.proto = Some(value),
+            "host" => forwarded.host = Some(value),
+            _ => {}
+        }
+    }
+    forwarded
+}
+
+/// The entry `hops` from the right of every `name` header's comma-separated
+/// entries, all lines together: the one the outermost trusted proxy wrote.
+fn trusted_value(headers: &HeaderMap, name: &str, hops: usize) -> Option<String> {
+    let entries: Vec<&str> = headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+    let at = entries.len().checked_sub(hops).filter(|_| hops > 0)?;
+    Some(entries[at].to_string()).filter(|value| !value.is_empty())
+}
+
+/// `host` or `host:port`: no user info, path or anything else a URL could
+/// smuggle in.
+fn is_host(authority: &str) -> bool {
+    !authority.contains('@')
+        && authority
+            .parse::<http::uri::Authority>()
+            .is_ok_and(|parsed| parsed.as_str() == authority)
+}
+
+/// Empty, or `/segment/segment` of plain path characters.
+fn is_prefix(prefix: &str) -> bool {
+    prefix.is_empty()
+        || (prefix.starts_with('/')
+            && !prefix.starts_with("//")
+            && prefix
+                .split('/')
+                .skip(1)
+                .all(|segment| !segment.is_empty() && segment != "." && segment != "..")
+            && prefix
+                .bytes()
+                .all(|byte| byte.is_ascii_alphanumeric() || b"/-._~!$&'()*+,;=:@%".contains(&byte)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    fn header_map(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn untrusted_headers_are_ignored() {
+        let headers = header_map(&[(X_FORWARDED_HOST, "evil.example")]);
+        assert_eq!(trusted_value(&headers, X_FORWARDED_HOST, 0), None);
+    }
+
+    #[test]
+    fn the_outermost_trusted_proxy_wins_over_the_client() {
+        // The client sent `evil.example`; the one proxy appended the real host.
+        let headers = header_map(&[(X_FORWARDED_HOST, "evil.example, api.example.com")]);
+        assert_eq!(
+            trusted_value(&headers, X_FORWARDED_HOST, 1).as_deref(),
+            Some("api.example.com")
+        );
+
+        // Two proxies: the outer one's entry is second from the right, on
+        // whichever line it landed.
+        let headers = header_map(&[
+            (X_FORWARDED_PROTO, "gopher, https"),
+            (X_FORWARDED_PROTO, "http"),
+        ]);
+        assert_eq!(
+            trusted_value(&headers, X_FORWARDED_PROTO, 2).as_deref(),
+            Some("https")
+        );
+    }
+
+    #[test]
+    fn fewer_entries_than_hops_are_ignored() {
+        let headers = header_map(&[(X_FORWARDED_PREFIX, "/api/v1")]);
+        assert_eq!(trusted_value(&headers, X_FORWARDED_PREFIX, 2), None);
+    }
+
+    #[test]
+    fn forwarded_takes_the_trusted_element() {
+        let headers = header_map(&[(
+            "forwarded",
+            "proto=javascript;host=evil.example, for=10.0.0.1;proto=https;host=api.example.com",
+        )]);
+        let forwarded = trusted_forwarded(&headers, 1);
+        assert_eq!(forwarded.proto.as_deref(), Some("https"));
+        assert_eq!(forwarded.host.as_deref(), Some("api.example.com"));
+    }
+
+    #[test]
+    fn hosts_are_host_and_port_only() {
+        assert!(is_host("api.example.com"));
+        assert!(is_host("api.example.com:8443"));
+        assert!(is_host("[::1]:3000"));
+        assert!(!is_host("evil.example/path"));
+        assert!(!is_host("user@evil.example"));
+        assert!(!is_host("evil.example\\@api.example.com"));
+        assert!(!is_host(""));
+    }
+
+    #[test]
+    fn prefixes_are_plain_absolute_paths() {
+        assert!(is_prefix(""));
+        assert!(is_prefix("/api/v1"));
+        assert!(!is_prefix("//evil.example"));
+        assert!(!is_prefix("api/v1"));
+        assert!(!is_prefix("/api/../admin"));
+        assert!(!is_prefix("/api\\evil"));
+        assert!(!is_prefix("/api?x=1"));
+        assert!(!is_prefix("/api#x"));
+    }
+}
@@ -0,0 +1,129 @@
+// ---------------------------------------------------------------------------
+// Server header
+// ---------------------------------------------------------------------------
+//
+// `Server: hyper/1.4` or `X-Powered-By: RustAPI 0.1` tells a scanner which
+// CVE list to try. Neither hyper nor RustAPI sends them, but a handler, a
+// layer or a backend whose response is passed through may — and behind a
+// gateway, what leaves the process is what the public sees.
+//
+//   .server_header(None)             no Server, no X-Powered-By
+//   .server_header(Some("gateway"))  Server: gateway, no X-Powered-By
+//
+// The layer runs on the way out, after every handler and inner layer, so
+// added first it has the last word. The one layer in front of it is the body
+// limit run() adds, whose 413 has no Server header to strip.
+
+use http::header::SERVER;
+use http::HeaderValue;
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_rs::prelude::*;
+use std::future::Future;
+use std::pin::Pin;
+
+/// Other headers that only exist to name the stack.
+const FINGERPRINTS: [&str; 1] = ["x-powered-by"];
+
+#[derive(Clone)]
+pub struct ServerHeaderLayer {
+    value: Option<HeaderValue>,
+}
+
+impl ServerHeaderLayer {
+    /// `Some(name)` sends `Server: name`; `None` sends no Server header.
+    /// Either way, X-Powered-By is removed.
+    ///
+    /// Panics if `name` isn't a valid header value — a startup error.
+    pub fn new(value: Option<&str>) -> Self {
+        Self {
+            value: value.map(|value| {
+                HeaderValue::try_from(value).expect("Server header must be a valid header value")
+            }),
+        }
+    }
+}
+
+/// Builder-style sugar: `RustApi::auto().server_header(None)`.
+pub trait ServerHeaderExt {
+    fn server_header(self, value: Option<&str>) -> Self;
+}
+
+impl ServerHeaderExt for RustApi {
+    fn server_header(self, value: Option<&str>) -> Self {
+        self.layer(ServerHeaderLayer::new(value))
+    }
+}
+
+impl MiddlewareLayer for ServerHeaderLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let value = self.value.clone();
+        Box::pin(async move {
+            let mut response = next(req).await;
+            let headers = response.headers_mut();
+            for name in FINGERPRINTS {
+                headers.remove(name);
+            }
+            match value {
+                Some(value) => {
+                    headers.insert(SERVER, value);
+                }
+                None => {
+                    headers.remove(SERVER);
+                }
+            }
+            response
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustapi_core::{BodyVariant, PathParams};
+
+    /// A handler that names its stack, the way a passed-through backend might.
+    async fn chatty() -> Response {
+        let mut response = "ok".into_response();
+        let headers = response.headers_mut();
+        headers.insert(SERVER, HeaderValue::from_static("hyper/1.4"));
+        headers.insert("x-powered-by", HeaderValue::from_static("RustAPI 0.1"));
+        response
+    }
+
+    async fn headers_with(value: Option<&str>) -> http::HeaderMap {
+        let app = RustApi::new()
+            .server_header(value)
+            .route("/", get(chatty))
+            .request_dispatcher();
+        let (parts, ()) = http::Request::get("/").body(()).unwrap().into_parts();
+        let request = Request::new(
+            parts,
+            BodyVariant::Buffered(Default::default()),
+            app.state_ref(),
+            PathParams::new(),
+        );
+        app.dispatch(request).await.headers().clone()
+    }
+
+    #[tokio::test]
+    async fn none_sends_no_server_header() {
+        let headers = headers_with(None).await;
+        assert!(headers.get(SERVER).is_none());
+        assert!(headers.get("x-powered-by").is_none());
+    }
+
+    #[tokio::test]
+    async fn some_replaces_the_server_header() {
+        let headers = headers_with(Some("gateway")).await;
+        assert_eq!(headers[SERVER], "gateway");
+        assert!(headers.get("x-powered-by").is_none());
+    }
+}
@@ -0,0 +1,207 @@
+// ---------------------------------------------------------------------------
+// OpenAPI servers
+// ---------------------------------------------------------------------------
+//
+// The spec RustApi::auto() serves describes paths as the app sees them
+// (`/users`). Behind a gateway that publishes the app under `/api/v1`, clients
+// need to know that prefix — OpenAPI's answer is the top-level `servers` list.
+//
+// `auto()` renders the spec when the app is built, so this layer fills in
+// `servers` on the way out of `/docs/openapi.json`. A relative entry such as
+// `/api/v1` is the base path: it resolves against whatever host served the
+// docs, which is exactly what "Try it out" needs behind a proxy.
+//
+// The Swagger page at `/docs` loads the spec from `./docs/openapi.json`,
+// relative to itself, so it finds it under any prefix. Reached as `/docs/`
+// that would be `/docs/docs/openapi.json`; the page redirects there to
+// `../docs` instead, which is `/docs` — or `/api/v1/docs` — again.
+
+use bytes::Bytes;
+use http::header::CONTENT_LENGTH;
+use http_body_util::BodyExt;
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_rs::prelude::*;
+use rustapi_rs::ResponseBody;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// `/docs` is where RustApi::auto() mounts Swagger UI.
+const DOCS_PATH: &str = "/docs";
+
+#[derive(Clone)]
+pub struct OpenApiServersLayer {
+    servers: Arc<Vec<String>>,
+}
+
+impl OpenApiServersLayer {
+    /// Publish `urls`, in order, as the spec's `servers`. Swagger UI selects
+    /// the first one by default.
+    pub fn new<I>(urls: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        Self {
+            servers: Arc::new(urls.into_iter().map(Into::into).collect()),
+        }
+    }
+}
+
+/// Builder-style sugar: `RustApi::auto().openapi_servers([...])`.
+pub trait OpenApiServersExt {
+    fn openapi_servers<I>(self, urls: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>;
+}
+
+impl OpenApiServersExt for RustApi {
+    fn openapi_servers<I>(self, urls: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.layer(OpenApiServersLayer::new(urls))
+    }
+}
+
+/// The spec with `servers` replaced, or `None` if it isn't JSON we understand.
+fn with_servers(spec: &[u8], servers: &[String]) -> Option<Vec<u8>> {
+    let mut spec: serde_json::Value = serde_json::from_slice(spec).ok()?;
+    let servers = servers
+        .iter()
+        .map(|url| serde_json::json!({ "url": url }))
+        .collect();
+    spec.as_object_mut()?
+        .insert("servers".to_string(), serde_json::Value::Array(servers));
+    serde_json::to_vec_pretty(&spec).ok()
+}
+
+impl MiddlewareLayer for OpenApiServersLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let servers = self.servers.clone();
+
+        Box::pin(async move {
+            let path = req.path().trim_end_matches('/').to_owned();
+
+            if path == DOCS_PATH && req.path() != DOCS_PATH {
+                // `/docs/` is a directory to the browser. One `../` per
+                // trailing slash climbs back out, keeping any prefix.
+                let slashes = req.path().len() - path.len();
+                return Redirect::permanent(&format!("{}docs", "../".repeat(slashes)))
+                    .into_response();
+            }
+            if path == DOCS_PATH {
+                // The stock page loads `/docs/openapi.json`, which misses the
+                // prefix when the page itself was reached as `/api/v1/docs`.
+                // Relative to the page, `./docs/openapi.json` works either way.
+                return rustapi_openapi::swagger_ui_html("./docs/openapi.json")
+                    .map(ResponseBody::Full);
+            }
+
+            let response = next(req).await;
+            if path != format!("{DOCS_PATH}/openapi.json") || !response.status().is_success() {
+                return response;
+            }
+
+            let (mut parts, body) = response.into_parts();
+            let original = match body.collect().await {
+                Ok(collected) => collected.to_bytes(),
+                Err(err) => return err.into_response(),
+            };
+            let patched = match with_servers(&original, &servers) {
+                Some(patched) => Bytes::from(patched),
+                None => {
+                    warn!("OpenAPI spec is not a JSON object; serving it unchanged");
+                    original
+                }
+            };
+
+            parts.headers.remove(CONTENT_LENGTH);
+            Response::from_parts(parts, ResponseBody::new(patched))
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::LOCATION;
+    use rustapi_core::{BodyVariant, PathParams};
+
+    async fn get(path: &str) -> (StatusCode, http::HeaderMap, String) {
+        let app = RustApi::auto()
+            .openapi_servers(["/api/v1"])
+            .request_dispatcher();
+        let (parts, ()) = http::Request::get(path).body(()).unwrap().into_parts();
+        let request = Request::new(
+            parts,
+            BodyVariant::Buffered(Default::default()),
+            app.state_ref(),
+            PathParams::new(),
+        );
+        let response = app.dispatch(request).await;
+        let (parts, body) = response.into_parts();
+        let body = body.collect().await.unwrap().to_bytes();
+        (
+            parts.status,
+            parts.headers,
+            String::from_utf8_lossy(&body).into_owned(),
+        )
+    }
+
+    /// `reference` resolved against `base`, for the relative forms used here.
+    fn resolve(base: &str, reference: &str) -> String {
+        let mut segments: Vec<&str> = base.split('/').collect();
+        segments.pop();
+        for part in reference.split('/') {
+            match part {
+                ".." => {
+                    segments.pop();
+                }
+                "." => {}
+                part => segments.push(part),
+            }
+        }
+        segments.join("/")
+    }
+
+    #[tokio::test]
+    async fn page_loads_the_spec_relative_to_itself() {
+        let (status, _, page) = get("/docs").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(page.contains("./docs/openapi.json"));
+        assert_eq!(
+            resolve("/api/v1/docs", "./docs/openapi.json"),
+            "/api/v1/docs/openapi.json"
+        );
+    }
+
+    #[tokio::test]
+    async fn trailing_slash_redirects_back_to_the_page() {
+        for (path, behind_prefix) in [("/docs/", "/api/v1/docs/"), ("/docs//", "/api/v1/docs//")] {
+            let (status, headers, _) = get(path).await;
+            assert_eq!(status, StatusCode::MOVED_PERMANENTLY);
+            let location = headers[LOCATION].to_str().unwrap();
+            assert_eq!(resolve(path, location), "/docs");
+            assert_eq!(resolve(behind_prefix, location), "/api/v1/docs");
+        }
+    }
+
+    #[tokio::test]
+    async fn spec_lists_the_servers() {
+        let (status, _, spec) = get("/docs/openapi.json").await;
+        assert_eq!(status, StatusCode::OK);
+        let spec: serde_json::Value = serde_json::from_str(&spec).unwrap();
+        assert_eq!(spec["servers"], serde_json::json!([{ "url": "/api/v1" }]));
+    }
+}
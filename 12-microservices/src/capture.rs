@@ -0,0 +1,506 @@
+// ---------------------------------------------------------------------------
+// Request capture, for debugging
+// ---------------------------------------------------------------------------
+//
+// A user reports "GET /api/v1/orders gave me a 502". The logs say when; a
+// capture says what was sent and what came back. CaptureLayer keeps a sample
+// of exchanges in a ring buffer, and GET /admin/captures shows them:
+//
+//   let captures = Captures::new(100);
+//   RustApi::new()
+//       .layer(CaptureLayer::new(captures.clone()).every(10).server_errors(true))
+//       .scope("/admin", Scope::new().route("/captures", get(list)).layer(bearer))
+//
+//   every(n)              every n-th request is captured (default 1: all)
+//   server_errors(true)   any 5xx is captured too, sampled or not
+//   skip(prefix)          never captured, nor counted: the admin routes
+//
+// A capture holds the method, the path and query, the headers and a prefix
+// of each body, the status and how long the exchange took. It's all bounded:
+//
+//   the buffer             `capacity` captures; the oldest goes first
+//   a body                 MAX_BODY bytes, copied out of the request's own
+//   headers                MAX_HEADERS of them, MAX_HEADER_VALUE bytes each
+//   path and query         MAX_TARGET bytes
+//
+// Before anything is stored it is redacted. Credentials never reach the
+// buffer:
+//
+//   headers        Authorization, Proxy-Authorization, Cookie, Set-Cookie,
+//                  X-Api-Key
+//   query, form,   fields named password, token, secret, api_key,
+//   JSON bodies    access_token, refresh_token, client_secret
+//                  (any case), at any depth
+//   other bodies   a JSON body cut off by MAX_BODY can't be searched, so
+//                  it's left out; text bodies are kept; binary ones too,
+//                  lossily
+//
+// Capturing is off unless CAPTURE_EVERY is set (see gateway::app()).
+//
+// NOTE: a request body is only captured when it was already buffered, as it
+//       is behind ReadyServer's min_request_rate. A streamed body is never
+//       read here just to be captured. A response body is only captured
+//       when it is known to fit MAX_BODY; larger or streamed ones are noted
+//       by size.
+
+use http::header::{CONTENT_LENGTH, CONTENT_TYPE, TRANSFER_ENCODING};
+use http::HeaderMap;
+use http_body_util::BodyExt;
+use hyper::body::Body as _;
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_rs::prelude::*;
+use rustapi_rs::ResponseBody;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Bytes of each body kept.
+pub const MAX_BODY: usize = 4 * 1024;
+/// Headers kept per request or response.
+pub const MAX_HEADERS: usize = 32;
+/// Bytes of each header value kept.
+pub const MAX_HEADER_VALUE: usize = 256;
+/// Bytes of the path and query kept.
+pub const MAX_TARGET: usize = 1024;
+
+const REDACTED: &str = "[redacted]";
+
+const SECRET_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "x-api-key",
+];
+
+const SECRET_FIELDS: &[&str] = &[
+    "password",
+    "token",
+    "secret",
+    "api_key",
+    "access_token",
+    "refresh_token",
+    "client_secret",
+];
+
+/// One header, as captured.
+#[derive(Debug, Clone, PartialEq, Serialize, Schema)]
+pub struct CapturedHeader {
+    pub name: String,
+    pub value: String,
+}
+
+/// One request and its response, redacted.
+#[derive(Debug, Clone, Serialize, Schema)]
+pub struct Capture {
+    pub id: u64,
+    pub at_unix_ms: u64,
+    pub duration_ms: u64,
+    pub method: String,
+    /// Path and query.
+    pub target: String,
+    pub request_headers: Vec<CapturedHeader>,
+    pub request_body: Option<String>,
+    pub status: u16,
+    pub response_headers: Vec<CapturedHeader>,
+    pub response_body: Option<String>,
+}
+
+/// The ring buffer. Clones share it.
+#[derive(Clone)]
+pub struct Captures {
+    inner: Arc<Mutex<VecDeque<Capture>>>,
+    capacity: usize,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Captures {
+    /// Keeps the last `capacity` captures.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity: capacity.max(1),
+            next_id: Arc::new(AtomicU64::new(1)),
+        }
+    }
+
+    pub fn push(&self, capture: Capture) {
+        let mut captures = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        if captures.len() == self.capacity {
+            captures.pop_front();
+        }
+        captures.push_back(capture);
+    }
+
+    /// Every capture held, newest first.
+    pub fn list(&self) -> Vec<Capture> {
+        let captures = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        captures.iter().rev().cloned().collect()
+    }
+
+    /// Drops every capture held.
+    pub fn clear(&self) {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
+
+/// `text` cut to at most `max` bytes, on a character boundary.
+fn truncate(text: &str, max: usize) -> String {
+    if text.len() <= max {
+        return text.to_string();
+    }
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}…", &text[..end])
+}
+
+fn is_secret_field(name: &str) -> bool {
+    SECRET_FIELDS
+        .iter()
+        .any(|secret| secret.eq_ignore_ascii_case(name))
+}
+
+/// Up to MAX_HEADERS headers, secret ones redacted, values cut short.
+pub fn redact_headers(headers: &HeaderMap) -> Vec<CapturedHeader> {
+    headers
+        .iter()
+        .take(MAX_HEADERS)
+        .map(|(name, value)| CapturedHeader {
+            name: name.to_string(),
+            value: if SECRET_HEADERS.contains(&name.as_str()) {
+                REDACTED.to_string()
+            } else {
+                truncate(&String::from_utf8_lossy(value.as_bytes()), MAX_HEADER_VALUE)
+            },
+        })
+        .collect()
+}
+
+/// `a=1&password=x` → `a=1&password=[redacted]`.
+fn redact_pairs(pairs: &str) -> String {
+    pairs
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name, _)) if is_secret_field(name) => format!("{}={}", name, REDACTED),
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// The path and query, secret parameters redacted, cut to MAX_TARGET.
+pub fn redact_target(path: &str, query: Option<&str>) -> String {
+    let target = match query {
+        Some(query) => format!("{}?{}", path, redact_pairs(query)),
+        None => path.to_string(),
+    };
+    truncate(&target, MAX_TARGET)
+}
+
+fn redact_json(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(object) => {
+            for (name, value) in object.iter_mut() {
+                if is_secret_field(name) {
+                    *value = REDACTED.into();
+                } else {
+                    redact_json(value);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact_json),
+        _ => {}
+    }
+}
+
+/// What's kept of a body: a prefix of at most MAX_BODY bytes, with secret
+/// fields redacted. None for an empty body.
+pub fn redact_body(content_type: Option<&str>, body: &[u8]) -> Option<String> {
+    if body.is_empty() {
+        return None;
+    }
+    let content_type = content_type.unwrap_or_default();
+    if content_type.starts_with("application/json") || content_type.contains("+json") {
+        if body.len() > MAX_BODY {
+            return Some(format!(
+                "[{} bytes of JSON, too long to redact]",
+                body.len()
+            ));
+        }
+        return Some(match serde_json::from_slice::<serde_json::Value>(body) {
+            Ok(mut value) => {
+                redact_json(&mut value);
+                value.to_string()
+            }
+            Err(_) => format!("[{} bytes of invalid JSON]", body.len()),
+        });
+    }
+
+    let shown = String::from_utf8_lossy(&body[..body.len().min(MAX_BODY)]);
+    let mut shown = if content_type.starts_with("application/x-www-form-urlencoded") {
+        redact_pairs(&shown)
+    } else {
+        shown.into_owned()
+    };
+    if body.len() > MAX_BODY {
+        shown.push_str(&format!("…(+{} bytes)", body.len() - MAX_BODY));
+    }
+    Some(shown)
+}
+
+fn content_type(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// Whether the request says it has a body at all.
+fn has_body(headers: &HeaderMap) -> bool {
+    headers.contains_key(TRANSFER_ENCODING)
+        || headers
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|length| length != "0")
+}
+
+fn millis(duration: std::time::Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+/// The part of a capture known before the response.
+struct Pending {
+    at_unix_ms: u64,
+    started: Instant,
+    sampled: bool,
+    method: String,
+    target: String,
+    request_headers: Vec<CapturedHeader>,
+    request_body: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct CaptureLayer {
+    captures: Captures,
+    every: u64,
+    server_errors: bool,
+    skipped: Vec<String>,
+    seen: Arc<AtomicU64>,
+}
+
+impl CaptureLayer {
+    pub fn new(captures: Captures) -> Self {
+        Self {
+            captures,
+            every: 1,
+            server_errors: false,
+            skipped: Vec::new(),
+            seen: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Captures one request in `n`. Default 1, every request.
+    pub fn every(mut self, n: u64) -> Self {
+        self.every = n.max(1);
+        self
+    }
+
+    /// Also captures every response with a 5xx status.
+    pub fn server_errors(mut self, enabled: bool) -> Self {
+        self.server_errors = enabled;
+        self
+    }
+
+    /// Leaves `prefix` and everything below it alone.
+    pub fn skip(mut self, prefix: &str) -> Self {
+        self.skipped.push(prefix.trim_end_matches('/').to_string());
+        self
+    }
+
+    fn skips(&self, path: &str) -> bool {
+        self.skipped.iter().any(|prefix| {
+            path.strip_prefix(prefix.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+    }
+}
+
+impl MiddlewareLayer for CaptureLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        if self.skips(req.path()) {
+            return next(req);
+        }
+        let sampled = self
+            .seen
+            .fetch_add(1, Ordering::Relaxed)
+            .is_multiple_of(self.every);
+        if !sampled && !self.server_errors {
+            return next(req);
+        }
+
+        let body = req.try_clone().and_then(|mut copy| copy.take_body());
+        let pending = Pending {
+            at_unix_ms: millis(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default(),
+            ),
+            started: Instant::now(),
+            sampled,
+            method: req.method().to_string(),
+            target: redact_target(req.path(), req.query_string()),
+            request_headers: redact_headers(req.headers()),
+            request_body: match body {
+                Some(body) => redact_body(content_type(req.headers()), &body),
+                None if has_body(req.headers()) => Some("[streamed, not captured]".to_string()),
+                None => None,
+            },
+        };
+        let captures = self.captures.clone();
+
+        Box::pin(async move {
+            let response = next(req).await;
+            if !pending.sampled && !response.status().is_server_error() {
+                return response;
+            }
+
+            let (parts, body) = response.into_parts();
+            let fits = body
+                .size_hint()
+                .upper()
+                .is_some_and(|length| length <= MAX_BODY as u64);
+            let (body, response_body) = if fits {
+                match body.collect().await {
+                    Ok(collected) => {
+                        let bytes = collected.to_bytes();
+                        let kept = redact_body(content_type(&parts.headers), &bytes);
+                        (ResponseBody::new(bytes), kept)
+                    }
+                    Err(err) => return err.into_response(),
+                }
+            } else {
+                let size = match body.size_hint().exact() {
+                    Some(length) => format!("[{} bytes, not captured]", length),
+                    None => "[streamed, not captured]".to_string(),
+                };
+                (body, Some(size))
+            };
+
+            captures.push(Capture {
+                id: captures.next_id.fetch_add(1, Ordering::Relaxed),
+                at_unix_ms: pending.at_unix_ms,
+                duration_ms: millis(pending.started.elapsed()),
+                method: pending.method,
+                target: pending.target,
+                request_headers: pending.request_headers,
+                request_body: pending.request_body,
+                status: parts.status.as_u16(),
+                response_headers: redact_headers(&parts.headers),
+                response_body,
+            });
+            Response::from_parts(parts, body)
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    #[test]
+    fn secret_headers_are_redacted() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "authorization",
+            HeaderValue::from_static("Bearer let-me-in"),
+        );
+        headers.insert("cookie", HeaderValue::from_static("session=abc"));
+        headers.insert("accept", HeaderValue::from_static("application/json"));
+        let kept = redact_headers(&headers);
+        for (name, value) in [
+            ("authorization", "[redacted]"),
+            ("cookie", "[redacted]"),
+            ("accept", "application/json"),
+        ] {
+            let header = CapturedHeader {
+                name: name.to_string(),
+                value: value.to_string(),
+            };
+            assert!(kept.contains(&header), "{:?}", kept);
+        }
+    }
+
+    #[test]
+    fn secret_query_parameters_are_redacted() {
+        assert_eq!(
+            redact_target("/api/v1/users", Some("page=2&TOKEN=abc&q=x")),
+            "/api/v1/users?page=2&TOKEN=[redacted]&q=x"
+        );
+    }
+
+    #[test]
+    fn secret_form_fields_are_redacted() {
+        let body = redact_body(
+            Some("application/x-www-form-urlencoded"),
+            b"user=ada&password=hunter2",
+        );
+        assert_eq!(body.as_deref(), Some("user=ada&password=[redacted]"));
+    }
+
+    #[test]
+    fn secret_json_fields_are_redacted_at_any_depth() {
+        let body = redact_body(
+            Some("application/json"),
+            br#"{"user":{"name":"ada","Password":"hunter2"},"tokens":[{"access_token":"x"}]}"#,
+        )
+        .unwrap();
+        assert!(!body.contains("hunter2"), "{}", body);
+        assert!(!body.contains("\"x\""), "{}", body);
+        assert!(body.contains("ada"), "{}", body);
+    }
+
+    #[test]
+    fn json_past_max_body_is_left_out() {
+        let long = format!(
+            r#"{{"password":"hunter2","pad":"{}"}}"#,
+            "a".repeat(MAX_BODY)
+        );
+        let body = redact_body(Some("application/json"), long.as_bytes()).unwrap();
+        assert!(!body.contains("hunter2"), "{}", body);
+        assert!(body.contains("too long"), "{}", body);
+    }
+
+    #[test]
+    fn a_full_buffer_drops_the_oldest() {
+        let captures = Captures::new(2);
+        for id in 1..=3 {
+            captures.push(Capture {
+                id,
+                at_unix_ms: 0,
+                duration_ms: 0,
+                method: "GET".to_string(),
+                target: "/".to_string(),
+                request_headers: Vec::new(),
+                request_body: None,
+                status: 200,
+                response_headers: Vec::new(),
+                response_body: None,
+            });
+        }
+        let ids: Vec<u64> = captures.list().iter().map(|capture| capture.id).collect();
+        assert_eq!(ids, [3, 2]);
+    }
+}
@@ -0,0 +1,374 @@
+// ---------------------------------------------------------------------------
+// Circuit breaker
+// ---------------------------------------------------------------------------
+//
+// When the order service is down, every gateway request for /orders waits
+// for a connection to fail and then answers 502. A circuit breaker notices
+// the pattern and stops trying for a while:
+//
+//   CircuitBreakerLayer::new()
+//       .failure_threshold(0.5)           // open at 50% failures...
+//       .window(Duration::from_secs(10))  // ...over the last 10 s...
+//       .min_requests(5)                  // ...once there are 5 to judge by
+//       .cooldown(Duration::from_secs(5))
+//       .on_state_change(|route, from, to| warn!("{route}: {from} -> {to}"))
+//
+//   Closed     requests pass; each 5xx response counts as a failure
+//   Open       requests are answered at once with 503 and Retry-After, without
+//              calling the handler (so without calling the upstream)
+//   HalfOpen   after the cooldown one request is let through as a probe.
+//              Success closes the circuit, failure opens it for another
+//              cooldown; the requests around the probe still get 503
+//
+// Each route has its own circuit: with the order service down, /orders opens
+// and /users keeps working. The window slides: outcomes older than `window`
+// no longer count.
+//
+// A circuit is keyed by method and route template, from the ScopedRoute a
+// Scope puts on each request: `/orders/{id}` is one circuit, whatever the id.
+// Requests without one (the layer isn't in a Scope, or the path matched no
+// route) pass straight through, untracked, so unknown paths can't grow the
+// table.
+//
+// NOTE: add it to a Scope, `Scope::new()...layer(breaker)`; on the app
+//       itself it tracks nothing.
+
+use crate::scope::ScopedRoute;
+use http::header::RETRY_AFTER;
+use http::HeaderValue;
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_rs::prelude::*;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl fmt::Display for CircuitState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Closed => "closed",
+            Self::Open => "open",
+            Self::HalfOpen => "half-open",
+        })
+    }
+}
+
+type StateChangeFn = dyn Fn(&str, CircuitState, CircuitState) + Send + Sync;
+
+/// One route's circuit.
+struct Circuit {
+    state: CircuitState,
+    /// When the circuit last opened.
+    opened_at: Instant,
+    /// A half-open probe is in flight.
+    probing: bool,
+    /// (when, failed) for the requests within the window, oldest first.
+    outcomes: VecDeque<(Instant, bool)>,
+}
+
+impl Circuit {
+    fn new() -> Self {
+        Self {
+            state: CircuitState::Closed,
+            opened_at: Instant::now(),
+            probing: false,
+            outcomes: VecDeque::new(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct CircuitBreakerLayer {
+    failure_threshold: f64,
+    window: Duration,
+    min_requests: usize,
+    cooldown: Duration,
+    on_state_change: Option<Arc<StateChangeFn>>,
+    circuits: Arc<Mutex<HashMap<String, Circuit>>>,
+}
+
+impl Default for CircuitBreakerLayer {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 0.5,
+            window: Duration::from_secs(10),
+            min_requests: 5,
+            cooldown: Duration::from_secs(5),
+            on_state_change: None,
+            circuits: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+/// What to do with a request, decided under the lock.
+enum Admit {
+    Pass,
+    Probe,
+    Reject(Duration),
+}
+
+impl CircuitBreakerLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Share of failed requests in the window that opens the circuit,
+    /// between 0.0 and 1.0. Default 0.5.
+    pub fn failure_threshold(mut self, threshold: f64) -> Self {
+        self.failure_threshold = threshold.clamp(0.0, 1.0);
+        self
+    }
+
+    /// How far back outcomes count. Default 10 s.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Requests the window must hold before the rate is judged, so that
+    /// one failure out of one isn't 100%. Default 5.
+    pub fn min_requests(mut self, min: usize) -> Self {
+        self.min_requests = min.max(1);
+        self
+    }
+
+    /// How long the circuit stays open before a probe. Default 5 s.
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Called with the route, the old and the new state on every change,
+    /// outside the breaker's lock.
+    pub fn on_state_change<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&str, CircuitState, CircuitState) + Send + Sync + 'static,
+    {
+        self.on_state_change = Some(Arc::new(callback));
+        self
+    }
+
+    fn notify(&self, route: &str, change: Option<(CircuitState, CircuitState)>) {
+        if let (Some(callback), Some((from, to))) = (&self.on_state_change, change) {
+            callback(route, from, to);
+        }
+    }
+
+    fn admit(&self, route: &str) -> Admit {
+        let (admit, change) = {
+            let mut circuits = self.circuits.lock().unwrap();
+            let circuit = circuits
+                .entry(route.to_string())
+                .or_insert_with(Circuit::new);
+            match circuit.state {
+                CircuitState::Closed => (Admit::Pass, None),
+                CircuitState::Open => {
+                    let open_for = circuit.opened_at.elapsed();
+                    if open_for < self.cooldown {
+                        (Admit::Reject(self.cooldown - open_for), None)
+                    } else {
+                        circuit.state = CircuitState::HalfOpen;
+                        circuit.probing = true;
+                        let change = (CircuitState::Open, CircuitState::HalfOpen);
+                        (Admit::Probe, Some(change))
+                    }
+                }
+                CircuitState::HalfOpen if !circuit.probing => {
+                    // The last probe went away without an answer.
+                    circuit.probing = true;
+                    (Admit::Probe, None)
+                }
+                // The probe's answer is due any moment.
+                CircuitState::HalfOpen => (Admit::Reject(Duration::from_secs(1)), None),
+            }
+        };
+        self.notify(route, change);
+        admit
+    }
+
+    fn record(&self, route: &str, failed: bool, probe: bool) {
+        let change = {
+            let mut circuits = self.circuits.lock().unwrap();
+            let circuit = circuits
+                .entry(route.to_string())
+                .or_insert_with(Circuit::new);
+            let now = Instant::now();
+
+            if probe {
+                circuit.probing = false;
+                circuit.outcomes.clear();
+                if failed {
+                    circuit.state = CircuitState::Open;
+                    circuit.opened_at = now;
+                    Some((CircuitState::HalfOpen, CircuitState::Open))
+                } else {
+                    circuit.state = CircuitState::Closed;
+                    Some((CircuitState::HalfOpen, CircuitState::Closed))
+                }
+            } else if circuit.state != CircuitState::Closed {
+                // Passed before the circuit opened; it doesn't change anything now.
+                None
+            } else {
+                circuit.outcomes.push_back((now, failed));
+                while circuit
+                    .outcomes
+                    .front()
+                    .is_some_and(|(at, _)| now.duration_since(*at) > self.window)
+                {
+                    circuit.outcomes.pop_front();
+                }
+
+                let total = circuit.outcomes.len();
+                let failures = circuit
+                    .outcomes
+                    .iter()
+                    .filter(|(_, failed)| *failed)
+                    .count();
+                if total >= self.min_requests
+                    && failures as f64 / total as f64 >= self.failure_threshold
+                {
+                    circuit.state = CircuitState::Open;
+                    circuit.opened_at = now;
+                    circuit.outcomes.clear();
+                    Some((CircuitState::Closed, CircuitState::Open))
+                } else {
+                    None
+                }
+            }
+        };
+        self.notify(route, change);
+    }
+}
+
+/// Gives the probe slot back if the probe is dropped before it finishes
+/// (the client disconnected), so the next request can probe instead.
+struct ProbeGuard {
+    layer: CircuitBreakerLayer,
+    route: String,
+    armed: bool,
+}
+
+impl Drop for ProbeGuard {
+    fn drop(&mut self) {
+        if self.armed {
+            if let Some(circuit) = self.layer.circuits.lock().unwrap().get_mut(&self.route) {
+                circuit.probing = false;
+            }
+        }
+    }
+}
+
+fn circuit_open(route: &str, retry_after: Duration) -> Response {
+    let mut response = ApiError::new(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "circuit_open",
+        format!("{} is failing; not trying it again for now", route),
+    )
+    .into_response();
+    // Whole seconds, rounded up: Retry-After: 0 would invite a retry storm.
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    response
+        .headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from(seconds.max(1)));
+    response
+}
+
+impl MiddlewareLayer for CircuitBreakerLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let Some(ScopedRoute(template)) = req.extensions().get::<ScopedRoute>() else {
+            return next(req);
+        };
+        let route = format!("{} {}", req.method(), template);
+        let probe = match self.admit(&route) {
+            Admit::Pass => false,
+            Admit::Probe => true,
+            Admit::Reject(retry_after) => {
+                return Box::pin(std::future::ready(circuit_open(&route, retry_after)))
+            }
+        };
+
+        let layer = self.clone();
+        Box::pin(async move {
+            let mut guard = ProbeGuard {
+                layer,
+                route,
+                armed: probe,
+            };
+            let response = next(req).await;
+            guard.armed = false;
+            guard
+                .layer
+                .record(&guard.route, response.status().is_server_error(), probe);
+            response
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustapi_core::{BodyVariant, PathParams};
+
+    fn request(path: &str, template: Option<&str>) -> Request {
+        let (mut parts, ()) = http::Request::get(path).body(()).unwrap().into_parts();
+        if let Some(template) = template {
+            parts.extensions.insert(ScopedRoute(Arc::from(template)));
+        }
+        Request::new(
+            parts,
+            BodyVariant::Buffered(Default::default()),
+            Default::default(),
+            PathParams::new(),
+        )
+    }
+
+    fn failing() -> BoxedNext {
+        Arc::new(|_req| {
+            Box::pin(async {
+                ApiError::new(StatusCode::BAD_GATEWAY, "bad_gateway", "down").into_response()
+            })
+        })
+    }
+
+    #[tokio::test]
+    async fn one_circuit_per_route_template() {
+        let breaker = CircuitBreakerLayer::new().min_requests(3);
+        for id in 1..=3 {
+            let req = request(&format!("/orders/{id}"), Some("/orders/{id}"));
+            breaker.call(req, failing()).await;
+        }
+
+        let circuits = breaker.circuits.lock().unwrap();
+        assert_eq!(circuits.len(), 1);
+        assert_eq!(circuits["GET /orders/{id}"].state, CircuitState::Open);
+    }
+
+    #[tokio::test]
+    async fn requests_outside_a_scope_are_not_tracked() {
+        let breaker = CircuitBreakerLayer::new();
+        for n in 0..10 {
+            let response = breaker
+                .call(request(&format!("/nope/{n}"), None), failing())
+                .await;
+            assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        }
+        assert!(breaker.circuits.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,154 @@
+// ---------------------------------------------------------------------------
+// Per-route concurrency limits
+// ---------------------------------------------------------------------------
+//
+// A global limit protects the process, not its other routes: eight slow
+// report generations can use up all of it while cheap lookups queue behind
+// them. Here an expensive route gets its own slots instead:
+//
+//   ConcurrencyLimits::new().route(Method::GET, "/orders/report", 4)
+//
+// A fifth concurrent report is answered at once with 503 and Retry-After;
+// every other route is untouched. A slot is held until the response has been
+// produced, so a handler that errors or panics still gives it back.
+//
+// The value is also the metrics handle: it's Clone, every clone shares the
+// counters, and `snapshot()` reports in-flight and rejected requests per
+// route. Register one clone as a layer and another as state.
+//
+// NOTE: the path is compared as-is, like SloLayer in 09-bookstore. Limit a
+//       route with parameters by its concrete paths, or limit in the handler.
+
+use http::header::RETRY_AFTER;
+use http::{HeaderValue, Method};
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_rs::prelude::*;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Sent as `Retry-After` on a 503, in seconds.
+const RETRY_AFTER_SECS: u32 = 1;
+
+struct LimitedRoute {
+    method: Method,
+    path: String,
+    max: usize,
+    in_flight: AtomicUsize,
+    rejected: AtomicU64,
+}
+
+impl LimitedRoute {
+    /// Takes a slot, or None if all `max` are in use.
+    fn try_acquire(self: &Arc<Self>) -> Option<Slot> {
+        self.in_flight
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                (n < self.max).then_some(n + 1)
+            })
+            .ok()
+            .map(|_| Slot(self.clone()))
+    }
+}
+
+/// A slot on a limited route, released on drop.
+struct Slot(Arc<LimitedRoute>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// One limited route's load, as `snapshot()` reports it.
+#[derive(Debug, Serialize, Schema)]
+pub struct RouteLoad {
+    /// `GET /orders/report`
+    pub route: String,
+    pub limit: usize,
+    pub in_flight: usize,
+    /// 503s since start.
+    pub rejected: u64,
+}
+
+#[derive(Clone, Default)]
+pub struct ConcurrencyLimits {
+    routes: Vec<Arc<LimitedRoute>>,
+}
+
+impl ConcurrencyLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// At most `max` concurrent `method path` requests (`max` at least 1).
+    pub fn route(mut self, method: Method, path: &str, max: usize) -> Self {
+        self.routes.push(Arc::new(LimitedRoute {
+            method,
+            path: path.to_string(),
+            max: max.max(1),
+            in_flight: AtomicUsize::new(0),
+            rejected: AtomicU64::new(0),
+        }));
+        self
+    }
+
+    /// Current load of every limited route, in registration order.
+    pub fn snapshot(&self) -> Vec<RouteLoad> {
+        self.routes
+            .iter()
+            .map(|route| RouteLoad {
+                route: format!("{} {}", route.method, route.path),
+                limit: route.max,
+                in_flight: route.in_flight.load(Ordering::Acquire),
+                rejected: route.rejected.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
+
+fn busy(route: &LimitedRoute) -> Response {
+    let mut response = ApiError::new(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "route_busy",
+        format!(
+            "{} {} is at its limit of {} concurrent requests, try again shortly",
+            route.method, route.path, route.max
+        ),
+    )
+    .into_response();
+    response
+        .headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from(RETRY_AFTER_SECS));
+    response
+}
+
+impl MiddlewareLayer for ConcurrencyLimits {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let Some(route) = self
+            .routes
+            .iter()
+            .find(|route| route.method == *req.method() && route.path == req.path())
+        else {
+            return Box::pin(next(req));
+        };
+
+        let Some(slot) = route.try_acquire() else {
+            route.rejected.fetch_add(1, Ordering::Relaxed);
+            return Box::pin(std::future::ready(busy(route)));
+        };
+        Box::pin(async move {
+            let response = next(req).await;
+            drop(slot);
+            response
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,172 @@
+// ---------------------------------------------------------------------------
+// GatewayError: the gateway's own error type
+// ---------------------------------------------------------------------------
+//
+// Handlers return `Result<Json<T>, GatewayError>`; `?` works on anything the
+// enum converts from, and the enum decides what the client sees:
+//
+//   async fn users(..) -> Result<Json<Vec<User>>, GatewayError> {
+//       upstreams.fetch("users", url).await.map(Json)
+//   }
+//
+//   variant                       status  error.type
+//   Unreachable (timed out)       504     upstream_timeout
+//   Unreachable (anything else)   502     upstream_unreachable
+//   UpstreamStatus                502     upstream_error
+//   BadBody                       502     upstream_bad_body
+//   NotFound                      404     not_found
+//
+// The body keeps rustapi's `{"error":{"type","message"}}` shape, plus the
+// backend it concerns:
+//
+//   {"error":{"type":"upstream_unreachable","message":"orders service unreachable"},
+//    "service":"orders"}
+//
+// Any error type works this way: a handler may return `Result<R, E>` for
+// any E that is IntoResponse and ResponseModifier. The first builds the
+// response; the second lists the statuses in /docs.
+//
+// NOTE: the reqwest error (address, refused connection, ...) is logged, not
+//       sent: it describes our network, not the client's request.
+
+use rustapi_openapi::{MediaType, Operation, ResponseModifier, ResponseSpec, SchemaRef};
+use rustapi_rs::prelude::*;
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum GatewayError {
+    /// The backend couldn't be reached, or didn't answer in time.
+    Unreachable {
+        service: &'static str,
+        source: reqwest::Error,
+    },
+    /// The backend answered with an error status.
+    UpstreamStatus {
+        service: &'static str,
+        status: StatusCode,
+    },
+    /// The backend's body isn't the JSON we expect.
+    BadBody {
+        service: &'static str,
+        source: reqwest::Error,
+    },
+    /// What the client asked for doesn't exist.
+    NotFound(&'static str),
+}
+
+impl GatewayError {
+    fn status(&self) -> StatusCode {
+        match self {
+            Self::Unreachable { source, .. } if source.is_timeout() => StatusCode::GATEWAY_TIMEOUT,
+            Self::Unreachable { .. } | Self::UpstreamStatus { .. } | Self::BadBody { .. } => {
+                StatusCode::BAD_GATEWAY
+            }
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+        }
+    }
+
+    fn error_type(&self) -> &'static str {
+        match self {
+            Self::Unreachable { source, .. } if source.is_timeout() => "upstream_timeout",
+            Self::Unreachable { .. } => "upstream_unreachable",
+            Self::UpstreamStatus { .. } => "upstream_error",
+            Self::BadBody { .. } => "upstream_bad_body",
+            Self::NotFound(_) => "not_found",
+        }
+    }
+
+    fn service(&self) -> Option<&'static str> {
+        match self {
+            Self::Unreachable { service, .. }
+            | Self::UpstreamStatus { service, .. }
+            | Self::BadBody { service, .. } => Some(service),
+            Self::NotFound(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for GatewayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unreachable { service, source } if source.is_timeout() => {
+                write!(f, "{} service timed out", service)
+            }
+            Self::Unreachable { service, .. } => write!(f, "{} service unreachable", service),
+            Self::UpstreamStatus { service, status } => {
+                write!(f, "{} service answered {}", service, status.as_u16())
+            }
+            Self::BadBody { service, .. } => write!(f, "{} service sent an invalid body", service),
+            Self::NotFound(what) => write!(f, "{} not found", what),
+        }
+    }
+}
+
+impl std::error::Error for GatewayError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Unreachable { source, .. } | Self::BadBody { source, .. } => Some(source),
+            Self::UpstreamStatus { .. } | Self::NotFound(_) => None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: ErrorDetail,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    service: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+struct ErrorDetail {
+    #[serde(rename = "type")]
+    error_type: &'static str,
+    message: String,
+}
+
+impl IntoResponse for GatewayError {
+    fn into_response(self) -> Response {
+        if let Some(source) = std::error::Error::source(&self) {
+            warn!("{}: {}", self, source);
+        }
+        let body = ErrorBody {
+            error: ErrorDetail {
+                error_type: self.error_type(),
+                message: self.to_string(),
+            },
+            service: self.service(),
+        };
+        (self.status(), Json(body)).into_response()
+    }
+}
+
+impl ResponseModifier for GatewayError {
+    fn update_response(op: &mut Operation) {
+        let statuses = [
+            ("404", "Not found"),
+            ("502", "A backend is unreachable or answered with an error"),
+            ("504", "A backend timed out"),
+        ];
+        for (status, description) in statuses {
+            let mut content = BTreeMap::new();
+            content.insert(
+                "application/json".to_string(),
+                MediaType {
+                    schema: Some(SchemaRef::Ref {
+                        reference: "#/components/schemas/ErrorSchema".to_string(),
+                    }),
+                    example: None,
+                },
+            );
+            op.responses.insert(
+                status.to_string(),
+                ResponseSpec {
+                    description: description.to_string(),
+                    content,
+                    headers: BTreeMap::new(),
+                },
+            );
+        }
+    }
+}
@@ -0,0 +1,254 @@
+// ---------------------------------------------------------------------------
+// API gateway
+// ---------------------------------------------------------------------------
+//
+// The only service clients talk to. It forwards to the user and order
+// services and joins their answers. Its API comes in two versions, each a
+// Scope (src/scope.rs) with its own layers:
+//
+//   /api/v1/users, /api/v1/orders    a circuit breaker stops calls to a
+//                                    backend that keeps failing (src/circuit.rs)
+//   /api/v2/users,                   `Authorization: Bearer <GATEWAY_TOKEN>`
+//   /api/v2/users/{user_id}/orders   required
+//
+// /docs is in neither scope, so it needs no token and has no circuit.
+//
+// With CAPTURE_EVERY=n set, every n-th request and every 5xx is captured
+// (src/capture.rs), and /admin/captures, behind the same bearer token as
+// /api/v2, lists the last CAPTURE_SIZE (default 100). Off otherwise.
+
+use crate::capture::{Capture, CaptureLayer, Captures};
+use crate::circuit::CircuitBreakerLayer;
+use crate::error::GatewayError;
+use crate::orders::Order;
+use crate::scope::{Scope, ScopeExt};
+use crate::users::User;
+use http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use http::HeaderValue;
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_rs::prelude::*;
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long a backend may take to answer before the gateway gives up (504).
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Used for /api/v2 when GATEWAY_TOKEN isn't set.
+pub const DEFAULT_TOKEN: &str = "let-me-in";
+
+/// Captures kept when CAPTURE_SIZE isn't set.
+const DEFAULT_CAPTURE_SIZE: usize = 100;
+
+#[derive(Clone)]
+pub struct Upstreams {
+    client: reqwest::Client,
+    users: String,
+    orders: String,
+}
+
+impl Upstreams {
+    pub fn new(users: SocketAddr, orders: SocketAddr) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(UPSTREAM_TIMEOUT)
+                .build()
+                .expect("HTTP client should build"),
+            users: format!("http://{}", users),
+            orders: format!("http://{}", orders),
+        }
+    }
+
+    /// GETs `url` from `service` and reads its JSON body.
+    async fn fetch<T: DeserializeOwned>(
+        &self,
+        service: &'static str,
+        url: String,
+    ) -> Result<T, GatewayError> {
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(|source| GatewayError::Unreachable { service, source })?;
+        if !response.status().is_success() {
+            return Err(GatewayError::UpstreamStatus {
+                service,
+                status: response.status(),
+            });
+        }
+        response
+            .json()
+            .await
+            .map_err(|source| GatewayError::BadBody { service, source })
+    }
+}
+
+#[derive(Debug, Serialize, Schema)]
+struct OrderView {
+    id: u64,
+    item: String,
+    customer: String,
+}
+
+/// Users, from the user service.
+async fn users(State(upstreams): State<Upstreams>) -> Result<Json<Vec<User>>, GatewayError> {
+    let url = format!("{}/users", upstreams.users);
+    upstreams.fetch("users", url).await.map(Json)
+}
+
+/// Orders with customer names, joined from both services.
+async fn orders(State(upstreams): State<Upstreams>) -> Result<Json<Vec<OrderView>>, GatewayError> {
+    let (orders, users) = tokio::try_join!(
+        upstreams.fetch::<Vec<Order>>("orders", format!("{}/orders", upstreams.orders)),
+        upstreams.fetch::<Vec<User>>("users", format!("{}/users", upstreams.users)),
+    )?;
+
+    let views = orders
+        .into_iter()
+        .map(|order| OrderView {
+            customer: users
+                .iter()
+                .find(|user| user.id == order.user_id)
+                .map(|user| user.name.clone())
+                .unwrap_or_else(|| "unknown".to_string()),
+            id: order.id,
+            item: order.item,
+        })
+        .collect();
+    Ok(Json(views))
+}
+
+/// One customer's orders. `user_id` comes from the enclosing scope's prefix.
+async fn user_orders(
+    State(upstreams): State<Upstreams>,
+    Path(user_id): Path<u64>,
+) -> Result<Json<Vec<Order>>, GatewayError> {
+    let (orders, users) = tokio::try_join!(
+        upstreams.fetch::<Vec<Order>>("orders", format!("{}/orders", upstreams.orders)),
+        upstreams.fetch::<Vec<User>>("users", format!("{}/users", upstreams.users)),
+    )?;
+    if !users.iter().any(|user| user.id == user_id) {
+        return Err(GatewayError::NotFound("User"));
+    }
+    let orders = orders
+        .into_iter()
+        .filter(|order| order.user_id == user_id)
+        .collect();
+    Ok(Json(orders))
+}
+
+/// The captured exchanges, newest first.
+async fn list_captures(State(captures): State<Captures>) -> Json<Vec<Capture>> {
+    Json(captures.list())
+}
+
+/// Forgets every captured exchange.
+async fn clear_captures(State(captures): State<Captures>) -> NoContent {
+    captures.clear();
+    NoContent
+}
+
+/// Lets through requests with `Authorization: Bearer <token>`; 401 otherwise.
+#[derive(Clone)]
+struct BearerLayer {
+    token: Arc<str>,
+}
+
+impl BearerLayer {
+    fn new(token: impl Into<Arc<str>>) -> Self {
+        Self {
+            token: token.into(),
+        }
+    }
+}
+
+impl MiddlewareLayer for BearerLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let given = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        // Compare every byte, so the time taken doesn't leak how much matched.
+        let same = given.len() == self.token.len()
+            && given
+                .bytes()
+                .zip(self.token.bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0;
+        if same {
+            return next(req);
+        }
+
+        let mut response = ApiError::unauthorized("Missing or wrong bearer token").into_response();
+        response
+            .headers_mut()
+            .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        Box::pin(async move { response })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+pub fn app(upstreams: Upstreams) -> RustApi {
+    let breaker = CircuitBreakerLayer::new()
+        .failure_threshold(0.5)
+        .window(Duration::from_secs(10))
+        .min_requests(5)
+        .cooldown(Duration::from_secs(5))
+        .on_state_change(|route, from, to| warn!("Circuit for {}: {} -> {}", route, from, to));
+    let token = std::env::var("GATEWAY_TOKEN").unwrap_or_else(|_| DEFAULT_TOKEN.to_string());
+
+    let v1 = Scope::new()
+        .route("/users", get(users))
+        .route("/orders", get(orders))
+        .layer(breaker);
+    let v2 = Scope::new()
+        .route("/users", get(users))
+        .nest(
+            "/users/{user_id}",
+            Scope::new().route("/orders", get(user_orders)),
+        )
+        .layer(BearerLayer::new(token.clone()));
+    let admin_token = token;
+
+    let app = RustApi::new()
+        .state(upstreams)
+        .scope("/api/v1", v1)
+        .scope("/api/v2", v2);
+
+    let capture_every = std::env::var("CAPTURE_EVERY")
+        .ok()
+        .and_then(|every| every.parse().ok());
+    let app = match capture_every {
+        Some(every) => {
+            let size = std::env::var("CAPTURE_SIZE")
+                .ok()
+                .and_then(|size| size.parse().ok())
+                .unwrap_or(DEFAULT_CAPTURE_SIZE);
+            let captures = Captures::new(size);
+            let admin = Scope::new()
+                .route("/captures", get(list_captures).delete(clear_captures))
+                .layer(BearerLayer::new(admin_token));
+            app.state(captures.clone()).scope("/admin", admin).layer(
+                CaptureLayer::new(captures)
+                    .every(every)
+                    .server_errors(true)
+                    .skip("/admin"),
+            )
+        }
+        None => app,
+    };
+    app.docs("/docs")
+}
@@ -0,0 +1,165 @@
+// Run with: cargo run -p microservices
+// Then visit: http://127.0.0.1:3000/api/v1/orders
+//
+// Or as separate processes, in any order:
+//           cargo run -p microservices -- gateway
+//           cargo run -p microservices -- users
+//           cargo run -p microservices -- orders
+//
+// Lesson: start dependents when their dependencies are *ready*, not after a
+//         guessed delay. In one process, each backend reports its bound
+//         address from an on_ready hook. Across processes, the gateway probes
+//         the backends' ports until they accept connections.
+//
+//         Stop in the reverse order: on Ctrl+C or SIGTERM the gateway drains
+//         first, while the backends still answer its in-flight requests.
+
+mod capture;
+mod circuit;
+mod concurrency;
+mod error;
+mod gateway;
+mod orders;
+mod ready;
+mod scope;
+mod server;
+mod users;
+
+use gateway::Upstreams;
+use rustapi_rs::prelude::*;
+use server::OnReadyExt;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinHandle;
+
+const GATEWAY_ADDR: &str = "127.0.0.1:3000";
+/// Fixed backend ports for the multi-process mode.
+const USERS_ADDR: &str = "127.0.0.1:3001";
+const ORDERS_ADDR: &str = "127.0.0.1:3002";
+/// How long the standalone gateway waits for its backends.
+const READY_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long each service lets in-flight requests finish on shutdown. The
+/// gateway's and then the backends' run one after the other, so keep the sum
+/// below the orchestrator's kill timeout.
+const GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// The gateway faces the clients: it closes connections that send a request
+/// too slowly to ever finish it (see src/server.rs).
+const HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+const MIN_BODY_RATE: u64 = 240;
+const BODY_RATE_GRACE: Duration = Duration::from_secs(5);
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Serves `app` on a free local port until `stop` fires, and resolves once it
+/// is accepting connections, with the port it got and the serving task.
+async fn spawn_service(
+    name: &'static str,
+    app: RustApi,
+    mut stop: watch::Receiver<bool>,
+) -> std::io::Result<(SocketAddr, JoinHandle<()>)> {
+    let (ready_tx, ready_rx) = oneshot::channel();
+
+    let server = app
+        .on_ready(move |addr| async move {
+            println!(" -> {} service listening on http://{}", name, addr);
+            let _ = ready_tx.send(addr);
+        })
+        .on_shutdown(move || async move {
+            println!(" -> {} service stopped", name);
+        })
+        .grace_period(GRACE_PERIOD);
+    let task = tokio::spawn(async move {
+        let stopped = async move {
+            let _ = stop.changed().await;
+        };
+        if let Err(err) = server.run_with_shutdown("127.0.0.1:0", stopped).await {
+            eprintln!("{} service failed: {}", name, err);
+        }
+    });
+
+    // The sender is dropped without sending only if the server failed to bind.
+    let addr = ready_rx.await.map_err(|_| {
+        std::io::Error::other(format!("{} service stopped before it was ready", name))
+    })?;
+    Ok((addr, task))
+}
+
+/// Serves one backend on its fixed port (multi-process mode).
+async fn run_service(name: &'static str, app: RustApi, addr: &str) -> Result<(), BoxError> {
+    app.on_ready(move |addr| async move {
+        println!(" -> {} service listening on http://{}", name, addr);
+    })
+    .on_shutdown(move || async move {
+        println!(" -> {} service stopped", name);
+    })
+    .grace_period(GRACE_PERIOD)
+    .run_graceful(addr)
+    .await
+}
+
+/// Serves the gateway until Ctrl+C or SIGTERM, and returns once it has
+/// drained.
+async fn run_gateway(users: SocketAddr, orders: SocketAddr) -> Result<(), BoxError> {
+    gateway::app(Upstreams::new(users, orders))
+        .on_ready(|addr| async move {
+            println!(" -> gateway ready on http://{}", addr);
+            println!(" -> GET  http://{}/api/v1/users", addr);
+            println!(" -> GET  http://{}/api/v1/orders", addr);
+            println!(
+                " -> GET  http://{}/api/v2/users/{{user_id}}/orders  (bearer token)",
+                addr
+            );
+            println!(" -> GET  http://{}/docs", addr);
+        })
+        .on_shutdown(|| async {
+            println!(" -> gateway stopped");
+        })
+        .grace_period(GRACE_PERIOD)
+        .header_timeout(HEADER_TIMEOUT)
+        .min_request_rate(MIN_BODY_RATE, BODY_RATE_GRACE)
+        .run_graceful(GATEWAY_ADDR)
+        .await
+}
+
+#[tokio::main]
+async fn main() -> Result<(), BoxError> {
+    let role = std::env::args().nth(1).unwrap_or_else(|| "all".to_string());
+    println!("Starting microservices example ({})…", role);
+
+    match role.as_str() {
+        // Everything in this process: on_ready tells us when, and where.
+        "all" => {
+            let (stop_tx, stop_rx) = watch::channel(false);
+            let ((users, users_task), (orders, orders_task)) = tokio::try_join!(
+                spawn_service("user", users::app(), stop_rx.clone()),
+                spawn_service("order", orders::app(), stop_rx),
+            )?;
+            let result = run_gateway(users, orders).await;
+
+            // The gateway is drained: nothing calls the backends any more.
+            let _ = stop_tx.send(true);
+            let _ = tokio::join!(users_task, orders_task);
+            result
+        }
+        "users" => run_service("user", users::app(), USERS_ADDR).await,
+        "orders" => run_service("order", orders::app(), ORDERS_ADDR).await,
+        // Backends live in other processes: probe their ports instead.
+        "gateway" => {
+            let (users, orders): (SocketAddr, SocketAddr) =
+                (USERS_ADDR.parse()?, ORDERS_ADDR.parse()?);
+            println!(" -> waiting for {} and {}…", users, orders);
+            tokio::try_join!(
+                ready::wait_until_ready(users, READY_TIMEOUT),
+                ready::wait_until_ready(orders, READY_TIMEOUT),
+            )?;
+            run_gateway(users, orders).await
+        }
+        other => Err(format!(
+            "unknown role '{}', expected all, users, orders or gateway",
+            other
+        )
+        .into()),
+    }
+}
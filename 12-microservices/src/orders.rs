@@ -0,0 +1,140 @@
+// ---------------------------------------------------------------------------
+// Order service
+// ---------------------------------------------------------------------------
+
+use crate::concurrency::{ConcurrencyLimits, RouteLoad};
+use rustapi_rs::prelude::*;
+use rustapi_rs::{errors, get, summary, tag};
+use std::time::Duration;
+
+/// Reports generated at once; more get a 503 instead of slowing the rest.
+const MAX_CONCURRENT_REPORTS: usize = 4;
+/// Stands in for the queries and rendering a real report takes.
+const REPORT_WORK: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Serialize, Deserialize, Schema)]
+pub struct Order {
+    pub id: u64,
+    pub user_id: u64,
+    pub item: String,
+}
+
+#[derive(Debug, Serialize, Schema)]
+struct Report {
+    orders: usize,
+    customers: usize,
+}
+
+fn all_orders() -> Vec<Order> {
+    vec![
+        Order {
+            id: 100,
+            user_id: 1,
+            item: "Keyboard".to_string(),
+        },
+        Order {
+            id: 101,
+            user_id: 2,
+            item: "Monitor".to_string(),
+        },
+        Order {
+            id: 102,
+            user_id: 1,
+            item: "Mouse".to_string(),
+        },
+    ]
+}
+
+#[get("/orders")]
+async fn list_orders() -> Json<Vec<Order>> {
+    Json(all_orders())
+}
+
+#[get("/orders/report")]
+#[tag("orders")]
+#[summary("Order report; slow, at most 4 at a time")]
+#[errors(503 = "Four reports are already being generated; see Retry-After")]
+async fn report() -> Json<Report> {
+    tokio::time::sleep(REPORT_WORK).await;
+    let orders = all_orders();
+    let mut customers: Vec<_> = orders.iter().map(|order| order.user_id).collect();
+    customers.sort_unstable();
+    customers.dedup();
+    Json(Report {
+        orders: orders.len(),
+        customers: customers.len(),
+    })
+}
+
+#[get("/metrics/concurrency")]
+#[tag("orders")]
+#[summary("In-flight and rejected requests per limited route")]
+async fn concurrency(State(limits): State<ConcurrencyLimits>) -> Json<Vec<RouteLoad>> {
+    Json(limits.snapshot())
+}
+
+pub fn app() -> RustApi {
+    let limits =
+        ConcurrencyLimits::new().route(http::Method::GET, "/orders/report", MAX_CONCURRENT_REPORTS);
+
+    RustApi::new()
+        .state(limits.clone())
+        .mount_route(list_orders_route())
+        .mount_route(report_route())
+        .mount_route(concurrency_route())
+        .layer(limits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustapi_core::{BodyVariant, PathParams};
+
+    async fn get(app: &RequestDispatcher, path: &str) -> Response {
+        let (parts, ()) = http::Request::get(path).body(()).unwrap().into_parts();
+        let request = Request::new(
+            parts,
+            BodyVariant::Buffered(Default::default()),
+            app.state_ref(),
+            PathParams::new(),
+        );
+        app.dispatch(request).await
+    }
+
+    // The clock is paused: the reports' sleep only ends once nothing else
+    // can run, so they hold their slots for the whole test.
+    #[tokio::test(start_paused = true)]
+    async fn other_routes_serve_while_reports_are_saturated() {
+        let app = app().request_dispatcher();
+        let limits = app.state_ref().get::<ConcurrencyLimits>().cloned().unwrap();
+
+        let reports: Vec<_> = (0..MAX_CONCURRENT_REPORTS)
+            .map(|_| {
+                let app = app.clone();
+                tokio::spawn(async move { get(&app, "/orders/report").await.status() })
+            })
+            .collect();
+        while limits.snapshot()[0].in_flight < MAX_CONCURRENT_REPORTS {
+            tokio::task::yield_now().await;
+        }
+
+        let busy = get(&app, "/orders/report").await;
+        assert_eq!(busy.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(busy.headers()[http::header::RETRY_AFTER], "1");
+
+        assert_eq!(get(&app, "/orders").await.status(), StatusCode::OK);
+        assert_eq!(
+            get(&app, "/metrics/concurrency").await.status(),
+            StatusCode::OK
+        );
+        let load = &limits.snapshot()[0];
+        assert_eq!((load.in_flight, load.rejected), (MAX_CONCURRENT_REPORTS, 1));
+
+        // The slots come back when the reports finish.
+        for report in reports {
+            assert_eq!(report.await.unwrap(), StatusCode::OK);
+        }
+        assert_eq!(limits.snapshot()[0].in_flight, 0);
+        assert_eq!(get(&app, "/orders/report").await.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,46 @@
+// ---------------------------------------------------------------------------
+// Readiness probe
+// ---------------------------------------------------------------------------
+//
+// on_ready only helps when we start the service ourselves. For a service in
+// another process (or container) the only signal is the port itself: poll it
+// until a TCP connect succeeds, or give up after a deadline.
+
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::{sleep, Instant};
+
+/// First retry delay; doubles after every failed attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(10);
+/// Upper bound for the retry delay, so a slow start is noticed quickly.
+const MAX_BACKOFF: Duration = Duration::from_millis(250);
+
+/// Resolves as soon as `addr` accepts a TCP connection.
+///
+/// Returns `ErrorKind::TimedOut` (with the last connect error in the
+/// message) if it still refuses connections after `timeout`.
+pub async fn wait_until_ready(addr: SocketAddr, timeout: Duration) -> io::Result<()> {
+    let deadline = Instant::now() + timeout;
+    let mut backoff = INITIAL_BACKOFF;
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        // A connect to a black-holed address can hang; never past the deadline.
+        let last_error = match tokio::time::timeout(remaining, TcpStream::connect(addr)).await {
+            Ok(Ok(_)) => return Ok(()),
+            Ok(Err(err)) => err.to_string(),
+            Err(_) => "connect timed out".to_string(),
+        };
+
+        if Instant::now() + backoff >= deadline {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("{} not ready after {:?}: {}", addr, timeout, last_error),
+            ));
+        }
+        sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
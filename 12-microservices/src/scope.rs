@@ -0,0 +1,200 @@
+// ---------------------------------------------------------------------------
+// Route scopes: a shared prefix and layers for a group of routes
+// ---------------------------------------------------------------------------
+//
+// RustApi::nest() prefixes a Router's routes, but a Router has no layers,
+// and a `{param}` in the prefix is taken literally. A Scope has both:
+//
+//   let orders = Scope::new().route("/orders", get(user_orders));
+//   let v2 = Scope::new()
+//       .route("/users", get(users))
+//       .nest("/users/{user_id}", orders)
+//       .layer(BearerLayer::new(token));
+//
+//   RustApi::new().scope("/api/v2", v2)
+//
+//   GET /api/v2/users                    through BearerLayer
+//   GET /api/v2/users/{user_id}/orders   through BearerLayer; the handler
+//                                        takes Path(user_id) from the prefix
+//   GET /docs                            not in the scope: no BearerLayer
+//
+// Each route is registered with its full path, so path parameters anywhere
+// in it reach the handler and show up in /docs. A scope's layers run for
+// its own routes and those of the scopes nested in it, matched by path
+// against the app's router; any other request skips them. Within a scope the
+// first `.layer()` is outermost, as on RustApi, and an enclosing scope's
+// layers run before a nested one's. All of them run inside the app's own
+// layers.
+//
+// A scope's layers can tell which route matched: the request carries a
+// ScopedRoute with its template, `/api/v2/users/{user_id}/orders`, not the
+// path with the id in it. Key per-route state on that.
+//
+// NOTE: routes take a MethodRouter, `get(handler)`. A `#[get("/x")]` route
+//       has its path fixed at compile time and can't be moved under a prefix,
+//       so /docs shows a scoped route's parameters and responses but not a
+//       `#[summary]` or `#[tag]`.
+
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_rs::prelude::*;
+use rustapi_rs::MethodRouter;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// The template of the scoped route a request matched, in the extensions of
+/// every request a scope's layers see.
+#[derive(Debug, Clone)]
+pub struct ScopedRoute(pub Arc<str>);
+
+/// A group of routes under one prefix, with layers of its own.
+#[derive(Default)]
+pub struct Scope {
+    routes: Vec<(String, MethodRouter)>,
+    scopes: Vec<(String, Scope)>,
+    layers: Vec<Arc<dyn MiddlewareLayer>>,
+}
+
+impl Scope {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a route, at `path` under the scope's prefix.
+    pub fn route(mut self, path: &str, method_router: MethodRouter) -> Self {
+        self.routes.push((path.to_string(), method_router));
+        self
+    }
+
+    /// Adds `scope` under `prefix`, which may contain `{param}`s.
+    pub fn nest(mut self, prefix: &str, scope: Scope) -> Self {
+        self.scopes.push((prefix.to_string(), scope));
+        self
+    }
+
+    /// Runs `layer` for this scope's routes, nested scopes included.
+    pub fn layer<L: MiddlewareLayer>(mut self, layer: L) -> Self {
+        self.layers.push(Arc::new(layer));
+        self
+    }
+}
+
+/// `/api` + `/users` = `/api/users`; `/api` + `/` = `/api`.
+fn join(prefix: &str, path: &str) -> String {
+    let prefix = prefix.trim_end_matches('/');
+    let path = path.trim_start_matches('/');
+    match (prefix, path) {
+        ("", "") => "/".to_string(),
+        (prefix, "") => prefix.to_string(),
+        (prefix, path) => format!("{}/{}", prefix, path),
+    }
+}
+
+/// The layers of one scope and the full paths of every route under it.
+struct Group {
+    paths: Vec<String>,
+    layers: Vec<Arc<dyn MiddlewareLayer>>,
+}
+
+/// Collects the routes of `scope` and its nested scopes with their full
+/// paths, and one Group per scope, enclosing scopes first.
+fn flatten(
+    prefix: &str,
+    scope: Scope,
+    routes: &mut Vec<(String, MethodRouter)>,
+    groups: &mut Vec<Group>,
+) {
+    let first_route = routes.len();
+    let group = groups.len();
+    groups.push(Group {
+        paths: Vec::new(),
+        layers: scope.layers,
+    });
+
+    for (path, method_router) in scope.routes {
+        routes.push((join(prefix, &path), method_router));
+    }
+    for (nested, scope) in scope.scopes {
+        flatten(&join(prefix, &nested), scope, routes, groups);
+    }
+    groups[group].paths = routes[first_route..]
+        .iter()
+        .map(|(path, _)| path.clone())
+        .collect();
+}
+
+#[derive(Clone)]
+struct ScopeLayer {
+    /// Each route's template, by the router's pattern for it.
+    matcher: Arc<matchit::Router<Arc<str>>>,
+    layers: Arc<[Arc<dyn MiddlewareLayer>]>,
+}
+
+impl MiddlewareLayer for ScopeLayer {
+    fn call(
+        &self,
+        mut req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let Ok(template) = self
+            .matcher
+            .at(req.path())
+            .map(|matched| matched.value.clone())
+        else {
+            return next(req);
+        };
+        req.extensions_mut().insert(ScopedRoute(template));
+
+        // The scope's layers, first one outermost, around the rest of the app.
+        let mut chain = next;
+        for layer in self.layers.iter().rev() {
+            let (layer, inner) = (layer.clone(), chain);
+            chain = Arc::new(move |req| layer.call(req, inner.clone()));
+        }
+        chain(req)
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+/// `RustApi::new().scope(prefix, scope)`
+pub trait ScopeExt {
+    /// Mounts every route of `scope` under `prefix`, and its layers in front
+    /// of those routes only. Panics on a route conflict, like `route()`.
+    fn scope(self, prefix: &str, scope: Scope) -> Self;
+}
+
+impl ScopeExt for RustApi {
+    fn scope(mut self, prefix: &str, scope: Scope) -> Self {
+        let (mut routes, mut groups) = (Vec::new(), Vec::new());
+        flatten(prefix, scope, &mut routes, &mut groups);
+        for (path, method_router) in routes {
+            self = self.route(&path, method_router);
+        }
+
+        // The router's own patterns, by the path each was registered with.
+        let patterns: HashMap<&str, &str> = self
+            .router()
+            .registered_routes()
+            .iter()
+            .map(|(pattern, info)| (info.path.as_str(), pattern.as_str()))
+            .collect();
+        let mut layers = Vec::new();
+        for group in groups.into_iter().filter(|group| !group.layers.is_empty()) {
+            let mut matcher = matchit::Router::new();
+            for path in &group.paths {
+                // Registered just above, so it is there and fits matchit.
+                let _ = matcher.insert(patterns[path.as_str()], Arc::from(path.as_str()));
+            }
+            layers.push(ScopeLayer {
+                matcher: Arc::new(matcher),
+                layers: group.layers.into(),
+            });
+        }
+
+        layers.into_iter().fold(self, |app, layer| app.layer(layer))
+    }
+}
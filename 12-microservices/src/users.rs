@@ -0,0 +1,45 @@
+// ---------------------------------------------------------------------------
+// User service
+// ---------------------------------------------------------------------------
+
+use rustapi_rs::get;
+use rustapi_rs::prelude::*;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Schema)]
+pub struct User {
+    pub id: u64,
+    pub name: String,
+}
+
+fn users() -> Vec<User> {
+    vec![
+        User {
+            id: 1,
+            name: "Alice".to_string(),
+        },
+        User {
+            id: 2,
+            name: "Bob".to_string(),
+        },
+    ]
+}
+
+#[get("/users")]
+async fn list_users() -> Json<Vec<User>> {
+    Json(users())
+}
+
+#[get("/users/{id}")]
+async fn get_user(Path(id): Path<u64>) -> Result<Json<User>, ApiError> {
+    users()
+        .into_iter()
+        .find(|user| user.id == id)
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found("User not found"))
+}
+
+pub fn app() -> RustApi {
+    RustApi::new()
+        .mount_route(list_users_route())
+        .mount_route(get_user_route())
+}
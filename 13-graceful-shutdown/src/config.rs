@@ -0,0 +1,247 @@
+// ---------------------------------------------------------------------------
+// Configuration
+// ---------------------------------------------------------------------------
+//
+// Every setting is read and checked at startup, and again on each SIGHUP
+// reload (src/reload.rs). A typo in DRAIN_TIMEOUT should stop the deploy, not
+// surface as odd behaviour at the first SIGTERM. All problems are collected
+// and reported together, so fixing a config is one round trip rather than
+// one per mistake.
+//
+// Settings come from the environment, or from CONFIG_FILE when it is set:
+// `KEY=value` lines, `#` comments. A key in the file wins over the same key
+// in the environment — the file is what a reload re-reads, and the
+// environment can't change under a running process. Unknown keys in the
+// file are errors, so a misspelt one isn't silently ignored.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Every key Config reads.
+const KEYS: [&str; 5] = [
+    "BIND_ADDR",
+    "DRAIN_TIMEOUT",
+    "SLOW_DURATION",
+    "ADMIN_TOKEN",
+    "PAGES_DIR",
+];
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// `BIND_ADDR`, e.g. `127.0.0.1:3000`.
+    pub bind_addr: SocketAddr,
+    /// `DRAIN_TIMEOUT`, e.g. `30s`, `500ms`, `2m`.
+    pub drain_timeout: Duration,
+    /// `SLOW_DURATION` — how long `/slow` takes.
+    pub slow_duration: Duration,
+    /// `ADMIN_TOKEN` — bearer token for `/admin/*`. Unset disables them.
+    pub admin_token: Option<String>,
+    /// `PAGES_DIR` — HTML pages that replace the built-in ones.
+    pub pages_dir: Option<PathBuf>,
+}
+
+/// One bad setting.
+#[derive(Debug)]
+pub struct ConfigError {
+    pub key: &'static str,
+    pub value: String,
+    pub reason: String,
+}
+
+/// Every bad setting found, in the order the keys are read.
+#[derive(Debug)]
+pub struct ConfigErrors(pub Vec<ConfigError>);
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid configuration ({} problem(s)):", self.0.len())?;
+        for error in &self.0 {
+            write!(f, "\n  - {}={:?}: {}", error.key, error.value, error.reason)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+/// `"30s"`, `"500ms"`, `"2m"`, or bare seconds (`"30"`).
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let number: u64 = number
+        .parse()
+        .map_err(|_| "expected a number with an optional unit, e.g. 30s, 500ms, 2m".to_string())?;
+    match unit {
+        "ms" => Ok(Duration::from_millis(number)),
+        "" | "s" => Ok(Duration::from_secs(number)),
+        "m" => number
+            .checked_mul(60)
+            .map(Duration::from_secs)
+            .ok_or_else(|| "too large".to_string()),
+        other => Err(format!("unknown unit '{}', expected ms, s or m", other)),
+    }
+}
+
+/// Reads settings through `lookup`, collecting every error instead of
+/// stopping at the first.
+struct Reader<F> {
+    lookup: F,
+    errors: Vec<ConfigError>,
+}
+
+impl<F: Fn(&str) -> Option<String>> Reader<F> {
+    /// The parsed value of `key`, `default` if unset, or `default` plus a
+    /// recorded error if the value doesn't parse or fails `check`.
+    fn read<T>(
+        &mut self,
+        key: &'static str,
+        default: T,
+        parse: impl Fn(&str) -> Result<T, String>,
+        check: impl Fn(&T) -> Result<(), String>,
+    ) -> T {
+        let Some(value) = (self.lookup)(key) else {
+            return default;
+        };
+        match parse(&value).and_then(|parsed| check(&parsed).map(|()| parsed)) {
+            Ok(parsed) => parsed,
+            Err(reason) => {
+                self.errors.push(ConfigError { key, value, reason });
+                default
+            }
+        }
+    }
+}
+
+fn positive(duration: &Duration) -> Result<(), String> {
+    if duration.is_zero() {
+        Err("must be greater than zero".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// The `KEY=value` lines of a config file. Problems are reported under
+/// `CONFIG_FILE`.
+fn read_file(path: &Path, errors: &mut Vec<ConfigError>) -> HashMap<String, String> {
+    let mut error = |reason: String| {
+        errors.push(ConfigError {
+            key: "CONFIG_FILE",
+            value: path.display().to_string(),
+            reason,
+        })
+    };
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) => {
+            error(format!("can't read it: {}", err));
+            return HashMap::new();
+        }
+    };
+
+    let mut values = HashMap::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once('=') {
+            Some((key, value)) if KEYS.contains(&key.trim()) => {
+                values.insert(key.trim().to_string(), value.trim().to_string());
+            }
+            Some((key, _)) => error(format!("line {}: unknown key {}", number + 1, key.trim())),
+            None => error(format!("line {}: expected KEY=value", number + 1)),
+        }
+    }
+    values
+}
+
+impl Config {
+    /// From `file` if given, falling back to the environment key by key.
+    pub fn load(file: Option<&Path>) -> Result<Self, ConfigErrors> {
+        let mut file_errors = Vec::new();
+        let values = file
+            .map(|path| read_file(path, &mut file_errors))
+            .unwrap_or_default();
+        let config =
+            Self::from_lookup(|key| values.get(key).cloned().or_else(|| std::env::var(key).ok()));
+
+        match config {
+            Ok(config) if file_errors.is_empty() => Ok(config),
+            Ok(_) => Err(ConfigErrors(file_errors)),
+            Err(ConfigErrors(errors)) => {
+                file_errors.extend(errors);
+                Err(ConfigErrors(file_errors))
+            }
+        }
+    }
+
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigErrors> {
+        let mut reader = Reader {
+            lookup,
+            errors: Vec::new(),
+        };
+
+        let bind_addr = reader.read(
+            "BIND_ADDR",
+            SocketAddr::from(([127, 0, 0, 1], 3000)),
+            |value| {
+                value
+                    .parse()
+                    .map_err(|_| "expected host:port, e.g. 127.0.0.1:3000".to_string())
+            },
+            |_| Ok(()),
+        );
+        let drain_timeout = reader.read(
+            "DRAIN_TIMEOUT",
+            crate::server::DEFAULT_DRAIN_TIMEOUT,
+            parse_duration,
+            positive,
+        );
+        let slow_duration = reader.read(
+            "SLOW_DURATION",
+            Duration::from_secs(35),
+            parse_duration,
+            positive,
+        );
+        let admin_token = reader.read(
+            "ADMIN_TOKEN",
+            None,
+            |value| Ok(Some(value.to_string())),
+            |token| match token {
+                Some(token) if token.len() < 16 => {
+                    Err("must be at least 16 characters".to_string())
+                }
+                _ => Ok(()),
+            },
+        );
+
+        let pages_dir = reader.read(
+            "PAGES_DIR",
+            None,
+            |value| Ok(Some(PathBuf::from(value))),
+            |dir| match dir {
+                Some(dir) if !dir.is_dir() => Err("not a directory".to_string()),
+                _ => Ok(()),
+            },
+        );
+
+        if reader.errors.is_empty() {
+            Ok(Self {
+                bind_addr,
+                drain_timeout,
+                slow_duration,
+                admin_token,
+                pages_dir,
+            })
+        } else {
+            Err(ConfigErrors(reader.errors))
+        }
+    }
+}
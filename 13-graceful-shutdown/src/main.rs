@@ -0,0 +1,380 @@
+// Run with: cargo run -p graceful-shutdown
+//           DRAIN_TIMEOUT=5s cargo run -p graceful-shutdown
+// Then: curl http://127.0.0.1:3000/slow & — and press Ctrl+C
+//
+// Lesson: graceful shutdown needs a deadline. Drain in-flight requests, but
+//         force-close whatever is still running when the drain timeout hits,
+//         and say how many requests that cut off.
+//
+// Maintenance mode: PUT /admin/maintenance (with ADMIN_TOKEN set), or
+//                   kill -USR1 <pid> to toggle it.
+// Reload:           kill -HUP <pid> re-reads CONFIG_FILE and PAGES_DIR.
+// Status pages:     every 404, 405 and 500 gets the same body, from
+//                   src/status_pages.rs; try /nope or /fail.
+
+mod config;
+mod maintenance;
+mod reload;
+mod server;
+mod status_pages;
+
+use config::Config;
+use maintenance::{MaintenanceLayer, MaintenanceSwitch};
+use reload::{Live, Pages, Reloader};
+use rustapi_openapi::{Operation, OperationModifier};
+use rustapi_rs::prelude::*;
+use rustapi_rs::{errors, get, put, summary, tag, FromRequestParts};
+use server::GracefulServer;
+use status_pages::{StatusContext, StatusResponders};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+// ---------------------------------------------------------------------------
+// Models
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Serialize, Deserialize, Schema)]
+struct MaintenanceStatus {
+    enabled: bool,
+}
+
+/// Proof the request carried `Authorization: Bearer <ADMIN_TOKEN>`.
+struct Admin;
+
+impl FromRequestParts for Admin {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        let expected = req
+            .state()
+            .get::<Live<Config>>()
+            .and_then(|config| config.get().admin_token.clone())
+            .ok_or_else(|| ApiError::forbidden("Admin endpoints are off; set ADMIN_TOKEN"))?;
+        let given = req
+            .headers()
+            .get(http::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        // Compare every byte, so the time taken doesn't leak how much matched.
+        let same = given.len() == expected.len()
+            && given
+                .bytes()
+                .zip(expected.bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0;
+        if same {
+            Ok(Admin)
+        } else {
+            Err(ApiError::unauthorized("Missing or wrong admin token"))
+        }
+    }
+}
+
+impl OperationModifier for Admin {
+    fn update_operation(_op: &mut Operation) {}
+}
+
+// ---------------------------------------------------------------------------
+// Handlers
+// ---------------------------------------------------------------------------
+
+#[get("/")]
+#[tag("demo")]
+#[summary("Answers immediately")]
+async fn index() -> &'static str {
+    "Hello! Try /slow, then stop the server."
+}
+
+#[get("/slow")]
+#[tag("demo")]
+#[summary("Takes SLOW_DURATION (35s) — longer than the default drain timeout")]
+async fn slow(State(config): State<Live<Config>>) -> &'static str {
+    let duration = config.get().slow_duration;
+    tokio::time::sleep(duration).await;
+    "Finally done."
+}
+
+#[get("/healthz")]
+#[tag("ops")]
+#[summary("Liveness; answered in maintenance mode too")]
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+#[get("/admin/maintenance")]
+#[tag("ops")]
+#[summary("Whether maintenance mode is on")]
+#[errors(401 = "Missing or wrong admin token", 403 = "ADMIN_TOKEN is not set")]
+async fn maintenance_status(
+    _admin: Admin,
+    State(switch): State<MaintenanceSwitch>,
+) -> Json<MaintenanceStatus> {
+    Json(MaintenanceStatus {
+        enabled: switch.is_on(),
+    })
+}
+
+#[put("/admin/maintenance")]
+#[tag("ops")]
+#[summary("Turn maintenance mode on or off")]
+#[errors(401 = "Missing or wrong admin token", 403 = "ADMIN_TOKEN is not set")]
+async fn set_maintenance(
+    _admin: Admin,
+    State(switch): State<MaintenanceSwitch>,
+    Json(status): Json<MaintenanceStatus>,
+) -> Json<MaintenanceStatus> {
+    if switch.set(status.enabled) != status.enabled {
+        println!("Maintenance mode {}", on_off(status.enabled));
+    }
+    Json(status)
+}
+
+#[get("/fail")]
+#[tag("demo")]
+#[summary("Always fails, to show the 500 body")]
+#[errors(500 = "Always")]
+async fn fail() -> Result<&'static str> {
+    Err(ApiError::internal("The reactor is out of flux"))
+}
+
+fn on_off(enabled: bool) -> &'static str {
+    if enabled {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Shutdown signal
+// ---------------------------------------------------------------------------
+
+/// Ctrl+C, or SIGTERM from an orchestrator.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    println!("Shutdown signal received, draining…");
+}
+
+/// SIGUSR1 toggles maintenance mode, for hosts where the admin endpoint is
+/// off or unreachable.
+#[cfg(unix)]
+fn toggle_on_sigusr1(switch: MaintenanceSwitch) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let Ok(mut sigusr1) = signal(SignalKind::user_defined1()) else {
+        eprintln!("Can't listen for SIGUSR1; use PUT /admin/maintenance");
+        return;
+    };
+    tokio::spawn(async move {
+        while sigusr1.recv().await.is_some() {
+            println!("SIGUSR1: maintenance mode {}", on_off(switch.toggle()));
+        }
+    });
+}
+
+/// SIGHUP reloads CONFIG_FILE and PAGES_DIR. A reload that fails changes
+/// nothing.
+#[cfg(unix)]
+fn reload_on_sighup(reloader: Reloader, pages: Live<Pages>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let Ok(mut sighup) = signal(SignalKind::hangup()) else {
+        eprintln!("Can't listen for SIGHUP; restart to change settings");
+        return;
+    };
+    tokio::spawn(async move {
+        while sighup.recv().await.is_some() {
+            match reloader.reload() {
+                Ok(restart) => {
+                    println!("SIGHUP: reloaded, {} page(s)", pages.get().count());
+                    for key in restart {
+                        eprintln!("SIGHUP: {key} changed; it applies after a restart");
+                    }
+                }
+                Err(err) => eprintln!("SIGHUP: reload failed, keeping the old settings: {err}"),
+            }
+        }
+    });
+}
+
+// ---------------------------------------------------------------------------
+// Status pages
+// ---------------------------------------------------------------------------
+
+/// The JSON body of every 404, 405 and 500.
+#[derive(Serialize)]
+struct StatusBody {
+    status: u16,
+    title: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_id: Option<String>,
+    help: &'static str,
+}
+
+/// `<status>.html` from PAGES_DIR, or a built-in page, for browsers; JSON for
+/// everyone else. A 5xx's message stays out: the error_id finds it in the
+/// logs.
+fn branded(ctx: &StatusContext, pages: &Live<Pages>) -> Response {
+    let title = ctx.status.canonical_reason().unwrap_or("Error");
+    if ctx.wants_html {
+        let page = pages
+            .get()
+            .get(&format!("{}.html", ctx.status.as_u16()))
+            .map(|page| page.to_string())
+            .unwrap_or_else(|| {
+                format!(
+                    "<!doctype html>\n<html>\n<head><meta charset=\"utf-8\"><title>{title}</title></head>\n\
+                     <body>\n  <h1>{} {title}</h1>\n  <p>{HELP}</p>\n</body>\n</html>",
+                    ctx.status.as_u16()
+                )
+            });
+        return Html(page).into_response();
+    }
+    Json(StatusBody {
+        status: ctx.status.as_u16(),
+        title,
+        detail: ctx
+            .message
+            .clone()
+            .filter(|_| !ctx.status.is_server_error()),
+        path: ctx.path.clone(),
+        error_id: ctx.error_id.clone(),
+        help: HELP,
+    })
+    .into_response()
+}
+
+const HELP: &str = "Stuck? Write to support@example.com.";
+
+/// Built in; a `maintenance.html` in PAGES_DIR replaces it.
+const MAINTENANCE_PAGE: &str = r#"<!doctype html>
+<html>
+<head><meta charset="utf-8"><title>Down for maintenance</title></head>
+<body>
+  <h1>Down for maintenance</h1>
+  <p>We'll be back in a few minutes.</p>
+</body>
+</html>"#;
+
+// ---------------------------------------------------------------------------
+// Entry point
+// ---------------------------------------------------------------------------
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Fail before binding anything, with every bad key listed.
+    let config_file = std::env::var_os("CONFIG_FILE").map(PathBuf::from);
+    let config = match Config::load(config_file.as_deref()) {
+        Ok(config) => config,
+        Err(errors) => {
+            eprintln!("Error: {errors}");
+            std::process::exit(1);
+        }
+    };
+    let pages = match Pages::load(config.pages_dir.as_deref()) {
+        Ok(pages) => pages,
+        Err(err) => {
+            eprintln!("Error: PAGES_DIR: {err}");
+            std::process::exit(1);
+        }
+    };
+
+    println!(
+        "Starting graceful-shutdown example (drain timeout {:?})…",
+        config.drain_timeout
+    );
+    println!(" -> GET  http://{}/", config.bind_addr);
+    println!(
+        " -> GET  http://{}/slow   ({:?})",
+        config.bind_addr, config.slow_duration
+    );
+
+    println!(" -> GET  http://{}/healthz", config.bind_addr);
+    println!(
+        " -> PUT  http://{}/admin/maintenance   ({})",
+        config.bind_addr,
+        if config.admin_token.is_some() {
+            "Bearer ADMIN_TOKEN"
+        } else {
+            "off: ADMIN_TOKEN not set"
+        }
+    );
+
+    let switch = MaintenanceSwitch::default();
+    #[cfg(unix)]
+    toggle_on_sigusr1(switch.clone());
+
+    let live_config = Live::new(config.clone());
+    let live_pages = Live::new(pages);
+    #[cfg(unix)]
+    reload_on_sighup(
+        Reloader::new(config_file, live_config.clone(), live_pages.clone()),
+        live_pages.clone(),
+    );
+
+    let status_pages = live_pages.clone();
+    let branded = move |ctx: &StatusContext| branded(ctx, &status_pages);
+    let app = RustApi::auto()
+        .state(live_config)
+        .state(switch.clone())
+        // run() puts this limit in front of every app; GracefulServer serves
+        // the dispatcher, which doesn't, so without it bodies are unbounded.
+        .layer(BodyLimitLayer::new(rustapi_core::DEFAULT_BODY_LIMIT))
+        // Next, so it also sees the responses of the layers below.
+        .layer(
+            StatusResponders::new()
+                .on(StatusCode::NOT_FOUND, branded.clone())
+                .on(StatusCode::METHOD_NOT_ALLOWED, branded.clone())
+                .on(StatusCode::INTERNAL_SERVER_ERROR, branded),
+        )
+        .layer(
+            MaintenanceLayer::new(switch)
+                .allow("/healthz")
+                .allow("/admin/maintenance")
+                .retry_after(Duration::from_secs(300))
+                .message("Down for maintenance; back within five minutes")
+                .html(move || {
+                    live_pages
+                        .get()
+                        .get("maintenance.html")
+                        .unwrap_or_else(|| MAINTENANCE_PAGE.into())
+                }),
+        );
+    let listener = TcpListener::bind(config.bind_addr).await?;
+
+    let report = GracefulServer::new(app.request_dispatcher())
+        .drain_timeout(config.drain_timeout)
+        .serve(listener, shutdown_signal())
+        .await;
+
+    println!(
+        "Shut down in {:?}: {} request(s) in flight at the signal, {} force-closed on {} connection(s)",
+        report.drain_time,
+        report.in_flight_at_signal,
+        report.forced_requests,
+        report.forced_connections,
+    );
+    Ok(())
+}
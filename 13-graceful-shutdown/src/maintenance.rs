@@ -0,0 +1,141 @@
+// ---------------------------------------------------------------------------
+// Maintenance mode
+// ---------------------------------------------------------------------------
+//
+// During a migration or an incident, the honest answer to most requests is
+// "not now, come back shortly": 503 with Retry-After. Clients and crawlers
+// back off, and load balancers see a deliberate answer rather than errors.
+//
+// MaintenanceSwitch is a shared flag; MaintenanceLayer answers every request
+// with a 503 while it's on, except for allowlisted paths:
+//
+//   /healthz              the orchestrator must keep seeing the process as
+//                         alive, or it restarts it mid-maintenance
+//   /admin/maintenance    or nothing could switch it back off
+//
+// The flag is checked per request, so flipping it takes effect on the very
+// next one; requests already past the layer finish normally.
+//
+// The 503 body is JSON by default, or an HTML page for browsers: with
+// `.html(|| page)`, a request whose Accept mentions text/html gets the page.
+// The closure runs per 503, so the page can come from something a reload
+// replaces.
+
+use http::header::{ACCEPT, RETRY_AFTER};
+use http::HeaderValue;
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_rs::prelude::*;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// On/off, shared by the layer and whatever flips it. Clone it freely.
+#[derive(Clone, Default)]
+pub struct MaintenanceSwitch(Arc<AtomicBool>);
+
+impl MaintenanceSwitch {
+    pub fn is_on(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Sets the mode; returns what it was before.
+    pub fn set(&self, on: bool) -> bool {
+        self.0.swap(on, Ordering::Relaxed)
+    }
+
+    /// Flips the mode; returns the new one.
+    pub fn toggle(&self) -> bool {
+        !self.0.fetch_xor(true, Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone)]
+pub struct MaintenanceLayer {
+    switch: MaintenanceSwitch,
+    allow: Arc<Vec<String>>,
+    retry_after: Duration,
+    message: String,
+    html: Option<Arc<dyn Fn() -> Arc<str> + Send + Sync>>,
+}
+
+impl MaintenanceLayer {
+    /// 503s while `switch` is on. Allows nothing until `.allow(...)`.
+    pub fn new(switch: MaintenanceSwitch) -> Self {
+        Self {
+            switch,
+            allow: Arc::new(Vec::new()),
+            retry_after: Duration::from_secs(120),
+            message: "Down for maintenance; try again shortly".to_string(),
+            html: None,
+        }
+    }
+
+    /// Keep serving `path` (exact match) in maintenance mode.
+    pub fn allow(mut self, path: &str) -> Self {
+        Arc::make_mut(&mut self.allow).push(path.to_string());
+        self
+    }
+
+    /// Sent as `Retry-After`, in seconds. Default 120.
+    pub fn retry_after(mut self, delay: Duration) -> Self {
+        self.retry_after = delay;
+        self
+    }
+
+    /// The `message` of the JSON error body.
+    pub fn message(mut self, message: &str) -> Self {
+        self.message = message.to_string();
+        self
+    }
+
+    /// The page for requests that accept text/html, as it is at the time.
+    pub fn html(mut self, page: impl Fn() -> Arc<str> + Send + Sync + 'static) -> Self {
+        self.html = Some(Arc::new(page));
+        self
+    }
+
+    fn unavailable(&self, wants_html: bool) -> Response {
+        let mut response = match &self.html {
+            Some(page) if wants_html => {
+                let mut response = Html(page().to_string()).into_response();
+                *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+                response
+            }
+            _ => ApiError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "maintenance",
+                self.message.clone(),
+            )
+            .into_response(),
+        };
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(self.retry_after.as_secs()));
+        response
+    }
+}
+
+impl MiddlewareLayer for MaintenanceLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        if !self.switch.is_on() || self.allow.iter().any(|path| path == req.path()) {
+            return next(req);
+        }
+        let wants_html = req
+            .headers()
+            .get(ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .is_some_and(|accept| accept.contains("text/html"));
+        let response = self.unavailable(wants_html);
+        Box::pin(async move { response })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,131 @@
+// ---------------------------------------------------------------------------
+// Reloading config and pages on SIGHUP
+// ---------------------------------------------------------------------------
+//
+// `kill -HUP <pid>` re-reads CONFIG_FILE and the pages in PAGES_DIR without a
+// restart: no dropped connections, no drain. Handlers and layers hold a
+// `Live<T>` rather than a `T` and read the current value per request:
+//
+//   async fn slow(State(config): State<Live<Config>>) -> ... {
+//       tokio::time::sleep(config.get().slow_duration).await;
+//
+// A reload is all or nothing. The new config is validated exactly as at
+// startup and every page is read; if anything fails, the error is logged
+// and the old config and pages stay in place, untouched.
+//
+// Hot-reloadable: SLOW_DURATION, ADMIN_TOKEN, PAGES_DIR and the pages in it.
+// BIND_ADDR and DRAIN_TIMEOUT are fixed once the listener and server exist;
+// a reload that changes them says so and keeps the running values.
+//
+// Requests already running keep the `Arc` they read; the swap only affects
+// the ones that read after it.
+
+use crate::config::Config;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// A value that a reload can replace. Clone it freely.
+pub struct Live<T>(Arc<RwLock<Arc<T>>>);
+
+impl<T> Clone for Live<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Live<T> {
+    pub fn new(value: T) -> Self {
+        Self(Arc::new(RwLock::new(Arc::new(value))))
+    }
+
+    /// The current value. Cheap: an `Arc` clone under a read lock.
+    pub fn get(&self) -> Arc<T> {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn set(&self, value: T) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(value);
+    }
+}
+
+/// The `*.html` files of PAGES_DIR, by file name.
+#[derive(Default)]
+pub struct Pages(HashMap<String, Arc<str>>);
+
+impl Pages {
+    /// Reads every `*.html` in `dir`; none at all without one. A page that
+    /// can't be read, isn't UTF-8 or is empty fails the whole load.
+    pub fn load(dir: Option<&Path>) -> Result<Self, String> {
+        let Some(dir) = dir else {
+            return Ok(Self::default());
+        };
+        let entries =
+            std::fs::read_dir(dir).map_err(|err| format!("{}: {}", dir.display(), err))?;
+
+        let mut pages = HashMap::new();
+        for entry in entries {
+            let path = entry
+                .map_err(|err| format!("{}: {}", dir.display(), err))?
+                .path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("html") {
+                continue;
+            }
+            let page = std::fs::read_to_string(&path)
+                .map_err(|err| format!("{}: {}", path.display(), err))?;
+            if page.trim().is_empty() {
+                return Err(format!("{}: empty page", path.display()));
+            }
+            let name = path.file_name().and_then(|name| name.to_str());
+            pages.insert(name.unwrap_or_default().to_string(), page.into());
+        }
+        Ok(Self(pages))
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<str>> {
+        self.0.get(name).cloned()
+    }
+
+    pub fn count(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// Re-reads CONFIG_FILE and PAGES_DIR into the app's live values.
+pub struct Reloader {
+    file: Option<PathBuf>,
+    config: Live<Config>,
+    pages: Live<Pages>,
+}
+
+impl Reloader {
+    pub fn new(file: Option<PathBuf>, config: Live<Config>, pages: Live<Pages>) -> Self {
+        Self {
+            file,
+            config,
+            pages,
+        }
+    }
+
+    /// Loads and applies everything, or nothing. Returns what needs a
+    /// restart to change, if anything asked to.
+    pub fn reload(&self) -> Result<Vec<&'static str>, String> {
+        let mut next = Config::load(self.file.as_deref()).map_err(|err| err.to_string())?;
+        let pages = Pages::load(next.pages_dir.as_deref())?;
+
+        let current = self.config.get();
+        let mut restart = Vec::new();
+        if next.bind_addr != current.bind_addr {
+            restart.push("BIND_ADDR");
+        }
+        if next.drain_timeout != current.drain_timeout {
+            restart.push("DRAIN_TIMEOUT");
+        }
+        next.bind_addr = current.bind_addr;
+        next.drain_timeout = current.drain_timeout;
+
+        self.config.set(next);
+        self.pages.set(pages);
+        Ok(restart)
+    }
+}
@@ -0,0 +1,184 @@
+// ---------------------------------------------------------------------------
+// Graceful shutdown with a drain deadline
+// ---------------------------------------------------------------------------
+//
+// On shutdown we stop accepting, let every open connection finish the request
+// it is on (idle keep-alive connections close straight away), and wait. But
+// never longer than the drain timeout: a stuck handler must not hold a
+// deploy hostage. Whatever is still running then is aborted and counted.
+
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper_util::rt::TokioIo;
+use rustapi_core::{BodyVariant, PathParams};
+use rustapi_rs::prelude::*;
+use std::convert::Infallible;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+
+/// Long enough for any sane request, short enough for a rolling deploy.
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What happened during shutdown.
+#[derive(Debug)]
+pub struct ShutdownReport {
+    /// Requests in flight when the signal arrived.
+    pub in_flight_at_signal: usize,
+    /// Requests still running at the deadline, aborted mid-handler.
+    pub forced_requests: usize,
+    /// Connections closed by force rather than gracefully.
+    pub forced_connections: usize,
+    /// Time from the signal until the last connection was gone.
+    pub drain_time: Duration,
+}
+
+pub struct GracefulServer {
+    dispatcher: RequestDispatcher,
+    drain_timeout: Duration,
+}
+
+/// Counts a request as in flight for as long as it lives — including when its
+/// task is aborted, since drop still runs.
+struct InFlight(Arc<AtomicUsize>);
+
+impl InFlight {
+    fn start(counter: &Arc<AtomicUsize>) -> Self {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Self(counter.clone())
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl GracefulServer {
+    pub fn new(dispatcher: RequestDispatcher) -> Self {
+        Self {
+            dispatcher,
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+        }
+    }
+
+    /// Longest time to wait for in-flight requests once shutdown starts.
+    pub fn drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = timeout;
+        self
+    }
+
+    /// Serves until `signal` resolves, then drains and reports.
+    pub async fn serve<F>(self, listener: TcpListener, signal: F) -> ShutdownReport
+    where
+        F: Future<Output = ()>,
+    {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let mut connections = JoinSet::new();
+
+        tokio::pin!(signal);
+        loop {
+            tokio::select! {
+                _ = &mut signal => break,
+                // Reap finished connections so the set doesn't grow forever.
+                Some(_) = connections.join_next(), if !connections.is_empty() => {}
+                accepted = listener.accept() => {
+                    let stream = match accepted {
+                        Ok((stream, _)) => stream,
+                        Err(e) => {
+                            error!("Accept error: {}", e);
+                            continue;
+                        }
+                    };
+                    let _ = stream.set_nodelay(true);
+                    connections.spawn(serve_connection(
+                        stream,
+                        self.dispatcher.clone(),
+                        in_flight.clone(),
+                        shutdown_rx.clone(),
+                    ));
+                }
+            }
+        }
+
+        // Stop accepting; new connections are refused from here on.
+        drop(listener);
+        let started = Instant::now();
+        let in_flight_at_signal = in_flight.load(Ordering::SeqCst);
+        let _ = shutdown_tx.send(true);
+
+        let drained = tokio::time::timeout(self.drain_timeout, async {
+            while connections.join_next().await.is_some() {}
+        })
+        .await;
+
+        let (forced_requests, forced_connections) = match drained {
+            Ok(()) => (0, 0),
+            Err(_) => {
+                let forced_requests = in_flight.load(Ordering::SeqCst);
+                let forced_connections = connections.len();
+                connections.abort_all();
+                while connections.join_next().await.is_some() {}
+                (forced_requests, forced_connections)
+            }
+        };
+
+        ShutdownReport {
+            in_flight_at_signal,
+            forced_requests,
+            forced_connections,
+            drain_time: started.elapsed(),
+        }
+    }
+}
+
+async fn serve_connection(
+    stream: tokio::net::TcpStream,
+    dispatcher: RequestDispatcher,
+    in_flight: Arc<AtomicUsize>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let service = service_fn(move |req: hyper::Request<Incoming>| {
+        let dispatcher = dispatcher.clone();
+        let guard = InFlight::start(&in_flight);
+        async move {
+            let _guard = guard;
+            let (parts, body) = req.into_parts();
+            let request = Request::new(
+                parts,
+                BodyVariant::Streaming(body),
+                dispatcher.state_ref(),
+                PathParams::new(),
+            );
+            Ok::<_, Infallible>(dispatcher.dispatch(request).await)
+        }
+    });
+
+    let conn = http1::Builder::new()
+        .keep_alive(true)
+        .serve_connection(TokioIo::new(stream), service);
+    tokio::pin!(conn);
+
+    let result = tokio::select! {
+        result = conn.as_mut() => result,
+        _ = shutdown.changed() => {
+            // Finish the current request (if any), answer it with
+            // `Connection: close`, then stop.
+            conn.as_mut().graceful_shutdown();
+            conn.await
+        }
+    };
+
+    if let Err(err) = result {
+        if !err.is_incomplete_message() {
+            error!("Connection error: {}", err);
+        }
+    }
+}
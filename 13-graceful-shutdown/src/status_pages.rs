@@ -0,0 +1,157 @@
+// ---------------------------------------------------------------------------
+// One body per status, app-wide
+// ---------------------------------------------------------------------------
+//
+// A handler that returns a bare StatusCode sends no body at all, and the
+// router's 404 and 405 send rustapi's generic `{"error":{...}}`. A fallback
+// route only covers the 404 for an unknown path. StatusResponders covers a
+// status wherever it comes from:
+//
+//   RustApi::auto().layer(
+//       StatusResponders::new()
+//           .on(StatusCode::NOT_FOUND, |ctx| page_or_json(ctx, "404.html"))
+//           .on(StatusCode::INTERNAL_SERVER_ERROR, |ctx| ...),
+//   )
+//
+// A response with a registered status gets the responder's body when its
+// own is
+//
+//   empty                     `StatusCode::NOT_FOUND` from a handler
+//   rustapi's error JSON      the router's 404/405, `ApiError`s, extractor
+//                             rejections; its message and error_id are
+//                             passed on in the StatusContext
+//
+// Any other body is the handler's own, and goes out as it is. The status
+// stays; so do the original headers (Allow, Retry-After, WWW-Authenticate),
+// except the body's Content-Type and Content-Length.
+//
+// The responder gets a StatusContext, with whether the client asked for
+// HTML, and returns any Response: JSON, an Html page, a rendered template.
+//
+// NOTE: responses made by layers outside this one (added before it) aren't
+//       seen. Add it first, so it wraps them all.
+
+use http::header::{ACCEPT, CONTENT_LENGTH, CONTENT_TYPE};
+use http_body::Body as _;
+use http_body_util::BodyExt;
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_rs::prelude::*;
+use rustapi_rs::ResponseBody;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// Error bodies larger than this are not read for their message; rustapi's
+/// are well under it.
+const MAX_ERROR_BODY: u64 = 16 * 1024;
+
+/// What a responder knows about the response it replaces.
+#[derive(Debug, Clone)]
+pub struct StatusContext {
+    pub status: StatusCode,
+    pub path: String,
+    /// The error's message, when the body was rustapi's error JSON.
+    pub message: Option<String>,
+    pub error_id: Option<String>,
+    /// The request's Accept mentions text/html.
+    pub wants_html: bool,
+}
+
+type Responder = Arc<dyn Fn(&StatusContext) -> Response + Send + Sync>;
+
+#[derive(Clone, Default)]
+pub struct StatusResponders {
+    responders: Arc<HashMap<StatusCode, Responder>>,
+}
+
+impl StatusResponders {
+    /// No statuses yet; every response goes out as it is.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bodies for `status` come from `responder`. Registering a status again
+    /// replaces its responder.
+    pub fn on(
+        mut self,
+        status: StatusCode,
+        responder: impl Fn(&StatusContext) -> Response + Send + Sync + 'static,
+    ) -> Self {
+        Arc::make_mut(&mut self.responders).insert(status, Arc::new(responder));
+        self
+    }
+}
+
+/// `(message, error_id)` if `body` is rustapi's error JSON, else None.
+fn rustapi_error(body: &[u8]) -> Option<(Option<String>, Option<String>)> {
+    let value: serde_json::Value = serde_json::from_slice(body).ok()?;
+    let error = value.get("error")?.as_object()?;
+    let text = |value: Option<&serde_json::Value>| value?.as_str().map(str::to_string);
+    Some((text(error.get("message")), text(value.get("error_id"))))
+}
+
+impl MiddlewareLayer for StatusResponders {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let responders = self.responders.clone();
+        let path = req.path().to_string();
+        let wants_html = req
+            .headers()
+            .get(ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .is_some_and(|accept| accept.contains("text/html"));
+
+        Box::pin(async move {
+            let response = next(req).await;
+            let Some(responder) = responders.get(&response.status()).cloned() else {
+                return response;
+            };
+            let readable = response
+                .body()
+                .size_hint()
+                .exact()
+                .is_some_and(|length| length <= MAX_ERROR_BODY);
+            if !readable {
+                return response;
+            }
+
+            let (parts, body) = response.into_parts();
+            let body = match body.collect().await {
+                Ok(collected) => collected.to_bytes(),
+                Err(err) => return err.into_response(),
+            };
+            let (message, error_id) = if body.is_empty() {
+                (None, None)
+            } else if let Some(found) = rustapi_error(&body) {
+                found
+            } else {
+                // The handler's own body: keep it.
+                return Response::from_parts(parts, ResponseBody::new(body));
+            };
+
+            let mut replaced = responder(&StatusContext {
+                status: parts.status,
+                path,
+                message,
+                error_id,
+                wants_html,
+            });
+            *replaced.status_mut() = parts.status;
+            let own: Vec<_> = replaced.headers().keys().cloned().collect();
+            for (name, value) in &parts.headers {
+                if name != CONTENT_TYPE && name != CONTENT_LENGTH && !own.contains(name) {
+                    replaced.headers_mut().append(name, value.clone());
+                }
+            }
+            replaced
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
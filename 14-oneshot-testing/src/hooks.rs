@@ -0,0 +1,87 @@
+// ---------------------------------------------------------------------------
+// Request and response hooks
+// ---------------------------------------------------------------------------
+//
+// Stamping one header or logging one field shouldn't need a MiddlewareLayer
+// impl with boxed futures, or a RequestInterceptor struct with clone_box. A
+// hook is a plain function:
+//
+//   .before_request(|req| debug!("{} {}", req.method(), req.path()))
+//   .after_response(|res| { res.headers_mut().insert(...); })
+//
+// Hooks are RustAPI's interceptors, so they sit outside every layer, no
+// matter where in the builder they're written:
+//
+//   before_request hooks   in the order added
+//   layers                 first added is outermost
+//   router, handler
+//   layers
+//   after_response hooks   in reverse order added
+//
+// So an after_response hook also sees what a layer answered by itself: CORS
+// preflights, 413s from the body limit, 429s from a rate limiter. It can't
+// be slotted *between* two layers; that still takes a layer.
+//
+// NOTE: hooks are synchronous. Request only lends out its extensions
+//       mutably; headers, method and URI are read-only there.
+
+use rustapi_core::interceptor::{RequestInterceptor, ResponseInterceptor};
+use rustapi_rs::prelude::*;
+use std::sync::Arc;
+
+#[derive(Clone)]
+struct BeforeRequest(Arc<dyn Fn(&mut Request) + Send + Sync>);
+
+#[derive(Clone)]
+struct AfterResponse(Arc<dyn Fn(&mut Response) + Send + Sync>);
+
+/// Builder-style sugar: `RustApi::auto().after_response(|res| ...)`.
+pub trait HooksExt {
+    /// Runs `hook` on every request, before any layer.
+    fn before_request<F>(self, hook: F) -> Self
+    where
+        F: Fn(&mut Request) + Send + Sync + 'static;
+
+    /// Runs `hook` on every response, after every layer.
+    fn after_response<F>(self, hook: F) -> Self
+    where
+        F: Fn(&mut Response) + Send + Sync + 'static;
+}
+
+impl HooksExt for RustApi {
+    fn before_request<F>(self, hook: F) -> Self
+    where
+        F: Fn(&mut Request) + Send + Sync + 'static,
+    {
+        self.request_interceptor(BeforeRequest(Arc::new(hook)))
+    }
+
+    fn after_response<F>(self, hook: F) -> Self
+    where
+        F: Fn(&mut Response) + Send + Sync + 'static,
+    {
+        self.response_interceptor(AfterResponse(Arc::new(hook)))
+    }
+}
+
+impl RequestInterceptor for BeforeRequest {
+    fn intercept(&self, mut request: Request) -> Request {
+        (self.0)(&mut request);
+        request
+    }
+
+    fn clone_box(&self) -> Box<dyn RequestInterceptor> {
+        Box::new(self.clone())
+    }
+}
+
+impl ResponseInterceptor for AfterResponse {
+    fn intercept(&self, mut response: Response) -> Response {
+        (self.0)(&mut response);
+        response
+    }
+
+    fn clone_box(&self) -> Box<dyn ResponseInterceptor> {
+        Box::new(self.clone())
+    }
+}
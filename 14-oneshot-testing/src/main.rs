@@ -0,0 +1,281 @@
+// Run with: cargo test -p oneshot-testing   (run the tests)
+//           cargo run -p oneshot-testing    (serve the same app)
+// Then visit: http://127.0.0.1:3000/docs
+//
+// Lesson: the app is a function from request to response. `oneshot` calls it
+//         with a hand-built `http::Request`, so tests can pin down exact
+//         headers — CORS preflights, 405s and their `Allow` list — without
+//         binding a port.
+
+#[cfg(test)]
+mod fuzz;
+mod hooks;
+#[cfg(test)]
+mod oneshot;
+mod path_syntax;
+mod routes;
+mod scoped_cors;
+
+use hooks::HooksExt;
+use http::{HeaderValue, Method};
+use path_syntax::NormalizedRoutes;
+use routes::RouteTable;
+use rustapi_rs::prelude::*;
+use rustapi_rs::{errors, get, post, summary, tag};
+use scoped_cors::ScopedCors;
+
+const FRONTEND: &str = "https://app.example.com";
+
+/// Stamped on every response by an `after_response` hook.
+const APP_VERSION: &str = "x-app-version";
+
+// ---------------------------------------------------------------------------
+// App
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Serialize, Deserialize, Schema)]
+struct Item {
+    name: String,
+}
+
+#[get("/items")]
+#[tag("items")]
+#[summary("List items")]
+async fn list_items() -> Json<Vec<Item>> {
+    Json(vec![Item {
+        name: "pen".to_string(),
+    }])
+}
+
+#[post("/items")]
+#[tag("items")]
+#[summary("Create an item")]
+#[errors(400 = "Body is not a JSON item")]
+async fn create_item(Json(item): Json<Item>) -> Created<Item> {
+    Created(item)
+}
+
+#[get("/public/catalog")]
+#[tag("public")]
+#[summary("Public catalog, readable from any origin")]
+async fn catalog() -> Json<Vec<Item>> {
+    list_items().await
+}
+
+/// Registered with `.route_normalized`, from a table written for a framework
+/// that uses `:name` parameters.
+async fn item_at(Path(index): Path<usize>) -> Result<Json<Item>, ApiError> {
+    list_items()
+        .await
+        .0
+        .into_iter()
+        .nth(index)
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found(format!("No item at {index}")))
+}
+
+/// The app under test — built the same way for `main` and for the tests.
+///
+/// Only our frontend may call the app, except `/public/*`, which anyone may
+/// read. Every response, preflights included, carries `x-app-version`.
+fn app() -> RustApi {
+    let strict = CorsLayer::new()
+        .allow_origins([FRONTEND])
+        .allow_methods([Method::GET, Method::POST])
+        .allow_headers(["content-type"]);
+
+    RustApi::auto()
+        .route_normalized("/items/:index", get(item_at))
+        .before_request(|req| debug!("{} {}", req.method(), req.path()))
+        // Hooks run outside every layer, so preflights that CORS answers by
+        // itself are stamped too.
+        .after_response(|res| {
+            res.headers_mut().insert(
+                APP_VERSION,
+                HeaderValue::from_static(env!("CARGO_PKG_VERSION")),
+            );
+        })
+        .layer(ScopedCors::new(strict).scope("/public", CorsLayer::permissive()))
+}
+
+// ---------------------------------------------------------------------------
+// Entry point
+// ---------------------------------------------------------------------------
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let app = app();
+    println!("Starting oneshot-testing example on http://127.0.0.1:3000…");
+    // The banner is the route table, so it can't go stale.
+    for route in app.routes() {
+        println!(" -> {route}");
+    }
+    app.run("127.0.0.1:3000").await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fuzz::fuzz_handler;
+    use crate::oneshot::Oneshot;
+    use http::header::{
+        ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_REQUEST_METHOD,
+        ALLOW, CONTENT_TYPE, ORIGIN,
+    };
+
+    fn preflight(path: &str, origin: &str, method: &str) -> http::Request<&'static str> {
+        http::Request::builder()
+            .method(Method::OPTIONS)
+            .uri(path)
+            .header(ORIGIN, origin)
+            .header(ACCESS_CONTROL_REQUEST_METHOD, method)
+            .body("")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn preflight_is_answered() {
+        let app = app().request_dispatcher();
+        let response = app
+            .oneshot(preflight("/items", FRONTEND, "POST"))
+            .await
+            .unwrap();
+        let headers = response.headers();
+
+        assert!(response.status().is_success(), "{}", response.status());
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_ORIGIN], FRONTEND);
+        let methods = headers[ACCESS_CONTROL_ALLOW_METHODS].to_str().unwrap();
+        assert!(methods.split(',').any(|m| m.trim() == "POST"), "{methods}");
+    }
+
+    #[tokio::test]
+    async fn foreign_origin_gets_no_cors_headers() {
+        let app = app().request_dispatcher();
+        let request = http::Request::builder()
+            .uri("/items")
+            .header(ORIGIN, "https://evil.example")
+            .body("")
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+
+        assert!(!response.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+
+    #[tokio::test]
+    async fn public_preflight_allows_any_origin() {
+        let app = app().request_dispatcher();
+        let request = preflight("/public/catalog", "https://elsewhere.example", "GET");
+        let response = app.oneshot(request).await.unwrap();
+
+        assert_eq!(response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+    }
+
+    #[tokio::test]
+    async fn strict_preflight_rejects_a_foreign_origin() {
+        let app = app().request_dispatcher();
+        let request = preflight("/items", "https://elsewhere.example", "GET");
+        let response = app.oneshot(request).await.unwrap();
+
+        assert!(!response.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+
+    #[tokio::test]
+    async fn wrong_method_is_405_with_allow() {
+        let app = app().request_dispatcher();
+        let request = http::Request::builder()
+            .method(Method::DELETE)
+            .uri("/items")
+            .body("")
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        // The order of `Allow` isn't specified; compare it sorted.
+        let mut allow: Vec<_> = response.headers()[ALLOW]
+            .to_str()
+            .unwrap()
+            .split(',')
+            .map(str::trim)
+            .collect();
+        allow.sort_unstable();
+        assert_eq!(allow, ["GET", "POST"]);
+    }
+
+    #[tokio::test]
+    async fn create_echoes_the_body() {
+        let app = app().request_dispatcher();
+        let request = http::Request::builder()
+            .method(Method::POST)
+            .uri("/items")
+            .header(CONTENT_TYPE, "application/json")
+            .body(r#"{"name":"ink"}"#)
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.body().as_ref(), br#"{"name":"ink"}"#);
+    }
+
+    #[tokio::test]
+    async fn colon_path_is_normalized() {
+        let routes = app().routes();
+        assert!(
+            routes.iter().any(|route| route.path == "/items/{index}"),
+            "{routes:?}"
+        );
+
+        let app = app().request_dispatcher();
+        let response = app
+            .oneshot(http::Request::get("/items/0").body("").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn every_response_is_stamped() {
+        let app = app().request_dispatcher();
+        let wrong_method = http::Request::builder()
+            .method(Method::DELETE)
+            .uri("/items")
+            .body("")
+            .unwrap();
+
+        for request in [preflight("/items", FRONTEND, "POST"), wrong_method] {
+            let what = format!("{} {}", request.method(), request.uri());
+            let response = app.oneshot(request).await.unwrap();
+            assert_eq!(
+                response.headers().get(APP_VERSION).map(|v| v.as_bytes()),
+                Some(env!("CARGO_PKG_VERSION").as_bytes()),
+                "{what}"
+            );
+        }
+    }
+
+    #[test]
+    fn every_handler_is_registered() {
+        // Catches a handler that auto() didn't pick up, e.g. one in a module
+        // that was never linked in.
+        let routes = app().routes();
+        for (method, path) in [
+            (Method::GET, "/items"),
+            (Method::POST, "/items"),
+            (Method::GET, "/public/catalog"),
+        ] {
+            assert!(
+                routes
+                    .iter()
+                    .any(|route| route.method == method && route.path == path),
+                "{method} {path} not in {routes:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn create_survives_fuzzing() {
+        // Fails with FUZZ_SEED and the offending request; rerun with that seed.
+        if let Err(err) = fuzz_handler(&app(), "POST /items").await {
+            panic!("{err}");
+        }
+    }
+}
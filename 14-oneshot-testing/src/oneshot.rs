@@ -0,0 +1,64 @@
+// ---------------------------------------------------------------------------
+// One request in, one response out
+// ---------------------------------------------------------------------------
+//
+// The lowest-level way to exercise an app: no socket, no client, no
+// serialization helpers. Build an `http::Request` by hand — any method, any
+// headers, byte-exact body — push it through the same pipeline a server
+// would (interceptors, layers, router, handler) and get the raw
+// `http::Response` back with its body collected.
+//
+// Accepted:  http::Request<B> where B: Into<Bytes>
+//            (Bytes, Vec<u8>, String, &'static str — "" for no body).
+//            Method, URI (path and query; scheme/authority optional),
+//            version, headers and extensions are passed through as-is.
+// Returned:  http::Response<Bytes> — status, headers and the whole body.
+//
+// NOTE: the body is read to the end before oneshot() returns. An endless
+//       stream (an SSE feed without a timeout) never completes; test those
+//       against a real server.
+
+use bytes::Bytes;
+use http_body_util::BodyExt;
+use rustapi_core::{BodyVariant, PathParams};
+use rustapi_rs::prelude::*;
+use std::future::Future;
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+pub trait Oneshot {
+    /// Dispatches `request` in-process and collects the response.
+    ///
+    /// Fails only if the response body stream itself errors.
+    fn oneshot<B>(
+        &self,
+        request: http::Request<B>,
+    ) -> impl Future<Output = Result<http::Response<Bytes>, BoxError>> + Send
+    where
+        B: Into<Bytes>;
+}
+
+impl Oneshot for RequestDispatcher {
+    fn oneshot<B>(
+        &self,
+        request: http::Request<B>,
+    ) -> impl Future<Output = Result<http::Response<Bytes>, BoxError>> + Send
+    where
+        B: Into<Bytes>,
+    {
+        let (parts, body) = request.into_parts();
+        let request = Request::new(
+            parts,
+            BodyVariant::Buffered(body.into()),
+            self.state_ref(),
+            PathParams::new(),
+        );
+        let response = self.dispatch(request);
+
+        async move {
+            let (parts, body) = response.await.into_parts();
+            let bytes = body.collect().await?.to_bytes();
+            Ok(http::Response::from_parts(parts, bytes))
+        }
+    }
+}
@@ -0,0 +1,63 @@
+// ---------------------------------------------------------------------------
+// `:id` route paths
+// ---------------------------------------------------------------------------
+//
+// RustAPI writes path parameters as `{id}`. Express, Rails and axum 0.7
+// write `:id`, and a path copied from one of them behaves differently
+// depending on where it lands:
+//
+//   #[get("/users/:id")]            compile error: invalid character ':'
+//   .route("/users/:id", get(h))    matches, but is listed as `/users/:id`
+//                                   and looks like a typo next to `{id}`
+//
+// normalize() rewrites every `:name` segment to `{name}`, and
+// `.route_normalized(...)` registers through it, so ported route tables can
+// keep their syntax and still end up consistent.
+//
+// NOTE: only whole segments are rewritten. A colon inside a segment, as in
+//       `/v1/books:batch` (Google-style custom methods), is literal text.
+
+use rustapi_rs::prelude::*;
+use rustapi_rs::MethodRouter;
+use std::borrow::Cow;
+
+/// `/users/:id/posts/:post_id` → `/users/{id}/posts/{post_id}`. Paths with
+/// no `:name` segments are returned as they are.
+pub fn normalize(path: &str) -> Cow<'_, str> {
+    let is_param = |segment: &str| {
+        segment.strip_prefix(':').is_some_and(|name| {
+            name.chars().next().is_some_and(|c| !c.is_ascii_digit())
+                && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+        })
+    };
+    if !path.split('/').any(is_param) {
+        return Cow::Borrowed(path);
+    }
+
+    let segments: Vec<String> = path
+        .split('/')
+        .map(|segment| {
+            if is_param(segment) {
+                format!("{{{}}}", &segment[1..])
+            } else {
+                segment.to_string()
+            }
+        })
+        .collect();
+    Cow::Owned(segments.join("/"))
+}
+
+/// Builder-style sugar: `.route_normalized("/users/:id", get(handler))`.
+pub trait NormalizedRoutes {
+    fn route_normalized(self, path: &str, method_router: MethodRouter) -> Self;
+}
+
+impl NormalizedRoutes for RustApi {
+    fn route_normalized(self, path: &str, method_router: MethodRouter) -> Self {
+        let normalized = normalize(path);
+        if normalized != path {
+            debug!("Route {} registered as {}", path, normalized);
+        }
+        self.route(&normalized, method_router)
+    }
+}
@@ -0,0 +1,93 @@
+// ---------------------------------------------------------------------------
+// What's registered
+// ---------------------------------------------------------------------------
+//
+// `app.routes()` lists every method and path the app answers, before run():
+//
+//   GET     /docs                -
+//   GET     /docs/openapi.json   -
+//   GET     /items               List items
+//   POST    /items               Create an item
+//   GET     /public/catalog      Public catalog, readable from any origin
+//
+// The router knows every path and its methods — `#[get]` routes found by
+// auto(), `.route(...)` calls and the docs alike. The spec adds each
+// operation's summary, where there is one.
+//
+// NOTE: a route doesn't keep its handler's function name; only the path,
+//       method and OpenAPI operation survive registration. The summary is
+//       the closest thing to a name, so give handlers a #[summary].
+
+use http::Method;
+use rustapi_openapi::{Operation, PathItem};
+use rustapi_rs::prelude::*;
+use std::fmt;
+
+/// One method on one path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteEntry {
+    pub method: Method,
+    /// The template, as registered: `/users/{id}`.
+    pub path: String,
+    /// The operation's `summary` in the spec, if it has one.
+    pub summary: Option<String>,
+}
+
+impl fmt::Display for RouteEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<7} {:<20} {}",
+            self.method.as_str(),
+            self.path,
+            self.summary.as_deref().unwrap_or("-")
+        )
+    }
+}
+
+pub trait RouteTable {
+    /// Every registered method and path, sorted by path then method.
+    fn routes(&self) -> Vec<RouteEntry>;
+}
+
+impl RouteTable for RustApi {
+    fn routes(&self) -> Vec<RouteEntry> {
+        let spec = self.openapi_spec();
+        let mut routes: Vec<RouteEntry> = self
+            .router()
+            .registered_routes()
+            .values()
+            .flat_map(|info| {
+                info.methods.iter().map(|method| {
+                    let summary = spec
+                        .paths
+                        .get(&info.path)
+                        .and_then(|item| operation(item, method))
+                        .and_then(|op| op.summary.clone());
+                    RouteEntry {
+                        method: method.clone(),
+                        path: info.path.clone(),
+                        summary,
+                    }
+                })
+            })
+            .collect();
+        routes.sort_by(|a, b| {
+            (a.path.as_str(), a.method.as_str()).cmp(&(b.path.as_str(), b.method.as_str()))
+        });
+        routes
+    }
+}
+
+fn operation<'a>(item: &'a PathItem, method: &Method) -> Option<&'a Operation> {
+    match *method {
+        Method::GET => item.get.as_ref(),
+        Method::POST => item.post.as_ref(),
+        Method::PUT => item.put.as_ref(),
+        Method::PATCH => item.patch.as_ref(),
+        Method::DELETE => item.delete.as_ref(),
+        Method::HEAD => item.head.as_ref(),
+        Method::OPTIONS => item.options.as_ref(),
+        _ => None,
+    }
+}
@@ -0,0 +1,74 @@
+// ---------------------------------------------------------------------------
+// CORS per route group
+// ---------------------------------------------------------------------------
+//
+// `RustApi::layer` applies to every route, and a CorsLayer answers preflights
+// itself, before routing. An app whose `/public/*` may be read from anywhere
+// but whose other routes only serve its own frontend needs more than one
+// policy, picked by path.
+//
+// ScopedCors holds a default policy and one policy per path prefix. Every
+// request — the preflight included — is handed to the policy with the longest
+// matching prefix. That is what makes preflights come out right: a browser
+// sends `OPTIONS` to the *same URL* the real request will hit, so the
+// preflight and the request it announces always land in the same scope.
+//
+// Prefixes match whole segments: `/public` covers `/public` and
+// `/public/catalog`, not `/publications`.
+
+use rustapi_core::middleware::{BoxedNext, MiddlewareLayer};
+use rustapi_rs::prelude::*;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct ScopedCors {
+    default: CorsLayer,
+    /// Longest prefix first, so the first match is the most specific.
+    scopes: Arc<Vec<(String, CorsLayer)>>,
+}
+
+impl ScopedCors {
+    /// `default` applies to every path no scope claims.
+    pub fn new(default: CorsLayer) -> Self {
+        Self {
+            default,
+            scopes: Arc::new(Vec::new()),
+        }
+    }
+
+    /// Use `policy` for `prefix` and everything below it.
+    pub fn scope(mut self, prefix: &str, policy: CorsLayer) -> Self {
+        let prefix = prefix.trim_end_matches('/').to_string();
+        let scopes = Arc::make_mut(&mut self.scopes);
+        scopes.push((prefix, policy));
+        scopes.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        self
+    }
+
+    /// The policy for `path`.
+    fn policy(&self, path: &str) -> &CorsLayer {
+        self.scopes
+            .iter()
+            .find(|(prefix, _)| {
+                path.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .map_or(&self.default, |(_, policy)| policy)
+    }
+}
+
+impl MiddlewareLayer for ScopedCors {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        self.policy(req.path()).call(req, next)
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,401 @@
+// Run with: cargo run -p file-upload
+// Then visit: http://127.0.0.1:3000/ (upload form) or /docs
+//
+// Lesson: an upload is streamed, not buffered. Parts are read off the
+//         connection as they arrive, files go to disk chunk by chunk, and a
+//         part or body over its limit is a 413 the moment it crosses it.
+//         A file whose first bytes contradict its Content-Type is a 422.
+
+mod multipart;
+mod sniff;
+
+use multipart::Multipart;
+use rustapi_rs::prelude::*;
+use rustapi_rs::{errors, get, post, summary, tag};
+use sniff::FileTypes;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::AsyncWriteExt;
+
+// ---------------------------------------------------------------------------
+// Models
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Serialize, Schema)]
+struct StoredFile {
+    /// The form field the file came in.
+    field: String,
+    /// The name the client gave it; informational only.
+    file_name: String,
+    content_type: Option<String>,
+    size: u64,
+    /// The name it was saved under, inside UPLOAD_DIR.
+    stored_as: String,
+}
+
+#[derive(Debug, Clone, Serialize, Schema)]
+struct TextField {
+    name: String,
+    value: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Schema)]
+struct UploadReport {
+    files: Vec<StoredFile>,
+    fields: Vec<TextField>,
+}
+
+/// Where uploads are written.
+#[derive(Clone)]
+struct UploadDir(PathBuf);
+
+/// The number tried first for the next `upload-<n>`. It starts over with
+/// each process; create_upload() skips the names already on disk.
+static NEXT_UPLOAD: AtomicU64 = AtomicU64::new(1);
+
+/// `/upload` takes any file, but its bytes must not contradict its type.
+const ANY_FILE: FileTypes = FileTypes::any();
+
+/// `/images` takes images and nothing else.
+const IMAGES: FileTypes = FileTypes::only(&["image/png", "image/jpeg", "image/gif", "image/webp"]);
+
+// ---------------------------------------------------------------------------
+// Handlers
+// ---------------------------------------------------------------------------
+
+const FORM_PAGE: &str = r#"<!doctype html>
+<html>
+<head><meta charset="utf-8"><title>Upload</title></head>
+<body>
+  <h1>Upload files</h1>
+  <form method="post" action="/upload" enctype="multipart/form-data">
+    <p><input type="file" name="files" multiple></p>
+    <p><label>Description <input name="description"></label></p>
+    <p><button>Upload</button></p>
+  </form>
+  <p>Up to 2 MiB per file, 16 MiB in total.</p>
+</body>
+</html>"#;
+
+#[get("/")]
+#[tag("upload")]
+#[summary("Upload form")]
+async fn index() -> Html<&'static str> {
+    Html(FORM_PAGE)
+}
+
+#[post("/upload")]
+#[tag("upload")]
+#[summary("Upload files and text fields as multipart/form-data")]
+#[errors(
+    400 = "Malformed multipart body",
+    413 = "A part, or the whole body, is over its limit",
+    415 = "Not multipart/form-data",
+    422 = "A file's content doesn't match its Content-Type"
+)]
+async fn upload(State(dir): State<UploadDir>, form: Multipart) -> Result<Json<UploadReport>> {
+    store(&dir.0, form, ANY_FILE).await
+}
+
+#[post("/images")]
+#[tag("upload")]
+#[summary("Upload PNG, JPEG, GIF or WebP images as multipart/form-data")]
+#[errors(
+    400 = "Malformed multipart body",
+    413 = "A part, or the whole body, is over its limit",
+    415 = "Not multipart/form-data",
+    422 = "A file isn't an allowed image type, or isn't what it claims to be"
+)]
+async fn upload_images(
+    State(dir): State<UploadDir>,
+    form: Multipart,
+) -> Result<Json<UploadReport>> {
+    store(&dir.0, form, IMAGES).await
+}
+
+/// Stores every part of `form` under `dir`, or none of them.
+async fn store(dir: &Path, mut form: Multipart, types: FileTypes) -> Result<Json<UploadReport>> {
+    let mut upload = Upload::new(dir);
+    receive(dir, &mut form, types, &mut upload.report).await?;
+    Ok(Json(upload.commit()))
+}
+
+/// The files an upload has written so far. All or nothing: unless the
+/// upload is committed, dropping it deletes them, whether the handler
+/// returned an error (a 413 on the fifth file removes the first four) or the
+/// client went away and the handler was dropped mid-body.
+struct Upload<'a> {
+    dir: &'a Path,
+    report: UploadReport,
+    committed: bool,
+}
+
+impl<'a> Upload<'a> {
+    fn new(dir: &'a Path) -> Self {
+        Self {
+            dir,
+            report: UploadReport::default(),
+            committed: false,
+        }
+    }
+
+    /// Keeps the files and hands back the report.
+    fn commit(mut self) -> UploadReport {
+        self.committed = true;
+        std::mem::take(&mut self.report)
+    }
+}
+
+impl Drop for Upload<'_> {
+    fn drop(&mut self) {
+        if self.committed {
+            return;
+        }
+        // Blocking, but Drop can't await; these are unlinks, not copies.
+        for file in &self.report.files {
+            let _ = std::fs::remove_file(self.dir.join(&file.stored_as));
+        }
+    }
+}
+
+/// Reads every part into `report`, saving files under `dir` as they stream in.
+/// Each file's first bytes are checked against `types` before it is written.
+async fn receive(
+    dir: &Path,
+    form: &mut Multipart,
+    types: FileTypes,
+    report: &mut UploadReport,
+) -> Result<()> {
+    let write_error =
+        |err: std::io::Error| ApiError::internal(format!("Can't save upload: {}", err));
+
+    while let Some(mut field) = form.next_field().await? {
+        let name = field.name().unwrap_or_default().to_string();
+        let Some(file_name) = field.file_name().map(str::to_string) else {
+            let value = field.bytes().await?;
+            report.fields.push(TextField {
+                name,
+                value: String::from_utf8_lossy(&value).into_owned(),
+            });
+            continue;
+        };
+        // A file input left empty still sends a part, with no file name.
+        if file_name.is_empty() {
+            continue;
+        }
+
+        // Nothing is written until the first bytes agree with the type.
+        let content_type = field.content_type().map(str::to_string);
+        let mut head = Vec::new();
+        while head.len() < sniff::SNIFF_LEN {
+            let Some(chunk) = field.chunk().await? else {
+                break;
+            };
+            head.extend_from_slice(&chunk);
+        }
+        types.check(&file_name, content_type.as_deref(), &head)?;
+
+        // Never the client's file name: it may be `../../etc/passwd`.
+        let (stored_as, mut file) = create_upload(dir).await.map_err(write_error)?;
+        let path = dir.join(&stored_as);
+        // Listed before it's written, so a failure part-way removes it too.
+        report.files.push(StoredFile {
+            field: name,
+            file_name,
+            content_type,
+            size: 0,
+            stored_as,
+        });
+
+        file.write_all(&head).await.map_err(write_error)?;
+        let mut size = head.len() as u64;
+        while let Some(chunk) = field.chunk().await? {
+            file.write_all(&chunk).await.map_err(write_error)?;
+            size += chunk.len() as u64;
+        }
+        file.flush().await.map_err(write_error)?;
+
+        let stored = report.files.last_mut().expect("pushed above");
+        stored.size = size;
+        info!(
+            "Stored {} ({} bytes) as {}",
+            stored.file_name,
+            size,
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Creates the next free `upload-<n>` in `dir`.
+///
+/// `create_new` fails if the name is taken, so a file from an earlier run is
+/// skipped rather than truncated, even if another process writes to `dir` too.
+async fn create_upload(dir: &Path) -> std::io::Result<(String, tokio::fs::File)> {
+    loop {
+        let stored_as = format!("upload-{}", NEXT_UPLOAD.fetch_add(1, Ordering::Relaxed));
+        let created = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(dir.join(&stored_as))
+            .await;
+        match created {
+            Ok(file) => return Ok((stored_as, file)),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Main
+// ---------------------------------------------------------------------------
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let dir = match std::env::var("UPLOAD_DIR") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => std::env::temp_dir().join("rustapi-uploads"),
+    };
+    std::fs::create_dir_all(&dir)?;
+
+    println!("Starting file-upload example…");
+    println!(" -> GET  http://127.0.0.1:3000/        (upload form)");
+    println!(" -> POST http://127.0.0.1:3000/upload");
+    println!(" -> POST http://127.0.0.1:3000/images  (PNG, JPEG, GIF, WebP only)");
+    println!(" -> GET  http://127.0.0.1:3000/docs");
+    println!("Uploads are written to {}", dir.display());
+
+    RustApi::auto()
+        .state(UploadDir(dir))
+        // No MultipartConfig in state: Multipart uses its defaults, 2 MiB per
+        // part and 16 MiB per body. The framework's Content-Length check
+        // must let such a body through.
+        .body_limit(multipart::DEFAULT_MAX_SIZE)
+        .run("127.0.0.1:3000")
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+    use http_body_util::BodyExt;
+    use rustapi_core::{BodyVariant, PathParams};
+
+    const BOUNDARY: &str = "XyZ";
+
+    /// A multipart body of file parts: field name, file name, type, bytes.
+    fn form(files: &[(&str, &str, &str, &[u8])]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (field, file_name, content_type, bytes) in files {
+            body.extend_from_slice(
+                format!(
+                    "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"{field}\"; filename=\"{file_name}\"\r\nContent-Type: {content_type}\r\n\r\n"
+                )
+                .as_bytes(),
+            );
+            body.extend_from_slice(bytes);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{BOUNDARY}--\r\n").as_bytes());
+        body
+    }
+
+    async fn post(dir: &Path, path: &str, body: Vec<u8>) -> (StatusCode, serde_json::Value) {
+        let app = RustApi::auto()
+            .state(UploadDir(dir.to_path_buf()))
+            .request_dispatcher();
+        let (parts, ()) = http::Request::post(path)
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={BOUNDARY}"),
+            )
+            .body(())
+            .unwrap()
+            .into_parts();
+        let request = Request::new(
+            parts,
+            BodyVariant::Buffered(body.into()),
+            app.state_ref(),
+            PathParams::new(),
+        );
+        let response = app.dispatch(request).await;
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn a_type_the_bytes_contradict_is_a_422_and_nothing_is_kept() {
+        let dir = std::env::temp_dir().join(format!("file-upload-sniff-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let body = form(&[
+            ("files", "ok.png", "image/png", b"\x89PNG\r\n\x1a\n\0\0"),
+            ("files", "fake.png", "image/png", b"GIF89a\x01\0\x01\0"),
+        ]);
+        let (status, error) = post(&dir, "/images", body).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error["error"]["type"], "file_type_mismatch", "{error}");
+        // The first file was fine, but the upload is all or nothing.
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_dropped_upload_removes_what_it_wrote() {
+        let dir = std::env::temp_dir().join(format!("file-upload-drop-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // The first file's bytes, then nothing: the client stopped sending
+        // before the next boundary, and the connection will be dropped.
+        let mut body = form(&[("files", "a.txt", "text/plain", &[b'a'; 4096])]);
+        body.truncate(body.len() - format!("\r\n--{BOUNDARY}--\r\n").len());
+        let stream = futures_util::stream::iter([Ok::<_, std::convert::Infallible>(
+            bytes::Bytes::from(body),
+        )])
+        .chain(futures_util::stream::pending());
+        let form = Multipart::new(stream, BOUNDARY, &multipart::default_config());
+
+        let mut upload = Box::pin(store(&dir, form, ANY_FILE));
+        tokio::select! {
+            _ = &mut upload => panic!("the body never ends"),
+            _ = async {
+                while std::fs::read_dir(&dir).unwrap().count() == 0 {
+                    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+                }
+            } => {}
+        }
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        // What hyper does with the handler when the client disconnects.
+        drop(upload);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn existing_uploads_are_skipped_not_truncated() {
+        let dir = std::env::temp_dir().join(format!("file-upload-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // What a previous run left behind, under the next numbers this one
+        // would try.
+        let next = NEXT_UPLOAD.load(Ordering::Relaxed);
+        let earlier: Vec<_> = (next..next + 3)
+            .map(|n| dir.join(format!("upload-{n}")))
+            .collect();
+        for path in &earlier {
+            std::fs::write(path, "kept").unwrap();
+        }
+
+        let (stored_as, _file) = create_upload(&dir).await.unwrap();
+
+        assert!(!earlier.contains(&dir.join(&stored_as)), "{stored_as}");
+        for path in &earlier {
+            assert_eq!(std::fs::read_to_string(path).unwrap(), "kept");
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,197 @@
+// ---------------------------------------------------------------------------
+// Streaming multipart extractor
+// ---------------------------------------------------------------------------
+//
+// RustAPI ships Multipart and StreamingMultipart, but neither implements
+// OperationModifier, so neither can be a `#[post]` handler argument. This
+// Multipart can, and it never holds the whole body: parts are parsed off the
+// connection as they arrive, and a file part can be read chunk by chunk.
+//
+//   async fn upload(mut form: Multipart) -> Result<...> {
+//       while let Some(mut field) = form.next_field().await? {
+//           field.name(); field.file_name(); field.content_type();
+//           while let Some(chunk) = field.chunk().await? { ... }
+//       }
+//   }
+//
+// Limits come from the app's MultipartConfig, or the defaults below when
+// there is none. Each is checked while reading, and exceeding one is a 413
+// at that point:
+//
+//   max_file_size   any one part, file or text        (default 2 MiB)
+//   max_size        the whole body, framing included  (default 16 MiB)
+//   max_fields      how many parts                    (default 100)
+//
+// NOTE: RustApi::run() also rejects a Content-Length above `.body_limit()`
+//       (1 MiB unless raised) before the handler runs. Set it to max_size.
+
+use bytes::Bytes;
+use futures_util::Stream;
+use http_body_util::BodyExt;
+use rustapi_openapi::{MediaType, Operation, OperationModifier, RequestBody, SchemaRef};
+use rustapi_rs::prelude::*;
+use rustapi_rs::FromRequest;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+
+pub const DEFAULT_MAX_FILE_SIZE: usize = 2 * 1024 * 1024;
+pub const DEFAULT_MAX_SIZE: usize = 16 * 1024 * 1024;
+
+/// The limits used when the app has no `MultipartConfig` in state.
+pub fn default_config() -> MultipartConfig {
+    MultipartConfig::new()
+        .max_file_size(DEFAULT_MAX_FILE_SIZE)
+        .max_size(DEFAULT_MAX_SIZE)
+}
+
+/// A `multipart/form-data` body, read one part at a time.
+pub struct Multipart {
+    inner: multer::Multipart<'static>,
+    max_fields: usize,
+    fields: usize,
+}
+
+/// One part: a file, or a plain form field.
+pub struct Field {
+    inner: multer::Field<'static>,
+}
+
+fn too_large(message: String) -> ApiError {
+    ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large", message)
+}
+
+fn multipart_error(err: multer::Error) -> ApiError {
+    match err {
+        multer::Error::FieldSizeExceeded { limit, field_name } => too_large(format!(
+            "Part {} exceeds limit of {} bytes",
+            field_name.as_deref().unwrap_or("(unnamed)"),
+            limit
+        )),
+        multer::Error::StreamSizeExceeded { limit } => {
+            too_large(format!("Multipart body exceeds limit of {} bytes", limit))
+        }
+        err => ApiError::bad_request(format!("Invalid multipart body: {}", err)),
+    }
+}
+
+impl Multipart {
+    /// Reads `stream`, a body split on `boundary`, within `config`'s limits.
+    pub fn new<S, E>(stream: S, boundary: impl Into<String>, config: &MultipartConfig) -> Self
+    where
+        S: Stream<Item = std::result::Result<Bytes, E>> + Send + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync>> + 'static,
+    {
+        let limits = multer::SizeLimit::new()
+            .whole_stream(config.max_size as u64)
+            .per_field(config.max_file_size as u64);
+        let constraints = multer::Constraints::new().size_limit(limits);
+        Self {
+            inner: multer::Multipart::with_constraints(stream, boundary, constraints),
+            max_fields: config.max_fields,
+            fields: 0,
+        }
+    }
+
+    /// The next part, or None after the last one. Drop or finish reading the
+    /// previous part first.
+    pub async fn next_field(&mut self) -> Result<Option<Field>> {
+        let Some(field) = self.inner.next_field().await.map_err(multipart_error)? else {
+            return Ok(None);
+        };
+        self.fields += 1;
+        if self.fields > self.max_fields {
+            return Err(too_large(format!(
+                "Multipart body has more than {} parts",
+                self.max_fields
+            )));
+        }
+        Ok(Some(Field { inner: field }))
+    }
+}
+
+impl Field {
+    /// The form field's `name`.
+    pub fn name(&self) -> Option<&str> {
+        self.inner.name()
+    }
+
+    /// The uploaded file's name as the client sent it. Never use it as a
+    /// path on disk.
+    pub fn file_name(&self) -> Option<&str> {
+        self.inner.file_name()
+    }
+
+    /// The part's Content-Type, as the client declared it.
+    pub fn content_type(&self) -> Option<&str> {
+        self.inner.content_type().map(|mime| mime.essence_str())
+    }
+
+    /// The next piece of the part, or None at its end.
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>> {
+        self.inner.chunk().await.map_err(multipart_error)
+    }
+
+    /// The rest of the part, collected. Bounded by `max_file_size`.
+    pub async fn bytes(self) -> Result<Bytes> {
+        self.inner.bytes().await.map_err(multipart_error)
+    }
+}
+
+impl FromRequest for Multipart {
+    async fn from_request(req: &mut Request) -> Result<Self> {
+        let content_type = req
+            .headers()
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let boundary = multer::parse_boundary(content_type).map_err(|_| {
+            ApiError::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "unsupported_media_type",
+                "Expected multipart/form-data with a boundary",
+            )
+        })?;
+
+        let config = req
+            .state()
+            .get::<MultipartConfig>()
+            .cloned()
+            .unwrap_or_else(default_config);
+
+        // Straight off the connection when we can; in-process dispatch (and
+        // any layer that read the body) leaves it buffered instead.
+        if let Some(stream) = req.take_stream() {
+            Ok(Self::new(stream.into_data_stream(), boundary, &config))
+        } else {
+            let body = req
+                .take_body()
+                .ok_or_else(|| ApiError::internal("Body already consumed"))?;
+            Ok(Self::new(
+                futures_util::stream::once(async move { Ok::<_, Infallible>(body) }),
+                boundary,
+                &config,
+            ))
+        }
+    }
+}
+
+impl OperationModifier for Multipart {
+    fn update_operation(op: &mut Operation) {
+        let mut content = BTreeMap::new();
+        content.insert(
+            "multipart/form-data".to_string(),
+            MediaType {
+                schema: Some(SchemaRef::Inline(serde_json::json!({
+                    "type": "object",
+                    "additionalProperties": { "type": "string", "format": "binary" }
+                }))),
+                example: None,
+            },
+        );
+        op.request_body = Some(RequestBody {
+            description: Some("Any number of files and text fields.".to_string()),
+            required: Some(true),
+            content,
+        });
+    }
+}
@@ -0,0 +1,284 @@
+// ---------------------------------------------------------------------------
+// Cookies in, Set-Cookie out
+// ---------------------------------------------------------------------------
+//
+// RustAPI's own cookie support sits behind the `cookies` feature and the
+// `cookie` crate. This is the small version, in two halves:
+//
+//   Cookies      extractor: the request's `Cookie` header(s) as a map,
+//                `cookies.get("session") -> Option<&str>`
+//   CookieJar    cookies to set or delete, attached to a response with
+//                `SetCookie(body, jar)`, the way Cached attaches a policy
+//
+//   let jar = CookieJar::new()
+//       .add(Cookie::new("session", id).path("/").http_only(true)
+//                .same_site(SameSite::Lax).max_age(Duration::from_secs(3600)))
+//       .remove("flash");
+//   Ok(SetCookie(Json(me), jar))
+//
+// Every cookie in the jar becomes its own `Set-Cookie` header. Unlike other
+// headers, Set-Cookie values must never be folded into one comma-separated
+// line: `Expires=Thu, 01 Jan ...` contains a comma itself.
+//
+// NOTE: a cookie is only replaced or deleted by one with the same name, Path
+//       and Domain. `remove(name)` deletes the cookie set with Path=/, which
+//       is every cookie this example sets.
+
+use http::header::{HeaderValue, COOKIE, SET_COOKIE};
+use rustapi_openapi::{OpenApiSpec, Operation, OperationModifier, ResponseModifier};
+use rustapi_rs::prelude::*;
+use rustapi_rs::FromRequestParts;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// ---------------------------------------------------------------------------
+// Reading
+// ---------------------------------------------------------------------------
+
+/// The cookies the client sent, by name.
+#[derive(Debug, Clone, Default)]
+pub struct Cookies(HashMap<String, String>);
+
+impl Cookies {
+    /// The value of cookie `name`, if it was sent.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+}
+
+impl FromRequestParts for Cookies {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        let mut cookies = HashMap::new();
+        // HTTP/2 clients may split the list over several Cookie headers.
+        for header in req.headers().get_all(COOKIE) {
+            let Ok(header) = header.to_str() else {
+                continue;
+            };
+            for pair in header.split(';') {
+                let Some((name, value)) = pair.trim().split_once('=') else {
+                    continue;
+                };
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+                    .unwrap_or(value);
+                // Browsers send the most specific path first; keep that one.
+                cookies
+                    .entry(name.to_string())
+                    .or_insert_with(|| value.to_string());
+            }
+        }
+        Ok(Cookies(cookies))
+    }
+}
+
+impl OperationModifier for Cookies {
+    fn update_operation(_op: &mut Operation) {}
+}
+
+// ---------------------------------------------------------------------------
+// Writing
+// ---------------------------------------------------------------------------
+
+/// Which cross-site requests carry the cookie.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    /// Same-site requests only.
+    Strict,
+    /// Same-site requests, plus top-level navigations from other sites.
+    Lax,
+    /// Every request. Browsers require `Secure` with it.
+    None,
+}
+
+impl std::str::FromStr for SameSite {
+    type Err = String;
+
+    /// `strict`, `lax` or `none`, in any case.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "strict" => Ok(SameSite::Strict),
+            "lax" => Ok(SameSite::Lax),
+            "none" => Ok(SameSite::None),
+            _ => Err(format!(
+                "unknown SameSite '{}', expected strict, lax or none",
+                value
+            )),
+        }
+    }
+}
+
+/// One `Set-Cookie` header value.
+#[derive(Debug, Clone)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    max_age: Option<Duration>,
+    expires: Option<SystemTime>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    /// A session cookie (gone when the browser closes) with no attributes.
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+            path: None,
+            max_age: None,
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        }
+    }
+
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Lifetime from now. Wins over `expires` where both are set.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// A fixed end, for clients that predate Max-Age.
+    pub fn expires(mut self, at: SystemTime) -> Self {
+        self.expires = Some(at);
+        self
+    }
+
+    /// Only sent over HTTPS.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// Hidden from JavaScript.
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    /// Whether name and value only use characters RFC 6265 allows.
+    fn is_valid(&self) -> bool {
+        let token = |byte: u8| byte.is_ascii_graphic() && !b"()<>@,;:\\\"/[]?={}".contains(&byte);
+        let octet = |byte: u8| byte.is_ascii_graphic() && !b"\",;\\".contains(&byte);
+        !self.name.is_empty() && self.name.bytes().all(token) && self.value.bytes().all(octet)
+    }
+}
+
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(path) = &self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", httpdate::fmt_http_date(expires))?;
+        }
+        if self.secure {
+            f.write_str("; Secure")?;
+        }
+        if self.http_only {
+            f.write_str("; HttpOnly")?;
+        }
+        match self.same_site {
+            Some(SameSite::Strict) => f.write_str("; SameSite=Strict")?,
+            Some(SameSite::Lax) => f.write_str("; SameSite=Lax")?,
+            Some(SameSite::None) => f.write_str("; SameSite=None")?,
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+/// Cookies to set (or delete) with a response.
+#[derive(Debug, Clone, Default)]
+pub struct CookieJar {
+    cookies: Vec<Cookie>,
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets `cookie`, replacing one of the same name already in the jar.
+    pub fn add(mut self, cookie: Cookie) -> Self {
+        self.cookies.retain(|held| held.name != cookie.name);
+        self.cookies.push(cookie);
+        self
+    }
+
+    /// Deletes cookie `name`, as set with `Path=/`.
+    pub fn remove(self, name: &str) -> Self {
+        // Max-Age=0 for current clients, an Expires in the past for old ones.
+        self.add(
+            Cookie::new(name, "")
+                .path("/")
+                .max_age(Duration::ZERO)
+                .expires(UNIX_EPOCH),
+        )
+    }
+}
+
+/// `T`, with a `Set-Cookie` header for every cookie in the jar.
+pub struct SetCookie<T>(pub T, pub CookieJar);
+
+impl<T: IntoResponse> IntoResponse for SetCookie<T> {
+    fn into_response(self) -> Response {
+        let SetCookie(inner, jar) = self;
+        let mut response = inner.into_response();
+        for cookie in jar.cookies {
+            if !cookie.is_valid() {
+                error!("Dropped cookie {:?}: invalid name or value", cookie.name);
+                continue;
+            }
+            match HeaderValue::try_from(cookie.to_string()) {
+                // append, not insert: one header per cookie.
+                Ok(value) => {
+                    response.headers_mut().append(SET_COOKIE, value);
+                }
+                Err(err) => error!("Dropped cookie {:?}: {}", cookie.name, err),
+            }
+        }
+        response
+    }
+}
+
+impl<T: ResponseModifier> ResponseModifier for SetCookie<T> {
+    fn update_response(op: &mut Operation) {
+        T::update_response(op);
+        for (status, response) in op.responses.iter_mut() {
+            if status.starts_with('2') {
+                // NOTE: rustapi_openapi doesn't export its Header type by name;
+                //       serde fills it in from the map's value type.
+                let header = serde_json::from_value(serde_json::json!({
+                    "description": "One header per cookie set or deleted",
+                    "schema": { "type": "string" }
+                }))
+                .expect("valid OpenAPI header object");
+                response.headers.insert("Set-Cookie".to_string(), header);
+            }
+        }
+    }
+
+    fn register_components(spec: &mut OpenApiSpec) {
+        T::register_components(spec);
+    }
+}
@@ -0,0 +1,210 @@
+// Run with: cargo run -p sessions
+//           SECURE_COOKIES=true cargo run -p sessions   (behind HTTPS)
+//           SECURE_COOKIES=true SAME_SITE=none cargo run -p sessions
+// Then: curl -c jar -b jar -X POST http://127.0.0.1:3000/login -d '{"name":"ada"}' \
+//            -H 'Content-Type: application/json'
+//       curl -c jar -b jar http://127.0.0.1:3000/me
+//
+// Lesson: a session is a random id in an HttpOnly cookie plus a server-side
+//         record. The cookie carries no data worth stealing or forging; log
+//         out deletes both halves.
+
+mod cookies;
+
+use cookies::{Cookie, CookieJar, Cookies, SameSite, SetCookie};
+use ring::rand::{SecureRandom, SystemRandom};
+use rustapi_rs::prelude::*;
+use rustapi_rs::{errors, get, post, summary, tag};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::RwLock;
+
+/// Name of the session cookie.
+const SESSION_COOKIE: &str = "session";
+
+/// How long a session lives after login.
+const SESSION_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// How long the browser remembers the last name used, for the login form.
+const REMEMBER_NAME_FOR: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+// ---------------------------------------------------------------------------
+// Models
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Deserialize, Schema)]
+struct Login {
+    name: String,
+}
+
+#[derive(Debug, Serialize, Schema)]
+struct Me {
+    name: String,
+    /// Requests to `/me` in this session, this one included.
+    visits: u32,
+    /// Seconds until the session expires.
+    expires_in: u64,
+}
+
+struct Session {
+    name: String,
+    visits: u32,
+    expires_at: Instant,
+}
+
+/// Sessions by id. In memory: a restart logs everyone out.
+#[derive(Clone, Default)]
+struct Sessions(Arc<RwLock<HashMap<String, Session>>>);
+
+/// Attributes every cookie here gets.
+#[derive(Clone, Copy)]
+struct CookiePolicy {
+    secure: bool,
+    same_site: SameSite,
+}
+
+/// 128 random bits, hex-encoded.
+fn new_session_id() -> String {
+    let mut bytes = [0u8; 16];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("the OS random source failed");
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn not_logged_in() -> ApiError {
+    ApiError::unauthorized("Not logged in")
+}
+
+// ---------------------------------------------------------------------------
+// Handlers
+// ---------------------------------------------------------------------------
+
+#[post("/login")]
+#[tag("session")]
+#[summary("Start a session; sets the session cookie")]
+#[errors(400 = "Empty name")]
+async fn login(
+    State(sessions): State<Sessions>,
+    State(policy): State<CookiePolicy>,
+    Json(login): Json<Login>,
+) -> Result<SetCookie<Json<Me>>> {
+    let name = login.name.trim().to_string();
+    if name.is_empty() {
+        return Err(ApiError::bad_request("Name must not be empty"));
+    }
+
+    let id = new_session_id();
+    let mut store = sessions.0.write().await;
+    // Expired sessions are only ever dropped here and in /me.
+    let now = Instant::now();
+    store.retain(|_, session| session.expires_at > now);
+    store.insert(
+        id.clone(),
+        Session {
+            name: name.clone(),
+            visits: 0,
+            expires_at: now + SESSION_TTL,
+        },
+    );
+
+    let jar = CookieJar::new()
+        .add(
+            Cookie::new(SESSION_COOKIE, id)
+                .path("/")
+                .http_only(true)
+                .secure(policy.secure)
+                .same_site(policy.same_site)
+                .max_age(SESSION_TTL),
+        )
+        // Not a secret: readable by the page's scripts to prefill a form.
+        // Cookie values can't hold spaces or semicolons, so those go.
+        .add(
+            Cookie::new(
+                "last_name",
+                name.replace(|c: char| !c.is_ascii_alphanumeric(), "_"),
+            )
+            .path("/")
+            .secure(policy.secure)
+            .same_site(policy.same_site)
+            .expires(SystemTime::now() + REMEMBER_NAME_FOR),
+        );
+
+    Ok(SetCookie(
+        Json(Me {
+            name,
+            visits: 0,
+            expires_in: SESSION_TTL.as_secs(),
+        }),
+        jar,
+    ))
+}
+
+#[get("/me")]
+#[tag("session")]
+#[summary("The session's user; counts visits")]
+#[errors(401 = "No session cookie, or the session expired")]
+async fn me(cookies: Cookies, State(sessions): State<Sessions>) -> Result<Json<Me>> {
+    let id = cookies.get(SESSION_COOKIE).ok_or_else(not_logged_in)?;
+    let mut store = sessions.0.write().await;
+    let now = Instant::now();
+    let session = store
+        .get_mut(id)
+        .filter(|session| session.expires_at > now)
+        .ok_or_else(not_logged_in)?;
+
+    session.visits += 1;
+    Ok(Json(Me {
+        name: session.name.clone(),
+        visits: session.visits,
+        expires_in: session.expires_at.duration_since(now).as_secs(),
+    }))
+}
+
+#[post("/logout")]
+#[tag("session")]
+#[summary("End the session; deletes the session cookie")]
+async fn logout(cookies: Cookies, State(sessions): State<Sessions>) -> SetCookie<NoContent> {
+    // Deleting the cookie alone isn't enough: a copy of it would still work.
+    if let Some(id) = cookies.get(SESSION_COOKIE) {
+        sessions.0.write().await.remove(id);
+    }
+    SetCookie(NoContent, CookieJar::new().remove(SESSION_COOKIE))
+}
+
+// ---------------------------------------------------------------------------
+// Main
+// ---------------------------------------------------------------------------
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Browsers keep Secure cookies away from http://, localhost aside; curl
+    // doesn't make that exception. Turn it on wherever HTTPS terminates.
+    let secure = match std::env::var("SECURE_COOKIES") {
+        Ok(value) => value.parse()?,
+        Err(_) => false,
+    };
+    // Lax: sent on same-site requests and on links followed from elsewhere.
+    let same_site = match std::env::var("SAME_SITE") {
+        Ok(value) => value.parse()?,
+        Err(_) => SameSite::Lax,
+    };
+    if same_site == SameSite::None && !secure {
+        return Err(
+            "SAME_SITE=none needs SECURE_COOKIES=true; browsers reject it otherwise".into(),
+        );
+    }
+
+    println!("Starting sessions example…");
+    println!(" -> POST http://127.0.0.1:3000/login   {{\"name\": \"ada\"}}");
+    println!(" -> GET  http://127.0.0.1:3000/me");
+    println!(" -> POST http://127.0.0.1:3000/logout");
+    println!(" -> GET  http://127.0.0.1:3000/docs");
+
+    RustApi::auto()
+        .state(Sessions::default())
+        .state(CookiePolicy { secure, same_site })
+        .run("127.0.0.1:3000")
+        .await
+}